@@ -0,0 +1,95 @@
+use std::process::Command;
+use anyhow::{Result, Context};
+use serde::Serialize;
+
+use crate::experimental;
+use crate::paths;
+use crate::powershell;
+use crate::vpn::VpnManager;
+
+/// Everything a bug report needs about this build and machine
+#[derive(Debug, Serialize)]
+struct AboutInfo {
+    version: &'static str,
+    target: String,
+    vpn_backend: &'static str,
+    experimental: bool,
+    windows_version: String,
+    powershell: String,
+    config_file: String,
+    data_dir: String,
+}
+
+pub struct AboutManager;
+
+impl AboutManager {
+    // Private utility functions
+
+    /// Windows version as reported by `ver` (e.g. "Microsoft Windows [Version 10.0.22631.4037]")
+    fn windows_version() -> String {
+        Command::new("cmd")
+            .args(["/c", "ver"])
+            .output()
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|version| !version.is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// PowerShell version, or why it can't be used
+    fn powershell_version() -> String {
+        if let Some(restriction) = powershell::restriction() {
+            return restriction.to_string();
+        }
+
+        powershell::query::<String>("$PSVersionTable.PSVersion.ToString()")
+            .ok()
+            .and_then(|versions| versions.into_iter().next())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Shows a path, or why it couldn't be found
+    fn path_or_error(path: Result<std::path::PathBuf>) -> String {
+        match path {
+            Ok(path) => path.display().to_string(),
+            Err(e) => format!("unavailable ({})", e),
+        }
+    }
+
+    // Public methods for the environment report
+
+    /// Prints the version, build and environment details (as JSON if requested), to paste into bug reports.
+    pub fn show(json: bool) -> Result<()> {
+        let info = AboutInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            target: format!(
+                "{}-{} ({})",
+                std::env::consts::ARCH,
+                std::env::consts::OS,
+                if cfg!(debug_assertions) { "debug" } else { "release" }
+            ),
+            vpn_backend: VpnManager::backend_name(),
+            experimental: experimental::enabled(),
+            windows_version: Self::windows_version(),
+            powershell: Self::powershell_version(),
+            config_file: Self::path_or_error(paths::config_file()),
+            data_dir: Self::path_or_error(paths::data_dir()),
+        };
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&info).context("Failed to serialize the environment report")?);
+            return Ok(());
+        }
+
+        println!("upv-cli {}", info.version);
+        println!("  Build target:    {}", info.target);
+        println!("  VPN backend:     {}", info.vpn_backend);
+        println!("  Experimental:    {}", if info.experimental { "on" } else { "off" });
+        println!("  Windows:         {}", info.windows_version);
+        println!("  PowerShell:      {}", info.powershell);
+        println!("  Config file:     {}", info.config_file);
+        println!("  Data directory:  {}", info.data_dir);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+use serde::Deserialize;
+
+use crate::error::UpvError;
+
+// Share catalogs are TOML files that map friendly names to share paths, so faculty resources can be mounted by name.
+// Every *.toml file in the catalog folders is read: the machine-wide one (%ProgramData%\upv\shares), where admins
+// or faculty installers drop theirs, and the user's own (%APPDATA%\upv\shares), whose entries win on name clashes.
+//
+// Example (etsinf.toml):
+//   [shares.ETSINF-Software]
+//   path = '\\fileserver.etsinf.upv.es\software'
+//   description = "Licensed software for ETSINF students"
+//   drive = "S"
+const CATALOG_FOLDER: &str = r"upv\shares";
+
+/// Contents of a catalog file
+#[derive(Debug, Deserialize)]
+struct CatalogFile {
+    #[serde(default)]
+    shares: BTreeMap<String, CatalogEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CatalogEntry {
+    path: String,
+    description: Option<String>,
+    drive: Option<char>,
+}
+
+/// A share listed in a catalog
+#[derive(Debug, Clone)]
+pub struct CatalogShare {
+    /// Friendly name of the share (e.g. "ETSINF-Software")
+    pub name: String,
+    /// UNC path of the share, which may use the same placeholders as share templates ({server}, {initial}, {user})
+    pub path: String,
+    pub description: Option<String>,
+    /// Drive letter suggested by the catalog, used when none is given
+    pub drive: Option<char>,
+    /// Catalog file the share comes from
+    pub source: PathBuf,
+}
+
+pub struct CatalogManager;
+
+impl CatalogManager {
+    // Private utility functions
+
+    /// Catalog folders, from lowest to highest priority (machine-wide first, then the user's).
+    fn catalog_dirs() -> Vec<PathBuf> {
+        ["ProgramData", "APPDATA"].iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .map(|dir| PathBuf::from(dir).join(CATALOG_FOLDER))
+            .collect()
+    }
+
+    /// Reads the shares of a catalog file.
+    fn read_catalog(path: &Path) -> Result<Vec<CatalogShare>> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the share catalog '{}'", path.display()))?;
+        let catalog: CatalogFile = toml::from_str(&content).map_err(|e| UpvError::Drive(
+            format!("The share catalog '{}' is not valid: {}", path.display(), e).into()
+        ))?;
+
+        let shares = catalog.shares.into_iter()
+            .map(|(name, entry)| CatalogShare {
+                name,
+                path: entry.path,
+                description: entry.description,
+                drive: entry.drive.map(|drive| drive.to_ascii_uppercase()),
+                source: path.to_path_buf(),
+            })
+            .collect();
+
+        Ok(shares)
+    }
+
+    // Public methods for share catalogs
+
+    /// Loads every share from the catalog folders, sorted by name. Shares with the same name (ignoring case)
+    /// are taken from the catalog with the highest priority.
+    pub fn load() -> Result<Vec<CatalogShare>> {
+        let mut shares: BTreeMap<String, CatalogShare> = BTreeMap::new();
+
+        for dir in Self::catalog_dirs() {
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read the share catalog folder '{}'", dir.display())),
+            };
+
+            // Files in a folder are read in name order, so clashes between them are resolved the same way every time
+            let mut files: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml")))
+                .collect();
+            files.sort();
+
+            for file in files {
+                for share in Self::read_catalog(&file)? {
+                    shares.insert(share.name.to_lowercase(), share);
+                }
+            }
+        }
+
+        Ok(shares.into_values().collect())
+    }
+
+    /// Finds a share by name (ignoring case).
+    pub fn find(name: &str) -> Result<CatalogShare> {
+        Self::load()?
+            .into_iter()
+            .find(|share| share.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| UpvError::Drive(
+                format!("There is no share named '{}' in the share catalogs", name).into()
+            ).with_hint("Run 'upv drive shares' to see the available shares").into())
+    }
+
+    /// Lists the shares of every catalog.
+    pub fn list() -> Result<()> {
+        let shares = Self::load()?;
+
+        if shares.is_empty() {
+            let dirs: Vec<String> = Self::catalog_dirs().iter().map(|dir| dir.display().to_string()).collect();
+            println!("No shares found. Share catalogs (*.toml files) are read from: {}", dirs.join(", "));
+            return Ok(());
+        }
+
+        println!("Shares available to 'upv drive mount-share':");
+        for share in &shares {
+            let drive = share.drive.map(|drive| format!(" [{}:]", drive)).unwrap_or_default();
+            println!("  - {}{} -> {}", share.name, drive, share.path);
+            if let Some(description) = &share.description {
+                println!("      {}", description);
+            }
+            println!("      \x1b[2mfrom {}\x1b[0m", share.source.display());
+        }
+
+        Ok(())
+    }
+}
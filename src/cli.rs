@@ -1,6 +1,17 @@
+use crate::catalog::CatalogManager;
+use crate::config::ConfigKey;
 use crate::drive::UPVDomain;
+use crate::env::ExportShell;
+use crate::manifest::PackageManager;
+use crate::schedule::ScheduledAction;
+use crate::vpn::{EntryOption, ExportFormat, TunnelType, VpnManager, UPV_DNS_SUFFIX};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use clap_complete::{ArgValueCandidates, CompletionCandidate};
+use anyhow::{Result, Context};
+use std::io::{self, BufRead};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(name = "upv")]
@@ -9,6 +20,79 @@ use clap::{Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Output format (in JSON mode, errors are printed to stderr as JSON objects)
+    #[arg(long, global = true, value_enum, ignore_case = true, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+    /// Print the commands that would change connections, drives or network settings instead of running them (passwords are redacted)
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Show more details: the command that failed along with errors (passwords are redacted), and a table in 'vpn list'
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+    /// Turn on experimental features, which may still change or break (also with UPV_EXPERIMENTAL=1 or 'upv config set experimental true')
+    #[arg(long, global = true)]
+    pub enable_experimental: bool,
+    /// Use the username, domain and defaults of an account in the config file ([accounts.<NAME>]) instead of the default ones
+    #[arg(long, global = true, value_name = "NAME")]
+    pub account: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable output
+    Text,
+    /// Machine-readable output
+    Json,
+}
+
+/// On/off value for settings flags
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Toggle {
+    /// Enable the setting
+    On,
+    /// Disable the setting
+    Off,
+}
+
+impl Toggle {
+    pub fn is_on(self) -> bool {
+        self == Toggle::On
+    }
+}
+
+/// Drive letter to mount to, or the first free one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DriveChoice {
+    Letter(char),
+    Auto,
+}
+
+/// Shells supported by `upv completions`
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CompletionShell {
+    /// Bourne Again SHell
+    Bash,
+    /// Elvish shell
+    Elvish,
+    /// Friendly Interactive SHell
+    Fish,
+    /// Windows PowerShell and PowerShell 7
+    Powershell,
+    /// Z SHell
+    Zsh,
+}
+
+impl CompletionShell {
+    /// Script generator for this shell
+    pub fn generator(self) -> clap_complete::Shell {
+        match self {
+            CompletionShell::Bash => clap_complete::Shell::Bash,
+            CompletionShell::Elvish => clap_complete::Shell::Elvish,
+            CompletionShell::Fish => clap_complete::Shell::Fish,
+            CompletionShell::Powershell => clap_complete::Shell::PowerShell,
+            CompletionShell::Zsh => clap_complete::Shell::Zsh,
+        }
+    }
 }
 
 #[derive(Subcommand)]
@@ -23,6 +107,80 @@ pub enum Commands {
         #[command(subcommand)]
         action: DriveAction,
     },
+    /// Network helpers (captive portals of guest Wi-Fi networks)
+    Net {
+        #[command(subcommand)]
+        action: NetAction,
+    },
+    /// Defaults used when arguments are omitted (username, domain, VPN name, drive letter...)
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// UPV credentials stored in the Windows Credential Manager
+    Creds {
+        #[command(subcommand)]
+        action: CredsAction,
+    },
+    /// Connect to or disconnect from the VPN automatically, at logon or at specific times, or mount drive profiles during their hours (through the Task Scheduler)
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+    /// Show the status of the UPV VPN connections and the network drives
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe status --watch --interval 5\x1b[0m  Redraws the status every 5 seconds, highlighting changes\n \x1b[1mupv.exe status --max-age 30\x1b[0m  Answers instantly from the last status read if it is at most 30 seconds old (for shell prompts)"
+    )]
+    Status {
+        /// Keep refreshing the status in place and highlight state changes (press Ctrl+C to stop)
+        #[arg(short, long)]
+        watch: bool,
+        /// Seconds between refreshes in watch mode
+        #[arg(short, long, default_value_t = 2, requires = "watch")]
+        interval: u64,
+        /// Show the status saved by a previous run if it is at most this many seconds old, instead of reading it again
+        #[arg(long, value_name = "SECONDS", conflicts_with = "watch")]
+        max_age: Option<u64>,
+    },
+    /// Print statements that export the VPN and drive state as environment variables, for scripts
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe env | Invoke-Expression\x1b[0m  Sets UPV_VPN_STATUS, UPV_VPN_IP, UPV_DRIVE, UPV_DRIVE_UNC... in the current PowerShell session\n \x1b[1meval \"$(upv.exe env --shell posix)\"\x1b[0m  Does the same in Git Bash or WSL"
+    )]
+    Env {
+        /// Shell to print the statements for
+        #[arg(short, long, value_enum, ignore_case = true, default_value_t = ExportShell::Powershell)]
+        shell: ExportShell,
+        /// Drive letter to export (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+    },
+    /// Check the machine for common problems (PowerShell, RAS service, VPN server, VPN connections, network drives)
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe doctor\x1b[0m  Prints a pass/fail report with hints on how to fix each problem"
+    )]
+    Doctor,
+    /// Print the version and environment details (Windows, PowerShell, VPN backend, paths) to include in bug reports
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe about --output json\x1b[0m  Prints the report as JSON"
+    )]
+    About,
+    /// Generate the winget or Scoop manifest of the current version, for maintainers and packagers
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe package-manifest scoop target\\release\\upv.exe\x1b[0m  Prints the Scoop manifest, with the hash of the binary\n \x1b[1mupv.exe package-manifest winget target\\release\\upv.exe --out-dir manifests\x1b[0m  Writes the winget manifests to the manifests directory"
+    )]
+    PackageManifest {
+        /// Package manager to generate the manifest for
+        #[arg(value_enum, ignore_case = true)]
+        manager: PackageManager,
+        /// Release artifact to hash (the upv.exe built with 'cargo build --release')
+        artifact: PathBuf,
+        /// Download URL of the artifact (defaults to the one in the GitHub release of this version)
+        #[arg(long)]
+        url: Option<String>,
+        /// Directory to write the manifest files to, instead of printing them
+        #[arg(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
     /// Generate an auto-completions script for your shell
     #[command(
       after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe completions powershell\x1b[0m  Generates a PowerShell script for auto-completions"
@@ -30,87 +188,731 @@ pub enum Commands {
     Completions {
         /// Shell type for completions
         #[arg(value_enum, ignore_case = true)]
-        shell: clap_complete::Shell,
+        shell: CompletionShell,
     },
 }
 
 #[derive(Subcommand)]
 pub enum VpnAction {
     /// Create a new UPV VPN connection
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe vpn create \"UPV IKEv2\" --from UPV --tunnel-type ikev2\x1b[0m  Creates a copy of the UPV connection that uses IKEv2, to compare both side by side"
+    )]
     Create {
-        /// Name for the VPN connection
-        name: String,
+        /// Name for the VPN connection (defaults to the configured one)
+        name: Option<String>,
+        /// VPN server to connect to, which must be a UPV host (defaults to the configured one, or vpn.upv.es)
+        #[arg(long, value_name = "ADDRESS")]
+        server: Option<String>,
+        /// VPN protocol to use (try IKEv2 if SSTP is slow, or SSTP if IKEv2 is blocked). Defaults to SSTP, or the one of --from
+        #[arg(short, long, value_enum, ignore_case = true)]
+        tunnel_type: Option<TunnelType>,
+        /// Only send UPV traffic (158.42.0.0/16) through the VPN. Routes can be changed later with 'upv vpn set'
+        #[arg(long)]
+        split_tunnel: bool,
         /// Connect immediately after creating
         #[arg(short, long)]
         connect: bool,
+        /// Succeed without changes if the connection already exists and is correctly configured, repairing it if its settings differ
+        #[arg(long)]
+        idempotent: bool,
+        /// Use this EAP configuration XML instead of the built-in UPV one (defaults to the configured one)
+        #[arg(long, value_name = "PATH")]
+        eap_config: Option<PathBuf>,
+        /// Make it the default VPN connection without asking
+        #[arg(long)]
+        set_default: bool,
+        /// DNS suffix of the connection, so short names like "nasupv" resolve to UPV hosts (pass "" to leave it unset)
+        #[arg(long, value_name = "SUFFIX", default_value = UPV_DNS_SUFFIX)]
+        dns_suffix: String,
+        /// Copy the settings of this existing connection (routes, DNS suffix, triggers...), generating its EAP configuration again
+        #[arg(long, value_name = "NAME", add = ArgValueCandidates::new(vpn_name_candidates), conflicts_with_all = ["server", "split_tunnel", "idempotent", "dns_suffix"])]
+        from: Option<String>,
     },
-    /// Connect to an existing UPV VPN using rasphone
+    /// Connect to an existing UPV VPN using rasphone (or rasdial, without any dialog)
     Connect {
-        /// Name of the VPN connection to connect to
-        name: String,
+        /// Name of the VPN connection to connect to (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// Connect without any dialog, using the credentials stored for the connection
+        #[arg(short, long, conflicts_with = "user")]
+        silent: bool,
+        /// Connect without any dialog using this username (your UPV email)
+        #[arg(short, long)]
+        user: Option<String>,
+        /// Password for --user (if not provided, uses the stored credentials or asks for it in the terminal)
+        #[arg(short, long, requires = "user")]
+        password: Option<String>,
+        /// Try even if logging in with this account failed several times recently (which could get it locked)
+        #[arg(long = "override")]
+        override_lockout: bool,
+        /// Wait until the connection is established, failing if it isn't
+        #[arg(short, long)]
+        wait: bool,
+        /// Seconds to wait before giving up
+        #[arg(short, long, default_value_t = 60, requires = "wait")]
+        timeout: u64,
+        /// Mount the network drive once connected (with the configured username, domain and drive). Implies --wait
+        #[arg(short, long)]
+        mount: bool,
+        /// Add the routes configured for this connection ('routes' in the config file) once connected. Implies --wait
+        #[arg(long)]
+        profiled_routes: bool,
+        /// Make sure names are looked up under upv.es (and the 'dns_suffixes' in the config file) once connected,
+        /// so short hostnames resolve (requires administrator rights). Implies --wait
+        #[arg(long)]
+        dns_suffix: bool,
+        /// Extra options for rasdial, separated by commas: phonebook=<PATH>, domain=<DOMAIN>, phone=<ADDRESS>, prefixsuffix
+        /// (only phonebook is supported by the connection dialog). Since the list is split at every comma, a phonebook path
+        /// can't contain one
+        #[arg(long, value_name = "OPTIONS", value_delimiter = ',')]
+        entry_options: Vec<EntryOption>,
     },
     /// Disconnect from UPV VPN
-    Disconnect,
+    Disconnect {
+        /// Unmount the UPV network drives first, so they aren't left broken
+        #[arg(long)]
+        unmount: bool,
+        /// Wait until the connection is actually gone (no RAS session and the adapter is down)
+        #[arg(short, long)]
+        wait: bool,
+        /// Seconds to wait before giving up
+        #[arg(short, long, default_value_t = 30, requires = "wait")]
+        timeout: u64,
+    },
+    /// Keep a connection up, redialing it with the stored credentials whenever it drops (press Ctrl+C to stop)
+    Watch {
+        /// Name of the VPN connection to keep up (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// Seconds between checks
+        #[arg(short, long, default_value_t = 10)]
+        interval: u64,
+        /// Seconds to wait before retrying a failed redial (doubled after each failure)
+        #[arg(long, default_value_t = 5)]
+        backoff: u64,
+        /// Give up after this many failed redials in a row (0 to never give up)
+        #[arg(long, default_value_t = 10)]
+        max_retries: u32,
+    },
+    /// Connect if disconnected, or disconnect if connected (handy to bind to a hotkey)
+    Toggle {
+        /// Name of the VPN connection to toggle (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+    },
     /// Delete an existing UPV VPN connection
     Delete {
-        /// Name of the VPN connection to delete
-        name: String,
+        /// Name of the VPN connection to delete, defaults to the configured one ("-" to read names from stdin)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
         /// Skip confirmation prompt
         #[arg(short, long)]
         force: bool,
+        /// Read the names of the connections to delete from stdin, one per line (requires --force)
+        #[arg(long, conflicts_with = "name", requires = "force")]
+        stdin: bool,
     },
     /// List all UPV VPN connections
-    List,
+    List {
+        /// Only print the connection names, one per line (useful for scripts). With --verbose, show the status,
+        /// tunnel type, authentication method and split tunneling of each connection in a table instead
+        #[arg(short, long, conflicts_with = "verbose")]
+        quiet: bool,
+    },
     /// Delete ALL UPV VPN connections (with double confirmation)
     Purge {
         /// Skip confirmation prompts
         #[arg(short, long)]
         force: bool,
         /// VPN connection names to exclude from deletion (can be used multiple times)
-        #[arg(short, long = "except", value_name = "NAME")]
+        #[arg(short, long = "except", value_name = "NAME", add = ArgValueCandidates::new(vpn_name_candidates))]
         except: Vec<String>,
     },
+    /// Recreate the VPN connections deleted by the last delete or purge (from the backups taken before deleting them)
+    UndoDelete,
     /// Check VPN connection status
     Status,
+    /// Measure latency and packet loss to the VPN server and (when connected) to the UPV network
+    Ping {
+        /// Probes per host
+        #[arg(short, long, default_value_t = 4)]
+        count: u32,
+    },
+    /// Show the VPN connects, disconnects and errors logged by Windows (RasClient events), explaining their error codes
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe vpn logs --since 2h --errors-only\x1b[0m  Shows why the connections failed in the last 2 hours (e.g. error 809 or 691)"
+    )]
+    Logs {
+        /// How far back to look: a duration (30m, 2h, 1d, 1w) or a date and time (2025-01-31, 2025-01-31 14:00)
+        #[arg(short, long, default_value = "24h", value_name = "WHEN")]
+        since: String,
+        /// Only show errors (such as failed connections)
+        #[arg(short, long)]
+        errors_only: bool,
+        /// Maximum number of events to show
+        #[arg(short, long, default_value_t = 50)]
+        max: u32,
+    },
+    /// Make a connection the default one, used when no name is given to the vpn commands
+    SetDefault {
+        /// Name of the VPN connection
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: String,
+    },
+    /// Show the settings of a VPN connection
+    Show {
+        /// Name of the VPN connection to show (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+    },
+    /// Apply the UPV settings and the current built-in EAP configuration to an existing VPN connection, keeping its server and protocol
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe vpn repair --outdated-only\x1b[0m  Upgrades every connection created with an older version of the built-in EAP configuration"
+    )]
+    Repair {
+        /// Name of the VPN connection to repair (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// Repair every UPV connection created with an older version of the built-in EAP configuration instead
+        #[arg(long, conflicts_with = "name")]
+        outdated_only: bool,
+    },
+    /// Compare a VPN connection against the UPV settings, listing the ones that differ (fails if any does)
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe --output json vpn verify UPV\x1b[0m  Prints the differing settings as JSON (setting, current and expected value)"
+    )]
+    Verify {
+        /// Name of the VPN connection to verify (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// VPN protocol the connection is expected to use
+        #[arg(short, long, value_enum, ignore_case = true, default_value_t = TunnelType::Sstp)]
+        tunnel_type: TunnelType,
+    },
+    /// Change settings of an existing VPN connection
+    Set {
+        /// Name of the VPN connection to change (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// Whether Windows caches the credentials used to connect
+        #[arg(long, value_enum, ignore_case = true)]
+        remember_credential: Option<Toggle>,
+        /// Whether only UPV traffic goes through the VPN (on, adding the UPV subnet routes if missing) or all of it (off)
+        #[arg(long, value_enum, ignore_case = true)]
+        split_tunnel: Option<Toggle>,
+        /// DNS suffix of the connection
+        #[arg(long, value_name = "SUFFIX")]
+        dns_suffix: Option<String>,
+        /// Seconds without traffic before disconnecting (0 to never disconnect)
+        #[arg(long, value_name = "SECONDS")]
+        idle_disconnect: Option<u32>,
+        /// DNS server to use while connected (can be used twice, for a primary and secondary server)
+        #[arg(long, value_name = "IP", num_args = 1, action = clap::ArgAction::Append, conflicts_with = "dns_auto")]
+        dns: Vec<Ipv4Addr>,
+        /// Go back to the DNS servers assigned by the VPN server
+        #[arg(long)]
+        dns_auto: bool,
+        /// Route a network prefix through the VPN (e.g. 158.42.0.0/16, can be used multiple times)
+        #[arg(long, value_name = "PREFIX", value_parser = parse_route)]
+        add_route: Vec<String>,
+        /// Stop routing a network prefix through the VPN (can be used multiple times)
+        #[arg(long, value_name = "PREFIX", value_parser = parse_route)]
+        remove_route: Vec<String>,
+    },
+    /// Set the DNS suffix of a VPN connection, and optionally an NRPT rule so UPV names always resolve through the VPN
+    Dns {
+        /// Name of the VPN connection to change (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// DNS suffix to set
+        #[arg(long, value_name = "SUFFIX", default_value = UPV_DNS_SUFFIX)]
+        suffix: String,
+        /// Also add an NRPT rule sending names under the suffix to the VPN's DNS servers (requires being connected and administrator rights)
+        #[arg(long, conflicts_with = "remove_nrpt")]
+        nrpt: bool,
+        /// Remove the NRPT rule added with --nrpt (requires administrator rights)
+        #[arg(long)]
+        remove_nrpt: bool,
+    },
+    /// Export a VPN connection as a phonebook (.pbk) section, as PowerShell commands to recreate it or as a JSON profile
+    Export {
+        /// Name of the VPN connection to export (defaults to the configured one)
+        #[arg(add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+        /// Export format
+        #[arg(short = 'F', long, value_enum, ignore_case = true, default_value_t = ExportFormat::Powershell)]
+        format: ExportFormat,
+        /// Write the export to this file instead of printing it
+        #[arg(short, long, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+    /// Recreate a VPN connection from a JSON profile made with 'upv vpn export --format json'
+    Import {
+        /// Path of the JSON profile
+        file: PathBuf,
+        /// Name for the connection (defaults to the one in the profile)
+        #[arg(short, long)]
+        name: Option<String>,
+        /// Replace the connection if it already exists
+        #[arg(long)]
+        replace: bool,
+    },
+    /// Restore the default routes and DNS servers saved before connecting (e.g. after a crashed session)
+    RestoreNetwork {
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum NetAction {
+    /// Detect whether a captive portal (like the one of the UPV guest Wi-Fi) is blocking the connection, and open its login page
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe net portal --wait; upv.exe vpn connect\x1b[0m  Opens the login page of the portal, and connects once you have logged in"
+    )]
+    Portal {
+        /// Only report the portal, without opening its login page
+        #[arg(long)]
+        no_open: bool,
+        /// Wait until you have logged in on the portal, failing if it takes too long
+        #[arg(short, long)]
+        wait: bool,
+        /// Seconds to wait before giving up
+        #[arg(short, long, default_value_t = 300, requires = "wait")]
+        timeout: u64,
+    },
 }
 
 #[derive(Subcommand)]
 pub enum DriveAction {
-    /// Mount the personal network drive (Disco W)
-    #[command(visible_alias = "connect")]
+    /// Mount the personal network drive (Disco W), or the drives of the profiles in the config file
+    #[command(
+      visible_alias = "connect",
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive mount --profile shared-lab\x1b[0m  Mounts the drive of the 'shared-lab' profile in the config file\n \x1b[1mupv.exe drive mount --all-profiles\x1b[0m  Mounts the drives of every profile in the config file\n \x1b[1mupv.exe drive mount --all-profiles --drive auto\x1b[0m  Mounts them, moving the profiles whose letter is taken to free ones"
+    )]
     Mount {
-        /// Your UPV username (example: if your email is "user@upv.es", your username is "user")
-        username: String,
+        /// Your UPV username (example: if your email is "user@upv.es", your username is "user"). Defaults to the configured one
+        username: Option<String>,
 
-        /// UPV domain
+        /// UPV domain (defaults to the configured one)
         #[arg(value_enum, ignore_case = true)]
-        domain: UPVDomain,
-        /// Password for network drive (if not provided, uses current VPN or Wi-Fi credentials)
+        domain: Option<UPVDomain>,
+        /// Password for network drive (if not provided, uses the stored credentials or the current VPN or Wi-Fi ones, and asks for it in the terminal if there are none)
         #[arg(short, long)]
         password: Option<String>,
-        /// Drive letter to mount to
-        #[arg(short, long, default_value = "W")]
-        drive: char,
+        /// Drive letter to mount to, or "auto" for the first free one (defaults to the configured one, or W)
+        #[arg(short, long, value_parser = parse_drive_choice)]
+        drive: Option<DriveChoice>,
         /// Open the drive in Explorer after mounting
         #[arg(short, long)]
         open: bool,
+        /// Seconds to wait for the server before cancelling the mount (0 to wait indefinitely)
+        #[arg(short, long, default_value_t = 30)]
+        timeout: u64,
+        /// If the server is unreachable, wait for a UPV VPN connection and retry once
+        #[arg(long)]
+        wait_for_vpn: bool,
+        /// If the server is unreachable, connect the default VPN connection first
+        #[arg(long, conflicts_with = "wait_for_vpn")]
+        auto_vpn: bool,
+        /// Don't check for reusable UPV credentials (VPN, Wi-Fi, Kerberos...) before mounting without a password
+        #[arg(long)]
+        skip_credential_check: bool,
+        /// Share path to mount instead of the default one for the domain.
+        /// Placeholders: {server} (nasupv.upv.es), {initial} (first letter of the username) and {user}
+        #[arg(long, value_name = "TEMPLATE")]
+        share_template: Option<String>,
+        /// Mount the drive through WebDAV (over HTTPS), which works off campus without the VPN
+        #[arg(long, conflicts_with = "no_webdav_fallback")]
+        webdav: bool,
+        /// Don't fall back to WebDAV when the server can't be reached through SMB
+        #[arg(long)]
+        no_webdav_fallback: bool,
+        /// WebDAV URL to mount instead of the default one for the domain (same placeholders as --share-template)
+        #[arg(long, value_name = "TEMPLATE")]
+        webdav_template: Option<String>,
+        /// Try even if logging in with this account failed several times recently (which could get it locked)
+        #[arg(long = "override")]
+        override_lockout: bool,
+        /// Don't notify Explorer about the drive change (for headless sessions)
+        #[arg(long)]
+        no_explorer_refresh: bool,
+        /// Have Windows restore the drive at every logon, so it survives reboots (by default it is removed at logoff)
+        #[arg(long)]
+        persistent: bool,
+        /// Replace the mapping of the drive letter if it points at another share, without asking (it can be put back with 'upv drive unmount --restore-previous')
+        #[arg(long)]
+        remap: bool,
+        /// Mount the drive of a profile in the config file ([drive_profiles.<NAME>]) instead of the personal drive
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["share_template", "webdav_template", "all_profiles"])]
+        profile: Option<String>,
+        /// Mount the drives of every profile in the config file, going on when one fails. The letters are checked first:
+        /// with --drive auto, profiles whose letter is taken move to free ones (otherwise, you are asked to)
+        #[arg(long, conflicts_with_all = ["share_template", "webdav_template"])]
+        all_profiles: bool,
     },
+    /// Mount any other UPV share, by its UNC path (e.g. a departmental or group drive) or its name in the share catalogs
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive mount-share ETSINF-Software --drive S\x1b[0m  Mounts the share named ETSINF-Software in a catalog to S:\n \x1b[1mupv.exe drive mount-share \\\\nasupv.upv.es\\dept --drive P\x1b[0m  Mounts the share \\\\nasupv.upv.es\\dept to P:"
+    )]
+    MountShare {
+        /// Name of the share, as shown by 'upv drive shares', or its UNC path (e.g. \\nasupv.upv.es\dept)
+        #[arg(add = ArgValueCandidates::new(share_name_candidates))]
+        name: String,
+        /// Your UPV username (defaults to the configured one)
+        username: Option<String>,
+        /// UPV domain (defaults to the configured one)
+        #[arg(value_enum, ignore_case = true)]
+        domain: Option<UPVDomain>,
+        /// Password for the share (if not provided, uses the stored credentials or the current VPN or Wi-Fi ones, and asks for it in the terminal if there are none)
+        #[arg(short, long)]
+        password: Option<String>,
+        /// Drive letter to mount to, or "auto" for the first free one (defaults to the one suggested by the catalog)
+        #[arg(short, long, value_parser = parse_drive_choice)]
+        drive: Option<DriveChoice>,
+        /// Open the drive in Explorer after mounting
+        #[arg(short, long)]
+        open: bool,
+        /// Seconds to wait for the server before cancelling the mount (0 to wait indefinitely)
+        #[arg(short, long, default_value_t = 30)]
+        timeout: u64,
+        /// If the server is unreachable, wait for a UPV VPN connection and retry once
+        #[arg(long)]
+        wait_for_vpn: bool,
+        /// If the server is unreachable, connect the default VPN connection first
+        #[arg(long, conflicts_with = "wait_for_vpn")]
+        auto_vpn: bool,
+        /// Don't check for reusable UPV credentials (VPN, Wi-Fi, Kerberos...) before mounting without a password
+        #[arg(long)]
+        skip_credential_check: bool,
+        /// Try even if logging in with this account failed several times recently (which could get it locked)
+        #[arg(long = "override")]
+        override_lockout: bool,
+        /// Don't notify Explorer about the drive change (for headless sessions)
+        #[arg(long)]
+        no_explorer_refresh: bool,
+        /// Have Windows restore the drive at every logon, so it survives reboots (by default it is removed at logoff)
+        #[arg(long)]
+        persistent: bool,
+        /// Replace the mapping of the drive letter if it points at another share, without asking (it can be put back with 'upv drive unmount --restore-previous')
+        #[arg(long)]
+        remap: bool,
+    },
+    /// List the shares of the share catalogs (%ProgramData%\upv\shares and %APPDATA%\upv\shares)
+    Shares,
     /// Unmount the personal network drive (Disco W)
     #[command(visible_alias = "disconnect")]
     Unmount {
-        /// Drive letter to unmount
-        #[arg(short, long, default_value = "W")]
-        drive: char,
+        /// Drive letter to unmount (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
         /// Skip confirmation prompt
         #[arg(short, long)]
         force: bool,
+        /// Read the drive letters to unmount from stdin, one per line (ignores --drive)
+        #[arg(long)]
+        stdin: bool,
+        /// Unmount every drive mapped to the UPV NAS (nasupv.upv.es)
+        #[arg(short, long, conflicts_with_all = ["drive", "stdin"])]
+        all: bool,
+        /// Map back the share the drive pointed at before 'upv drive mount --remap' replaced it
+        #[arg(long, conflicts_with_all = ["stdin", "all"])]
+        restore_previous: bool,
+        /// Don't notify Explorer about the drive change (for headless sessions)
+        #[arg(long)]
+        no_explorer_refresh: bool,
     },
-    /// Open the personal network drive in Explorer
+    /// Unmount a drive and mount it again with the same parameters as last time (for mappings left stale after sleeping or reconnecting the VPN)
+    Remount {
+        /// Drive letter to remount (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+        /// Unmount it even if it is in use
+        #[arg(short, long)]
+        force: bool,
+        /// Open the drive in Explorer after mounting
+        #[arg(short, long)]
+        open: bool,
+        /// Don't notify Explorer about the drive change (for headless sessions)
+        #[arg(long)]
+        no_explorer_refresh: bool,
+    },
+    /// Unmount a drive only if no application is using it, listing the ones that are
+    EjectSafe {
+        /// Drive letter to unmount (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+        /// Don't notify Explorer about the drive change (for headless sessions)
+        #[arg(long)]
+        no_explorer_refresh: bool,
+    },
+    /// Open the personal network drive (or a folder in it) in Explorer
     Open {
-        /// Drive letter to open
-        #[arg(short, long, default_value = "W")]
+        /// Folder to open, relative to the root of the drive (e.g. docencia/practicas). Opens the whole drive if omitted
+        path: Option<String>,
+        /// Drive letter to open (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+    },
+    /// Show the details of a drive mapping: share, status, SMB dialect, accounts, persistence and latency to the server
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive info --drive W\x1b[0m  Shows how W: is mapped and how fast its server answers"
+    )]
+    Info {
+        /// Drive letter to inspect (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+    },
+    /// Copy a local folder to a folder of the drive (or back with --pull) with robocopy, only copying new and changed files, with a progress bar and a summary
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive sync C:\\Projects\\tfg backups\\tfg\x1b[0m  Copies the new and changed files of the project to W:\\backups\\tfg\n \x1b[1mupv.exe --dry-run drive sync C:\\Projects\\tfg backups\\tfg --mirror\x1b[0m  Lists what would be copied and deleted to make W:\\backups\\tfg an exact copy\n \x1b[1mupv.exe --output json drive sync C:\\Projects\\tfg backups\\tfg\x1b[0m  Prints the summary of the copy (files copied and deleted, failed files) as JSON\n \x1b[1mupv.exe drive sync C:\\Projects\\tfg backups\\tfg --throttle 50\x1b[0m  Copies it slowly enough to keep the VPN responsive (50 ms between blocks of 64 KB)"
+    )]
+    Sync {
+        /// Local folder
+        local: PathBuf,
+        /// Folder of the drive, relative to its root (e.g. backups\project)
+        remote: String,
+        /// Drive letter to sync with (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+        /// Make the destination an exact copy, DELETING the files that aren't in the source
+        #[arg(long)]
+        mirror: bool,
+        /// Copy from the drive to the local folder instead
+        #[arg(long)]
+        pull: bool,
+        /// Skip the confirmation prompt of --mirror
+        #[arg(short, long)]
+        force: bool,
+        /// Wait this many milliseconds between blocks of 64 KB (robocopy /IPG), so a big copy doesn't take up the whole VPN link
+        #[arg(long, value_name = "MS")]
+        throttle: Option<u32>,
+    },
+    /// Check that a drive actually works (listing it with a timeout and measuring its latency), detecting stale mappings
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive check -d W\x1b[0m  Checks whether W: answers, and suggests remounting it if it is stale"
+    )]
+    Check {
+        /// Drive letter to check (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+        /// Seconds the drive has to answer
+        #[arg(short, long, default_value_t = 5)]
+        timeout: u64,
+    },
+    /// Show how much of a drive's space (your quota, on UPV drives) is used, warning when it is almost full
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive quota --drive W\x1b[0m  Shows the used and free space of W: with a usage bar"
+    )]
+    Quota {
+        /// Drive letter to check (defaults to the configured one, or W)
+        #[arg(short, long)]
+        drive: Option<char>,
+    },
+    /// Check the status of the UPV network drives
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe drive status --drive W\x1b[0m  Shows W:, failing if it isn't mounted (to test it from scripts)"
+    )]
+    Status {
+        /// Show every network drive, not just the UPV ones
+        #[arg(short, long, conflicts_with = "drive")]
+        all: bool,
+        /// Only show this drive, failing if it isn't mounted
+        #[arg(short, long, conflicts_with = "watch")]
+        drive: Option<char>,
+        /// Keep refreshing the status and highlight state changes (press Ctrl+C to stop)
+        #[arg(short, long)]
+        watch: bool,
+        /// Seconds between refreshes in watch mode
+        #[arg(short, long, default_value_t = 2, requires = "watch")]
+        interval: u64,
+        /// Also try to list the root of each UPV drive, catching mappings listed as OK that hang on access
+        #[arg(long, conflicts_with = "watch")]
+        verify: bool,
+    },
+    /// Manage drive mappings created for every user of this machine (requires administrator rights)
+    Provision {
+        #[command(subcommand)]
+        action: ProvisionAction,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ProvisionAction {
+    /// Map a share to a drive letter for every user on logon
+    Add {
+        /// Drive letter to map the share to
         drive: char,
+        /// UNC path of the share (e.g. \\server\share)
+        path: String,
     },
-    /// Check network drive status
-    Status,
-}
\ No newline at end of file
+    /// Stop mapping a drive letter on logon
+    Remove {
+        /// Drive letter to remove
+        drive: char,
+    },
+    /// List the drives mapped for every user on logon
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the value of a setting
+    Get {
+        /// Setting to print
+        #[arg(value_enum)]
+        key: ConfigKey,
+    },
+    /// Change the value of a setting
+    Set {
+        /// Setting to change
+        #[arg(value_enum)]
+        key: ConfigKey,
+        /// New value
+        value: String,
+    },
+    /// Remove a setting, going back to its default
+    Unset {
+        /// Setting to remove
+        #[arg(value_enum)]
+        key: ConfigKey,
+    },
+    /// List every setting and its value
+    List,
+    /// Open the config file in your editor ($EDITOR, or Notepad), checking it when the editor closes
+    Edit,
+}
+
+#[derive(Subcommand)]
+pub enum CredsAction {
+    /// Save your UPV credentials, to be used when no password is given (the password is asked in the terminal)
+    Set {
+        /// Your UPV username (example: if your email is "user@upv.es", your username is "user"). Defaults to the configured one
+        username: Option<String>,
+        /// UPV domain (defaults to the configured one)
+        #[arg(value_enum, ignore_case = true)]
+        domain: Option<UPVDomain>,
+        /// Read the password from the first line of stdin instead of asking for it
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Show the stored UPV credentials (without the password)
+    Show,
+    /// Remove the stored UPV credentials
+    Clear,
+}
+
+#[derive(Subcommand)]
+pub enum ScheduleAction {
+    /// Schedule a connect or disconnect, at logon or every day at a time (replacing the same one if it already exists)
+    #[command(
+      after_help = "\x1b[1;4mExamples\x1b[0m:\n \x1b[1mupv.exe schedule add connect --logon\x1b[0m  Connects to the VPN every time you log on\n \x1b[1mupv.exe schedule add disconnect --at 20:00\x1b[0m  Disconnects from the VPN every day at 20:00"
+    )]
+    Add {
+        /// What to do
+        #[arg(value_enum, ignore_case = true)]
+        task: ScheduledAction,
+        /// Run every time you log on
+        #[arg(long, conflicts_with = "at", required_unless_present = "at")]
+        logon: bool,
+        /// Run every day at this time (24-hour format)
+        #[arg(long, value_name = "HH:MM")]
+        at: Option<String>,
+        /// Name of the VPN connection to connect to (defaults to the configured one)
+        #[arg(short, long, add = ArgValueCandidates::new(vpn_name_candidates))]
+        name: Option<String>,
+    },
+    /// Remove a scheduled connect or disconnect
+    Remove {
+        /// Name of the task, as shown by 'upv schedule list' (e.g. "disconnect-at-2000")
+        name: String,
+    },
+    /// Mount and unmount the drive profiles that have a schedule in the config file on their own, through tasks that
+    /// connect to the VPN when needed and disconnect from it at the end (removing the tasks of profiles without one)
+    #[command(
+      after_help = "\x1b[1;4mExample\x1b[0m (in the config file):\n [drive_profiles.lab]\n drive = \"L\"\n schedule = { days = [\"mon\", \"tue\", \"wed\", \"thu\", \"fri\"], from = \"08:00\", to = \"18:00\" }"
+    )]
+    Profiles,
+    /// List the scheduled connects, disconnects and profiles
+    List,
+}
+
+/// Completion candidates for VPN connection names, annotated with their connection status
+fn vpn_name_candidates() -> Vec<CompletionCandidate> {
+    VpnManager::get_upv_connection_statuses()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, status)| CompletionCandidate::new(name).help(Some(status.into())))
+        .collect()
+}
+
+/// Completion candidates for share names, annotated with their description (or path)
+fn share_name_candidates() -> Vec<CompletionCandidate> {
+    CatalogManager::load()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|share| {
+            let help = share.description.unwrap_or(share.path);
+            CompletionCandidate::new(share.name).help(Some(help.into()))
+        })
+        .collect()
+}
+
+/// Reads the non-empty lines from stdin, for commands that accept `--stdin`
+pub fn read_stdin_lines() -> Result<Vec<String>> {
+    io::stdin().lock().lines()
+        .map(|line| line.map(|line| line.trim().to_string()))
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .collect::<io::Result<Vec<String>>>()
+        .context("Failed to read from stdin")
+}
+
+/// Parses a drive letter as given on stdin ("W" or "W:")
+pub fn parse_drive_letter(value: &str) -> Option<char> {
+    let mut chars = value.trim_end_matches(':').chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_alphabetic() => Some(letter.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+/// Parses a network prefix to route (an IPv4 or IPv6 address and a prefix length, e.g. 158.42.0.0/16)
+fn parse_route(value: &str) -> Result<String, String> {
+    let invalid = || format!("'{}' is not a network prefix (expected something like 158.42.0.0/16)", value);
+    let (address, length) = value.trim().split_once('/').ok_or_else(invalid)?;
+    let address: IpAddr = address.parse().map_err(|_| invalid())?;
+    let length: u8 = length.parse().map_err(|_| invalid())?;
+
+    let max_length = if address.is_ipv4() { 32 } else { 128 };
+    if length > max_length {
+        return Err(format!("'{}' has a prefix length over {}", value, max_length));
+    }
+    Ok(format!("{}/{}", address, length))
+}
+
+/// Parses the drive to mount to: a drive letter ("W" or "W:") or "auto"
+fn parse_drive_choice(value: &str) -> Result<DriveChoice, String> {
+    if value.eq_ignore_ascii_case("auto") {
+        return Ok(DriveChoice::Auto);
+    }
+    parse_drive_letter(value)
+        .map(DriveChoice::Letter)
+        .ok_or_else(|| format!("'{}' is not a drive letter or \"auto\"", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ipv4_and_ipv6_routes() {
+        assert_eq!(parse_route("158.42.0.0/16"), Ok("158.42.0.0/16".to_string()));
+        assert_eq!(parse_route(" 2001:db8::/32 "), Ok("2001:db8::/32".to_string()));
+        assert!(parse_route("158.42.0.0/33").is_err());
+        assert!(parse_route("158.42.0.0").is_err());
+        assert!(parse_route("158.42.0.0/16'; calc; '").is_err());
+        assert!(parse_route("158.42.0.0/16\nIpDnsAddress=1.2.3.4").is_err());
+    }
+}
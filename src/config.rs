@@ -0,0 +1,772 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, ErrorKind, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{Result, Context};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::cli;
+use crate::confirm::Confirmations;
+use crate::creds::{CredentialSource, CredsManager};
+use crate::diff::{self, Difference};
+use crate::dry_run;
+use crate::drive::UPVDomain;
+use crate::error::UpvError;
+use crate::paths;
+use crate::vpn::{VpnManager, VPN_SERVER_ADDRESS};
+
+// Written when the config file is created by 'upv config edit', so every setting is discoverable
+const CONFIG_TEMPLATE: &str = "\
+# upv-cli configuration (used when the corresponding arguments are omitted)
+
+# Your UPV username (example: if your email is \"user@upv.es\", your username is \"user\")
+# username = \"user\"
+
+# UPV domain (ALUMNO or UPVNET)
+# domain = \"UPVNET\"
+
+# Where the password comes from when none is given (the credentials saved with 'upv creds set' if not set):
+# \"prompt\" to ask for it every time, an environment variable, a generic credential of the Windows Credential Manager
+# (e.g. added with 'cmdkey /generic:upv-lab /user:user /pass'), or a file with the password encrypted for your Windows user
+# (written with 'Read-Host -AsSecureString | ConvertFrom-SecureString | Set-Content upv.pwd')
+# credentials = \"prompt\"
+# credentials = { env = \"UPV_PASSWORD\" }
+# credentials = { credential_manager = \"upv-lab\" }
+# credentials = { dpapi = 'C:\\Users\\user\\upv.pwd' }
+
+# Name of the VPN connection used by the vpn commands
+# vpn_name = \"UPV\"
+
+# VPN server that new connections are created with (a UPV host, vpn.upv.es by default)
+# vpn_server = \"vpn.upv.es\"
+
+# DNS suffixes added by 'upv vpn connect --dns-suffix' besides upv.es (e.g. a department's)
+# dns_suffixes = [\"dsic.upv.es\"]
+
+# Drive letter used by the drive commands
+# drive = \"W\"
+
+# Drive letters tried by 'upv drive mount --drive auto' after the one above, in order (Z to D if not set)
+# drive_fallbacks = [\"X\", \"Y\"]
+
+# Other NAS hosts (names or IPs) tried in order when nasupv.upv.es can't be reached, for drives on the NAS
+# nas_hosts = [\"nasupv2.upv.es\"]
+
+# Share path to mount instead of the default one for the domain
+# share_template = '\\\\{server}\\discos\\{initial}\\{user}'
+
+# WebDAV URL to mount instead of the default one for the domain, when mounting over HTTPS
+# webdav_template = 'https://{server}/discos/{initial}/{user}'
+
+# EAP configuration XML used by 'upv vpn create' instead of the built-in UPV one
+# eap_config = 'C:\\Users\\user\\UPV_Config.xml'
+
+# UPV status feed (JSON) or maintenance calendar (iCalendar), so 'upv doctor' and 'upv status' tell about announced outages
+# status_feed = 'https://example.upv.es/maintenance.ics'
+
+# Named drive profiles, mounted with 'upv drive mount --profile <NAME>' (or all at once with --all-profiles).
+# Each one needs a drive letter, and may set a share (same placeholders as share_template, the personal drive if not set),
+# a WebDAV URL to fall back to for that share, and the account (or username, domain and credentials) to mount it with
+# (the defaults if not set)
+# [drive_profiles.w-personal]
+# drive = \"W\"
+# [drive_profiles.shared-lab]
+# drive = \"L\"
+# share = '\\\\{server}\\lab\\shared'
+# domain = \"UPVNET\"
+# credentials = { env = \"UPV_LAB_PASSWORD\" }
+# A profile can also be mounted (connecting to the VPN if needed) and unmounted (disconnecting from it) at set times,
+# through Task Scheduler tasks created by 'upv schedule profiles'
+# schedule = { days = [\"mon\", \"tue\", \"wed\", \"thu\", \"fri\"], from = \"08:00\", to = \"18:00\" }
+
+# Other UPV identities (e.g. a staff and a student account), used with '--account <NAME>' on any command, or by drive
+# profiles with 'account = \"<NAME>\"'. Each one needs a username, and may set its own domain, credentials, vpn_name and drive
+# [accounts.staff]
+# username = \"user\"
+# domain = \"UPVNET\"
+# [accounts.student]
+# username = \"user2\"
+# domain = \"ALUMNO\"
+# credentials = { credential_manager = \"upv-student\" }
+
+# Turn on experimental features, which may still change or break (like the native VPN backend)
+# experimental = true
+
+# How much destructive commands ask before going on (their --force flags still skip it): \"none\" doesn't ask, \"yes\" asks y/N,
+# \"typed\" asks to type the name of the connection (or DELETE), and \"double\" asks both. By default, 'upv vpn delete' asks y/N
+# (or to type the name, for the default connection), 'upv vpn purge' asks both, and 'upv drive sync --mirror' asks y/N
+# [confirmations]
+# delete = \"typed\"
+# purge = \"yes\"
+# mirror = \"yes\"
+
+# Extra routes added through each VPN connection by 'upv vpn connect --profiled-routes' (and removed on disconnect),
+# for subnets that the connection doesn't route by itself (e.g. lab networks)
+# [routes]
+# UPV = [\"10.236.0.0/16\", \"192.168.50.0/24\"]
+";
+
+/// Settings of the config file
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ConfigKey {
+    /// Your UPV username
+    #[value(name = "username")]
+    Username,
+    /// UPV domain (ALUMNO or UPVNET)
+    #[value(name = "domain")]
+    Domain,
+    /// Name of the VPN connection used by the vpn commands
+    #[value(name = "vpn_name")]
+    VpnName,
+    /// VPN server that new connections are created with (vpn.upv.es by default)
+    #[value(name = "vpn_server")]
+    VpnServer,
+    /// DNS suffixes added by 'upv vpn connect --dns-suffix' besides upv.es (comma-separated)
+    #[value(name = "dns_suffixes")]
+    DnsSuffixes,
+    /// Drive letter used by the drive commands
+    #[value(name = "drive")]
+    Drive,
+    /// Drive letters tried by 'upv drive mount --drive auto' after the default one (comma-separated, e.g. X,Y)
+    #[value(name = "drive_fallbacks")]
+    DriveFallbacks,
+    /// Share path to mount instead of the default one for the domain
+    #[value(name = "share_template")]
+    ShareTemplate,
+    /// Other NAS hosts tried in order when nasupv.upv.es can't be reached (comma-separated)
+    #[value(name = "nas_hosts")]
+    NasHosts,
+    /// WebDAV URL to mount instead of the default one for the domain
+    #[value(name = "webdav_template")]
+    WebdavTemplate,
+    /// EAP configuration XML used by 'upv vpn create' instead of the built-in UPV one
+    #[value(name = "eap_config")]
+    EapConfig,
+    /// UPV status feed or maintenance calendar URL, checked by 'upv doctor' and 'upv status'
+    #[value(name = "status_feed")]
+    StatusFeed,
+    /// Turn on experimental features (true or false)
+    #[value(name = "experimental")]
+    Experimental,
+}
+
+impl ConfigKey {
+    fn name(self) -> String {
+        self.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default()
+    }
+}
+
+/// A named drive to mount with 'upv drive mount --profile'
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DriveProfile {
+    /// Drive letter to mount to
+    pub drive: char,
+    /// Share path template (the personal drive of the domain if not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share: Option<String>,
+    /// WebDAV URL template to fall back to (only the personal drive has one if not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webdav: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<UPVDomain>,
+    /// Account to mount it with, whose username, domain and credentials apply where the profile doesn't set them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Where the password comes from (the configured source if not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialSource>,
+    /// When to keep it mounted, set up with 'upv schedule profiles'
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<ProfileSchedule>,
+}
+
+/// Hours a drive profile is mounted (and the VPN connected) on its own, e.g. on weekdays from 08:00 to 18:00
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileSchedule {
+    /// Days of the week (mon, tue...), every day if not set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<String>,
+    /// Time to mount it (HH:MM)
+    pub from: String,
+    /// Time to unmount it and disconnect from the VPN (HH:MM)
+    pub to: String,
+}
+
+/// A UPV identity with its own defaults, used with '--account' or by drive profiles (e.g. a staff and a student account)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<UPVDomain>,
+    /// Where the password comes from (the configured source if not set)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialSource>,
+    /// VPN connection used by the vpn commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpn_name: Option<String>,
+    /// Drive letter used by the drive commands
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drive: Option<char>,
+}
+
+/// Defaults used when arguments are omitted, stored in `%APPDATA%\upv\config.toml`
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<UPVDomain>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<CredentialSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpn_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpn_server: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dns_suffixes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drive: Option<char>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drive_fallbacks: Vec<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share_template: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nas_hosts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webdav_template: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eap_config: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_feed: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experimental: Option<bool>,
+    /// Extra routes for each VPN connection, by connection name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub routes: BTreeMap<String, Vec<String>>,
+    /// Named drives to mount, by profile name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub drive_profiles: BTreeMap<String, DriveProfile>,
+    /// Other UPV identities, by account name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, Account>,
+    /// How much the destructive commands ask before going on
+    #[serde(default, skip_serializing_if = "Confirmations::is_empty")]
+    pub confirmations: Confirmations,
+}
+
+impl Config {
+    /// Error for a required argument that was omitted and has no configured default
+    fn missing(what: &str, key: ConfigKey) -> anyhow::Error {
+        UpvError::Config(
+            format!("No {} was given, and there is no default one configured", what).into()
+        ).with_hint(format!("Pass it as an argument, or set a default with 'upv config set {} <VALUE>'", key.name())).into()
+    }
+
+    /// The given VPN connection name, or the configured one
+    pub fn vpn_name(&self, name: Option<String>) -> Result<String> {
+        name.or_else(|| self.vpn_name.clone())
+            .ok_or_else(|| Self::missing("VPN connection name", ConfigKey::VpnName))
+    }
+
+    /// The given VPN server, or the configured one (vpn.upv.es by default)
+    pub fn vpn_server(&self, server: Option<String>) -> String {
+        server.or_else(|| self.vpn_server.clone()).unwrap_or_else(|| VPN_SERVER_ADDRESS.to_string())
+    }
+
+    /// The given username, or the configured one
+    pub fn username(&self, username: Option<String>) -> Result<String> {
+        username.or_else(|| self.username.clone())
+            .ok_or_else(|| Self::missing("username", ConfigKey::Username))
+    }
+
+    /// The given domain, or the configured one
+    pub fn domain(&self, domain: Option<UPVDomain>) -> Result<UPVDomain> {
+        domain.or_else(|| self.domain.clone())
+            .ok_or_else(|| Self::missing("domain", ConfigKey::Domain))
+    }
+
+    /// The password of an account from the given credential source, or the configured one. None means that the stored
+    /// credentials (if any) are used, as without a source
+    pub fn password(&self, source: Option<&CredentialSource>, username: &str, domain: &UPVDomain) -> Result<Option<String>> {
+        match source.or(self.credentials.as_ref()) {
+            Some(source) => CredsManager::password(source, username, domain),
+            None => Ok(None),
+        }
+    }
+
+    /// The given drive letter, or the configured one (W by default)
+    pub fn drive(&self, drive: Option<char>) -> char {
+        drive.or(self.drive).unwrap_or('W')
+    }
+
+    /// Drive letters to try when mounting to the first free one: the preferred one (or the configured one, W by default),
+    /// then the configured fallbacks, or every letter from Z to D (the ones Windows leaves for network drives) if there are none
+    pub fn drive_candidates(&self, preferred: Option<char>) -> Vec<char> {
+        let fallbacks: Vec<char> = if self.drive_fallbacks.is_empty() {
+            ('D'..='Z').rev().collect()
+        } else {
+            self.drive_fallbacks.iter().map(char::to_ascii_uppercase).collect()
+        };
+
+        let mut candidates = vec![self.drive(preferred).to_ascii_uppercase()];
+        for letter in fallbacks {
+            if !candidates.contains(&letter) {
+                candidates.push(letter);
+            }
+        }
+        candidates
+    }
+
+    /// The given share template, or the configured one
+    pub fn share_template(&self, template: Option<String>) -> Option<String> {
+        template.or_else(|| self.share_template.clone())
+    }
+
+    /// The given WebDAV URL template, or the configured one
+    pub fn webdav_template(&self, template: Option<String>) -> Option<String> {
+        template.or_else(|| self.webdav_template.clone())
+    }
+
+    /// The given EAP config path, or the configured one
+    pub fn eap_config(&self, path: Option<PathBuf>) -> Option<PathBuf> {
+        path.or_else(|| self.eap_config.clone())
+    }
+
+    /// Extra routes configured for a VPN connection (none if there aren't any)
+    pub fn routes(&self, name: &str) -> &[String] {
+        self.routes.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The account with the given name
+    pub fn account(&self, name: &str) -> Result<&Account> {
+        self.accounts.get(name).ok_or_else(|| {
+            let hint = if self.accounts.is_empty() {
+                "Add one to the config file with 'upv config edit' ([accounts.<NAME>])".to_string()
+            } else {
+                format!("Available accounts: {}", self.accounts.keys().cloned().collect::<Vec<_>>().join(", "))
+            };
+            UpvError::Config(format!("There is no account named '{}'", name).into()).with_hint(hint).into()
+        })
+    }
+
+    /// The config as seen with an account selected: its username, domain and defaults replace the top-level ones
+    pub fn with_account(mut self, name: Option<&str>) -> Result<Self> {
+        let Some(name) = name else {
+            return Ok(self);
+        };
+        let account = self.account(name)?.clone();
+
+        self.username = Some(account.username);
+        self.domain = account.domain.or(self.domain);
+        self.credentials = account.credentials.or(self.credentials);
+        self.vpn_name = account.vpn_name.or(self.vpn_name);
+        self.drive = account.drive.or(self.drive);
+        Ok(self)
+    }
+
+    /// The username, domain and credential source to mount a drive profile with: the ones it sets, then the ones of its
+    /// account, then the configured ones (the given username and domain win over all of them)
+    pub fn profile_identity(&self, profile: &DriveProfile, username: Option<String>, domain: Option<UPVDomain>) -> Result<(String, UPVDomain, Option<CredentialSource>)> {
+        let account = profile.account.as_deref().map(|name| self.account(name)).transpose()?;
+
+        let username = username.or_else(|| profile.username.clone()).or_else(|| account.map(|account| account.username.clone()));
+        let domain = domain.or_else(|| profile.domain.clone()).or_else(|| account.and_then(|account| account.domain.clone()));
+        let credentials = profile.credentials.clone().or_else(|| account.and_then(|account| account.credentials.clone()));
+        Ok((self.username(username)?, self.domain(domain)?, credentials))
+    }
+
+    /// The drive profile with the given name
+    pub fn drive_profile(&self, name: &str) -> Result<&DriveProfile> {
+        self.drive_profiles.get(name).ok_or_else(|| {
+            let hint = if self.drive_profiles.is_empty() {
+                "Add one to the config file with 'upv config edit' ([drive_profiles.<NAME>])".to_string()
+            } else {
+                format!("Available profiles: {}", self.drive_profiles.keys().cloned().collect::<Vec<_>>().join(", "))
+            };
+            UpvError::Config(format!("There is no drive profile named '{}'", name).into()).with_hint(hint).into()
+        })
+    }
+}
+
+pub struct ConfigManager;
+
+impl ConfigManager {
+    // Private utility functions
+
+    fn save(config: &Config) -> Result<()> {
+        let path = paths::config_file()?;
+        let content = toml::to_string(config).context("Failed to serialize the configuration")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))
+    }
+
+    /// Parses the contents of the config file. Errors point at the offending line and column
+    fn parse(path: &Path, content: &str) -> Result<Config> {
+        toml::from_str(content).map_err(|e| UpvError::Config(
+            format!("The config file '{}' is not valid: {}", path.display(), e).into()
+        ).with_hint("Fix it with 'upv config edit'").into())
+    }
+
+    /// Asks whether to open the editor again after saving an invalid config.
+    /// Without anyone to answer (stdin isn't a terminal, or is closed), it isn't, so an editor that saves the same
+    /// invalid file every time (like EDITOR=true in CI) can't loop forever.
+    fn ask_edit_again() -> Result<bool> {
+        if !io::stdin().is_terminal() {
+            return Ok(false);
+        }
+
+        print!("Edit it again? (Y/n): ");
+        io::stdout().flush().context("Failed to flush stdout")?;
+
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).context("Failed to read user input")? == 0 {
+            println!();
+            return Ok(false);
+        }
+
+        let answer = input.trim().to_lowercase();
+        Ok(answer != "n" && answer != "no")
+    }
+
+    fn value(config: &Config, key: ConfigKey) -> Option<String> {
+        match key {
+            ConfigKey::Username => config.username.clone(),
+            ConfigKey::Domain => config.domain.as_ref().map(ToString::to_string),
+            ConfigKey::VpnName => config.vpn_name.clone(),
+            ConfigKey::VpnServer => config.vpn_server.clone(),
+            ConfigKey::DnsSuffixes => (!config.dns_suffixes.is_empty()).then(|| config.dns_suffixes.join(",")),
+            ConfigKey::Drive => config.drive.map(String::from),
+            ConfigKey::DriveFallbacks => (!config.drive_fallbacks.is_empty())
+                .then(|| config.drive_fallbacks.iter().map(char::to_string).collect::<Vec<_>>().join(",")),
+            ConfigKey::ShareTemplate => config.share_template.clone(),
+            ConfigKey::NasHosts => (!config.nas_hosts.is_empty()).then(|| config.nas_hosts.join(",")),
+            ConfigKey::WebdavTemplate => config.webdav_template.clone(),
+            ConfigKey::EapConfig => config.eap_config.as_ref().map(|path| path.display().to_string()),
+            ConfigKey::StatusFeed => config.status_feed.clone(),
+            ConfigKey::Experimental => config.experimental.map(|experimental| experimental.to_string()),
+        }
+    }
+
+    /// Prints the change a dry run would make to a setting (as JSON if requested).
+    fn print_change(key: ConfigKey, current: Option<String>, new: Option<String>, json: bool) -> Result<()> {
+        let differences: Vec<Difference> = (current != new)
+            .then(|| Difference::new(key.name(), current.unwrap_or_default(), new.unwrap_or_default()))
+            .into_iter()
+            .collect();
+
+        if json {
+            return diff::print(&differences, true);
+        }
+
+        if differences.is_empty() {
+            dry_run::print_change(&format!("'{}' is already set to this value, nothing would change", key.name()));
+            return Ok(());
+        }
+
+        dry_run::print_change(&format!("Change the config file ({})", paths::config_file()?.display()));
+        diff::print(&differences, false)
+    }
+
+    // Public methods for configuration management
+
+    /// Loads the config file (all defaults if it doesn't exist).
+    pub fn load() -> Result<Config> {
+        let path = paths::config_file()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+
+        Self::parse(&path, &content)
+    }
+
+    /// Prints the value of a setting
+    pub fn get(key: ConfigKey) -> Result<()> {
+        let value = Self::value(&Self::load()?, key).ok_or_else(|| UpvError::Config(
+            format!("'{}' is not set", key.name()).into()
+        ))?;
+
+        println!("{}", value);
+
+        Ok(())
+    }
+
+    /// Changes the value of a setting. In a dry run, the change is printed instead (as JSON if requested).
+    pub fn set(key: ConfigKey, value: &str, json: bool) -> Result<()> {
+        let mut config = Self::load()?;
+        let current = Self::value(&config, key);
+
+        match key {
+            ConfigKey::Username => config.username = Some(value.to_string()),
+            ConfigKey::Domain => config.domain = Some(UPVDomain::from_str(value, true).map_err(|_| UpvError::Config(
+                format!("'{}' is not a valid domain (expected ALUMNO or UPVNET)", value).into()
+            ))?),
+            ConfigKey::VpnName => config.vpn_name = Some(value.to_string()),
+            ConfigKey::VpnServer => {
+                VpnManager::check_server(value)?;
+                config.vpn_server = Some(value.trim().to_ascii_lowercase());
+            }
+            ConfigKey::Drive => config.drive = Some(cli::parse_drive_letter(value).ok_or_else(|| UpvError::Config(
+                format!("'{}' is not a valid drive letter", value).into()
+            ))?),
+            ConfigKey::DnsSuffixes => config.dns_suffixes = value.split(',')
+                .map(|suffix| suffix.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|suffix| !suffix.is_empty())
+                .collect(),
+            ConfigKey::DriveFallbacks => config.drive_fallbacks = value.split(',')
+                .map(|letter| cli::parse_drive_letter(letter.trim()).ok_or_else(|| UpvError::Config(
+                    format!("'{}' is not a valid drive letter", letter.trim()).into()
+                )))
+                .collect::<Result<Vec<char>, UpvError>>()?,
+            ConfigKey::ShareTemplate => config.share_template = Some(value.to_string()),
+            ConfigKey::NasHosts => config.nas_hosts = value.split(',')
+                .map(|host| host.trim().to_string())
+                .filter(|host| !host.is_empty())
+                .collect(),
+            ConfigKey::WebdavTemplate => config.webdav_template = Some(value.to_string()),
+            ConfigKey::EapConfig => config.eap_config = Some(PathBuf::from(value)),
+            ConfigKey::StatusFeed => {
+                if !value.starts_with("https://") && !value.starts_with("http://") {
+                    return Err(UpvError::Config(
+                        format!("'{}' is not a valid value for status_feed (expected an http:// or https:// URL)", value).into()
+                    ).into());
+                }
+                config.status_feed = Some(value.to_string());
+            }
+            ConfigKey::Experimental => config.experimental = Some(match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => true,
+                "false" | "off" | "no" | "0" => false,
+                _ => return Err(UpvError::Config(
+                    format!("'{}' is not a valid value for experimental (expected true or false)", value).into()
+                ).into()),
+            }),
+        }
+
+        if dry_run::enabled() {
+            return Self::print_change(key, current, Self::value(&config, key), json);
+        }
+
+        Self::save(&config)?;
+
+        println!("{} = {}", key.name(), Self::value(&config, key).unwrap_or_default());
+
+        Ok(())
+    }
+
+    /// Removes a setting, so the built-in default is used again. In a dry run, the change is printed instead.
+    pub fn unset(key: ConfigKey, json: bool) -> Result<()> {
+        let mut config = Self::load()?;
+        let current = Self::value(&config, key);
+
+        match key {
+            ConfigKey::Username => config.username = None,
+            ConfigKey::Domain => config.domain = None,
+            ConfigKey::VpnName => config.vpn_name = None,
+            ConfigKey::VpnServer => config.vpn_server = None,
+            ConfigKey::Drive => config.drive = None,
+            ConfigKey::DnsSuffixes => config.dns_suffixes.clear(),
+            ConfigKey::DriveFallbacks => config.drive_fallbacks.clear(),
+            ConfigKey::ShareTemplate => config.share_template = None,
+            ConfigKey::NasHosts => config.nas_hosts.clear(),
+            ConfigKey::WebdavTemplate => config.webdav_template = None,
+            ConfigKey::EapConfig => config.eap_config = None,
+            ConfigKey::StatusFeed => config.status_feed = None,
+            ConfigKey::Experimental => config.experimental = None,
+        }
+
+        if dry_run::enabled() {
+            return Self::print_change(key, current, None, json);
+        }
+
+        Self::save(&config)?;
+
+        println!("'{}' is no longer set", key.name());
+
+        Ok(())
+    }
+
+    /// Lists every setting and its value
+    pub fn list() -> Result<()> {
+        let config = Self::load()?;
+
+        println!("Configuration ({}):", paths::config_file()?.display());
+        for key in ConfigKey::value_variants() {
+            match Self::value(&config, *key) {
+                Some(value) => println!("  {} = {}", key.name(), value),
+                None => println!("  {} \x1b[2m(not set)\x1b[0m", key.name()),
+            }
+        }
+        // Routes and drive profiles are set with 'upv config edit', since they are tables
+        for (name, routes) in &config.routes {
+            println!("  routes.{} = {}", name, routes.join(", "));
+        }
+        for (name, profile) in &config.drive_profiles {
+            let share = profile.share.as_deref().unwrap_or("personal drive");
+            match &profile.schedule {
+                Some(schedule) => println!(
+                    "  drive_profiles.{} = {}: {} ({} {}-{})",
+                    name, profile.drive.to_ascii_uppercase(), share,
+                    if schedule.days.is_empty() { "daily".to_string() } else { schedule.days.join(",") }, schedule.from, schedule.to
+                ),
+                None => println!("  drive_profiles.{} = {}: {}", name, profile.drive.to_ascii_uppercase(), share),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Opens the config file in an editor ($EDITOR, or Notepad), creating it first if needed.
+    /// The result is checked when the editor closes: if it isn't valid, the errors are shown and it can be edited again,
+    /// or the previous valid version (backed up to `config.toml.bak`) is put back.
+    pub fn edit() -> Result<()> {
+        let path = paths::config_file()?;
+        if !path.exists() {
+            fs::write(&path, CONFIG_TEMPLATE)
+                .with_context(|| format!("Failed to create '{}'", path.display()))?;
+        }
+
+        let previous = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let backup = path.with_extension("toml.bak");
+        let previous_valid = Self::parse(&path, &previous).is_ok();
+        if previous_valid {
+            fs::write(&backup, &previous)
+                .with_context(|| format!("Failed to back up the config to '{}'", backup.display()))?;
+        }
+
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| "notepad".to_string());
+        loop {
+            println!("Opening '{}' with {}...", path.display(), editor);
+
+            let status = Command::new(&editor)
+                .arg(&path)
+                .status()
+                .with_context(|| format!("Failed to open the editor '{}'", editor))?;
+
+            if !status.success() {
+                return Err(UpvError::Config(
+                    format!("The editor '{}' exited with an error", editor).into()
+                ).into());
+            }
+
+            // Check the result, so mistakes are caught now instead of on the next command
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read '{}'", path.display()))?;
+            let error = match Self::parse(&path, &content) {
+                Ok(_) => {
+                    println!("Configuration saved");
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+
+            eprintln!("\x1b[31m{}\x1b[0m", error.to_string().trim_end());
+            if Self::ask_edit_again()? {
+                continue;
+            }
+
+            if !previous_valid {
+                return Err(error);
+            }
+
+            fs::write(&path, &previous)
+                .with_context(|| format!("Failed to restore '{}'", path.display()))?;
+            println!("The changes were discarded and the previous configuration was restored (a copy is kept in '{}')", backup.display());
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(drive: Option<char>, fallbacks: &[char]) -> Config {
+        Config { drive, drive_fallbacks: fallbacks.to_vec(), ..Config::default() }
+    }
+
+    #[test]
+    fn tries_the_preferred_letter_first_then_z_to_d() {
+        let candidates = config(None, &[]).drive_candidates(None);
+        assert_eq!(candidates.len(), 23);
+        assert_eq!(&candidates[..4], ['W', 'Z', 'Y', 'X']);
+        assert_eq!(candidates.last(), Some(&'D'));
+        assert_eq!(candidates.iter().filter(|&&letter| letter == 'W').count(), 1);
+    }
+
+    #[test]
+    fn given_letter_wins_over_the_configured_one() {
+        assert_eq!(config(Some('X'), &['Y']).drive_candidates(Some('m')), ['M', 'Y']);
+        assert_eq!(config(Some('x'), &['Y']).drive_candidates(None), ['X', 'Y']);
+    }
+
+    #[test]
+    fn configured_fallbacks_keep_their_order_without_duplicates() {
+        assert_eq!(config(None, &['v', 'W', 'u', 'V', 'w']).drive_candidates(None), ['W', 'V', 'U']);
+        assert_eq!(config(Some('T'), &['s', 'S', 'r']).drive_candidates(Some('s')), ['S', 'R']);
+    }
+
+    #[test]
+    fn reads_every_credential_source() {
+        let content = r#"
+            credentials = "prompt"
+            [drive_profiles.env]
+            drive = "L"
+            credentials = { env = "UPV_LAB_PASSWORD" }
+            [drive_profiles.manager]
+            drive = "M"
+            credentials = { credential_manager = "upv-lab" }
+            [drive_profiles.dpapi]
+            drive = "N"
+            credentials = { dpapi = 'C:\Users\user\upv.pwd' }
+        "#;
+        let config = ConfigManager::parse(Path::new("config.toml"), content).unwrap();
+
+        assert_eq!(config.credentials, Some(CredentialSource::Prompt));
+        assert_eq!(config.drive_profiles["env"].credentials, Some(CredentialSource::Env("UPV_LAB_PASSWORD".to_string())));
+        assert_eq!(config.drive_profiles["manager"].credentials, Some(CredentialSource::CredentialManager("upv-lab".to_string())));
+        assert_eq!(config.drive_profiles["dpapi"].credentials, Some(CredentialSource::Dpapi(PathBuf::from(r"C:\Users\user\upv.pwd"))));
+    }
+
+    #[test]
+    fn accounts_fill_in_what_is_not_set() {
+        let content = r#"
+            username = "staff"
+            domain = "UPVNET"
+            drive = "W"
+            [accounts.student]
+            username = "student"
+            domain = "ALUMNO"
+            drive = "S"
+            credentials = { env = "UPV_STUDENT_PASSWORD" }
+            [drive_profiles.lab]
+            drive = "L"
+            account = "student"
+            [drive_profiles.other]
+            drive = "O"
+            account = "student"
+            username = "guest"
+        "#;
+        let config = ConfigManager::parse(Path::new("config.toml"), content).unwrap();
+
+        let (username, domain, credentials) = config.profile_identity(&config.drive_profiles["lab"], None, None).unwrap();
+        assert_eq!((username.as_str(), domain.to_string().as_str()), ("student", "ALUMNO"));
+        assert_eq!(credentials, Some(CredentialSource::Env("UPV_STUDENT_PASSWORD".to_string())));
+        let (username, ..) = config.profile_identity(&config.drive_profiles["other"], None, None).unwrap();
+        assert_eq!(username, "guest");
+
+        let student = ConfigManager::parse(Path::new("config.toml"), content).unwrap().with_account(Some("student")).unwrap();
+        assert_eq!(student.username(None).unwrap(), "student");
+        assert_eq!(student.drive(None), 'S');
+        assert!(config.with_account(Some("missing")).is_err());
+    }
+}
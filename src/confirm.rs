@@ -0,0 +1,77 @@
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+
+// Destructive commands (deleting VPN connections, mirroring a folder) ask for confirmation unless forced. How much they
+// ask can be changed in the [confirmations] table of the config file, so cautious users can ask for more and power
+// users for less, without passing --force every time.
+static CONFIRMATIONS: OnceLock<Confirmations> = OnceLock::new();
+
+/// How much confirming a destructive command takes
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// Don't ask (like --force)
+    None,
+    /// Answer y/N
+    Yes,
+    /// Type a word (the name of the connection, or DELETE)
+    Typed,
+    /// Answer y/N, then type the word
+    Double,
+}
+
+/// Confirmation levels of the destructive commands (their defaults if not set)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Confirmations {
+    /// 'upv vpn delete' (yes, or typed for the default connection)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete: Option<Level>,
+    /// 'upv vpn purge' (double)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purge: Option<Level>,
+    /// 'upv drive sync --mirror' (yes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<Level>,
+}
+
+impl Confirmations {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+pub fn set(confirmations: Confirmations) {
+    let _ = CONFIRMATIONS.set(confirmations);
+}
+
+/// The configured confirmation levels
+pub fn levels() -> &'static Confirmations {
+    CONFIRMATIONS.get_or_init(Confirmations::default)
+}
+
+fn read_answer(prompt: &str) -> Result<String> {
+    print!("{}", prompt);
+    io::stdout().flush().context("Failed to flush stdout")?;
+
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).context("Failed to read user input")?;
+    Ok(input.trim().to_string())
+}
+
+/// Asks for confirmation at a level, with a y/N `question` and/or a `prompt` to type `word`. Returns whether it was confirmed
+pub fn ask(level: Level, question: &str, prompt: &str, word: &str) -> Result<bool> {
+    if matches!(level, Level::Yes | Level::Double) {
+        let answer = read_answer(&format!("{} (y/N): ", question))?.to_lowercase();
+        if answer != "y" && answer != "yes" {
+            return Ok(false);
+        }
+    }
+    if matches!(level, Level::Typed | Level::Double) && read_answer(&format!("{} ", prompt))? != word {
+        return Ok(false);
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,199 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::slice;
+use anyhow::{Result, Context};
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use windows_sys::Win32::Foundation::LocalFree;
+use windows_sys::Win32::Security::Cryptography::{CryptUnprotectData, CRYPT_INTEGER_BLOB};
+
+use crate::drive::UPVDomain;
+use crate::error::UpvError;
+
+// UPV credentials are kept in the Windows Credential Manager as a single generic credential,
+// whose secret holds the username, domain and password (as JSON).
+const CREDENTIAL_TARGET: &str = "upv-cli";
+
+/// UPV credentials stored in the Windows Credential Manager
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredCredentials {
+    pub username: String,
+    pub domain: UPVDomain,
+    pub password: String,
+}
+
+impl StoredCredentials {
+    /// Username for the VPN, which is the UPV email address
+    pub fn vpn_username(&self) -> String {
+        format!("{}@{}", self.username, self.domain.email_domain())
+    }
+}
+
+/// Where the password of an account comes from when none is given as an argument (set with `credentials` in the
+/// config file, for every command or for a drive profile)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// The credentials saved with 'upv creds set', if they belong to the account (what is used without a source)
+    Stored,
+    /// Asked in the terminal every time
+    Prompt,
+    /// An environment variable holding the password
+    Env(String),
+    /// A generic credential of the Windows Credential Manager, by name (e.g. one added with 'cmdkey /generic:NAME')
+    CredentialManager(String),
+    /// A file with the password encrypted for the current Windows user (DPAPI), as written by ConvertFrom-SecureString
+    Dpapi(PathBuf),
+}
+
+pub struct CredsManager;
+
+impl CredsManager {
+    // Private utility functions
+
+    fn entry() -> Result<Entry> {
+        Entry::new_with_target(CREDENTIAL_TARGET, CREDENTIAL_TARGET, CREDENTIAL_TARGET)
+            .context("Failed to access the Windows Credential Manager")
+    }
+
+    /// Decrypts data encrypted with DPAPI (CryptProtectData) by the current Windows user
+    fn unprotect(encrypted: &[u8]) -> io::Result<Vec<u8>> {
+        let input = CRYPT_INTEGER_BLOB { cbData: encrypted.len() as u32, pbData: encrypted.as_ptr().cast_mut() };
+        let mut output = CRYPT_INTEGER_BLOB { cbData: 0, pbData: ptr::null_mut() };
+        // SAFETY: the input blob points to `encrypted`, which outlives the call, and the optional arguments are null
+        let decrypted = unsafe { CryptUnprotectData(&input, ptr::null_mut(), ptr::null(), ptr::null(), ptr::null(), 0, &mut output) };
+        if decrypted == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: the call succeeded, so the output blob holds `cbData` bytes allocated with LocalAlloc, freed after copying them
+        let data = unsafe { slice::from_raw_parts(output.pbData, output.cbData as usize) }.to_vec();
+        unsafe { LocalFree(output.pbData.cast()) };
+        Ok(data)
+    }
+
+    /// Reads a password saved with `Read-Host -AsSecureString | ConvertFrom-SecureString`: the hexadecimal DPAPI blob
+    /// of the password in UTF-16, which only the same Windows user on the same computer can decrypt
+    fn read_dpapi_file(path: &Path) -> Result<String> {
+        let content = fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let hex = content.trim();
+        let encrypted = (0..hex.len()).step_by(2)
+            .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|bytes| !bytes.is_empty())
+            .ok_or_else(|| UpvError::Credentials(
+                format!("'{}' doesn't hold an encrypted password", path.display()).into()
+            ).with_hint("Write it with: Read-Host -AsSecureString | ConvertFrom-SecureString | Set-Content <FILE>"))?;
+
+        let decrypted = Self::unprotect(&encrypted).map_err(|e| UpvError::Credentials(
+            format!("Failed to decrypt the password in '{}': {}", path.display(), e).into()
+        ).with_hint("It can only be decrypted by the Windows user that wrote it, on the same computer").with_os_code(e.raw_os_error()))?;
+
+        let utf16: Vec<u16> = decrypted.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        String::from_utf16(&utf16).with_context(|| format!("The password in '{}' is not valid text", path.display()))
+    }
+
+    // Public methods for credential management
+
+    /// Gets the password of an account from a credential source. The stored credentials give none here, since the
+    /// vpn and drive commands already fall back to them (only for the account they belong to).
+    pub fn password(source: &CredentialSource, username: &str, domain: &UPVDomain) -> Result<Option<String>> {
+        let password = match source {
+            CredentialSource::Stored => return Ok(None),
+            CredentialSource::Prompt => Self::prompt_password(username, domain)?,
+            CredentialSource::Env(variable) => env::var(variable).ok().filter(|password| !password.is_empty()).ok_or_else(|| UpvError::Credentials(
+                format!("The environment variable {} (where the password of {}\\{} comes from) is not set", variable, domain, username).into()
+            ).with_hint("Set it, or change the credential source with 'upv config edit'"))?,
+            CredentialSource::CredentialManager(target) => {
+                let entry = Entry::new_with_target(target, target, username)
+                    .context("Failed to access the Windows Credential Manager")?;
+                match entry.get_password() {
+                    Ok(password) => password,
+                    Err(keyring::Error::NoEntry) => return Err(UpvError::Credentials(
+                        format!("There is no credential named '{}' in the Windows Credential Manager", target).into()
+                    ).with_hint(format!("Add it with: cmdkey /generic:{} /user:{} /pass", target, username)).into()),
+                    Err(e) => return Err(e).with_context(|| format!("Failed to read the credential '{}' from the Windows Credential Manager", target)),
+                }
+            }
+            CredentialSource::Dpapi(path) => Self::read_dpapi_file(path)?,
+        };
+
+        Ok(Some(password))
+    }
+
+    /// Loads the stored UPV credentials, if any.
+    pub fn load() -> Result<Option<StoredCredentials>> {
+        let secret = match Self::entry()?.get_password() {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read the UPV credentials from the Windows Credential Manager"),
+        };
+
+        let credentials = serde_json::from_str(&secret)
+            .context("Failed to parse the stored UPV credentials")?;
+
+        Ok(Some(credentials))
+    }
+
+    /// Asks for a password in the terminal, without echoing it.
+    pub fn prompt_password(username: &str, domain: &UPVDomain) -> Result<String> {
+        let password = rpassword::prompt_password(format!("Password for {}\\{}: ", domain, username))
+            .context("Failed to read the password")?;
+
+        if password.is_empty() {
+            return Err(UpvError::Credentials("The password can't be empty".into()).into());
+        }
+
+        Ok(password)
+    }
+
+    /// Stores the UPV credentials, asking for the password if it isn't given.
+    pub fn set(username: &str, domain: UPVDomain, password: Option<String>) -> Result<()> {
+        let password = match password {
+            Some(password) if password.is_empty() => return Err(UpvError::Credentials("The password can't be empty".into()).into()),
+            Some(password) => password,
+            None => Self::prompt_password(username, &domain)?,
+        };
+
+        let credentials = StoredCredentials { username: username.to_string(), domain, password };
+        let secret = serde_json::to_string(&credentials).context("Failed to serialize the UPV credentials")?;
+
+        Self::entry()?.set_password(&secret)
+            .context("Failed to save the UPV credentials to the Windows Credential Manager")?;
+
+        println!("Credentials for {}\\{} saved to the Windows Credential Manager", credentials.domain, credentials.username);
+        println!("They will be used by 'upv vpn connect' and 'upv drive mount' when no password is given.");
+
+        Ok(())
+    }
+
+    /// Shows the stored UPV credentials (without the password).
+    pub fn show() -> Result<()> {
+        match Self::load()? {
+            Some(credentials) => {
+                println!("Stored UPV credentials:");
+                println!("  Username:      {}", credentials.username);
+                println!("  Domain:        {}", credentials.domain);
+                println!("  VPN username:  {}", credentials.vpn_username());
+                println!("  Password:      ********");
+            }
+            None => println!("No UPV credentials stored. Save them with 'upv creds set <USERNAME> <DOMAIN>'."),
+        }
+
+        Ok(())
+    }
+
+    /// Removes the stored UPV credentials.
+    pub fn clear() -> Result<()> {
+        match Self::entry()?.delete_credential() {
+            Ok(()) => println!("UPV credentials removed from the Windows Credential Manager"),
+            Err(keyring::Error::NoEntry) => println!("No UPV credentials stored, nothing to remove."),
+            Err(e) => return Err(e).context("Failed to remove the UPV credentials from the Windows Credential Manager"),
+        }
+
+        Ok(())
+    }
+}
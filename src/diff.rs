@@ -0,0 +1,41 @@
+use anyhow::{Result, Context};
+use serde::Serialize;
+
+/// A setting whose current value isn't the expected one (or the one a command would set)
+#[derive(Debug, Clone, Serialize)]
+pub struct Difference {
+    pub setting: String,
+    pub current: String,
+    pub expected: String,
+}
+
+impl Difference {
+    pub fn new(setting: impl Into<String>, current: impl Into<String>, expected: impl Into<String>) -> Self {
+        Difference { setting: setting.into(), current: current.into(), expected: expected.into() }
+    }
+}
+
+/// Prints a list of differences as an aligned table, or as a JSON array so they can be reviewed by scripts.
+/// Empty values are shown as "(unset)" in the table.
+pub fn print(differences: &[Difference], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(differences).context("Failed to serialize the differences")?);
+        return Ok(());
+    }
+
+    let show = |value: &str| if value.is_empty() { "(unset)".to_string() } else { value.to_string() };
+    let setting_width = differences.iter().map(|difference| difference.setting.len()).chain([7]).max().unwrap_or_default();
+    let current_width = differences.iter().map(|difference| show(&difference.current).len()).chain([7]).max().unwrap_or_default();
+
+    println!("  \x1b[1m{:<setting_width$}  {:<current_width$}  EXPECTED\x1b[0m", "SETTING", "CURRENT");
+    for difference in differences {
+        println!(
+            "  {:<setting_width$}  \x1b[31m{:<current_width$}\x1b[0m  \x1b[32m{}\x1b[0m",
+            difference.setting,
+            show(&difference.current),
+            show(&difference.expected)
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,303 @@
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, Context};
+use chrono::Local;
+use serde::Serialize;
+
+use crate::drive::{DriveManager, NAS_SERVER};
+use crate::error::UpvError;
+use crate::maintenance::MaintenanceManager;
+use crate::powershell;
+use crate::vpn::VpnManager;
+
+// How long to wait for a TCP connection before considering a server unreachable
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Ports checked for each server (SSTP runs over HTTPS, network drives over SMB)
+const VPN_PORT: u16 = 443;
+const SMB_PORT: u16 = 445;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of a single diagnostic check
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to fix the problem, if the check didn't pass
+    pub hint: Option<String>,
+}
+
+impl CheckResult {
+    fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+pub struct DoctorManager;
+
+impl DoctorManager {
+    // Private utility functions
+
+    fn check_powershell() -> CheckResult {
+        match powershell::restriction() {
+            None => CheckResult::new("PowerShell", CheckStatus::Pass, "PowerShell is available"),
+            Some(restriction) => CheckResult::new("PowerShell", CheckStatus::Warn, restriction)
+                .with_hint("Some operations will use slower fallbacks or won't be available. Ask your IT administrator if PowerShell can be allowed"),
+        }
+    }
+
+    /// Checks the Remote Access Connection Manager service, which every VPN connection depends on.
+    fn check_ras_service() -> CheckResult {
+        const NAME: &str = "RAS service";
+
+        let output = match Command::new("sc").args(["query", "RasMan"]).output() {
+            Ok(output) => output,
+            Err(e) => return CheckResult::new(NAME, CheckStatus::Fail, format!("Failed to query the service: {}", e)),
+        };
+
+        // The state names are not localized by sc
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            CheckResult::new(NAME, CheckStatus::Fail, format!("Failed to query the service: {}", stdout.trim()))
+                .with_hint("The Remote Access Connection Manager (RasMan) service may be missing or disabled on this machine")
+        } else if stdout.contains("RUNNING") {
+            CheckResult::new(NAME, CheckStatus::Pass, "Remote Access Connection Manager is running")
+        } else {
+            // It is started on demand, so it being stopped is only a problem if it can't start
+            CheckResult::new(NAME, CheckStatus::Warn, "Remote Access Connection Manager is not running")
+                .with_hint("It is usually started when connecting. If connecting fails, start it with 'sc start RasMan' from an elevated prompt")
+        }
+    }
+
+    /// Resolves a host name, returning its addresses (or a check failure).
+    fn resolve(name: &str, host: &str, port: u16) -> Result<Vec<SocketAddr>, CheckResult> {
+        match (host, port).to_socket_addrs() {
+            Ok(addresses) => {
+                let addresses: Vec<SocketAddr> = addresses.collect();
+                if addresses.is_empty() {
+                    Err(CheckResult::new(name, CheckStatus::Fail, format!("'{}' has no addresses", host))
+                        .with_hint("Check your DNS settings"))
+                } else {
+                    Ok(addresses)
+                }
+            }
+            Err(e) => Err(CheckResult::new(name, CheckStatus::Fail, format!("Failed to resolve '{}': {}", host, e))
+                .with_hint("Check your Internet connection and DNS settings")),
+        }
+    }
+
+    /// Tries to open a TCP connection to any of the given addresses, all at once (so unreachable ones don't add up their timeouts).
+    fn check_reachable(name: &str, host: &str, port: u16, addresses: &[SocketAddr], hint: &str) -> CheckResult {
+        let reachable = thread::scope(|scope| {
+            let attempts: Vec<_> = addresses.iter()
+                .map(|address| scope.spawn(move || TcpStream::connect_timeout(address, CONNECT_TIMEOUT).is_ok()))
+                .collect();
+            attempts.into_iter().any(|attempt| attempt.join().unwrap_or(false))
+        });
+        if reachable {
+            CheckResult::new(name, CheckStatus::Pass, format!("{}:{} is reachable", host, port))
+        } else {
+            CheckResult::new(name, CheckStatus::Fail, format!("{}:{} is not reachable", host, port))
+                .with_hint(hint)
+        }
+    }
+
+    fn check_vpn_server() -> Vec<CheckResult> {
+        let server = VpnManager::server_address();
+        let addresses = match Self::resolve("VPN server DNS", &server, VPN_PORT) {
+            Ok(addresses) => addresses,
+            Err(failure) => return vec![failure],
+        };
+
+        vec![
+            CheckResult::new(
+                "VPN server DNS",
+                CheckStatus::Pass,
+                format!("'{}' resolves to {}", server, addresses[0].ip())
+            ),
+            Self::check_reachable(
+                "VPN server",
+                &server,
+                VPN_PORT,
+                &addresses,
+                "Check your Internet connection. Some networks (or firewalls) block VPN traffic"
+            ),
+        ]
+    }
+
+    fn check_vpn_connections() -> Vec<CheckResult> {
+        const NAME: &str = "VPN connections";
+
+        let connections = match VpnManager::get_upv_connection_statuses() {
+            Ok(connections) => connections,
+            Err(e) => return vec![CheckResult::new(NAME, CheckStatus::Fail, format!("{:#}", e))],
+        };
+
+        if connections.is_empty() {
+            return vec![
+                CheckResult::new(NAME, CheckStatus::Warn, "There are no UPV VPN connections")
+                    .with_hint("Create one with 'upv vpn create <NAME>'")
+            ];
+        }
+
+        connections.iter().map(|(name, status)| {
+            let check = format!("VPN connection '{}'", name);
+            match VpnManager::get_connection_problems(name) {
+                Ok(problems) if problems.is_empty() => CheckResult::new(&check, CheckStatus::Pass, format!("Correctly configured ({})", status)),
+                Ok(problems) => CheckResult::new(&check, CheckStatus::Fail, format!("Misconfigured: {}", problems.join(", ")))
+                    .with_hint(format!("Repair it with 'upv vpn create \"{}\" --idempotent'", name)),
+                // Its settings can't be read without PowerShell, but it exists
+                Err(_) if powershell::restriction().is_some() => CheckResult::new(&check, CheckStatus::Warn, format!("Settings can't be checked ({})", status)),
+                Err(e) => CheckResult::new(&check, CheckStatus::Fail, format!("{:#}", e)),
+            }
+        }).collect()
+    }
+
+    fn check_nas() -> CheckResult {
+        const NAME: &str = "Network drive server";
+
+        match Self::resolve(NAME, NAS_SERVER, SMB_PORT) {
+            Ok(addresses) => Self::check_reachable(
+                NAME,
+                NAS_SERVER,
+                SMB_PORT,
+                &addresses,
+                "It is only reachable from the UPV network. Connect to the VPN first with 'upv vpn connect'"
+            ),
+            Err(failure) => failure.with_hint("It is only resolvable from the UPV network. Connect to the VPN first with 'upv vpn connect'"),
+        }
+    }
+
+    /// Looks for drive mappings that are not connected anymore.
+    fn check_drive_mappings() -> Vec<CheckResult> {
+        const NAME: &str = "Drive mappings";
+
+        let mappings = match DriveManager::get_mappings() {
+            Ok(mappings) => mappings,
+            Err(e) => return vec![CheckResult::new(NAME, CheckStatus::Fail, format!("{:#}", e))],
+        };
+
+        let stale: Vec<_> = mappings.iter()
+            .filter(|entry| !entry.status.is_empty() && !entry.status.eq_ignore_ascii_case("OK"))
+            .collect();
+
+        if stale.is_empty() {
+            return vec![CheckResult::new(NAME, CheckStatus::Pass, format!("{} mapping(s), none of them stale", mappings.len()))];
+        }
+
+        stale.iter().map(|entry| {
+            let local = entry.local.as_deref().unwrap_or("-");
+            let result = CheckResult::new(NAME, CheckStatus::Warn, format!("{} {} is {}", local, entry.remote, entry.status));
+            match &entry.local {
+                Some(local) => result.with_hint(format!(
+                    "Reconnect to the VPN, or remove it with 'upv drive unmount -d {} --force'",
+                    local.trim_end_matches(':')
+                )),
+                None => result.with_hint(format!("Reconnect to the VPN, or remove it with 'net use \"{}\" /delete'", entry.remote)),
+            }
+        }).collect()
+    }
+
+    /// Looks for announced maintenance going on or coming up, which would explain connection problems.
+    fn check_maintenance(status_feed: &str) -> Vec<CheckResult> {
+        const NAME: &str = "UPV maintenance";
+
+        let windows = match MaintenanceManager::upcoming(status_feed) {
+            Ok(windows) => windows,
+            Err(e) => return vec![CheckResult::new(NAME, CheckStatus::Warn, format!("{:#}", e))
+                .with_hint("Check the URL with 'upv config get status_feed'")],
+        };
+
+        if windows.is_empty() {
+            return vec![CheckResult::new(NAME, CheckStatus::Pass, "No scheduled maintenance in the next 24 hours")];
+        }
+
+        let now = Local::now();
+        windows.iter().map(|window| {
+            CheckResult::new(NAME, CheckStatus::Warn, MaintenanceManager::describe(window, &now))
+                .with_hint("The VPN or the network drives may not work during it, even if your setup is fine")
+        }).collect()
+    }
+
+    fn print_result(result: &CheckResult) {
+        let label = match result.status {
+            CheckStatus::Pass => "\x1b[32m[PASS]\x1b[0m",
+            CheckStatus::Warn => "\x1b[33m[WARN]\x1b[0m",
+            CheckStatus::Fail => "\x1b[31m[FAIL]\x1b[0m",
+        };
+        println!("{} {}: {}", label, result.name, result.detail);
+        if let Some(hint) = &result.hint {
+            println!("       \x1b[2mHint: {}\x1b[0m", hint);
+        }
+    }
+
+    // Public methods for diagnostics
+
+    /// Runs every check, printing a pass/fail report (as JSON if requested).
+    /// With a status feed, announced maintenance is reported too.
+    /// Fails if any check failed, so it can be used in scripts.
+    pub fn run(json: bool, status_feed: Option<&str>) -> Result<()> {
+        if !json {
+            println!("Running diagnostics...");
+        }
+
+        // The network probes run while the local checks do, so the whole report takes about one connection timeout
+        let (local, vpn_server, nas, maintenance) = thread::scope(|scope| {
+            let vpn_server = scope.spawn(Self::check_vpn_server);
+            let nas = scope.spawn(Self::check_nas);
+            let maintenance = status_feed.map(|status_feed| scope.spawn(move || Self::check_maintenance(status_feed)));
+            let local = (Self::check_powershell(), Self::check_ras_service(), Self::check_vpn_connections(), Self::check_drive_mappings());
+            (local, vpn_server.join(), nas.join(), maintenance.map(|maintenance| maintenance.join()))
+        });
+        let (powershell, ras_service, vpn_connections, drive_mappings) = local;
+        let stopped = |name: &str| CheckResult::new(name, CheckStatus::Fail, "The check stopped unexpectedly");
+
+        let mut results = vec![powershell, ras_service];
+        results.extend(vpn_server.unwrap_or_else(|_| vec![stopped("VPN server")]));
+        results.extend(vpn_connections);
+        results.push(nas.unwrap_or_else(|_| stopped("Network drive server")));
+        results.extend(drive_mappings);
+        if let Some(maintenance) = maintenance {
+            results.extend(maintenance.unwrap_or_else(|_| vec![stopped("UPV maintenance")]));
+        }
+
+        let failed = results.iter().filter(|result| result.status == CheckStatus::Fail).count();
+        let warned = results.iter().filter(|result| result.status == CheckStatus::Warn).count();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&results).context("Failed to serialize diagnostics")?);
+        } else {
+            for result in &results {
+                Self::print_result(result);
+            }
+            println!();
+            println!("{} check(s): {} passed, {} warning(s), {} failed", results.len(), results.len() - failed - warned, warned, failed);
+        }
+
+        if failed > 0 {
+            return Err(UpvError::ChecksFailed(format!("{} check(s) failed", failed).into()).into());
+        }
+
+        Ok(())
+    }
+}
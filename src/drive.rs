@@ -1,16 +1,116 @@
 use clap::{ValueEnum};
-use std::process::{Command};
+use std::process::{Command, Output, Stdio};
 use anyhow::{Result, Context};
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
 
-use crate::error::{UpvError, EXIT_UPV_DRIVE_ERROR, EXIT_UPV_DRIVE_IN_USE_ERROR};
+use crate::cli;
+use crate::confirm::{self, Level};
+use crate::creds::CredsManager;
+use crate::dry_run;
+use crate::error::{self, Cause, UpvError};
+use crate::interrupt;
+use crate::job;
+use crate::lockout::LockoutGuard;
+use crate::paths;
+use crate::ping::PingManager;
+use crate::powershell;
+use crate::vpn::VpnManager;
+use crate::wide;
+use crate::wnet;
 
-#[derive(Debug, Clone, ValueEnum)]
+// How long `--wait-for-vpn` waits for a VPN connection before giving up
+const VPN_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+// How long `--auto-vpn` waits for the VPN connection it starts
+const AUTO_VPN_TIMEOUT: Duration = Duration::from_secs(60);
+
+// How many times (and how often) a freshly mounted drive is checked before opening it in Explorer
+const OPEN_RETRIES: u32 = 10;
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(300);
+
+// How long a drive has to answer a directory listing when verifying it
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
+// Latency from which 'upv drive check' reports a drive as slow
+const SLOW_LATENCY: Duration = Duration::from_secs(1);
+
+// Server hosting the personal network drives
+pub const NAS_SERVER: &str = "nasupv.upv.es";
+
+// Port network drives are accessed through
+pub const SMB_PORT: u16 = 445;
+
+// Parameters of the last mount of each drive letter, so 'upv drive remount' doesn't need them again (passwords aren't saved)
+const MOUNTS_FILE: &str = "mounts.json";
+// Shares that were mapped to a drive letter before it was remapped with 'upv drive mount --remap', so
+// 'upv drive unmount --restore-previous' can map them back
+const PREVIOUS_MAPPINGS_FILE: &str = "previous-mappings.json";
+
+// Win32 errors for a drive that can't be unmounted because files or folders on it are open
+const ERROR_OPEN_FILES: i32 = 2401;
+const ERROR_DEVICE_IN_USE: i32 = 2404;
+
+// Share of the quota used from which 'upv drive quota' warns that the drive is almost full
+const QUOTA_WARNING_PERCENT: f64 = 90.0;
+const QUOTA_BAR_WIDTH: usize = 30;
+
+// How many times (and how many seconds apart) 'upv drive sync' retries a file that fails to copy.
+// Robocopy's defaults (a million retries, 30s apart) would hang on a dropped VPN
+const SYNC_RETRIES: u32 = 2;
+const SYNC_RETRY_WAIT: u32 = 5;
+
+// Wi-Fi networks whose credentials Windows can reuse to access UPV shares
+const UPV_WIFI_SSIDS: [&str; 2] = ["eduroam", "UPVNET"];
+
+// Default locations of the personal network drive for each domain.
+// Placeholders: {server} (the NAS), {initial} (first letter of the username, lowercase) and {user}
+const ALUMNO_SHARE_TEMPLATE: &str = r"\\{server}\alumnos\{initial}\{user}";
+const UPVNET_SHARE_TEMPLATE: &str = r"\\{server}\discos\{initial}\{user}";
+
+// The same drives, as published over HTTPS (WebDAV), for when the SMB port can't be reached (off campus without the VPN).
+// Same placeholders as the share templates
+const ALUMNO_WEBDAV_TEMPLATE: &str = "https://{server}/alumnos/{initial}/{user}";
+const UPVNET_WEBDAV_TEMPLATE: &str = "https://{server}/discos/{initial}/{user}";
+
+#[derive(Debug, Clone, ValueEnum, Serialize, Deserialize)]
 pub enum UPVDomain {
+    /// Students (user@alumno.upv.es)
     ALUMNO,
+    /// Staff and faculty (user@upv.es)
     UPVNET,
 }
 
+impl UPVDomain {
+    /// Share path template used for this domain's personal network drives by default
+    pub fn default_share_template(&self) -> &'static str {
+        match self {
+            UPVDomain::ALUMNO => ALUMNO_SHARE_TEMPLATE,
+            UPVDomain::UPVNET => UPVNET_SHARE_TEMPLATE,
+        }
+    }
+
+    /// WebDAV URL template used for this domain's personal network drives by default
+    pub fn default_webdav_template(&self) -> &'static str {
+        match self {
+            UPVDomain::ALUMNO => ALUMNO_WEBDAV_TEMPLATE,
+            UPVDomain::UPVNET => UPVNET_WEBDAV_TEMPLATE,
+        }
+    }
+
+    /// Domain of the UPV email addresses of this domain's users
+    pub fn email_domain(&self) -> &'static str {
+        match self {
+            UPVDomain::ALUMNO => "alumno.upv.es",
+            UPVDomain::UPVNET => "upv.es",
+        }
+    }
+}
+
 impl std::fmt::Display for UPVDomain {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -20,53 +120,1156 @@ impl std::fmt::Display for UPVDomain {
     }
 }
 
+/// A single mapping as listed by `net use` (or the WNet API, which lists the same ones)
+#[derive(Debug, Clone)]
+pub struct NetUseEntry {
+    /// Connection state ("OK", "Disconnected", "Unavailable"...). May be empty.
+    pub status: String,
+    /// Local drive letter (e.g. "W:"), if the mapping has one
+    pub local: Option<String>,
+    /// UNC path of the mapped share
+    pub remote: String,
+}
+
+impl NetUseEntry {
+    /// Key that identifies the same mapping across refreshes
+    pub fn key(&self) -> String {
+        format!("{} {}", self.local.as_deref().unwrap_or("-"), self.remote)
+    }
+
+    /// Whether the share is on a UPV file server (the NAS or any other *.upv.es host)
+    pub fn is_upv(&self) -> bool {
+        let server = self.remote.trim_start_matches('\\').split('\\').next().unwrap_or_default().to_lowercase();
+        server == NAS_SERVER || server.ends_with(".upv.es")
+    }
+}
+
+/// Details of a drive mapping, as reported by Get-SmbMapping and Get-SmbConnection
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all(deserialize = "PascalCase"))]
+struct DriveInfo {
+    drive: String,
+    remote_path: String,
+    status: String,
+    server: String,
+    share: String,
+    /// SMB dialect negotiated with the server (only while connected)
+    #[serde(default)]
+    dialect: Option<String>,
+    /// Local account the connection belongs to (only while connected)
+    #[serde(default)]
+    user: Option<String>,
+    /// Account used to log in to the server (only while connected)
+    #[serde(default)]
+    credential: Option<String>,
+    /// Whether the mapping is restored at logon
+    #[serde(default)]
+    persistent: bool,
+    /// Time to open a connection to the server's SMB port
+    #[serde(default)]
+    latency_ms: Option<u128>,
+}
+
+/// Space of a drive, as seen by the current user (on UPV shares, the total is the user's quota)
+#[derive(Debug, Serialize)]
+struct DriveSpace {
+    drive: String,
+    total_bytes: u64,
+    free_bytes: u64,
+    used_bytes: u64,
+    used_percent: f64,
+}
+
+/// Result of 'upv drive check' for a drive
+#[derive(Debug, Serialize)]
+struct DriveCheck {
+    drive: String,
+    remote: String,
+    /// State reported by 'net use' (which stays "OK" for stale mappings)
+    status: String,
+    healthy: bool,
+    latency_ms: Option<u128>,
+    error: Option<String>,
+}
+
+/// Result of 'upv drive sync', as parsed from the output of robocopy
+#[derive(Debug, Serialize)]
+struct SyncSummary {
+    source: String,
+    destination: String,
+    /// Whether the files were only listed (--dry-run), so the counts are what a real run would do
+    dry_run: bool,
+    files_copied: u64,
+    bytes_copied: u64,
+    /// Files of the destination that weren't in the source (only removed with --mirror)
+    files_deleted: u64,
+    errors: Vec<SyncError>,
+    robocopy_exit_code: Option<i32>,
+}
+
+/// A file (or folder) that robocopy couldn't copy, with the last error it got for it
+#[derive(Debug, Serialize)]
+struct SyncError {
+    path: String,
+    code: Option<i32>,
+    message: String,
+}
+
+/// A line of robocopy's output, as printed with the options 'upv drive sync' uses (full paths and sizes, no classes)
+#[derive(Debug, PartialEq)]
+enum RobocopyLine<'a> {
+    /// A file of the source being copied (or that would be, with /L)
+    Copy { path: &'a str, size: u64 },
+    /// A file of the destination that isn't in the source
+    Extra { path: &'a str },
+    /// A failure on a file or folder. The description of the error comes in the next line
+    Error { path: &'a str, code: Option<i32> },
+    /// Anything else (descriptions of errors, retry notices...)
+    Other(&'a str),
+}
+
+/// How a drive answered a directory listing
+enum ListingProbe {
+    Answered(Duration),
+    Failed(io::Error),
+    TimedOut,
+}
+
+/// Why a share couldn't be mapped to (or unmapped from) a drive letter
+struct NetFailure {
+    /// Description of the error (from the system, or as `net use` printed it)
+    message: String,
+    /// Win32 error code behind it, if known
+    os_code: Option<i32>,
+}
+
+/// What to do when a drive can't be unmounted because it is in use
+enum InUseAction {
+    /// Check again (after the user closed what was using it)
+    Retry,
+    /// Unmount it anyway
+    Force,
+    /// Give up, failing as if no choice was offered
+    Cancel,
+}
+
+/// When to mount a drive through WebDAV (over HTTPS) instead of SMB
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WebdavMode {
+    /// Only through SMB
+    Never,
+    /// Through WebDAV if the server's SMB port doesn't answer
+    Fallback,
+    /// Always through WebDAV
+    Always,
+}
+
+/// Options for mounting the personal network drive
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MountOptions {
+    /// Password for the share (if `None`, existing VPN/Wi-Fi credentials are reused). Never saved
+    #[serde(skip)]
+    pub password: Option<String>,
+    /// Share path template to use instead of the domain's default one
+    pub share_template: Option<String>,
+    /// When to mount through WebDAV instead of SMB
+    pub webdav: WebdavMode,
+    /// WebDAV URL template to use instead of the domain's default one
+    pub webdav_template: Option<String>,
+    /// NAS hosts (names or IPs) tried in order when the NAS can't be reached, for shares on the NAS
+    #[serde(default)]
+    pub alternate_servers: Vec<String>,
+    /// Name shown in the messages instead of "Disco W" (for shares from a catalog)
+    pub label: Option<String>,
+    /// Drive letter to mount to
+    pub drive: char,
+    /// Open the drive in Explorer after mounting
+    pub open_explorer: bool,
+    /// Seconds to wait for the server before cancelling (0 to wait indefinitely)
+    pub timeout: u64,
+    /// Retry once when a UPV VPN connection becomes active if the server is unreachable
+    pub wait_for_vpn: bool,
+    /// Tell Explorer about the new drive, so open windows show it right away
+    pub refresh_explorer: bool,
+    /// Fail early if there's no password and no credentials to reuse
+    pub check_credentials: bool,
+    /// Try even if the account failed to log in too many times recently
+    pub override_lockout: bool,
+    /// Have Windows restore the mapping at every logon
+    pub persistent: bool,
+    /// Replace the mapping of the drive letter if it points at another share (asking first in a terminal otherwise)
+    #[serde(skip)]
+    pub remap: bool,
+    /// VPN connection to connect first if the server can't be reached
+    #[serde(skip)]
+    pub auto_vpn: Option<String>,
+}
+
+/// Options for copying a folder to or from a drive ('upv drive sync')
+#[derive(Clone, Copy)]
+pub struct SyncOptions {
+    /// Make the destination an exact copy, deleting the files that aren't in the source
+    pub mirror: bool,
+    /// Copy from the drive to the local folder instead
+    pub pull: bool,
+    /// Don't ask before deleting files with `mirror`
+    pub force: bool,
+    /// Milliseconds to wait between blocks of 64 KB (robocopy's inter-packet gap), so the copy leaves room on the link
+    pub throttle: Option<u32>,
+}
+
+/// A successful mount, as saved for 'upv drive remount'
+#[derive(Serialize, Deserialize)]
+struct SavedMount {
+    username: String,
+    domain: UPVDomain,
+    #[serde(flatten)]
+    options: MountOptions,
+}
+
+/// A mapping replaced by 'upv drive mount --remap', as saved for 'upv drive unmount --restore-previous'
+#[derive(Serialize, Deserialize)]
+struct PreviousMapping {
+    remote: String,
+    persistent: bool,
+}
+
 pub struct DriveManager;
 
 impl DriveManager {
-    /// Mounts the UPV Personal Network Drive (Disco W) to a specified drive letter
-    pub fn mount(username: &str, domain: &UPVDomain, password: Option<&str>, drive: char, open_explorer: bool) -> Result<()> {
-        println!("Mounting Disco W to drive {}:...", drive);
-        
+    // Private utility functions
+
+    /// Lists the current mappings through the WNet API, or by running `net use` and parsing its output if that fails.
+    pub fn get_mappings() -> Result<Vec<NetUseEntry>> {
+        if let Ok(connections) = wnet::connections() {
+            return Ok(connections.into_iter()
+                .map(|connection| NetUseEntry { status: connection.status, local: connection.local, remote: connection.remote })
+                .collect());
+        }
+
+        let output = Command::new("net")
+            .arg("use")
+            .output()
+            .context("Failed to check drive status")?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Drive(
+                format!("Failed to list network drives: {}", error).into()
+            ).with_os_code(error::parse_os_code(&error)).into());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(Self::parse_net_use(&stdout))
+    }
+
+    /// Parses the table printed by `net use`.
+    /// Only the UNC path is used as an anchor, since the headers are localized.
+    fn parse_net_use(output: &str) -> Vec<NetUseEntry> {
+        output.lines()
+            .filter_map(|line| {
+                let unc_start = line.find(r"\\")?;
+                let (prefix, rest) = line.split_at(unc_start);
+
+                // The remote path ends where the "Network" column starts (2+ spaces)
+                let remote = rest.split("  ").next().unwrap_or(rest).trim().to_string();
+
+                let mut tokens: Vec<&str> = prefix.split_whitespace().collect();
+                let local = match tokens.last() {
+                    Some(token) if token.len() == 2 && token.ends_with(':') => {
+                        tokens.pop().map(|t| t.to_uppercase())
+                    }
+                    _ => None,
+                };
+
+                Some(NetUseEntry {
+                    status: tokens.join(" "),
+                    local,
+                    remote,
+                })
+            })
+            .collect()
+    }
+
+    /// Fills in the placeholders of a share path or WebDAV URL template for the given user and NAS host.
+    fn fill_template(template: &str, username: &str, server: &str) -> Result<String> {
         let first_letter = username.chars().next()
             .context("Username cannot be empty")?
             .to_lowercase()
             .to_string();
-        
-        let server_path = match domain {
-            UPVDomain::ALUMNO => format!(r"\\nasupv.upv.es\alumnos\{}\{}", first_letter, username),
-            UPVDomain::UPVNET => format!(r"\\nasupv.upv.es\discos\{}\{}", first_letter, username),
+
+        Ok(template
+            .replace("{server}", server)
+            .replace("{initial}", &first_letter)
+            .replace("{user}", username))
+    }
+
+    /// Fills in the placeholders of a share path template for the given user.
+    fn expand_share_template(template: &str, username: &str, server: &str) -> Result<String> {
+        let path = Self::fill_template(template, username, server)?;
+
+        if !path.starts_with(r"\\") {
+            return Err(UpvError::Drive(
+                format!("The share path '{}' is not a UNC path (expected something like \\\\server\\share)", path).into()
+            ).into());
+        }
+
+        Ok(path)
+    }
+
+    /// Fills in the placeholders of a WebDAV URL template for the given user.
+    fn expand_webdav_template(template: &str, username: &str) -> Result<String> {
+        let url = Self::fill_template(template, username, NAS_SERVER)?;
+
+        // The WebClient service only sends credentials over plain HTTP to the local intranet, so HTTPS is required
+        if !url.to_ascii_lowercase().starts_with("https://") {
+            return Err(UpvError::Drive(
+                format!("The WebDAV URL '{}' is not an HTTPS URL (expected something like https://server/path)", url).into()
+            ).into());
+        }
+
+        Ok(url)
+    }
+
+    /// Whether the SMB port of the server of a share path answers (it is blocked from outside the UPV network)
+    fn smb_reachable(server_path: &str) -> bool {
+        let server = server_path.trim_start_matches('\\').split('\\').next().unwrap_or_default();
+        PingManager::connect_time(server, SMB_PORT).is_some()
+    }
+
+    /// Finds the first alternate NAS host whose SMB port answers, returning it along with the share path on it
+    fn find_reachable_alternate<'a>(template: &str, username: &str, servers: &'a [String]) -> Result<Option<(&'a str, String)>> {
+        for server in servers {
+            let path = Self::expand_share_template(template, username, server)?;
+            if Self::smb_reachable(&path) {
+                return Ok(Some((server, path)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Target a DFS path is currently served from (as `\\server\share`), or `None` if it isn't a DFS path.
+    /// DFS namespaces refer clients to one of several file servers, so the path mounted doesn't say which one is used.
+    fn dfs_target(path: &str) -> Option<String> {
+        use windows_sys::Win32::NetworkManagement::NetManagement::NetApiBufferFree;
+        use windows_sys::Win32::Storage::DistributedFileSystem::{NetDfsGetClientInfo, DFS_INFO_3, DFS_STORAGE_STATE_ACTIVE};
+
+        let path = wide::to_wide(path);
+        let mut buffer: *mut u8 = std::ptr::null_mut();
+        // SAFETY: the path is a valid null-terminated UTF-16 string, and the buffer is only read if the call succeeds
+        if unsafe { NetDfsGetClientInfo(path.as_ptr(), std::ptr::null(), std::ptr::null(), 3, &mut buffer) } != 0 {
+            return None;
+        }
+
+        // SAFETY: level 3 returns a DFS_INFO_3 whose storage array has NumberOfStorages entries, whose names are
+        // null-terminated strings in the same buffer. The buffer is freed once read
+        unsafe {
+            let info = &*(buffer as *const DFS_INFO_3);
+            let storages = std::slice::from_raw_parts(info.Storage, info.NumberOfStorages as usize);
+            let target = storages.iter()
+                .find(|storage| storage.State & DFS_STORAGE_STATE_ACTIVE != 0)
+                .map(|storage| format!(r"\\{}\{}", wide::from_wide_ptr(storage.ServerName).unwrap_or_default(), wide::from_wide_ptr(storage.ShareName).unwrap_or_default()));
+            NetApiBufferFree(buffer.cast());
+            target
+        }
+    }
+
+    /// Checks that the WebClient service, which maps WebDAV URLs to drive letters, can be started.
+    /// It is started on demand by net use, but it isn't installed on Windows Server and can be disabled.
+    fn check_web_client() -> Result<()> {
+        // Without PowerShell, net use itself reports the problem (less clearly)
+        if powershell::restriction().is_some() {
+            return Ok(());
+        }
+
+        let output = powershell::run("(Get-Service WebClient -ErrorAction SilentlyContinue).StartType")?;
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "" => Err(UpvError::Drive(
+                "The WebClient service, needed to mount drives through WebDAV, is not installed".into()
+            ).with_hint("On Windows Server, install the WebDAV Redirector feature ('Install-WindowsFeature WebDAV-Redirector' as administrator) and restart").into()),
+            "Disabled" => Err(UpvError::Drive(
+                "The WebClient service, needed to mount drives through WebDAV, is disabled".into()
+            ).with_hint("Enable it as administrator with 'Set-Service WebClient -StartupType Manual'").into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs a command, killing it if it doesn't finish within the timeout.
+    /// Returns `None` if the command timed out.
+    fn output_with_timeout(cmd: &mut Command, timeout: Option<Duration>) -> Result<Option<Output>> {
+        let mut child = cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to execute net use command")?;
+
+        let start = Instant::now();
+        loop {
+            if child.try_wait().context("Failed to wait for net use command")?.is_some() {
+                return child.wait_with_output()
+                    .map(Some)
+                    .context("Failed to read net use output");
+            }
+
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                // The process may have finished right now, so errors killing it are irrelevant
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Starts mapping a share to a drive letter through the WNet API, with the given (user, password) or the credentials
+    /// Windows already has. The call is made in a thread, so the caller can stop waiting for a server that doesn't answer.
+    fn start_mapping(drive: char, remote: &str, credentials: (Option<&str>, Option<&str>), persistent: bool) -> mpsc::Receiver<io::Result<()>> {
+        let (sender, receiver) = mpsc::channel();
+        let remote = remote.to_string();
+        let (user, password) = (credentials.0.map(String::from), credentials.1.map(String::from));
+        thread::spawn(move || {
+            let _ = sender.send(wnet::add_connection(drive, &remote, user.as_deref(), password.as_deref(), persistent));
+        });
+        receiver
+    }
+
+    /// Waits for a mapping started with `start_mapping`. Returns `None` if it doesn't finish within the timeout; the API
+    /// call can't be cancelled, so it keeps running and can be waited for again. Failures only the network provider can
+    /// describe (like WebDAV ones) are retried with the equivalent `net use` command, which reports them in its own words.
+    fn wait_for_mapping(mapping: &mpsc::Receiver<io::Result<()>>, cmd: &mut Command, timeout: Option<Duration>) -> Result<Option<Result<(), NetFailure>>> {
+        let result = match timeout {
+            Some(timeout) => mapping.recv_timeout(timeout).ok(),
+            None => mapping.recv().ok(),
         };
-        
+
+        match result {
+            None => Ok(None),
+            Some(Ok(())) => Ok(Some(Ok(()))),
+            Some(Err(e)) if e.raw_os_error().is_some_and(|code| wnet::PROVIDER_ERRORS.contains(&(code as u32))) => {
+                let Some(output) = Self::output_with_timeout(cmd, timeout)? else {
+                    return Ok(None);
+                };
+                if output.status.success() {
+                    return Ok(Some(Ok(())));
+                }
+
+                let error = String::from_utf8_lossy(&output.stderr);
+                Ok(Some(Err(NetFailure { message: error.trim().to_string(), os_code: error::parse_os_code(&error) })))
+            }
+            Some(Err(e)) => Ok(Some(Err(NetFailure { message: wnet::describe(&e), os_code: e.raw_os_error() }))),
+        }
+    }
+
+    /// Waits until a UPV VPN connection is active (or the wait times out).
+    fn wait_for_vpn() -> Result<()> {
+        println!("Waiting for a UPV VPN connection to be established (press Ctrl+C to cancel)...");
+
+        let start = Instant::now();
+        loop {
+            let connected = VpnManager::get_connected_upv_connections()?;
+            if let Some(name) = connected.first() {
+                println!("VPN connection '{}' is active", name);
+                return Ok(());
+            }
+
+            if start.elapsed() >= VPN_WAIT_TIMEOUT {
+                return Err(UpvError::Drive(
+                    format!("No UPV VPN connection was established within {}s", VPN_WAIT_TIMEOUT.as_secs()).into()
+                ).into());
+            }
+
+            thread::sleep(Duration::from_secs(2));
+        }
+    }
+
+    /// Looks for UPV credentials Windows can reuse when mounting without a password.
+    /// Returns a description of where they come from, or `None` if there aren't any.
+    fn find_reusable_credentials() -> Result<Option<String>> {
+        // Logged on with a UPV domain account (e.g. university computers)
+        if let Ok(user_domain) = std::env::var("USERDOMAIN")
+            && ["UPVNET", "ALUMNO"].iter().any(|domain| user_domain.eq_ignore_ascii_case(domain)) {
+            return Ok(Some(format!("Windows session in the {} domain", user_domain.to_uppercase())));
+        }
+
+        // The other checks are best effort: if a tool is missing or fails, that source is just skipped
+        let run = |program: &str, args: &[&str]| -> String {
+            Command::new(program)
+                .args(args)
+                .output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).to_lowercase())
+                .unwrap_or_default()
+        };
+
+        // Credentials saved in the Windows Credential Manager for the NAS
+        if run("cmdkey", &["/list"]).contains(NAS_SERVER) {
+            return Ok(Some("credentials saved in the Windows Credential Manager".to_string()));
+        }
+
+        // Cached Kerberos tickets from the UPV realm
+        if run("klist", &[]).contains("upv.es") {
+            return Ok(Some("cached UPV Kerberos tickets".to_string()));
+        }
+
+        // Connected to UPV Wi-Fi, whose 802.1X credentials Windows can reuse
+        let wlan = run("netsh", &["wlan", "show", "interfaces"]);
+        let ssid = wlan.lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with("ssid"))
+            .find_map(|line| line.split_once(':').map(|(_, value)| value.trim().to_string()));
+        if let Some(ssid) = ssid
+            && UPV_WIFI_SSIDS.iter().any(|upv_ssid| ssid.eq_ignore_ascii_case(upv_ssid)) {
+            return Ok(Some(format!("Wi-Fi connection to '{}'", ssid)));
+        }
+
+        // Active VPN session
+        if let Some(name) = VpnManager::get_connected_upv_connections()?.first() {
+            return Ok(Some(format!("VPN connection '{}'", name)));
+        }
+
+        Ok(None)
+    }
+
+    /// Lists what is using a drive (best effort, an empty list doesn't guarantee the drive is free).
+    /// Uses Sysinternals' `handle` to enumerate open handles if it's available, and otherwise
+    /// looks for processes running from the drive or with modules loaded from it, and Explorer windows open on it.
+    fn find_drive_users(drive: char) -> Vec<String> {
+        let root = format!("{}:\\", drive.to_ascii_uppercase());
+
+        for handle_exe in ["handle64", "handle"] {
+            let Ok(output) = Command::new(handle_exe)
+                .args(["-accepteula", "-nobanner", &root])
+                .output() else {
+                continue;
+            };
+
+            // Lines look like: notepad.exe        pid: 1234   type: File          1A4: W:\notes.txt
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            return stdout.lines()
+                .filter(|line| line.contains("pid:"))
+                .map(|line| {
+                    let process = line.split_whitespace().next().unwrap_or_default();
+                    let pid = line.split("pid:").nth(1).and_then(|rest| rest.split_whitespace().next()).unwrap_or_default();
+                    let file = line.find(&root).map(|start| &line[start..]).unwrap_or_default();
+                    format!("{} (PID {}) has {} open", process, pid, file.trim())
+                })
+                .collect();
+        }
+
+        let ps_command = format!(
+            "$d = '{}'; \
+            Get-Process | ForEach-Object {{ $p = $_; try {{ \
+                if ($p.Path -like ($d + '*')) {{ $p.ProcessName + '.exe (PID ' + $p.Id + ') is running from ' + $p.Path }} \
+                foreach ($m in $p.Modules) {{ if ($m.FileName -like ($d + '*')) {{ $p.ProcessName + '.exe (PID ' + $p.Id + ') has loaded ' + $m.FileName }} }} \
+            }} catch {{}} }}; \
+            (New-Object -ComObject Shell.Application).Windows() | ForEach-Object {{ \
+                $path = $_.Document.Folder.Self.Path; if ($path -like ($d + '*')) {{ 'Explorer window open on ' + $path }} \
+            }}",
+            root
+        );
+
+        powershell::query(&ps_command).unwrap_or_default()
+    }
+
+    /// Drive letters of the mappings Windows restores at logon, which it keeps under HKCU\Network (one key per drive).
+    fn persistent_drives() -> Vec<char> {
+        let Ok(output) = Command::new("reg").args(["query", r"HKCU\Network"]).output() else {
+            return Vec::new();
+        };
+
+        // Lines look like: HKEY_CURRENT_USER\Network\W
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.trim().rsplit_once('\\'))
+            .filter(|(key, drive)| key.to_ascii_lowercase().ends_with(r"\network") && drive.len() == 1)
+            .filter_map(|(_, drive)| drive.chars().next())
+            .map(|drive| drive.to_ascii_uppercase())
+            .collect()
+    }
+
+    fn mounts_path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(MOUNTS_FILE))
+    }
+
+    /// Reads the parameters of the last mount of each drive letter
+    fn load_mounts() -> Result<BTreeMap<char, SavedMount>> {
+        let path = Self::mounts_path()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+
+        // A corrupted file only means the parameters have to be given again
+        Ok(serde_json::from_str(&content).unwrap_or_default())
+    }
+
+    /// Saves the parameters of a successful mount, replacing the previous ones for the same drive letter
+    fn save_mount(username: &str, domain: &UPVDomain, options: &MountOptions) -> Result<()> {
+        let mut mounts = Self::load_mounts()?;
+        mounts.insert(options.drive.to_ascii_uppercase(), SavedMount {
+            username: username.to_string(),
+            domain: domain.clone(),
+            options: options.clone(),
+        });
+
+        let path = Self::mounts_path()?;
+        let content = serde_json::to_string_pretty(&mounts).context("Failed to serialize the mount parameters")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))
+    }
+
+    fn previous_mappings_path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(PREVIOUS_MAPPINGS_FILE))
+    }
+
+    /// Reads the mappings replaced by remapping each drive letter
+    fn load_previous_mappings() -> Result<BTreeMap<char, PreviousMapping>> {
+        let path = Self::previous_mappings_path()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+
+        Ok(serde_json::from_str(&content).unwrap_or_default())
+    }
+
+    fn save_previous_mappings(mappings: &BTreeMap<char, PreviousMapping>) -> Result<()> {
+        let path = Self::previous_mappings_path()?;
+        let content = serde_json::to_string_pretty(mappings).context("Failed to serialize the previous mappings")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))
+    }
+
+    /// Whether two share paths point at the same share. WebDAV URLs are compared in the UNC form 'net use' lists them in
+    /// (https://host/path is \\host@SSL\path)
+    fn same_share(a: &str, b: &str) -> bool {
+        let normalize = |path: &str| {
+            let path = match path.strip_prefix("https://") {
+                Some(rest) => {
+                    let (host, rest) = rest.split_once('/').unwrap_or((rest, ""));
+                    format!("\\\\{}@SSL\\{}", host, rest.replace('/', "\\"))
+                }
+                None => path.to_string(),
+            };
+            path.trim_end_matches('\\').to_lowercase()
+        };
+        normalize(a) == normalize(b)
+    }
+
+    /// Makes room to mount a share to a drive letter mapped to another one, unmounting it (if `remap` is set, or the user
+    /// agrees to it in a terminal) and recording the share it pointed at, so it can be mapped back later.
+    fn replace_mapping(drive: char, server_path: &str, remap: bool, refresh_explorer: bool) -> Result<()> {
+        let local = format!("{}:", drive);
+        let Some(existing) = Self::get_mappings()?.into_iter()
+            .find(|entry| entry.local.as_deref().is_some_and(|letter| letter.eq_ignore_ascii_case(&local))) else {
+            return Ok(());
+        };
+        if Self::same_share(&existing.remote, server_path) {
+            return Ok(());
+        }
+
+        let mut confirmed = remap;
+        if !confirmed && io::stdin().is_terminal() && !dry_run::enabled() {
+            print!("Drive {} is already mapped to {}. Replace it? (y/N): ", local, existing.remote);
+            io::stdout().flush().context("Failed to flush stdout")?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).context("Failed to read user input")?;
+
+            let answer = input.trim().to_lowercase();
+            confirmed = answer == "y" || answer == "yes";
+        }
+        if !confirmed {
+            return Err(UpvError::Drive(
+                format!("Drive {} is already mapped to {}", local, existing.remote).into()
+            ).with_hint("Replace it with --remap (and put it back later with 'upv drive unmount --restore-previous'), or pick another letter with --drive").into());
+        }
+
+        let persistent = Self::persistent_drives().contains(&drive);
+        Self::unmount(drive, false, refresh_explorer)?;
+
+        if dry_run::enabled() {
+            return Ok(());
+        }
+
+        let mut previous = Self::load_previous_mappings()?;
+        previous.insert(drive, PreviousMapping { remote: existing.remote, persistent });
+        Self::save_previous_mappings(&previous)
+    }
+
+    /// Reads the total and free space of a drive, as reported to the current user (so quotas are taken into account)
+    fn space(drive: char) -> Result<DriveSpace> {
+        use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let path = wide::to_wide(&format!("{}:\\", drive));
+        let (mut free, mut total, mut total_free) = (0u64, 0u64, 0u64);
+
+        // SAFETY: the path is a valid null-terminated UTF-16 string, and the outputs outlive the call
+        if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut free, &mut total, &mut total_free) } == 0 {
+            let e = io::Error::last_os_error();
+            return Err(UpvError::Drive(
+                format!("Failed to read the space of drive {}: {}", drive, e).into()
+            ).with_hint("Make sure the drive is mounted and reachable ('upv drive status')").with_os_code(e.raw_os_error()).into());
+        }
+
+        let used = total.saturating_sub(free);
+        Ok(DriveSpace {
+            drive: format!("{}:", drive),
+            total_bytes: total,
+            free_bytes: free,
+            used_bytes: used,
+            used_percent: if total == 0 { 0.0 } else { used as f64 * 100.0 / total as f64 },
+        })
+    }
+
+    /// Formats a size in bytes with binary units (e.g. "1.5 GB")
+    fn format_size(bytes: u64) -> String {
+        const UNITS: [&str; 5] = ["bytes", "KB", "MB", "GB", "TB"];
+        let mut size = bytes as f64;
+        let mut unit = 0;
+        while size >= 1024.0 && unit < UNITS.len() - 1 {
+            size /= 1024.0;
+            unit += 1;
+        }
+
+        if unit == 0 { format!("{} {}", bytes, UNITS[0]) } else { format!("{:.1} {}", size, UNITS[unit]) }
+    }
+
+    /// Notifies the shell that a drive was added or removed, so open Explorer windows update their drive list.
+    fn notify_shell(drive: char, added: bool) {
+        use windows_sys::Win32::UI::Shell::{SHChangeNotify, SHCNE_DRIVEADD, SHCNE_DRIVEREMOVED, SHCNF_FLUSH, SHCNF_PATHW};
+
+        let path = wide::to_wide(&format!("{}:\\", drive));
+        let event = if added { SHCNE_DRIVEADD } else { SHCNE_DRIVEREMOVED };
+
+        // SAFETY: the path is a valid null-terminated UTF-16 string that outlives the (synchronous, flushed) call
+        unsafe {
+            SHChangeNotify(event as i32, SHCNF_PATHW | SHCNF_FLUSH, path.as_ptr().cast(), std::ptr::null());
+        }
+    }
+
+    /// Picks the first of the given drive letters that isn't used by a disk or a network drive (even a disconnected one).
+    pub fn first_free_drive(candidates: &[char]) -> Result<char> {
+        let mapped: Vec<char> = Self::get_mappings()?
+            .iter()
+            .filter_map(|entry| entry.local.as_deref().and_then(cli::parse_drive_letter))
+            .collect();
+        let in_use = |letter: &char| mapped.contains(letter) || Path::new(&format!("{}:\\", letter)).exists();
+
+        let letter = candidates.iter().copied().find(|letter| !in_use(letter)).ok_or_else(|| UpvError::Drive(
+            format!("None of the drive letters tried is free ({})", candidates.iter().map(char::to_string).collect::<Vec<_>>().join(", ")).into()
+        ).with_hint("Free one of them, or set other ones with 'upv config set drive_fallbacks <LETTERS>'"))?;
+
+        if candidates.first() != Some(&letter) {
+            println!("Drive {}: is already in use, using {}: instead", candidates[0], letter);
+        }
+
+        Ok(letter)
+    }
+
+    /// Wraps a status in an ANSI color (green for OK, red for anything else)
+    pub fn colorize_status(status: &str) -> String {
+        if status.is_empty() {
+            "\x1b[2m(none)\x1b[0m".to_string()
+        } else if status.eq_ignore_ascii_case("OK") {
+            format!("\x1b[32m{}\x1b[0m", status)
+        } else {
+            format!("\x1b[1;31m{}\x1b[0m", status)
+        }
+    }
+
+    // Public methods for drive management
+
+    /// Mounts the UPV Personal Network Drive (Disco W) to a specified drive letter
+    /// If a timeout is set, the mount is cancelled when the server doesn't answer within that many seconds,
+    /// and retried once when a UPV VPN connection becomes active if `wait_for_vpn` is set.
+    pub fn mount(username: &str, domain: &UPVDomain, options: &MountOptions) -> Result<()> {
+        let drive = options.drive;
+
+        // Without a password, use the one stored with 'upv creds set' if it belongs to this user
+        let stored_password = match &options.password {
+            Some(_) => None,
+            None => CredsManager::load()?
+                .filter(|credentials| credentials.username.eq_ignore_ascii_case(username))
+                .map(|credentials| credentials.password),
+        };
+        if stored_password.is_some() {
+            println!("Using the password stored for {} in the Windows Credential Manager", username);
+        }
+        let password = options.password.as_ref().or(stored_password.as_ref());
+
+        let template = options.share_template.as_deref().unwrap_or(domain.default_share_template());
+        let mut server_path = Self::expand_share_template(template, username, NAS_SERVER)?;
+
+        // When the NAS can't be reached through SMB, the alternate NAS hosts are tried in order (if the share is on the NAS).
+        // Off campus without the VPN the SMB port is blocked everywhere, so the VPN is connected (if asked to), or the drive
+        // is mounted through HTTPS, where it is still published. Otherwise, net use would only fail with a network error
+        let webdav = match options.webdav {
+            WebdavMode::Always => true,
+            _ if Self::smb_reachable(&server_path) => false,
+            mode => {
+                let alternates = if template.contains("{server}") { options.alternate_servers.as_slice() } else { &[] };
+
+                if let Some((server, path)) = Self::find_reachable_alternate(template, username, alternates)? {
+                    println!("{} can't be reached through SMB, using the alternate NAS host {}", NAS_SERVER, server);
+                    server_path = path;
+                    false
+                } else if options.wait_for_vpn {
+                    false
+                } else if let Some(vpn) = &options.auto_vpn {
+                    println!("The server can't be reached through SMB (port {}), connecting to the VPN ('{}') first...", SMB_PORT, vpn);
+                    VpnManager::connect(vpn, false, None, None, options.override_lockout, Some(AUTO_VPN_TIMEOUT), &[])?;
+                    false
+                } else if mode == WebdavMode::Fallback {
+                    println!("The server can't be reached through SMB (port {}), mounting through WebDAV instead", SMB_PORT);
+                    true
+                } else {
+                    return Err(Self::server_unreachable_error(&server_path).into());
+                }
+            }
+        };
+
+        // Windows doesn't reuse UPV credentials for WebDAV, so the password is needed
+        if webdav && password.is_none() {
+            if io::stdin().is_terminal() {
+                return Self::mount_with_prompted_password(username, domain, &MountOptions { webdav: WebdavMode::Always, ..options.clone() });
+            }
+            return Err(UpvError::Drive(
+                "A password is needed to mount through WebDAV".into()
+            ).with_hint("Pass your password with --password, or save it with 'upv creds set'").into());
+        }
+
+        // Without a password, net use can only succeed by reusing existing credentials, so ask for it if there are none
+        // (or fail early, when nobody can answer)
+        if password.is_none() && options.check_credentials && !options.wait_for_vpn {
+            match Self::find_reusable_credentials()? {
+                Some(source) => println!("Reusing UPV credentials from: {}", source),
+                None if io::stdin().is_terminal() => {
+                    println!("There are no UPV credentials to reuse (no UPV VPN or Wi-Fi connection, Kerberos tickets or saved NAS credentials)");
+                    return Self::mount_with_prompted_password(username, domain, options);
+                }
+                None => return Err(UpvError::Drive(
+                    "No password was given, and there are no UPV credentials to reuse: no active UPV VPN connection, no UPV Wi-Fi connection, no cached UPV Kerberos tickets and no saved credentials for the NAS".into()
+                ).with_hint("Connect to the UPV VPN first ('upv vpn connect <NAME>'), pass your password with --password (or save it with 'upv creds set'), or use --skip-credential-check if you know your credentials are available").into()),
+            }
+        }
+
+        let label = options.label.as_deref().unwrap_or("Disco W");
+        println!("Mounting {} to drive {}:...", label, drive);
+
+        let server_path = if webdav {
+            Self::check_web_client()?;
+            let template = options.webdav_template.as_deref().unwrap_or(domain.default_webdav_template());
+            Self::expand_webdav_template(template, username)?
+        } else {
+            server_path
+        };
+
+        Self::replace_mapping(drive, &server_path, options.remap, options.refresh_explorer)?;
+
+        // The equivalent `net use` command is what dry runs print, and what provider errors are retried with
+        let user = password.map(|_| format!("{}\\{}", domain, username));
         let mut cmd = Command::new("net");
         cmd.arg("use")
            .arg(format!("{}:", drive))
-           .arg(&server_path);
+           .arg(&server_path)
+           .arg(format!("/persistent:{}", if options.persistent { "yes" } else { "no" }));
         
         // Only add /USER if password is provided
-        if let Some(pwd) = password {
-            cmd.arg(format!("/user:{}\\{}", domain, username))
+        if let (Some(user), Some(pwd)) = (&user, password) {
+            cmd.arg(format!("/user:{}", user))
                .arg(pwd);
         }
         
-        let output = cmd.output()
-            .context("Failed to execute net use command")?;
-        
-        if output.status.success() {
-            println!("Disco W mounted successfully to drive {}:", drive);
-            
-            // Open in Explorer if requested
-            if open_explorer {
+        if password.is_some() {
+            LockoutGuard::check(username, options.override_lockout, UpvError::Drive)?;
+        }
+
+        if dry_run::enabled() {
+            dry_run::print_command(&cmd, &[password.map(String::as_str).unwrap_or_default()]);
+            if options.open_explorer {
                 Self::open_drive(drive, false)?;
             }
+            return Ok(());
+        }
+
+        let timeout = (options.timeout > 0).then(|| Duration::from_secs(options.timeout));
+        let credentials = (user.as_deref(), password.map(String::as_str));
+
+        let first = Self::start_mapping(drive, &server_path, credentials, options.persistent);
+        let result = match Self::wait_for_mapping(&first, &mut cmd, timeout)? {
+            Some(result) => result,
+            None if options.wait_for_vpn => {
+                println!("Server unreachable within {}s, retrying once the VPN is connected...", timeout.unwrap_or_default().as_secs());
+                Self::wait_for_vpn()?;
+
+                // The first attempt can't be cancelled, and another one on the same letter would race with it,
+                // so it is waited for (it may go through now that the VPN is up) before trying again
+                match Self::wait_for_mapping(&first, &mut cmd, timeout)? {
+                    Some(Ok(())) => Ok(()),
+                    Some(Err(_)) => {
+                        println!("Retrying mount of drive {}:...", drive);
+                        let retry = Self::start_mapping(drive, &server_path, credentials, options.persistent);
+                        Self::wait_for_mapping(&retry, &mut cmd, timeout)?
+                            .ok_or_else(|| Self::unreachable_error(timeout.unwrap_or_default()))?
+                    }
+                    None => return Err(Self::unreachable_error(timeout.unwrap_or_default()).into()),
+                }
+            }
+            None => return Err(Self::unreachable_error(timeout.unwrap_or_default()).into()),
+        };
+        
+        match result {
+            Ok(()) => {
+                println!("{} mounted successfully to drive {}:", label, drive);
+
+                if !webdav && let Some(target) = Self::dfs_target(&server_path) {
+                    println!("{} is a DFS path, served from {}", server_path, target);
+                }
+
+                if options.refresh_explorer {
+                    Self::notify_shell(drive, true);
+                }
+
+                if password.is_some() {
+                    LockoutGuard::record_success(username)?;
+                }
+
+                Self::save_mount(username, domain, options)?;
+                
+                // Open in Explorer if requested
+                if options.open_explorer {
+                    Self::open_mounted_drive(drive, &server_path)?;
+                }
+            }
+            Err(failure) => {
+                // System errors 86 and 1326 mean the username or password is incorrect
+                let os_code = failure.os_code;
+                if password.is_some() && matches!(os_code, Some(86 | 1326)) {
+                    LockoutGuard::record_failure(username)?;
+                }
+
+                // The reused credentials weren't accepted, so the password is asked for instead (once)
+                if password.is_none() && matches!(os_code, Some(86 | 1326)) && io::stdin().is_terminal() {
+                    println!("The server didn't accept the reused UPV credentials");
+                    return Self::mount_with_prompted_password(username, domain, options);
+                }
+
+                // System errors 53 and 1231 mean the server can't be reached at all
+                let cause = match os_code {
+                    Some(86 | 1326) => UpvError::DriveAuthFailed,
+                    Some(53 | 1231) => UpvError::DriveUnreachable,
+                    _ => UpvError::Drive,
+                };
+
+                // System error 67 means the server answered, but there is no such share on it
+                let hint = if os_code == Some(67) {
+                    format!("The server has no share at {}. Check the username, or the share template if you set one", server_path)
+                } else if webdav {
+                    "Make sure the username, domain and password are correct, and that the WebClient service can be started".to_string()
+                } else {
+                    "Make sure you are connected to the UPV network (or the UPV VPN) and that the username and domain are correct".to_string()
+                };
+
+                return Err(cause(
+                    format!("Failed to mount drive {}: {}", drive, failure.message).into()
+                ).with_hint(hint)
+                    .with_os_code(os_code)
+                    .with_command(&cmd, &[password.map(String::as_str).unwrap_or_default()])
+                    .into());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Finds the mounts whose letter can't be used: the ones given a letter that an earlier mount already has, and the
+    /// ones whose letter is a local disk or is mapped to a share outside UPV (unless `remap` replaces it). Letters mapped
+    /// to UPV shares aren't conflicts, since mounting over them is either a no-op or a remount.
+    /// Each conflict comes with the index of the mount that would have to move.
+    fn letter_conflicts(mounts: &[(String, String, UPVDomain, MountOptions)], mappings: &[NetUseEntry]) -> Vec<(usize, String)> {
+        let mut conflicts = Vec::new();
+
+        for (index, (name, _, _, options)) in mounts.iter().enumerate() {
+            let local = format!("{}:", options.drive);
+            if let Some((first, ..)) = mounts[..index].iter().find(|(_, _, _, earlier)| earlier.drive.eq_ignore_ascii_case(&options.drive)) {
+                conflicts.push((index, format!("{} is given to both {} and {}", local, first, name)));
+                continue;
+            }
+
+            match mappings.iter().find(|entry| entry.local.as_deref().is_some_and(|mapped| mapped.eq_ignore_ascii_case(&local))) {
+                Some(entry) if !entry.is_upv() && !options.remap => {
+                    conflicts.push((index, format!("{} is mapped to {} (needed by {})", local, entry.remote, name)));
+                }
+                None if Path::new(&format!("{}\\", local)).exists() => {
+                    conflicts.push((index, format!("{} is a local disk (needed by {})", local, name)));
+                }
+                _ => {}
+            }
+        }
+
+        conflicts
+    }
+
+    /// Moves the mounts in conflict to the first of the candidate letters that no disk, mapping or other mount uses
+    fn reassign_letters(mounts: &mut [(String, String, UPVDomain, MountOptions)], conflicts: &[(usize, String)], mappings: &[NetUseEntry], candidates: &[char]) -> Result<()> {
+        let mut taken: Vec<char> = mappings.iter()
+            .filter_map(|entry| entry.local.as_deref().and_then(cli::parse_drive_letter))
+            .collect();
+        taken.extend(mounts.iter().enumerate()
+            .filter(|(index, _)| !conflicts.iter().any(|(moved, _)| moved == index))
+            .map(|(_, (_, _, _, options))| options.drive.to_ascii_uppercase()));
+
+        for &(index, _) in conflicts {
+            let (name, _, _, options) = &mut mounts[index];
+            let letter = candidates.iter().copied()
+                .find(|letter| !taken.contains(letter) && !Path::new(&format!("{}:\\", letter)).exists())
+                .ok_or_else(|| UpvError::Drive(
+                    format!("There is no free drive letter left for {} ({} tried)", name, candidates.iter().map(char::to_string).collect::<Vec<_>>().join(", ")).into()
+                ).with_hint("Free some of them, or set other ones with 'upv config set drive_fallbacks <LETTERS>'"))?;
+
+            println!("Drive {}: can't be used for {}, using {}: instead", options.drive, name, letter);
+            options.drive = letter;
+            taken.push(letter);
+        }
+
+        Ok(())
+    }
+
+    /// Mounts several drives one after the other (e.g. every drive profile), going on when one fails.
+    /// Each mount is given with the name shown for it, and fails with the exit code of the first one that failed.
+    /// The letters are checked first, so conflicts between them (or with other mappings) don't stop it halfway:
+    /// with `auto`, the mounts in conflict move to the first free candidate letters; otherwise, the user is asked to
+    /// move them in a terminal, and nothing is mounted if they aren't.
+    /// If Ctrl+C is pressed, it stops and unmounts the drives it already mounted, telling which are left.
+    pub fn mount_many(mut mounts: Vec<(String, String, UPVDomain, MountOptions)>, candidates: &[char], auto: bool) -> Result<()> {
+        if mounts.is_empty() {
+            println!("No drives to mount.");
+            return Ok(());
+        }
+
+        let mappings = Self::get_mappings()?;
+        let conflicts = Self::letter_conflicts(&mounts, &mappings);
+        if !conflicts.is_empty() {
+            let mut confirmed = auto;
+            if !confirmed {
+                for (_, conflict) in &conflicts {
+                    eprintln!("  ✗ {}", conflict);
+                }
+            }
+            if !confirmed && io::stdin().is_terminal() && !dry_run::enabled() {
+                print!("Mount them to free drive letters instead? (y/N): ");
+                io::stdout().flush().context("Failed to flush stdout")?;
+
+                let mut input = String::new();
+                io::stdin().read_line(&mut input).context("Failed to read user input")?;
+
+                let answer = input.trim().to_lowercase();
+                confirmed = answer == "y" || answer == "yes";
+            }
+            if !confirmed {
+                return Err(UpvError::Drive(
+                    format!("{} drive letter conflict(s) found, so no drives were mounted", conflicts.len()).into()
+                ).with_hint("Use --drive auto to move them to free letters, give the profiles different letters ('upv config edit'), or use --remap to replace other mappings").into());
+            }
+            Self::reassign_letters(&mut mounts, &conflicts, &mappings, candidates)?;
+        }
+
+        let _interrupt = interrupt::catch();
+        let mut failed: Vec<(&str, Cause)> = Vec::new();
+        let mut mounted = Vec::new();
+        for (name, username, domain, options) in &mounts {
+            let result = interrupt::check().and_then(|()| Self::mount(username, domain, options));
+            if interrupt::requested() {
+                Self::undo_mounts(&mounted);
+                return Err(UpvError::Interrupted(
+                    format!("Interrupted by Ctrl+C after mounting {} of {} drives, which were unmounted", mounted.len(), mounts.len()).into()
+                ).into());
+            }
+            if let Err(e) = result {
+                eprintln!("  ✗ {}", e);
+                let cause = e.downcast_ref::<UpvError>()
+                    .map(UpvError::cause)
+                    .unwrap_or(UpvError::Drive);
+                failed.push((name, cause));
+            } else {
+                mounted.push(options.drive);
+            }
+        }
+
+        if let Some(&(_, cause)) = failed.first() {
+            let names: Vec<&str> = failed.iter().map(|(name, _)| *name).collect();
+            return Err(cause(
+                format!("{} of {} drives failed to mount ({})", failed.len(), mounts.len(), names.join(", ")).into()
+            ).into());
+        }
+
+        Ok(())
+    }
+
+    /// Unmounts the drives mounted by a command that was interrupted, telling which are still mounted
+    fn undo_mounts(drives: &[char]) {
+        eprintln!("Undoing the mounts...");
+        if drives.is_empty() {
+            eprintln!("  ✓ No drives had been mounted, so nothing was left behind");
+        }
+        for &drive in drives {
+            match Self::unmount(drive, true, true) {
+                Ok(()) => eprintln!("  ✓ Unmounted {}:", drive),
+                Err(e) => eprintln!("  ✗ {}: is still mounted, since unmounting it failed: {}", drive, e),
+            }
+        }
+    }
+
+    /// Mounts again after asking for the password in the terminal, so it doesn't have to be given on the command line
+    /// (where it would end up in the shell history).
+    fn mount_with_prompted_password(username: &str, domain: &UPVDomain, options: &MountOptions) -> Result<()> {
+        let password = CredsManager::prompt_password(username, domain)?;
+        Self::mount(username, domain, &MountOptions { password: Some(password), ..options.clone() })
+    }
+
+    /// Opens a drive right after mounting it. Right after it is mapped, the drive may not be browsable yet
+    /// (which opens an empty or failing Explorer window), so it is retried briefly before opening the share path instead.
+    fn open_mounted_drive(drive: char, server_path: &str) -> Result<()> {
+        let path = format!("{}:\\", drive);
+
+        for _ in 0..OPEN_RETRIES {
+            if fs::read_dir(&path).is_ok() {
+                return Self::open_drive(drive, false);
+            }
+            thread::sleep(OPEN_RETRY_INTERVAL);
+        }
+
+        println!("Drive {}: is not browsable yet, opening {} in Explorer instead...", drive, server_path);
+        job::detached(Command::new("explorer.exe").arg(server_path))
+            .spawn()
+            .context("Failed to launch Explorer")?;
+
+        Ok(())
+    }
+
+    /// Error for a server that doesn't answer through SMB, telling whether it is because the VPN is down
+    fn server_unreachable_error(server_path: &str) -> UpvError {
+        let server = server_path.trim_start_matches('\\').split('\\').next().unwrap_or_default();
+        if VpnManager::get_connected_upv_connections().unwrap_or_default().is_empty() {
+            UpvError::DriveUnreachable(
+                format!("{} can't be reached, and the UPV VPN is not connected", server).into()
+            ).with_hint("Off campus, UPV servers only answer through the VPN. Connect it with 'upv vpn connect', or mount with --auto-vpn to connect it first")
         } else {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to mount drive {}: {}", drive, error),
-                EXIT_UPV_DRIVE_ERROR
-            ).into());
+            UpvError::DriveUnreachable(
+                format!("{} can't be reached through SMB (port {}), even though the UPV VPN is connected", server, SMB_PORT).into()
+            ).with_hint("Check whether the VPN works with 'upv vpn ping', or reconnect it")
         }
-        
-        Ok(())
+    }
+
+    /// Error for a mount that didn't finish within the timeout
+    fn unreachable_error(timeout: Duration) -> UpvError {
+        UpvError::DriveUnreachable(
+            format!("Server unreachable within {}s — are you connected to the VPN?", timeout.as_secs()).into()
+        ).with_hint("Connect to the UPV VPN with 'upv vpn connect <NAME>', or use --wait-for-vpn to retry automatically once it is connected")
     }
     
     /// Opens the specified drive in Windows Explorer
@@ -74,23 +1277,63 @@ impl DriveManager {
         let path = format!("{}:\\", drive);
 
         if check_if_exists && !Path::new(&path).exists() {
-            return Err(UpvError::new(
-                format!("Drive {} does not exist", drive),
-                EXIT_UPV_DRIVE_ERROR
+            return Err(UpvError::Drive(
+                format!("Drive {} does not exist", drive).into()
             ).into());
         }
 
+        let mut command = Command::new("explorer.exe");
+        command.arg(&path);
+
+        if dry_run::enabled() {
+            dry_run::print_command(&command, &[]);
+            return Ok(());
+        }
+
         println!("Opening drive {}: in Explorer...", drive);
-        Command::new("explorer.exe")
-            .arg(&path)
+        job::detached(&mut command)
             .spawn()
             .context("Failed to launch Explorer")?;
 
         Ok(())
     }
     
-    /// Unmounts the network drive
-    pub fn unmount(drive: char, force: bool) -> Result<()> {
+    /// Opens a folder of a mounted drive in Windows Explorer, given relative to the drive's root (with \\ or /).
+    pub fn open_folder(drive: char, folder: &str) -> Result<()> {
+        let root = format!("{}:\\", drive);
+        if !Path::new(&root).exists() {
+            return Err(UpvError::Drive(
+                format!("Drive {} does not exist", drive).into()
+            ).with_hint("Mount it with 'upv drive mount' first").into());
+        }
+
+        let relative = folder.replace('/', "\\");
+        let path = Path::new(&root).join(relative.trim_start_matches('\\'));
+        if !path.is_dir() {
+            return Err(UpvError::Drive(
+                format!("There is no folder '{}' in drive {}:", relative, drive).into()
+            ).with_hint(format!("Check the path (it is relative to {}), or open the whole drive with 'upv drive open'", root)).into());
+        }
+
+        let mut command = Command::new("explorer.exe");
+        command.arg(&path);
+
+        if dry_run::enabled() {
+            dry_run::print_command(&command, &[]);
+            return Ok(());
+        }
+
+        println!("Opening {} in Explorer...", path.display());
+        job::detached(&mut command)
+            .spawn()
+            .context("Failed to launch Explorer")?;
+
+        Ok(())
+    }
+
+    /// Unmounts the network drive.
+    /// If it is in use (and not forced), the user is asked in a terminal whether to retry, force it or cancel.
+    pub fn unmount(drive: char, force: bool, refresh_explorer: bool) -> Result<()> {
         println!("Unmounting drive {}:...", drive);
         
         let mut cmd = Command::new("net");
@@ -102,46 +1345,878 @@ impl DriveManager {
         if force {
             cmd.arg("/y");
         }
+
+        if dry_run::enabled() {
+            dry_run::print_command(&cmd, &[]);
+            return Ok(());
+        }
         
-        let output = cmd.output()
-            .context("Failed to execute net use delete command")?;
+        let result = match wnet::cancel_connection(drive, force) {
+            Err(e) if e.raw_os_error().is_some_and(|code| wnet::PROVIDER_ERRORS.contains(&(code as u32))) => {
+                let output = cmd.output()
+                    .context("Failed to execute net use delete command")?;
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let error = String::from_utf8_lossy(&output.stderr);
+
+                if output.status.success() {
+                    Ok(())
+                } else if stdout.contains("/N") {
+                    // If stdout contains "/N" it's part of "(Y/N)". This confirmation shows when it's trying to unmount a drive that is in use
+                    // (files are open, the folder is open, etc.)
+                    Err(NetFailure { message: error.trim().to_string(), os_code: Some(ERROR_OPEN_FILES) })
+                } else {
+                    Err(NetFailure { message: error.trim().to_string(), os_code: error::parse_os_code(&error) })
+                }
+            }
+            result => result.map_err(|e| NetFailure { message: wnet::describe(&e), os_code: e.raw_os_error() }),
+        };
         
-        if output.status.success() {
-            println!("Drive {}: unmounted successfully", drive);
-        } else {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            
-            // If stdout contains "/N" it's part of "(Y/N)". This confirmation shows when it's trying to unmount a drive that is in use
-            // (files are open, the folder is open, etc.)
-            if stdout.contains("/N") {
-                return Err(UpvError::new(
-                    format!("Drive {}: is currently IN USE. Please CLOSE any open files or folders on this drive and try again, or run this again with the --force option to unmount it anyways, accepting that INFORMATION COULD BE LOST.", drive),
-                    EXIT_UPV_DRIVE_IN_USE_ERROR
-                ).into());
+        match result {
+            Ok(()) => {
+                println!("Drive {}: unmounted successfully", drive);
+
+                if refresh_explorer {
+                    Self::notify_shell(drive, false);
+                }
             }
+            // Files or folders on the drive are open
+            Err(NetFailure { os_code: Some(ERROR_OPEN_FILES | ERROR_DEVICE_IN_USE), .. }) => {
+                Self::print_drive_users(drive, &Self::find_drive_users(drive));
 
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to unmount drive {}: {}", drive, error),
-                EXIT_UPV_DRIVE_ERROR
-            ).into());
+                // In a terminal, the user can deal with it right away instead of running the command again
+                if io::stdin().is_terminal() {
+                    match Self::ask_in_use_action(drive)? {
+                        InUseAction::Retry => return Self::unmount(drive, false, refresh_explorer),
+                        InUseAction::Force => return Self::unmount(drive, true, refresh_explorer),
+                        InUseAction::Cancel => {}
+                    }
+                }
+
+                return Err(UpvError::DriveInUse(
+                    format!("Drive {}: is currently IN USE.", drive).into()
+                ).with_hint("Please CLOSE any open files or folders on this drive and try again, or run this again with the --force option to unmount it anyways, accepting that INFORMATION COULD BE LOST.").into());
+            }
+            Err(failure) => {
+                return Err(UpvError::Drive(
+                    format!("Failed to unmount drive {}: {}", drive, failure.message).into()
+                ).with_os_code(failure.os_code).with_command(&cmd, &[]).into());
+            }
         }
         
         Ok(())
     }
     
-    /// Checks the status of the network drive by listing all network drives
-    pub fn status() -> Result<()> {
-        println!("Checking network drive status...");
-        
-        let output = Command::new("net")
-            .arg("use")
-            .output()
-            .context("Failed to check drive status")?;
-        
-        let status = String::from_utf8_lossy(&output.stdout);
-        println!("{}", status);
-        
+    /// Unmounts a drive and maps back the share it pointed at before 'upv drive mount --remap' replaced it,
+    /// with the credentials Windows has for it (and persistent again if it was).
+    pub fn restore_previous(drive: char, force: bool, refresh_explorer: bool) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+        let mut mappings = Self::load_previous_mappings()?;
+        let Some(previous) = mappings.remove(&drive) else {
+            return Err(UpvError::Drive(
+                format!("There is no previous mapping recorded for drive {}:", drive).into()
+            ).with_hint("Only mappings replaced by 'upv drive mount --remap' are recorded").into());
+        };
+
+        Self::unmount(drive, force, refresh_explorer)?;
+
+        let mut cmd = Command::new("net");
+        cmd.arg("use")
+           .arg(format!("{}:", drive))
+           .arg(&previous.remote)
+           .arg(format!("/persistent:{}", if previous.persistent { "yes" } else { "no" }));
+
+        if dry_run::enabled() {
+            dry_run::print_command(&cmd, &[]);
+            return Ok(());
+        }
+
+        println!("Mapping {} back to drive {}:...", previous.remote, drive);
+        let mapping = Self::start_mapping(drive, &previous.remote, (None, None), previous.persistent);
+        let result = Self::wait_for_mapping(&mapping, &mut cmd, None)?;
+
+        if let Some(Err(failure)) = result {
+            return Err(UpvError::Drive(
+                format!("Failed to map {} back to drive {}: {}", previous.remote, drive, failure.message).into()
+            ).with_os_code(failure.os_code).with_command(&cmd, &[]).into());
+        }
+
+        println!("{} mapped back to drive {}:", previous.remote, drive);
+        if refresh_explorer {
+            Self::notify_shell(drive, true);
+        }
+
+        Self::save_previous_mappings(&mappings)
+    }
+
+    /// Unmounts a drive (if it is mounted) and mounts it again with the parameters of its last mount,
+    /// for mappings left stale after sleeping or reconnecting the VPN.
+    pub fn remount(drive: char, force: bool, open_explorer: bool, refresh_explorer: bool) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+        let saved = Self::load_mounts()?.remove(&drive).ok_or_else(|| UpvError::Drive(
+            format!("Drive {}: hasn't been mounted with upv-cli before, so there is nothing to remount", drive).into()
+        ).with_hint("Mount it with 'upv drive mount' (or 'upv drive mount-share') first"))?;
+
+        let local = format!("{}:", drive);
+        if Self::get_mappings()?.iter().any(|entry| entry.local.as_deref().is_some_and(|mapped| mapped.eq_ignore_ascii_case(&local))) {
+            Self::unmount(drive, force, refresh_explorer)?;
+        } else {
+            println!("Drive {}: is not mounted, mounting it again", drive);
+        }
+
+        let options = MountOptions { open_explorer, refresh_explorer, override_lockout: false, ..saved.options };
+        Self::mount(&saved.username, &saved.domain, &options)
+    }
+
+    /// Asks what to do about a drive that couldn't be unmounted because it is in use.
+    fn ask_in_use_action(drive: char) -> Result<InUseAction> {
+        print!("Close what is using drive {}: and [r]etry, [f]orce the unmount (unsaved changes could be lost), or [c]ancel? (r/f/C): ", drive);
+        io::stdout().flush().context("Failed to flush stdout")?;
+
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).context("Failed to read user input")?;
+
+        Ok(match input.trim().to_lowercase().as_str() {
+            "r" | "retry" => InUseAction::Retry,
+            "f" | "force" => InUseAction::Force,
+            _ => InUseAction::Cancel,
+        })
+    }
+
+    /// Prints what is using a drive, if anything could be found.
+    fn print_drive_users(drive: char, users: &[String]) {
+        if !users.is_empty() {
+            eprintln!("Drive {}: is being used by:", drive);
+            for user in users {
+                eprintln!("  - {}", user);
+            }
+        }
+    }
+
+    /// Unmounts a drive only after checking that no application is using it,
+    /// listing the applications that are so the user knows which ones to close.
+    pub fn eject_safe(drive: char, refresh_explorer: bool) -> Result<()> {
+        println!("Checking whether drive {}: is in use...", drive);
+
+        let users = Self::find_drive_users(drive);
+        if !users.is_empty() {
+            Self::print_drive_users(drive, &users);
+            return Err(UpvError::DriveInUse(
+                format!("Drive {}: is in use by {} application(s), so it was not unmounted.", drive, users.len()).into()
+            ).with_hint("Close the applications listed above and try again").into());
+        }
+
+        // Nothing was found, but let Windows have the last word (without forcing it)
+        Self::unmount(drive, false, refresh_explorer)
+    }
+    
+    /// Unmounts several drives at once (e.g. letters read from stdin), reporting the ones that failed.
+    pub fn unmount_many(drives: &[char], force: bool, refresh_explorer: bool) -> Result<()> {
+        if drives.is_empty() {
+            println!("No drive letters were given.");
+            return Ok(());
+        }
+
+        let mut failed: Vec<(char, Cause)> = Vec::new();
+        for &drive in drives {
+            if let Err(e) = Self::unmount(drive, force, refresh_explorer) {
+                eprintln!("  ✗ {}", e);
+                let cause = e.downcast_ref::<UpvError>()
+                    .map(UpvError::cause)
+                    .unwrap_or(UpvError::Drive);
+                failed.push((drive, cause));
+            }
+        }
+
+        if let Some(&(_, cause)) = failed.first() {
+            let letters: Vec<String> = failed.iter().map(|(drive, _)| format!("{}:", drive)).collect();
+            return Err(cause(
+                format!("{} of {} drives failed to unmount ({})", failed.len(), drives.len(), letters.join(", ")).into()
+            ).into());
+        }
+
+        Ok(())
+    }
+
+    /// Unmounts every drive mapped to the UPV server (e.g. before disconnecting the VPN, which would leave them broken).
+    pub fn unmount_upv_drives(force: bool, refresh_explorer: bool) -> Result<()> {
+        let drives: Vec<char> = Self::get_mappings()?
+            .into_iter()
+            .filter(|entry| entry.remote.to_lowercase().contains(NAS_SERVER))
+            .filter_map(|entry| entry.local.as_deref().and_then(cli::parse_drive_letter))
+            .collect();
+
+        if drives.is_empty() {
+            println!("No UPV network drives to unmount");
+            return Ok(());
+        }
+
+        Self::unmount_many(&drives, force, refresh_explorer)
+    }
+
+    /// Prints how much of a drive's space (the user's quota, on UPV shares) is used, as a bar and in bytes
+    /// (as JSON if requested), warning when it is almost full.
+    pub fn quota(drive: char, json: bool) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+        let space = Self::space(drive)?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&space).context("Failed to serialize the drive space")?);
+            return Ok(());
+        }
+
+        let almost_full = space.used_percent >= QUOTA_WARNING_PERCENT;
+        let filled = ((space.used_percent / 100.0 * QUOTA_BAR_WIDTH as f64).round() as usize).min(QUOTA_BAR_WIDTH);
+        let color = if almost_full { "\x1b[31m" } else if space.used_percent >= 75.0 { "\x1b[33m" } else { "\x1b[32m" };
+
+        println!("Drive {}:", drive);
+        println!("  [{}{}\x1b[0m{}] {:.1}%", color, "█".repeat(filled), "░".repeat(QUOTA_BAR_WIDTH - filled), space.used_percent);
+        println!("  Used:  {}", Self::format_size(space.used_bytes));
+        println!("  Free:  {}", Self::format_size(space.free_bytes));
+        println!("  Quota: {}", Self::format_size(space.total_bytes));
+
+        if almost_full {
+            println!(
+                "\x1b[33m!\x1b[0m The drive is almost full ({:.0}% of your quota is used). Once it is full, saving files to it will fail",
+                space.used_percent
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Copies a local folder to a folder of the drive (or the other way around with `pull`) with robocopy, showing its progress.
+    /// Only new and changed files are copied; with `mirror`, files missing from the source are also deleted from the
+    /// destination (after confirming it, unless forced). In a dry run, robocopy only lists what it would do.
+    /// Ends with a summary of what was copied and deleted, and of the files that failed (as JSON if requested).
+    pub fn sync(drive: char, local: &Path, subpath: &str, options: &SyncOptions, json: bool) -> Result<()> {
+        let SyncOptions { mirror, pull, force, throttle } = *options;
+
+        // The folder is resolved here ("." and ".." included), so a path that ends up at the root can be told apart
+        let mut folders: Vec<&str> = Vec::new();
+        for part in subpath.split(['\\', '/']) {
+            match part.trim() {
+                "" | "." => {}
+                ".." => { folders.pop(); }
+                _ => folders.push(part),
+            }
+        }
+
+        // Mirroring to the root would delete everything on the drive that isn't in the local folder
+        if mirror && !pull && folders.is_empty() {
+            return Err(UpvError::Drive(
+                format!("'{}' is the root of drive {}:, and mirroring to it would DELETE everything on the drive that isn't in '{}'", subpath, drive, local.display()).into()
+            ).with_hint("Give the folder of the drive to mirror to (e.g. backups\\project), or sync without --mirror").into());
+        }
+
+        let root = format!("{}:\\", drive.to_ascii_uppercase());
+        if !Path::new(&root).exists() {
+            return Err(UpvError::Drive(
+                format!("Drive {}: is not mounted", drive).into()
+            ).with_hint("Mount it with 'upv drive mount' first").into());
+        }
+        if !pull && !local.is_dir() {
+            return Err(UpvError::Drive(
+                format!("'{}' is not a folder", local.display()).into()
+            ).into());
+        }
+
+        // Robocopy lists the files by full path, so the local folder is made absolute to tell them apart
+        let local = std::path::absolute(local).unwrap_or_else(|_| local.to_path_buf());
+        let remote = Path::new(&root).join(folders.join("\\"));
+        let (source, destination) = if pull { (remote.as_path(), local.as_path()) } else { (local.as_path(), remote.as_path()) };
+
+        if mirror && !force && !dry_run::enabled() && !confirm::ask(
+            confirm::levels().mirror.unwrap_or(Level::Yes),
+            &format!("Files in '{}' that aren't in '{}' will be DELETED. Continue?", destination.display(), source.display()),
+            "Type 'DELETE' to confirm:",
+            "DELETE"
+        )? {
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+
+        // /FFT tolerates the coarser timestamps of the NAS, so unchanged files aren't copied again every time.
+        // The job header and summary are left out (and files are listed by full path and size in bytes), since they are
+        // printed in the language of Windows: the summary is made from the listed files instead
+        let robocopy = || {
+            let mut cmd = Command::new("robocopy");
+            cmd.arg(source)
+               .arg(destination)
+               .arg(if mirror { "/MIR" } else { "/E" })
+               .args(["/FFT", "/XJ", "/NDL", "/NC", "/NP", "/FP", "/BYTES", "/NJH", "/NJS"])
+               .arg(format!("/R:{}", SYNC_RETRIES))
+               .arg(format!("/W:{}", SYNC_RETRY_WAIT));
+            if let Some(gap) = throttle {
+                cmd.arg(format!("/IPG:{}", gap));
+            }
+            cmd
+        };
+        let mut cmd = robocopy();
+
+        // The progress bar needs to know how much there is to copy, which a listing run (/L) tells quickly
+        let progress = !json && !dry_run::enabled() && io::stdout().is_terminal();
+        let mut total = (0, 0);
+        if progress {
+            println!("Comparing '{}' with '{}'...", source.display(), destination.display());
+            Self::run_robocopy(robocopy().arg("/L"), source, destination, |line| {
+                if let RobocopyLine::Copy { size, .. } = line {
+                    total = (total.0 + 1, total.1 + size);
+                }
+            })?;
+        }
+
+        if dry_run::enabled() {
+            if !json {
+                dry_run::print_command(&cmd, &[]);
+                println!("Listing what would be copied{}...", if mirror { " and deleted" } else { "" });
+            }
+            cmd.arg("/L");
+        } else if !json {
+            println!("Syncing '{}' to '{}'...", source.display(), destination.display());
+        }
+
+        let mut summary = SyncSummary {
+            source: source.display().to_string(),
+            destination: destination.display().to_string(),
+            dry_run: dry_run::enabled(),
+            files_copied: 0,
+            bytes_copied: 0,
+            files_deleted: 0,
+            errors: Vec::new(),
+            robocopy_exit_code: None,
+        };
+        // The file being copied, which stops counting as copied if an error follows it (robocopy lists it again to retry)
+        let mut current: Option<(String, u64)> = None;
+        let mut describing_error = false;
+        let started = Instant::now();
+
+        let code = Self::run_robocopy(&mut cmd, source, destination, |line| {
+            let describes_error = std::mem::take(&mut describing_error);
+            match line {
+                RobocopyLine::Copy { path, size } => {
+                    summary.errors.retain(|error| error.path != path);
+                    summary.files_copied += 1;
+                    summary.bytes_copied += size;
+                    current = Some((path.to_string(), size));
+
+                    if summary.dry_run && !json {
+                        dry_run::print_change(&format!("Copy {} ({})", path, Self::format_size(size)));
+                    } else if progress {
+                        Self::print_sync_progress(summary.files_copied, summary.bytes_copied - size, total, started.elapsed());
+                    }
+                }
+                RobocopyLine::Extra { path } => {
+                    summary.files_deleted += 1;
+                    if summary.dry_run && !json && mirror {
+                        dry_run::print_change(&format!("Delete {}", path));
+                    }
+                }
+                RobocopyLine::Error { path, code } => {
+                    if let Some((_, size)) = current.take_if(|(file, _)| file == path) {
+                        summary.files_copied -= 1;
+                        summary.bytes_copied -= size;
+                    }
+                    summary.errors.retain(|error| error.path != path);
+                    summary.errors.push(SyncError { path: path.to_string(), code, message: String::new() });
+                    describing_error = true;
+                }
+                RobocopyLine::Other(text) if describes_error && !text.is_empty() => {
+                    if let Some(error) = summary.errors.last_mut() {
+                        error.message = text.to_string();
+                    }
+                }
+                RobocopyLine::Other(_) => describing_error = describes_error,
+            }
+        })?;
+        summary.robocopy_exit_code = code;
+        if !mirror {
+            // Without /MIR, the extra files are only reported, not deleted
+            summary.files_deleted = 0;
+        }
+
+        if progress {
+            // Clears the progress bar
+            print!("\r\x1b[2K");
+        }
+
+        // Robocopy's exit code is a bit mask: 1 = files copied, 2 = extra files found, 4 = mismatches, 8 or more = failures
+        let failed = !matches!(code, Some(0..=7));
+        if json {
+            println!("{}", serde_json::to_string_pretty(&summary).context("Failed to serialize the sync summary")?);
+        } else {
+            for error in &summary.errors {
+                let code = error.code.map(|code| format!(" (error {})", code)).unwrap_or_default();
+                eprintln!("  ✗ {}: {}{}", error.path, error.message, code);
+            }
+
+            let size = Self::format_size(summary.bytes_copied);
+            if summary.dry_run {
+                let deleted = if mirror { format!(" and delete {}", summary.files_deleted) } else { String::new() };
+                println!("Would copy {} file(s) ({}){}", summary.files_copied, size, deleted);
+            } else if code == Some(0) {
+                println!("Already in sync, nothing to copy");
+            } else if !failed {
+                let deleted = if mirror { format!(", {} deleted", summary.files_deleted) } else { String::new() };
+                println!("'{}' synced to '{}' ({} file(s) copied, {}{})", source.display(), destination.display(), summary.files_copied, size, deleted);
+            }
+        }
+
+        if failed {
+            let problem = match summary.errors.len() {
+                0 => "Some files".to_string(),
+                count => format!("{} file(s)", count),
+            };
+            return Err(UpvError::Drive(
+                format!("{} couldn't be copied to '{}' (robocopy exit code {})", problem, destination.display(), code.map(|code| code.to_string()).unwrap_or_else(|| "unknown".to_string())).into()
+            ).with_hint("Check the errors above: files in use, missing permissions or a drive that became unavailable (run it again to retry the failed ones)")
+                .with_command(&cmd, &[])
+                .into());
+        }
+
+        Ok(())
+    }
+
+    /// Tells apart the lines robocopy prints for 'upv drive sync': files by whether their path is in the source or the
+    /// destination, and errors by their hexadecimal code (the rest of those lines is in the language of Windows).
+    fn parse_robocopy_line<'a>(line: &'a str, source: &str, destination: &str) -> RobocopyLine<'a> {
+        let line = line.trim();
+        // Folders are compared with a trailing backslash, so C:\a isn't taken for a file of C:\ab
+        let folder = |path: &str| format!("{}\\", path.trim_end_matches('\\')).to_lowercase();
+        let (source, destination) = (folder(source), folder(destination));
+        let starts_with = |path: &str, folder: &str| path.get(..folder.len()).is_some_and(|start| start.to_lowercase() == folder);
+
+        // e.g. "2026/10/16 12:00:00 ERROR 32 (0x00000020) Copying File C:\Projects\tfg\notes.docx"
+        if let Some(hex) = line.find("(0x") {
+            let lowercase = line.to_lowercase();
+            if let Some(at) = lowercase.find(&source).or_else(|| lowercase.find(&destination)).filter(|&at| line.is_char_boundary(at)) {
+                let code = line[..hex].split_whitespace().last().and_then(|code| code.parse().ok());
+                return RobocopyLine::Error { path: &line[at..], code };
+            }
+        }
+
+        // e.g. "12345	C:\Projects\tfg\notes.docx" (after some indentation)
+        if let Some((size, path)) = line.split_once('\t')
+            && let Ok(size) = size.trim().parse()
+        {
+            let path = path.trim();
+            if starts_with(path, &source) {
+                return RobocopyLine::Copy { path, size };
+            }
+            if starts_with(path, &destination) {
+                return RobocopyLine::Extra { path };
+            }
+        }
+
+        RobocopyLine::Other(line)
+    }
+
+    /// Runs robocopy, passing each line of its output (parsed) to `on_line` as it is printed. Returns its exit code.
+    fn run_robocopy(cmd: &mut Command, source: &Path, destination: &Path, mut on_line: impl FnMut(RobocopyLine)) -> Result<Option<i32>> {
+        let mut child = cmd.stdout(Stdio::piped()).spawn().context("Failed to execute robocopy")?;
+        let mut stdout = io::BufReader::new(child.stdout.take().context("Failed to read the output of robocopy")?);
+        let (source, destination) = (source.display().to_string(), destination.display().to_string());
+
+        let mut line = Vec::new();
+        while stdout.read_until(b'\n', &mut line).context("Failed to read the output of robocopy")? > 0 {
+            on_line(Self::parse_robocopy_line(&String::from_utf8_lossy(&line), &source, &destination));
+            line.clear();
+        }
+
+        Ok(child.wait().context("Failed to execute robocopy")?.code())
+    }
+
+    /// Draws a progress bar of a sync in place, with the files started so far and an estimate of the time left
+    fn print_sync_progress(files: u64, bytes: u64, total: (u64, u64), elapsed: Duration) {
+        const WIDTH: usize = 24;
+
+        let (total_files, total_bytes) = total;
+        let fraction = if total_bytes == 0 { 0.0 } else { (bytes as f64 / total_bytes as f64).min(1.0) };
+        let filled = (fraction * WIDTH as f64) as usize;
+        let eta = if bytes == 0 {
+            String::new()
+        } else {
+            let left = elapsed.as_secs_f64() * (total_bytes.saturating_sub(bytes)) as f64 / bytes as f64;
+            format!(", about {}:{:02} left", left as u64 / 60, left as u64 % 60)
+        };
+
+        print!(
+            "\r\x1b[2K[{}{}] {:>3}%  file {} of {}, {} of {}{}",
+            "#".repeat(filled),
+            " ".repeat(WIDTH - filled),
+            (fraction * 100.0) as u32,
+            files,
+            total_files.max(files),
+            Self::format_size(bytes),
+            Self::format_size(total_bytes),
+            eta
+        );
+        let _ = io::stdout().flush();
+    }
+
+    /// Prints the details of a drive mapping (as JSON if requested): its share, status, SMB dialect,
+    /// the accounts it uses, whether it is persistent and the latency to its server.
+    pub fn info(drive: char, json: bool) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+
+        // The SMB connection is the one to the mapping's share, and only exists while it is connected.
+        // Persistent mappings are the ones Windows stores in the user's registry to restore them at logon.
+        let ps_command = format!(
+            "$m = Get-SmbMapping -LocalPath '{0}:' -ErrorAction SilentlyContinue | Select-Object -First 1; \
+            if ($m) {{ \
+                $parts = $m.RemotePath.TrimStart('\\') -split '\\\\'; \
+                $c = Get-SmbConnection -ServerName $parts[0] -ErrorAction SilentlyContinue | Where-Object {{ $_.ShareName -eq $parts[1] }} | Select-Object -First 1; \
+                [PSCustomObject]@{{ Drive = $m.LocalPath; RemotePath = $m.RemotePath; Status = [string]$m.Status; Server = $parts[0]; Share = $parts[1]; \
+                    Dialect = $c.Dialect; User = $c.UserName; Credential = $c.Credential; Persistent = (Test-Path 'HKCU:\\Network\\{0}') }} \
+            }}",
+            drive
+        );
+
+        let mut info: DriveInfo = powershell::query(&ps_command)
+            .map_err(|e| UpvError::Drive(format!("Failed to read how drive {} is mapped: {}", drive, e).into()))?
+            .pop()
+            .ok_or_else(|| UpvError::Drive(
+                format!("Drive {}: is not a mapped network drive", drive).into()
+            ).with_hint("Run 'upv drive status' to see the mapped drives"))?;
+
+        info.latency_ms = PingManager::connect_time(&info.server, SMB_PORT).map(|latency| latency.as_millis());
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&info).context("Failed to serialize the drive details")?);
+            return Ok(());
+        }
+
+        let unknown = || "\x1b[2m(not connected)\x1b[0m".to_string();
+
+        println!("Drive {}:", info.drive);
+        println!("  Share:        {}", info.remote_path);
+        println!("  Status:       {}", Self::colorize_status(&info.status));
+        println!("  Server:       {}", info.server);
+        println!("  SMB dialect:  {}", info.dialect.clone().unwrap_or_else(unknown));
+        println!("  User:         {}", info.user.clone().unwrap_or_else(unknown));
+        println!("  Logged in as: {}", info.credential.clone().unwrap_or_else(unknown));
+        println!("  Persistent:   {}", if info.persistent { "yes (restored at logon)" } else { "no" });
+        match info.latency_ms {
+            Some(latency) => println!("  Latency:      {} ms", latency),
+            None => println!("  Latency:      \x1b[31munreachable\x1b[0m (port {} didn't answer)", SMB_PORT),
+        }
+
+        Ok(())
+    }
+
+    /// Lists the UPV drive mappings (or all of them) with their state, marking the persistent ones.
+    /// With a drive letter, only that drive is shown, failing if it isn't mounted so scripts can test it.
+    pub fn status(all: bool, drive: Option<char>) -> Result<()> {
+        let mappings = Self::get_mappings()?;
+        let persistent = Self::persistent_drives();
+
+        let shown: Vec<&NetUseEntry> = match drive {
+            Some(drive) => {
+                let local = format!("{}:", drive.to_ascii_uppercase());
+                let shown: Vec<&NetUseEntry> = mappings.iter().filter(|entry| entry.local.as_deref() == Some(local.as_str())).collect();
+                if shown.is_empty() {
+                    return Err(UpvError::Drive(
+                        format!("Drive {} is not mounted", local).into()
+                    ).with_hint("Mount it with 'upv drive mount'").into());
+                }
+                shown
+            }
+            None => mappings.iter().filter(|entry| all || entry.is_upv()).collect(),
+        };
+
+        if shown.is_empty() {
+            println!("No {}network drives mapped", if all { "" } else { "UPV " });
+        } else {
+            println!("{}:", if all || drive.is_some() { "Network drives" } else { "UPV network drives" });
+        }
+
+        let local_width = shown.iter().map(|entry| entry.local.as_deref().unwrap_or("-").len()).max().unwrap_or_default();
+        let remote_width = shown.iter().map(|entry| entry.remote.len()).max().unwrap_or_default();
+        for entry in &shown {
+            let mut notes = Vec::new();
+            if all && entry.is_upv() {
+                notes.push("\x1b[36mUPV\x1b[0m");
+            }
+            if entry.local.as_deref().and_then(cli::parse_drive_letter).is_some_and(|letter| persistent.contains(&letter)) {
+                notes.push("persistent");
+            }
+            let notes = if notes.is_empty() { String::new() } else { format!("  ({})", notes.join(", ")) };
+
+            println!(
+                "  {:<local_width$}  {:<remote_width$}  {}{}",
+                entry.local.as_deref().unwrap_or("-"),
+                entry.remote,
+                Self::colorize_status(&entry.status),
+                notes
+            );
+        }
+
+        let hidden = mappings.iter().filter(|entry| !entry.is_upv()).count();
+        if !all && drive.is_none() && hidden > 0 {
+            println!("\x1b[2m{} other mapping(s) not shown, use --all to show them\x1b[0m", hidden);
+        }
+
+        Ok(())
+    }
+
+    /// Lists the root of a path, giving up after the timeout.
+    fn probe_listing(path: &str, timeout: Duration) -> ListingProbe {
+        // A hung share blocks the read forever, so it is done in a thread that is abandoned after the timeout
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+        let probe_path = path.to_string();
+        thread::spawn(move || {
+            let _ = sender.send(fs::read_dir(&probe_path).map(|mut entries| entries.next().transpose()));
+        });
+
+        match receiver.recv_timeout(timeout) {
+            Ok(Ok(_)) => ListingProbe::Answered(start.elapsed()),
+            Ok(Err(e)) => ListingProbe::Failed(e),
+            Err(_) => ListingProbe::TimedOut,
+        }
+    }
+
+    /// Checks that a mapped drive actually works, listing its root with a timeout and measuring how long it takes.
+    /// `net use` keeps listing a mapping as OK after its share stops answering (e.g. after sleeping or reconnecting the VPN),
+    /// so those stale mappings are told apart and a remount is suggested. Fails if the drive doesn't answer.
+    pub fn check(drive: char, timeout: Duration, json: bool) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+        let local = format!("{}:", drive);
+        let entry = Self::get_mappings()?
+            .into_iter()
+            .find(|entry| entry.local.as_deref().is_some_and(|letter| letter.eq_ignore_ascii_case(&local)))
+            .ok_or_else(|| UpvError::Drive(
+                format!("Drive {} is not mapped to any network share", local).into()
+            ).with_hint("Mount it with 'upv drive mount' (or 'upv drive mount-share')"))?;
+
+        let probe = Self::probe_listing(&format!("{}\\", local), timeout);
+        let check = DriveCheck {
+            drive: local.clone(),
+            remote: entry.remote.clone(),
+            status: entry.status.clone(),
+            healthy: matches!(probe, ListingProbe::Answered(_)),
+            latency_ms: match &probe {
+                ListingProbe::Answered(latency) => Some(latency.as_millis()),
+                _ => None,
+            },
+            error: match &probe {
+                ListingProbe::Answered(_) => None,
+                ListingProbe::Failed(e) => Some(e.to_string()),
+                ListingProbe::TimedOut => Some(format!("No answer within {}s", timeout.as_secs())),
+            },
+        };
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&check).context("Failed to serialize the drive check")?);
+        } else {
+            println!("Drive {} ({})", local, entry.remote);
+            println!("  net use status: {}", Self::colorize_status(if entry.status.is_empty() { "-" } else { &entry.status }));
+            match &probe {
+                ListingProbe::Answered(latency) if *latency >= SLOW_LATENCY => println!(
+                    "  \x1b[33m!\x1b[0m Answered in {} ms, which is slow (is the VPN or the network congested?)",
+                    latency.as_millis()
+                ),
+                ListingProbe::Answered(latency) => println!("  \x1b[32m✓\x1b[0m Answered in {} ms", latency.as_millis()),
+                ListingProbe::Failed(e) => println!("  \x1b[1;31m✗\x1b[0m Listing it failed: {}", e),
+                ListingProbe::TimedOut => println!("  \x1b[1;31m✗\x1b[0m Didn't answer within {}s", timeout.as_secs()),
+            }
+        }
+
+        if check.healthy {
+            return Ok(());
+        }
+
+        let message = if entry.status.eq_ignore_ascii_case("OK") {
+            format!("Drive {} is stale: Windows lists it as OK, but its share doesn't answer", local)
+        } else {
+            format!("Drive {} is not available", local)
+        };
+        let hint = if Self::load_mounts().is_ok_and(|mounts| mounts.contains_key(&drive)) {
+            format!("Check your VPN connection, and remount it with 'upv drive remount --drive {}'", drive)
+        } else {
+            format!("Check your VPN connection, and remount it ('upv drive unmount --drive {} --force' and 'upv drive mount')", drive)
+        };
+        Err(UpvError::Drive(message.into()).with_hint(hint).into())
+    }
+
+    /// Lists the root of every UPV drive mapping with a short timeout, reporting which ones actually answer.
+    /// Fails if any of them doesn't, since Windows can keep listing a mapping as OK while accessing it hangs.
+    pub fn verify_mappings() -> Result<()> {
+        let mappings: Vec<NetUseEntry> = Self::get_mappings()?
+            .into_iter()
+            .filter(|entry| entry.remote.to_lowercase().contains(NAS_SERVER))
+            .collect();
+
+        if mappings.is_empty() {
+            println!("No UPV network drives to verify");
+            return Ok(());
+        }
+
+        println!("Verifying UPV network drives...");
+
+        let mut failed_count = 0;
+        for entry in &mappings {
+            let path = match &entry.local {
+                Some(local) => format!("{}\\", local),
+                None => entry.remote.clone(),
+            };
+            let label = format!("{} {}", entry.local.as_deref().unwrap_or("-"), entry.remote);
+
+            match Self::probe_listing(&path, VERIFY_TIMEOUT) {
+                ListingProbe::Answered(latency) => println!("  \x1b[32m✓\x1b[0m {} answered in {} ms", label, latency.as_millis()),
+                ListingProbe::Failed(e) => {
+                    println!("  \x1b[1;31m✗\x1b[0m {} failed: {}", label, e);
+                    failed_count += 1;
+                }
+                ListingProbe::TimedOut => {
+                    println!("  \x1b[1;31m✗\x1b[0m {} didn't answer within {}s", label, VERIFY_TIMEOUT.as_secs());
+                    failed_count += 1;
+                }
+            }
+        }
+
+        if failed_count > 0 {
+            return Err(UpvError::Drive(
+                format!("{} of {} UPV network drives are not available", failed_count, mappings.len()).into()
+            ).with_hint("Check your VPN connection, or remount the drive ('upv drive unmount --force' and 'upv drive mount')").into());
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Polls the network drive status periodically, printing every state transition as it happens
+    pub fn watch_status(interval: u64) -> Result<()> {
+        println!("Watching network drive status every {}s (press Ctrl+C to stop)...", interval);
+
+        let mut previous: BTreeMap<String, NetUseEntry> = Self::get_mappings()?
+            .into_iter()
+            .map(|entry| (entry.key(), entry))
+            .collect();
+
+        let now = chrono::Local::now().format("%H:%M:%S");
+        if previous.is_empty() {
+            println!("[{}] No network drives mapped", now);
+        }
+        for entry in previous.values() {
+            println!(
+                "[{}] {} {} is {}",
+                now,
+                entry.local.as_deref().unwrap_or("-"),
+                entry.remote,
+                Self::colorize_status(&entry.status)
+            );
+        }
+
+        loop {
+            thread::sleep(Duration::from_secs(interval.max(1)));
+
+            let current: BTreeMap<String, NetUseEntry> = match Self::get_mappings() {
+                Ok(entries) => entries.into_iter().map(|entry| (entry.key(), entry)).collect(),
+                Err(e) => {
+                    // A failed refresh shouldn't stop the watch, it may be part of the issue
+                    eprintln!("[{}] \x1b[33mFailed to refresh status: {}\x1b[0m", chrono::Local::now().format("%H:%M:%S"), e);
+                    continue;
+                }
+            };
+
+            let now = chrono::Local::now().format("%H:%M:%S");
+
+            for (key, entry) in &current {
+                let old_status = previous.get(key).map(|old| old.status.as_str());
+                if old_status != Some(entry.status.as_str()) {
+                    println!(
+                        "[{}] {} {}: {} → {}",
+                        now,
+                        entry.local.as_deref().unwrap_or("-"),
+                        entry.remote,
+                        old_status.map(Self::colorize_status).unwrap_or_else(|| "\x1b[2m(not mapped)\x1b[0m".to_string()),
+                        Self::colorize_status(&entry.status)
+                    );
+                }
+            }
+
+            for (key, entry) in &previous {
+                if !current.contains_key(key) {
+                    println!(
+                        "[{}] {} {}: {} → \x1b[1;31m(removed)\x1b[0m",
+                        now,
+                        entry.local.as_deref().unwrap_or("-"),
+                        entry.remote,
+                        Self::colorize_status(&entry.status)
+                    );
+                }
+            }
+
+            previous = current;
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r"C:\Projects\tfg";
+    const DESTINATION: &str = r"W:\backups\tfg";
+
+    fn parse(line: &str) -> RobocopyLine<'_> {
+        DriveManager::parse_robocopy_line(line, SOURCE, DESTINATION)
+    }
+
+    #[test]
+    fn tells_copies_from_extra_files_by_their_folder() {
+        assert_eq!(parse("\t\t\t        1234\tC:\\Projects\\tfg\\notes.docx\r\n"), RobocopyLine::Copy { path: r"C:\Projects\tfg\notes.docx", size: 1234 });
+        assert_eq!(parse("\t\t\t           0\tw:\\Backups\\tfg\\old.txt"), RobocopyLine::Extra { path: r"w:\Backups\tfg\old.txt" });
+        // A sibling folder whose name starts like the source isn't part of it
+        assert_eq!(parse("\t\t\t          12\tC:\\Projects\\tfg2\\a.txt"), RobocopyLine::Other("12\tC:\\Projects\\tfg2\\a.txt"));
+    }
+
+    #[test]
+    fn parses_errors_in_any_language() {
+        assert_eq!(
+            parse("2026/10/16 12:00:00 ERROR 32 (0x00000020) Copying File C:\\Projects\\tfg\\notes.docx"),
+            RobocopyLine::Error { path: r"C:\Projects\tfg\notes.docx", code: Some(32) }
+        );
+        assert_eq!(
+            parse("2026/10/16 12:00:00 ERROR 5 (0x00000005) Copiando archivo W:\\backups\\tfg\\notes.docx"),
+            RobocopyLine::Error { path: r"W:\backups\tfg\notes.docx", code: Some(5) }
+        );
+    }
+
+    #[test]
+    fn leaves_other_lines_alone() {
+        assert_eq!(parse("The process cannot access the file because it is being used by another process."), RobocopyLine::Other("The process cannot access the file because it is being used by another process."));
+        assert_eq!(parse("ERROR: RETRY LIMIT EXCEEDED."), RobocopyLine::Other("ERROR: RETRY LIMIT EXCEEDED."));
+        assert_eq!(parse("   "), RobocopyLine::Other(""));
+    }
+
+    fn entries(output: &str) -> Vec<(String, Option<String>, String)> {
+        DriveManager::parse_net_use(output).into_iter().map(|entry| (entry.status, entry.local, entry.remote)).collect()
+    }
+
+    #[test]
+    fn parses_net_use_tables_in_any_language() {
+        let english = "New connections will be remembered.\r\n\r\n\
+            Status       Local     Remote                    Network\r\n\
+            -------------------------------------------------------------------------------\r\n\
+            OK           W:        \\\\nasupv.upv.es\\discos\\a\\alice  Microsoft Windows Network\r\n\
+            OK                     \\\\nasupv.upv.es\\lab         Microsoft Windows Network\r\n\
+            The command completed successfully.\r\n";
+        assert_eq!(entries(english), [
+            ("OK".to_string(), Some("W:".to_string()), r"\\nasupv.upv.es\discos\a\alice".to_string()),
+            // Connections without a drive letter (e.g. opened from Explorer) have no local column
+            ("OK".to_string(), None, r"\\nasupv.upv.es\lab".to_string()),
+        ]);
+
+        let spanish = "Se recordarán las nuevas conexiones.\r\n\r\n\
+            Estado       Local     Remoto                    Red\r\n\
+            -------------------------------------------------------------------------------\r\n\
+            No disponible p:       \\\\files.dsic.upv.es\\dept   Microsoft Windows Network\r\n\
+            Desconectado W:        \\\\nasupv.upv.es\\discos\\a\\alice-with-a-long-name\r\n\
+            \x20                                               Microsoft Windows Network\r\n\
+            Se ha completado el comando correctamente.\r\n";
+        assert_eq!(entries(spanish), [
+            ("No disponible".to_string(), Some("P:".to_string()), r"\\files.dsic.upv.es\dept".to_string()),
+            // Long paths push the network name to the next line
+            ("Desconectado".to_string(), Some("W:".to_string()), r"\\nasupv.upv.es\discos\a\alice-with-a-long-name".to_string()),
+        ]);
+    }
+}
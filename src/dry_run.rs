@@ -0,0 +1,61 @@
+use std::ffi::OsStr;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::powershell;
+
+// In dry-run mode, the commands that would change something (connections, drives, network settings) are printed
+// instead of run. Read-only queries still run, so the printed commands are the ones a real run would execute.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+const REDACTED: &str = "********";
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Quotes an argument the way it would have to be typed in a terminal
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"') {
+        return arg.into_owned();
+    }
+    format!("\"{}\"", arg.replace('"', "\\\""))
+}
+
+/// Formats a command the way it would be typed in a terminal, replacing the given secrets (passwords) in its arguments.
+pub fn format_command(command: &Command, secrets: &[&str]) -> String {
+    let args: Vec<String> = command.get_args()
+        .map(|arg| match secrets.iter().find(|secret| !secret.is_empty() && OsStr::new(secret) == arg) {
+            Some(_) => REDACTED.to_string(),
+            None => quote(arg),
+        })
+        .collect();
+
+    format!("{} {}", quote(command.get_program()), args.join(" "))
+}
+
+/// Prints a command instead of running it, replacing the given secrets (passwords) in its arguments.
+pub fn print_command(command: &Command, secrets: &[&str]) {
+    println!("[dry-run] {}", format_command(command, secrets));
+}
+
+/// Prints a PowerShell command instead of running it.
+pub fn print_powershell(ps_command: &str) {
+    print_command(&powershell::command(ps_command), &[]);
+}
+
+/// Prints a PowerShell script (run through stdin) instead of running it.
+pub fn print_powershell_stdin(script: &str) {
+    print_command(&powershell::command("-"), &[]);
+    println!("{}", script.trim_end());
+}
+
+/// Prints a change that would be made without running any command.
+pub fn print_change(description: &str) {
+    println!("[dry-run] {}", description);
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use anyhow::{Result, Context};
+use clap::ValueEnum;
+
+use crate::drive::DriveManager;
+use crate::vpn::VpnManager;
+
+/// Shell to print the environment variable statements for
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum ExportShell {
+    /// Windows PowerShell and PowerShell 7 (run with 'upv env | Invoke-Expression')
+    #[default]
+    Powershell,
+    /// Command Prompt (run with 'for /f "delims=" %i in ('upv env --shell cmd') do %i')
+    Cmd,
+    /// POSIX shells like Git Bash or WSL (run with 'eval "$(upv env --shell posix)"')
+    Posix,
+}
+
+pub struct EnvManager;
+
+impl EnvManager {
+    // Private utility functions
+
+    /// Statement that sets a variable, or removes it if there is no value (so values from a previous run don't linger).
+    /// Values (like VPN connection names) are quoted so nothing in them can run as a command once the output is evaluated
+    fn statement(shell: ExportShell, name: &str, value: Option<&str>) -> String {
+        match (shell, value) {
+            // PowerShell also takes typographic single quotes as quotes, so they are doubled as well
+            (ExportShell::Powershell, Some(value)) => {
+                let mut quoted = String::with_capacity(value.len());
+                for c in value.chars() {
+                    if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}') {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                format!("$env:{} = '{}'", name, quoted)
+            }
+            (ExportShell::Powershell, None) => format!("Remove-Item Env:{} -ErrorAction SilentlyContinue", name),
+            // cmd has no way to escape a double quote (or a line break) inside a quoted 'set', so they are dropped
+            (ExportShell::Cmd, Some(value)) => format!("set \"{}={}\"", name, value.replace(['"', '\r', '\n'], "")),
+            (ExportShell::Cmd, None) => format!("set \"{}=\"", name),
+            (ExportShell::Posix, Some(value)) => format!("export {}='{}'", name, value.replace('\'', "'\\''")),
+            (ExportShell::Posix, None) => format!("unset {}", name),
+        }
+    }
+
+    // Public methods for exporting the state
+
+    /// Prints statements that export the state of the VPN and the drive as environment variables, so scripts can use them
+    /// (or prints the variables as JSON if requested). The VPN connection is the connected UPV one, or the configured one:
+    /// - UPV_VPN_NAME, UPV_VPN_STATUS and UPV_VPN_IP (the address the VPN assigned, while connected)
+    /// - UPV_DRIVE, UPV_DRIVE_UNC and UPV_DRIVE_STATUS (if the drive letter is mapped)
+    pub fn export(shell: ExportShell, vpn_name: Option<&str>, drive: char, json: bool) -> Result<()> {
+        let mut variables: BTreeMap<&str, Option<String>> = BTreeMap::new();
+
+        let connections = VpnManager::get_upv_connection_statuses()?;
+        let connection = connections.iter()
+            .find(|(_, status)| status == "Connected")
+            .or_else(|| connections.iter().find(|(name, _)| Some(name.as_str()) == vpn_name));
+        let ip = match connection {
+            Some((name, status)) if status == "Connected" => VpnManager::connection_ip(name)?,
+            _ => None,
+        };
+        variables.insert("UPV_VPN_NAME", connection.map(|(name, _)| name.clone()));
+        variables.insert("UPV_VPN_STATUS", connection.map(|(_, status)| status.clone()));
+        variables.insert("UPV_VPN_IP", ip);
+
+        let local = format!("{}:", drive.to_ascii_uppercase());
+        let mapping = DriveManager::get_mappings()?
+            .into_iter()
+            .find(|entry| entry.local.as_deref().is_some_and(|letter| letter.eq_ignore_ascii_case(&local)));
+        variables.insert("UPV_DRIVE", mapping.as_ref().map(|_| local.clone()));
+        variables.insert("UPV_DRIVE_UNC", mapping.as_ref().map(|entry| entry.remote.clone()));
+        variables.insert("UPV_DRIVE_STATUS", mapping.as_ref().map(|entry| entry.status.clone()).filter(|status| !status.is_empty()));
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&variables).context("Failed to serialize the variables")?);
+            return Ok(());
+        }
+
+        for (name, value) in &variables {
+            println!("{}", Self::statement(shell, name, value.as_deref()));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_values_for_powershell() {
+        assert_eq!(EnvManager::statement(ExportShell::Powershell, "UPV_VPN_NAME", Some("UPV")), "$env:UPV_VPN_NAME = 'UPV'");
+        assert_eq!(EnvManager::statement(ExportShell::Powershell, "UPV_VPN_NAME", Some("Alice's VPN")), "$env:UPV_VPN_NAME = 'Alice''s VPN'");
+        assert_eq!(EnvManager::statement(ExportShell::Powershell, "UPV_VPN_NAME", Some("x\u{2019}; calc")), "$env:UPV_VPN_NAME = 'x\u{2019}\u{2019}; calc'");
+        assert_eq!(EnvManager::statement(ExportShell::Powershell, "UPV_VPN_NAME", Some("$(calc) \"a\"")), "$env:UPV_VPN_NAME = '$(calc) \"a\"'");
+        assert_eq!(EnvManager::statement(ExportShell::Powershell, "UPV_VPN_IP", None), "Remove-Item Env:UPV_VPN_IP -ErrorAction SilentlyContinue");
+    }
+
+    #[test]
+    fn quotes_values_for_cmd() {
+        assert_eq!(EnvManager::statement(ExportShell::Cmd, "UPV_DRIVE_UNC", Some(r"\\nasupv.upv.es\discos\a\alice")), r#"set "UPV_DRIVE_UNC=\\nasupv.upv.es\discos\a\alice""#);
+        assert_eq!(EnvManager::statement(ExportShell::Cmd, "UPV_VPN_NAME", Some("a & b")), r#"set "UPV_VPN_NAME=a & b""#);
+        assert_eq!(EnvManager::statement(ExportShell::Cmd, "UPV_VPN_NAME", Some("x\" & calc & \"")), r#"set "UPV_VPN_NAME=x & calc & ""#);
+        assert_eq!(EnvManager::statement(ExportShell::Cmd, "UPV_VPN_NAME", Some("a\r\ncalc")), r#"set "UPV_VPN_NAME=acalc""#);
+        assert_eq!(EnvManager::statement(ExportShell::Cmd, "UPV_VPN_IP", None), r#"set "UPV_VPN_IP=""#);
+    }
+
+    #[test]
+    fn quotes_values_for_posix_shells() {
+        assert_eq!(EnvManager::statement(ExportShell::Posix, "UPV_VPN_NAME", Some("UPV")), "export UPV_VPN_NAME='UPV'");
+        assert_eq!(EnvManager::statement(ExportShell::Posix, "UPV_VPN_NAME", Some("it's")), r"export UPV_VPN_NAME='it'\''s'");
+        assert_eq!(EnvManager::statement(ExportShell::Posix, "UPV_VPN_NAME", Some("$(calc) `x` \"y\"")), "export UPV_VPN_NAME='$(calc) `x` \"y\"'");
+        assert_eq!(EnvManager::statement(ExportShell::Posix, "UPV_VPN_IP", None), "unset UPV_VPN_IP");
+    }
+}
@@ -1,34 +1,401 @@
-use std::fmt;
+use std::process::Command;
+use thiserror::Error;
+
+use crate::dry_run;
 
 // Program-level exit codes (0-9)
 pub const EXIT_SUCCESS: i32 = 0;
 pub const EXIT_PROGRAM_ERROR: i32 = 1;
 
-// Exit codes for specific errors in upv-cli (10-19)
-//pub const EXIT_UPV_ERROR: i32 = 10;
-pub const EXIT_UPV_VPN_ERROR: i32 = 11;
-pub const EXIT_UPV_DRIVE_ERROR: i32 = 12;
-pub const EXIT_UPV_DRIVE_IN_USE_ERROR: i32 = 13;
+// Exit codes for specific errors in upv-cli (10-19), given by the UpvError variants
+//const EXIT_UPV_ERROR: i32 = 10;
+const EXIT_UPV_VPN_ERROR: i32 = 11;
+const EXIT_UPV_DRIVE_ERROR: i32 = 12;
+const EXIT_UPV_DRIVE_IN_USE_ERROR: i32 = 13;
+
+// Exit codes for specific causes, so scripts can react to them (e.g. asking for the password again on an auth error)
+const EXIT_UPV_VPN_NOT_FOUND_ERROR: i32 = 14;
+const EXIT_UPV_AUTH_ERROR: i32 = 15;
+const EXIT_UPV_UNREACHABLE_ERROR: i32 = 16;
+const EXIT_UPV_POWERSHELL_ERROR: i32 = 17;
+const EXIT_UPV_INTERRUPTED_ERROR: i32 = 18;
 
-#[derive(Debug)]
-pub struct UpvError {
+/// What is known about an error besides its cause
+#[derive(Debug, Default)]
+pub struct Details {
     pub message: String,
-    pub exit_code: i32,
+    /// Suggestion on how to fix the problem, if any
+    pub hint: Option<String>,
+    /// Error code reported by the underlying system call or command, if any
+    pub os_code: Option<i32>,
+    /// Command that failed, with its passwords redacted (shown with --verbose)
+    pub command: Option<String>,
+}
+
+impl From<String> for Details {
+    fn from(message: String) -> Self {
+        Self { message, ..Default::default() }
+    }
+}
+
+impl From<&str> for Details {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+/// Builds an error with a given cause out of its details (the tuple variants of UpvError are these)
+pub type Cause = fn(Details) -> UpvError;
+
+/// Errors with a known cause, which decides the exit code (and the kind in JSON output) scripts can react to
+#[derive(Debug, Error)]
+pub enum UpvError {
+    /// A VPN operation failed
+    #[error("{}", .0.message)]
+    Vpn(Details),
+    /// The VPN connection doesn't exist
+    #[error("{}", .0.message)]
+    VpnNotFound(Details),
+    /// The VPN server turned down the username or password
+    #[error("{}", .0.message)]
+    VpnAuthFailed(Details),
+    /// The VPN server couldn't be reached
+    #[error("{}", .0.message)]
+    VpnUnreachable(Details),
+    /// A network drive operation failed
+    #[error("{}", .0.message)]
+    Drive(Details),
+    /// The drive has open files, so it can't be unmounted
+    #[error("{}", .0.message)]
+    DriveInUse(Details),
+    /// The file server turned down the username or password
+    #[error("{}", .0.message)]
+    DriveAuthFailed(Details),
+    /// The file server couldn't be reached
+    #[error("{}", .0.message)]
+    DriveUnreachable(Details),
+    /// The Internet couldn't be reached (e.g. behind a captive portal)
+    #[error("{}", .0.message)]
+    InternetUnreachable(Details),
+    /// PowerShell couldn't be used (e.g. restricted by policy)
+    #[error("{}", .0.message)]
+    PowerShellFailed(Details),
+    /// The config file (or a value for it) is not valid, or lacks something that is needed
+    #[error("{}", .0.message)]
+    Config(Details),
+    /// A password couldn't be read from where it is said to come from
+    #[error("{}", .0.message)]
+    Credentials(Details),
+    /// An argument is not valid (e.g. a malformed time)
+    #[error("{}", .0.message)]
+    InvalidValue(Details),
+    /// Some of the 'upv doctor' checks failed
+    #[error("{}", .0.message)]
+    ChecksFailed(Details),
+    /// The Task Scheduler refused a change to the scheduled tasks
+    #[error("{}", .0.message)]
+    TaskScheduler(Details),
+    /// A release artifact to describe in a package manifest doesn't exist
+    #[error("{}", .0.message)]
+    ArtifactNotFound(Details),
+    /// Ctrl+C was pressed in the middle of an operation, which was undone
+    #[error("{}", .0.message)]
+    Interrupted(Details),
 }
 
 impl UpvError {
-    pub fn new(message: impl Into<String>, exit_code: i32) -> Self {
-        Self {
-            message: message.into(),
-            exit_code,
+    pub fn details(&self) -> &Details {
+        match self {
+            UpvError::Vpn(details)
+            | UpvError::VpnNotFound(details)
+            | UpvError::VpnAuthFailed(details)
+            | UpvError::VpnUnreachable(details)
+            | UpvError::Drive(details)
+            | UpvError::DriveInUse(details)
+            | UpvError::DriveAuthFailed(details)
+            | UpvError::DriveUnreachable(details)
+            | UpvError::InternetUnreachable(details)
+            | UpvError::PowerShellFailed(details)
+            | UpvError::Config(details)
+            | UpvError::Credentials(details)
+            | UpvError::InvalidValue(details)
+            | UpvError::ChecksFailed(details)
+            | UpvError::TaskScheduler(details)
+            | UpvError::ArtifactNotFound(details)
+            | UpvError::Interrupted(details) => details,
+        }
+    }
+
+    fn details_mut(&mut self) -> &mut Details {
+        match self {
+            UpvError::Vpn(details)
+            | UpvError::VpnNotFound(details)
+            | UpvError::VpnAuthFailed(details)
+            | UpvError::VpnUnreachable(details)
+            | UpvError::Drive(details)
+            | UpvError::DriveInUse(details)
+            | UpvError::DriveAuthFailed(details)
+            | UpvError::DriveUnreachable(details)
+            | UpvError::InternetUnreachable(details)
+            | UpvError::PowerShellFailed(details)
+            | UpvError::Config(details)
+            | UpvError::Credentials(details)
+            | UpvError::InvalidValue(details)
+            | UpvError::ChecksFailed(details)
+            | UpvError::TaskScheduler(details)
+            | UpvError::ArtifactNotFound(details)
+            | UpvError::Interrupted(details) => details,
         }
     }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.details_mut().hint = Some(hint.into());
+        self
+    }
+
+    /// Attaches the Win32/RAS error code behind this error, if it is known
+    pub fn with_os_code(mut self, os_code: Option<i32>) -> Self {
+        self.details_mut().os_code = os_code;
+        self
+    }
+
+    /// Attaches the external command that failed, replacing the given secrets (passwords) in its arguments
+    pub fn with_command(mut self, command: &Command, secrets: &[&str]) -> Self {
+        self.details_mut().command = Some(dry_run::format_command(command, secrets));
+        self
+    }
+
+    /// Builds errors with the same cause as this one (e.g. to sum up several failures by the first one)
+    pub fn cause(&self) -> Cause {
+        match self {
+            UpvError::Vpn(_) => UpvError::Vpn,
+            UpvError::VpnNotFound(_) => UpvError::VpnNotFound,
+            UpvError::VpnAuthFailed(_) => UpvError::VpnAuthFailed,
+            UpvError::VpnUnreachable(_) => UpvError::VpnUnreachable,
+            UpvError::Drive(_) => UpvError::Drive,
+            UpvError::DriveInUse(_) => UpvError::DriveInUse,
+            UpvError::DriveAuthFailed(_) => UpvError::DriveAuthFailed,
+            UpvError::DriveUnreachable(_) => UpvError::DriveUnreachable,
+            UpvError::InternetUnreachable(_) => UpvError::InternetUnreachable,
+            UpvError::PowerShellFailed(_) => UpvError::PowerShellFailed,
+            UpvError::Config(_) => UpvError::Config,
+            UpvError::Credentials(_) => UpvError::Credentials,
+            UpvError::InvalidValue(_) => UpvError::InvalidValue,
+            UpvError::ChecksFailed(_) => UpvError::ChecksFailed,
+            UpvError::TaskScheduler(_) => UpvError::TaskScheduler,
+            UpvError::ArtifactNotFound(_) => UpvError::ArtifactNotFound,
+            UpvError::Interrupted(_) => UpvError::Interrupted,
+        }
+    }
+
+    /// Exit code of the program when it fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            UpvError::Vpn(_) => EXIT_UPV_VPN_ERROR,
+            UpvError::VpnNotFound(_) => EXIT_UPV_VPN_NOT_FOUND_ERROR,
+            UpvError::VpnAuthFailed(_) | UpvError::DriveAuthFailed(_) => EXIT_UPV_AUTH_ERROR,
+            UpvError::VpnUnreachable(_) | UpvError::DriveUnreachable(_) | UpvError::InternetUnreachable(_) => EXIT_UPV_UNREACHABLE_ERROR,
+            UpvError::Drive(_) => EXIT_UPV_DRIVE_ERROR,
+            UpvError::DriveInUse(_) => EXIT_UPV_DRIVE_IN_USE_ERROR,
+            UpvError::PowerShellFailed(_) => EXIT_UPV_POWERSHELL_ERROR,
+            UpvError::Interrupted(_) => EXIT_UPV_INTERRUPTED_ERROR,
+            UpvError::Config(_)
+            | UpvError::Credentials(_)
+            | UpvError::InvalidValue(_)
+            | UpvError::ChecksFailed(_)
+            | UpvError::TaskScheduler(_)
+            | UpvError::ArtifactNotFound(_) => EXIT_PROGRAM_ERROR,
+        }
+    }
+
+    /// Machine-readable name for the category of this error
+    pub fn kind(&self) -> &'static str {
+        match self {
+            UpvError::Vpn(_) => "vpn_error",
+            UpvError::VpnNotFound(_) => "vpn_not_found",
+            UpvError::VpnAuthFailed(_) | UpvError::DriveAuthFailed(_) => "auth_failed",
+            UpvError::VpnUnreachable(_) | UpvError::DriveUnreachable(_) | UpvError::InternetUnreachable(_) => "unreachable",
+            UpvError::Drive(_) => "drive_error",
+            UpvError::DriveInUse(_) => "drive_in_use",
+            UpvError::PowerShellFailed(_) => "powershell_unavailable",
+            UpvError::Config(_) => "config_error",
+            UpvError::Credentials(_) => "credentials_error",
+            UpvError::InvalidValue(_) => "invalid_value",
+            UpvError::ChecksFailed(_) => "checks_failed",
+            UpvError::TaskScheduler(_) => "task_scheduler_error",
+            UpvError::ArtifactNotFound(_) => "artifact_not_found",
+            UpvError::Interrupted(_) => "interrupted",
+        }
+    }
+}
+
+/// Extracts the system error number from the output of commands like `net use`
+/// (e.g. "System error 86 has occurred.", or "... typing NET HELPMSG 2250." for network errors).
+/// The text around it is localized, so the number after HELPMSG is taken if there is one, or else the first word that
+/// starts with a number (as in "53이(가)"). Digits in server names, IP addresses or paths (\\10.0.0.1\share2) don't count.
+pub fn parse_os_code(output: &str) -> Option<i32> {
+    let number = |word: &str| {
+        let digits = word.len() - word.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let mut rest = word[digits..].chars();
+        let standalone = match rest.next() {
+            None => true,
+            Some(c) => !c.is_ascii_alphanumeric() && !matches!(c, '\\' | '/' | ':') && !rest.next().is_some_and(|c| c.is_ascii_digit()),
+        };
+        (digits > 0 && standalone).then(|| word[..digits].parse().ok()).flatten()
+    };
+
+    let words: Vec<&str> = output.split_whitespace().collect();
+    words.windows(2)
+        .find(|pair| pair[0].eq_ignore_ascii_case("HELPMSG"))
+        .and_then(|pair| number(pair[1]))
+        .or_else(|| words.iter().find_map(|word| number(word)))
 }
 
-impl fmt::Display for UpvError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.message)
+/// Description of a Win32/RAS error code, as given by `net helpmsg` (in the system language)
+pub fn os_message(os_code: i32) -> Option<String> {
+    let output = Command::new("net")
+        .args(["helpmsg", &os_code.to_string()])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
     }
+
+    let message = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    (!message.is_empty()).then_some(message)
 }
 
-impl std::error::Error for UpvError {}
\ No newline at end of file
+/// Win32/RAS error code behind an error, if any
+pub fn os_code(error: &anyhow::Error) -> Option<i32> {
+    // Look for an OS error code anywhere in the chain (e.g. a command that could not be spawned)
+    let io_code = error.chain()
+        .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .and_then(|io_error| io_error.raw_os_error());
+
+    error.downcast_ref::<UpvError>()
+        .and_then(|upv_error| upv_error.details().os_code)
+        .or(io_code)
+}
+
+/// Messages of the errors that caused this one, from the closest to the root cause
+fn causes(error: &anyhow::Error) -> Vec<String> {
+    error.chain().skip(1).map(ToString::to_string).collect()
+}
+
+/// Prints an error for humans: the error, what caused it, the system error code and a hint on how to fix it,
+/// plus the command that failed in verbose mode.
+pub fn print(error: &anyhow::Error, verbose: bool) {
+    let upv_error = error.downcast_ref::<UpvError>();
+
+    eprintln!("\x1b[1;31mError:\x1b[0m {}", error);
+
+    let causes = causes(error);
+    if !causes.is_empty() {
+        eprintln!("\x1b[33mCaused by:\x1b[0m");
+        for cause in &causes {
+            eprintln!("  - {}", cause);
+        }
+    }
+
+    if let Some(os_code) = os_code(error) {
+        match os_message(os_code) {
+            Some(os_message) => eprintln!("\x1b[33mError code:\x1b[0m {} ({})", os_code, os_message),
+            None => eprintln!("\x1b[33mError code:\x1b[0m {}", os_code),
+        }
+    }
+
+    if verbose && let Some(command) = upv_error.and_then(|err| err.details().command.as_ref()) {
+        eprintln!("\x1b[2mCommand: {}\x1b[0m", command);
+    }
+
+    if let Some(hint) = upv_error.and_then(|err| err.details().hint.as_ref()) {
+        eprintln!("\x1b[36mHint:\x1b[0m {}", hint);
+    }
+}
+
+/// Builds the JSON error object printed in `--output json` mode (with the command that failed in verbose mode)
+pub fn to_json(error: &anyhow::Error, verbose: bool) -> serde_json::Value {
+    let os_code = os_code(error);
+    let os_message = os_code.and_then(os_message);
+
+    match error.downcast_ref::<UpvError>() {
+        Some(upv_error) => serde_json::json!({
+            "kind": upv_error.kind(),
+            "message": error.to_string(),
+            "causes": causes(error),
+            "exit_code": upv_error.exit_code(),
+            "hint": upv_error.details().hint,
+            "os_code": os_code,
+            "os_message": os_message,
+            "command": upv_error.details().command.as_ref().filter(|_| verbose),
+        }),
+        None => serde_json::json!({
+            "kind": "program_error",
+            "message": error.to_string(),
+            "causes": causes(error),
+            "exit_code": EXIT_PROGRAM_ERROR,
+            "hint": null,
+            "os_code": os_code,
+            "os_message": os_message,
+            "command": null,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_system_errors_in_any_language() {
+        assert_eq!(parse_os_code("System error 53 has occurred.\r\n\r\nThe network path was not found.\r\n"), Some(53));
+        assert_eq!(parse_os_code("Error de sistema 1326.\r\n\r\nEl nombre de usuario o la contraseña no son correctos.\r\n"), Some(1326));
+        assert_eq!(parse_os_code("Systemfehler 86 aufgetreten.\r\n"), Some(86));
+        assert_eq!(parse_os_code("시스템 오류 53이(가) 발생했습니다.\r\n"), Some(53));
+        assert_eq!(parse_os_code("发生系统错误 1231。\r\n"), Some(1231));
+    }
+
+    #[test]
+    fn prefers_the_helpmsg_number() {
+        let output = "The network connection could not be found.\r\n\r\nMore help is available by typing NET HELPMSG 2250.\r\n";
+        assert_eq!(parse_os_code(output), Some(2250));
+        assert_eq!(parse_os_code("Se produjo un error de red 3 veces.\r\nPara obtener más ayuda, escriba NET HELPMSG 2250.\r\n"), Some(2250));
+    }
+
+    #[test]
+    fn ignores_numbers_in_servers_and_paths() {
+        assert_eq!(parse_os_code("\\\\10.0.0.1\\share2: System error 67 has occurred."), Some(67));
+        assert_eq!(parse_os_code("W: \\\\nas2.upv.es\\discos\\a\\alice\r\nSystem error 1219 has occurred."), Some(1219));
+        assert_eq!(parse_os_code("Drive Z: at 192.168.1.20 was not found"), None);
+    }
+
+    #[test]
+    fn causes_keep_their_exit_codes() {
+        let causes: [(Cause, i32, &str); 5] = [
+            (UpvError::Vpn, 11, "vpn_error"),
+            (UpvError::DriveInUse, 13, "drive_in_use"),
+            (UpvError::DriveAuthFailed, 15, "auth_failed"),
+            (UpvError::VpnUnreachable, 16, "unreachable"),
+            (UpvError::Config, EXIT_PROGRAM_ERROR, "config_error"),
+        ];
+        for (cause, exit_code, kind) in causes {
+            let error = cause("Failed".into()).with_hint("Try again");
+            assert_eq!((error.exit_code(), error.kind()), (exit_code, kind));
+            assert_eq!(error.cause()("Failed again".into()).kind(), kind);
+            assert_eq!(error.to_string(), "Failed");
+        }
+    }
+
+    #[test]
+    fn returns_none_without_a_code() {
+        assert_eq!(parse_os_code(""), None);
+        assert_eq!(parse_os_code("The command completed successfully.\r\n"), None);
+    }
+}
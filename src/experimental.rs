@@ -0,0 +1,22 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Experimental features ship early for feedback, but stay off unless asked for, so the default experience stays stable.
+// They are turned on with --enable-experimental, the UPV_EXPERIMENTAL environment variable or `experimental = true`
+// in the config file. Current ones:
+// - Native VPN backend: lists, checks, connects, disconnects and deletes connections with the RAS API instead of PowerShell
+pub const ENV_VAR: &str = "UPV_EXPERIMENTAL";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether the environment variable asks for experimental features (any value but empty, "0" or "false")
+pub fn requested_by_env() -> bool {
+    std::env::var(ENV_VAR).is_ok_and(|value| !matches!(value.trim().to_ascii_lowercase().as_str(), "" | "0" | "false"))
+}
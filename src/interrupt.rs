@@ -0,0 +1,55 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows_sys::core::BOOL;
+use windows_sys::Win32::System::Console::{SetConsoleCtrlHandler, CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+use crate::error::UpvError;
+
+// Commands that take several steps (connecting and then mounting, mounting every profile...) catch Ctrl+C while they
+// run, so they can undo the steps already taken instead of leaving the VPN up or half of the drives mounted. The child
+// processes share the console, so they still get the Ctrl+C and end, which makes the step in progress fail; the steps
+// check for it in between. A second Ctrl+C ends upv-cli right away, as usual.
+
+// Whether Ctrl+C was pressed while it was being caught
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "system" fn handler(ctrl_type: u32) -> BOOL {
+    if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+        return 0;
+    }
+
+    // Handled the first time (so upv-cli goes on to clean up), passed on to the default handler (which exits) afterwards
+    if REQUESTED.swap(true, Ordering::SeqCst) { 0 } else { 1 }
+}
+
+/// Catches Ctrl+C until it is dropped
+pub struct Guard(());
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // SAFETY: the handler was added by `catch` and is a function that lives for the whole program
+        unsafe { SetConsoleCtrlHandler(Some(handler), 0) };
+    }
+}
+
+/// Starts catching Ctrl+C, so a composite operation can check for it with `check` and undo what it did
+pub fn catch() -> Guard {
+    REQUESTED.store(false, Ordering::SeqCst);
+    // SAFETY: the handler is a function that lives for the whole program, and only touches an atomic
+    unsafe { SetConsoleCtrlHandler(Some(handler), 1) };
+    Guard(())
+}
+
+/// Whether Ctrl+C was pressed since it started being caught
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fails if Ctrl+C was pressed, so the operation stops before its next step
+pub fn check() -> Result<()> {
+    if requested() {
+        return Err(UpvError::Interrupted("Interrupted by Ctrl+C".into()).into());
+    }
+
+    Ok(())
+}
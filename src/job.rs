@@ -0,0 +1,61 @@
+use std::mem::size_of;
+use std::process::Command;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use windows_sys::Win32::System::JobObjects::{
+    AssignProcessToJobObject, CreateJobObjectW, IsProcessInJob, JobObjectExtendedLimitInformation, SetInformationJobObject,
+    JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_BREAKAWAY_OK, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+};
+use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+// upv-cli runs inside a job object that kills every process in it when its last handle is closed. That handle is never
+// closed explicitly, so Windows closes it when upv-cli exits for any reason (finishing, Ctrl+C, a crash or being killed),
+// taking along any PowerShell, net, rasdial or rasphone process still running. Otherwise those could outlive it and keep
+// the phonebook or a drive mapping locked. Processes started afterwards join the job automatically.
+
+// Whether upv-cli is in its own job, so processes that must outlive it have to leave it
+static CONTAINED: AtomicBool = AtomicBool::new(false);
+
+/// Puts upv-cli in a job object that terminates its child processes when it exits.
+/// If it is already in a job (started by a service, a CI runner or some terminals), that job is left in charge of them,
+/// since it may not allow processes like Explorer to break away.
+pub fn contain_children() {
+    let mut in_job = 0;
+    // SAFETY: the pseudo-handle of the current process is always valid, and the result outlives the call
+    if unsafe { IsProcessInJob(GetCurrentProcess(), ptr::null_mut(), &mut in_job) } == 0 || in_job != 0 {
+        return;
+    }
+
+    // SAFETY: no security attributes or name are passed, so the job is unnamed and its handle isn't inherited
+    let job = unsafe { CreateJobObjectW(ptr::null(), ptr::null()) };
+    if job.is_null() {
+        return;
+    }
+
+    let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+    info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE | JOB_OBJECT_LIMIT_BREAKAWAY_OK;
+
+    // SAFETY: the job handle is valid, and the info struct has the size passed and outlives the calls.
+    // The job handle is deliberately leaked, since closing it is what kills the children.
+    let contained = unsafe {
+        SetInformationJobObject(
+            job,
+            JobObjectExtendedLimitInformation,
+            &info as *const JOBOBJECT_EXTENDED_LIMIT_INFORMATION as *const _,
+            size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        ) != 0 && AssignProcessToJobObject(job, GetCurrentProcess()) != 0
+    };
+    CONTAINED.store(contained, Ordering::Relaxed);
+}
+
+/// Lets a process started with this command outlive upv-cli (like an Explorer window), by starting it outside of its job.
+pub fn detached(command: &mut Command) -> &mut Command {
+    // Creation flags only exist on Windows (this is also built elsewhere, where main() exits right away)
+    #[cfg(windows)]
+    if CONTAINED.load(Ordering::Relaxed) {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(windows_sys::Win32::System::Threading::CREATE_BREAKAWAY_FROM_JOB);
+    }
+
+    command
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use anyhow::{Result, Context};
+use chrono::{DateTime, Duration, Local};
+
+use crate::error::Cause;
+use crate::paths;
+
+// UPV locks accounts after several failed logins in a short time, so retries with wrong credentials are limited:
+// after MAX_FAILURES failed attempts within FAILURE_WINDOW, attempts are refused until the oldest one expires.
+const FAILURES_FILE: &str = "auth-failures.json";
+const MAX_FAILURES: usize = 3;
+const FAILURE_WINDOW: Duration = Duration::minutes(15);
+
+pub struct LockoutGuard;
+
+impl LockoutGuard {
+    // Private utility functions
+
+    fn failures_path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(FAILURES_FILE))
+    }
+
+    /// The same account can be given as "user" or "user@upv.es"
+    fn account_key(account: &str) -> String {
+        account.split('@').next().unwrap_or(account).to_lowercase()
+    }
+
+    /// Reads the failed attempts of every account, dropping the ones outside of the window.
+    fn load() -> Result<BTreeMap<String, Vec<DateTime<Local>>>> {
+        let path = Self::failures_path()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+
+        // A corrupted file shouldn't prevent logging in, so it is just ignored
+        let mut failures: BTreeMap<String, Vec<DateTime<Local>>> = serde_json::from_str(&content).unwrap_or_default();
+
+        let cutoff = Local::now() - FAILURE_WINDOW;
+        for attempts in failures.values_mut() {
+            attempts.retain(|attempt| *attempt > cutoff);
+        }
+        failures.retain(|_, attempts| !attempts.is_empty());
+
+        Ok(failures)
+    }
+
+    fn save(failures: &BTreeMap<String, Vec<DateTime<Local>>>) -> Result<()> {
+        let path = Self::failures_path()?;
+        let content = serde_json::to_string_pretty(failures).context("Failed to serialize failed login attempts")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))
+    }
+
+    // Public methods for login attempt tracking
+
+    /// Refuses to log in with an account that failed too many times recently (unless overridden),
+    /// and warns when the next failure would reach the limit.
+    pub fn check(account: &str, override_lockout: bool, cause: Cause) -> Result<()> {
+        let failures = Self::load()?;
+        let Some(attempts) = failures.get(&Self::account_key(account)) else {
+            return Ok(());
+        };
+
+        if attempts.len() >= MAX_FAILURES && !override_lockout {
+            let retry_at = attempts.iter().min().map(|oldest| *oldest + FAILURE_WINDOW).unwrap_or_else(Local::now);
+            return Err(cause(
+                format!(
+                    "Logging in as '{}' failed {} times in the last {} minutes. Not trying again until {}, to avoid getting the account locked by UPV.",
+                    account, attempts.len(), FAILURE_WINDOW.num_minutes(), retry_at.format("%H:%M")
+                ).into()
+            ).with_hint("Check your username and password. If you are sure they are correct now, run this again with --override").into());
+        }
+
+        if attempts.len() + 1 >= MAX_FAILURES {
+            eprintln!(
+                "Warning: logging in as '{}' already failed {} time(s) recently. Another failure could get the account locked by UPV.",
+                account, attempts.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Records a login that failed because of wrong credentials.
+    pub fn record_failure(account: &str) -> Result<()> {
+        let mut failures = Self::load()?;
+        failures.entry(Self::account_key(account)).or_default().push(Local::now());
+        Self::save(&failures)
+    }
+
+    /// Forgets the failed attempts of an account after a successful login.
+    pub fn record_success(account: &str) -> Result<()> {
+        let mut failures = Self::load()?;
+        if failures.remove(&Self::account_key(account)).is_some() {
+            Self::save(&failures)?;
+        }
+        Ok(())
+    }
+}
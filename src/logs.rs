@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use anyhow::{Result, Context};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{self, UpvError};
+use crate::powershell;
+
+// Windows logs every VPN dial, connection, disconnection and failure as a RasClient event in the Application log.
+// Event levels: 1 = Critical, 2 = Error, 3 = Warning, 4 = Information
+const MAX_ERROR_LEVEL: u8 = 2;
+const WARNING_LEVEL: u8 = 3;
+
+// Events whose message ends with an error code: 20226 (disconnected, with the reason) and 20227 (dial failed)
+const EVENTS_WITH_CODE: [u32; 2] = [20226, 20227];
+
+/// A RasClient event, as read from the event log
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "PascalCase"))]
+struct RasEvent {
+    time: String,
+    id: u32,
+    level: u8,
+    message: String,
+    /// Error or reason code reported by the event, if any
+    #[serde(default)]
+    code: Option<i32>,
+}
+
+impl RasEvent {
+    /// The message without the connection id every RasClient message starts with ("CoId={...}: ")
+    fn text(&self) -> &str {
+        match self.message.split_once("}: ") {
+            Some((prefix, text)) if prefix.starts_with("CoId=") => text,
+            _ => &self.message,
+        }
+    }
+}
+
+pub struct LogsManager;
+
+impl LogsManager {
+    // Private utility functions
+
+    /// Parses how far back to look: a duration ("30m", "2h", "1d", "1w") or a local date and time
+    /// ("2025-01-31" or "2025-01-31 14:00").
+    fn parse_since(since: &str) -> Result<DateTime<Local>> {
+        let since = since.trim();
+        let invalid = || UpvError::InvalidValue(
+            format!("'{}' is not a valid time (expected a duration like 30m, 2h, 1d or 1w, or a date like 2025-01-31 or 2025-01-31 14:00)", since).into()
+        );
+
+        if let Some(unit) = since.chars().last().filter(char::is_ascii_alphabetic) {
+            let amount: i64 = since[..since.len() - 1].parse().map_err(|_| invalid())?;
+            let duration = match unit.to_ascii_lowercase() {
+                'm' => Duration::minutes(amount),
+                'h' => Duration::hours(amount),
+                'd' => Duration::days(amount),
+                'w' => Duration::weeks(amount),
+                _ => return Err(invalid().into()),
+            };
+            return Ok(Local::now() - duration);
+        }
+
+        let naive = NaiveDateTime::parse_from_str(since, "%Y-%m-%d %H:%M")
+            .or_else(|_| NaiveDate::parse_from_str(since, "%Y-%m-%d").map(|date| date.and_time(Default::default())))
+            .map_err(|_| invalid())?;
+        Local.from_local_datetime(&naive).earliest().ok_or_else(|| invalid().into())
+    }
+
+    /// Reads the RasClient events since the given time, newest first.
+    fn read_events(since: DateTime<Local>, errors_only: bool, max: u32) -> Result<Vec<RasEvent>> {
+        let levels = if errors_only { "; Level = 1, 2" } else { "" };
+
+        // Get-WinEvent fails when nothing matches, which is not an error here. Levels are read as numbers, since their names are localized
+        let ps_command = format!(
+            "Get-WinEvent -FilterHashtable @{{ LogName = 'Application'; ProviderName = 'RasClient'; StartTime = [datetime]'{}'{} }} -MaxEvents {} -ErrorAction SilentlyContinue | \
+            ForEach-Object {{ [PSCustomObject]@{{ Time = $_.TimeCreated.ToString('yyyy-MM-dd HH:mm:ss'); Id = $_.Id; Level = [int]$_.Level; Message = ($_.Message -replace '\\s+', ' ').Trim() }} }}",
+            since.format("%Y-%m-%dT%H:%M:%S"),
+            levels,
+            max.max(1)
+        );
+
+        let mut events: Vec<RasEvent> = powershell::query(&ps_command)
+            .map_err(|e| UpvError::Vpn(format!("Failed to read the RasClient event log: {}", e).into()))?;
+
+        // The codes are the last number in the message (its wording is localized)
+        for event in events.iter_mut().filter(|event| EVENTS_WITH_CODE.contains(&event.id)) {
+            event.code = event.message
+                .split(|c: char| !c.is_ascii_digit())
+                .rfind(|number| !number.is_empty())
+                .and_then(|number| number.parse().ok());
+        }
+
+        Ok(events)
+    }
+
+    // Public methods for the VPN event log
+
+    /// Prints the recent VPN connects, disconnects and errors logged by Windows (as JSON if requested),
+    /// explaining the error codes of failed connections (such as 809 or 691).
+    pub fn show(since: &str, errors_only: bool, max: u32, json: bool) -> Result<()> {
+        let since = Self::parse_since(since)?;
+        let events = Self::read_events(since, errors_only, max)?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&events).context("Failed to serialize the VPN events")?);
+            return Ok(());
+        }
+
+        let kind = if errors_only { "VPN errors" } else { "VPN events" };
+        if events.is_empty() {
+            println!("No {} logged since {}", kind, since.format("%Y-%m-%d %H:%M"));
+            return Ok(());
+        }
+
+        println!("{} since {} (newest first):", kind, since.format("%Y-%m-%d %H:%M"));
+
+        // Several events usually share the same code, so each one is only looked up once
+        let mut descriptions: BTreeMap<i32, Option<String>> = BTreeMap::new();
+        for event in &events {
+            let symbol = match event.level {
+                1..=MAX_ERROR_LEVEL => "\x1b[31m✗\x1b[0m",
+                WARNING_LEVEL => "\x1b[33m!\x1b[0m",
+                _ => "\x1b[2m·\x1b[0m",
+            };
+            println!("  {} {}  {}", symbol, event.time, event.text());
+
+            if let Some(code) = event.code.filter(|code| *code != 0)
+                && let Some(description) = descriptions.entry(code).or_insert_with(|| error::os_message(code)) {
+                println!("      \x1b[2m{}: {}\x1b[0m", code, description);
+            }
+        }
+
+        Ok(())
+    }
+}
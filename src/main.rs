@@ -3,25 +3,73 @@
 // This CLI tool manages UPV's VPN connection and Personal Network Drive (Disco W) on Windows.
 // It allows users to create, connect, disconnect, and check the status of VPN connections,
 // as well as mount, unmount, and check the status of the personal network drive.
-// It uses PowerShell commands for VPN management and Windows commands for network drive operations.
+// It uses PowerShell commands (or the RAS API, as an experimental feature) for VPN management and Windows commands for network drive operations.
 
 // Dependencies:
 // - clap: For command-line argument parsing
 // - anyhow: For error handling
+// - chrono: For timestamps in watch modes
+// - serde / serde_json: For JSON output and local state files
+// - keyring / rpassword: For storing UPV credentials in the Windows Credential Manager
+// - toml: For the config file
+// - windows-sys: For Windows APIs without a command-line equivalent (shell notifications, job objects) or much faster than one (RAS)
 
+mod about;
+mod catalog;
 mod cli;
+mod config;
+mod confirm;
+mod creds;
+mod diff;
+mod doctor;
 mod drive;
-mod vpn;
+mod dry_run;
+mod env;
 mod error;
+mod experimental;
+mod interrupt;
+mod job;
+mod lockout;
+mod logs;
+mod maintenance;
+mod manifest;
+mod network;
+mod paths;
+mod phonebook;
+mod ping;
+mod portal;
+mod powershell;
+mod provision;
+mod ras;
+mod schedule;
+mod status;
+mod vpn;
+mod vpn_backend;
+mod wide;
+mod wnet;
 
 use clap::{Parser, CommandFactory};
 use anyhow::Result;
-use clap_complete::generate;
+use clap_complete::{generate, CompleteEnv};
 use std::{io, process};
+use std::time::Duration;
 
-use cli::{Cli, Commands, VpnAction, DriveAction};
-use drive::DriveManager;
-use vpn::VpnManager;
+use cli::{Cli, Commands, VpnAction, NetAction, DriveAction, ProvisionAction, ConfigAction, CredsAction, ScheduleAction, OutputFormat, Toggle, DriveChoice};
+use about::AboutManager;
+use catalog::CatalogManager;
+use config::{Config, ConfigManager, DriveProfile};
+use creds::CredsManager;
+use doctor::DoctorManager;
+use drive::{DriveManager, MountOptions, SyncOptions, WebdavMode};
+use env::EnvManager;
+use logs::LogsManager;
+use manifest::ManifestManager;
+use ping::PingManager;
+use portal::PortalManager;
+use provision::ProvisionManager;
+use schedule::{ScheduleManager, ScheduleTrigger, ScheduledAction};
+use status::StatusManager;
+use vpn::{CreateOptions, VpnManager, VpnSettings};
 use error::{UpvError, EXIT_SUCCESS, EXIT_PROGRAM_ERROR};
 
 #[cfg(not(target_os = "windows"))]
@@ -32,14 +80,27 @@ fn main() -> anyhow::Result<()> {
 
 #[cfg(target_os = "windows")]
 fn main() {
-    let exit_code = match run() {
+    // Child processes (PowerShell, net, rasdial...) are ended along with upv, even if it is interrupted
+    job::contain_children();
+
+    // Dynamic completions: when run by the shell's completion script (COMPLETE=<shell>), print the candidates and exit
+    CompleteEnv::with_factory(Cli::command).complete();
+
+    let cli = Cli::parse();
+    let output = cli.output;
+    let verbose = cli.verbose;
+
+    let exit_code = match run(cli) {
         Ok(()) => EXIT_SUCCESS,
         Err(e) => {
-            eprintln!("Error: {}", e);
+            match output {
+                OutputFormat::Json => eprintln!("{}", error::to_json(&e, verbose)),
+                OutputFormat::Text => error::print(&e, verbose),
+            }
             
             // Extraer código de salida si es CliError
             if let Some(cli_error) = e.downcast_ref::<UpvError>() {
-                cli_error.exit_code
+                cli_error.exit_code()
             } else {
                 EXIT_PROGRAM_ERROR // Error técnico/anyhow genérico
             }
@@ -49,53 +110,524 @@ fn main() {
     process::exit(exit_code);
 }
 
-fn run() -> Result<()> {
-    let cli = Cli::parse();
-    
+fn run(cli: Cli) -> Result<()> {
+    let json = cli.output == OutputFormat::Json;
+    if cli.dry_run {
+        dry_run::enable();
+    }
+
+    // Defaults for omitted arguments (not loaded by 'upv config', so a broken config file can still be fixed),
+    // as seen from the account selected with --account
+    let config = match cli.command {
+        Commands::Config { .. } | Commands::Completions { .. } | Commands::PackageManifest { .. } | Commands::Net { .. } => Config::default(),
+        _ => ConfigManager::load()?.with_account(cli.account.as_deref())?,
+    };
+
+    if cli.enable_experimental || experimental::requested_by_env() || config.experimental == Some(true) {
+        experimental::enable();
+    }
+    VpnManager::set_server_address(config.vpn_server(None));
+    confirm::set(config.confirmations.clone());
+
+    // Connecting, disconnecting, mounting or unmounting makes the cached status out of date
+    let changes_status = matches!(
+        &cli.command,
+        Commands::Vpn { action: VpnAction::Create { .. } | VpnAction::Connect { .. } | VpnAction::Disconnect { .. } | VpnAction::Toggle { .. } | VpnAction::Delete { .. } | VpnAction::Purge { .. } | VpnAction::UndoDelete }
+            | Commands::Drive { action: DriveAction::Mount { .. } | DriveAction::MountShare { .. } | DriveAction::Unmount { .. } | DriveAction::Remount { .. } | DriveAction::EjectSafe { .. } }
+    );
+    if changes_status && !dry_run::enabled() {
+        StatusManager::invalidate_cache();
+    }
+
     match cli.command {
         Commands::Vpn { action } => {
             match action {
-                VpnAction::Create { name, connect } => {
-                    VpnManager::create(&name, connect)?;
+                VpnAction::Create { name, server, tunnel_type, split_tunnel, connect, idempotent, eap_config, set_default, dns_suffix, from } => {
+                    let options = CreateOptions {
+                        server: config.vpn_server(server),
+                        tunnel_type: tunnel_type.unwrap_or_default(),
+                        split_tunnel,
+                        auto_connect: connect,
+                        idempotent,
+                        eap_config: config.eap_config(eap_config),
+                        set_default,
+                        dns_suffix: Some(dns_suffix),
+                    };
+                    match from {
+                        Some(source) => VpnManager::clone_connection(&source, &config.vpn_name(name)?, &options, tunnel_type)?,
+                        None => VpnManager::create(&config.vpn_name(name)?, &options)?,
+                    }
                 }
-                VpnAction::Connect { name } => {
-                    VpnManager::connect(&name)?;
+                VpnAction::Connect { name, silent, user, password, override_lockout, wait, timeout, mount, profiled_routes, dns_suffix, entry_options } => {
+                    // The drive, the routes and the DNS suffixes need the tunnel to be up, so they wait for it. The drive settings are resolved first, so missing ones fail before connecting
+                    let name = config.vpn_name(name)?;
+                    let mount = if mount { Some((config.username(None)?, config.domain(None)?)) } else { None };
+                    if profiled_routes {
+                        VpnManager::check_profiled_routes(&name, config.routes(&name))?;
+                    }
+                    let wait = (wait || mount.is_some() || profiled_routes || dns_suffix).then(|| Duration::from_secs(timeout));
+                    // A configured credential source gives the password of the configured account, and --account picks the account
+                    // to log in with (the stored credentials are used otherwise, if they are its own)
+                    let (user, password) = match (user, password) {
+                        (None, None) if config.credentials.is_some() || cli.account.is_some() => {
+                            let (username, domain) = (config.username(None)?, config.domain(None)?);
+                            let user = format!("{}@{}", username, domain.email_domain());
+                            match config.password(None, &username, &domain)? {
+                                Some(password) => (Some(user), Some(password)),
+                                None if cli.account.is_some() => (Some(user), None),
+                                None => (None, None),
+                            }
+                        }
+                        given => given,
+                    };
+
+                    // The steps after connecting are undone (disconnecting) if one of them fails or Ctrl+C is pressed,
+                    // unless it was already connected before
+                    let composite = mount.is_some() || profiled_routes || dns_suffix;
+                    let was_connected = composite && VpnManager::get_connected_upv_connections().map_or(true, |connected| connected.contains(&name));
+                    let _interrupt = composite.then(interrupt::catch);
+                    let result = (|| -> Result<()> {
+                        VpnManager::connect(&name, silent, user.as_deref(), password.as_deref(), override_lockout, wait, &entry_options)?;
+
+                        if profiled_routes {
+                            interrupt::check()?;
+                            VpnManager::add_profiled_routes(&name, config.routes(&name))?;
+                        }
+                        if dns_suffix {
+                            interrupt::check()?;
+                            VpnManager::apply_dns_suffixes(&name, &config.dns_suffixes)?;
+                        }
+
+                        let Some((username, domain)) = mount else {
+                            return Ok(());
+                        };
+                        interrupt::check()?;
+                        let options = MountOptions {
+                            password: None,
+                            share_template: config.share_template(None),
+                            webdav: WebdavMode::Never,
+                            webdav_template: None,
+                            alternate_servers: config.nas_hosts.clone(),
+                            label: None,
+                            drive: config.drive(None),
+                            open_explorer: false,
+                            timeout: 30,
+                            wait_for_vpn: false,
+                            refresh_explorer: true,
+                            check_credentials: true,
+                            override_lockout,
+                            persistent: false,
+                            remap: false,
+                            auto_vpn: None,
+                        };
+                        DriveManager::mount(&username, &domain, &options)
+                    })();
+
+                    if let Err(e) = result {
+                        if composite && !was_connected {
+                            let extra_suffixes = if dns_suffix { config.dns_suffixes.as_slice() } else { &[] };
+                            VpnManager::undo_connect(&name, extra_suffixes);
+                        }
+                        return Err(e);
+                    }
+                }
+                VpnAction::Disconnect { unmount, wait, timeout } => {
+                    if unmount {
+                        DriveManager::unmount_upv_drives(false, true)?;
+                    }
+                    VpnManager::disconnect(wait.then(|| Duration::from_secs(timeout)))?;
+                }
+                VpnAction::Watch { name, interval, backoff, max_retries } => {
+                    VpnManager::watch(&config.vpn_name(name)?, Duration::from_secs(interval.max(1)), Duration::from_secs(backoff), max_retries)?;
                 }
-                VpnAction::Disconnect => {
-                    VpnManager::disconnect()?;
+                VpnAction::Toggle { name } => {
+                    VpnManager::toggle(&config.vpn_name(name)?)?;
                 }
-                VpnAction::Delete { name, force } => {
-                    VpnManager::delete(&name, force)?;
+                VpnAction::Delete { name, force, stdin } => {
+                    if stdin || name.as_deref() == Some("-") {
+                        VpnManager::delete_many(&cli::read_stdin_lines()?, force)?;
+                    } else {
+                        VpnManager::delete(&config.vpn_name(name)?, force)?;
+                    }
                 }
-                VpnAction::List => {
-                    VpnManager::list()?;
+                VpnAction::List { quiet } => {
+                    VpnManager::list(quiet, cli.verbose)?;
                 }
                 VpnAction::Purge { force, except } => {
                     VpnManager::purge(force, except)?;
                 }
+                VpnAction::UndoDelete => {
+                    VpnManager::undo_delete()?;
+                }
                 VpnAction::Status => {
                     VpnManager::status()?;
                 }
+                VpnAction::Ping { count } => {
+                    PingManager::run(count)?;
+                }
+                VpnAction::Logs { since, errors_only, max } => {
+                    LogsManager::show(&since, errors_only, max, json)?;
+                }
+                VpnAction::SetDefault { name } => {
+                    VpnManager::set_default(&name)?;
+                }
+                VpnAction::Show { name } => {
+                    VpnManager::show(&config.vpn_name(name)?, json)?;
+                }
+                VpnAction::Repair { name, outdated_only } => {
+                    if outdated_only {
+                        VpnManager::repair_outdated()?;
+                    } else {
+                        VpnManager::repair(&config.vpn_name(name)?)?;
+                    }
+                }
+                VpnAction::Verify { name, tunnel_type } => {
+                    VpnManager::verify(&config.vpn_name(name)?, &config.vpn_server(None), tunnel_type, config.eap_config(None).as_deref(), json)?;
+                }
+                VpnAction::Set { name, remember_credential, split_tunnel, dns_suffix, idle_disconnect, dns, dns_auto, add_route, remove_route } => {
+                    let settings = VpnSettings {
+                        remember_credential: remember_credential.map(Toggle::is_on),
+                        split_tunneling: split_tunnel.map(Toggle::is_on),
+                        dns_suffix,
+                        idle_disconnect_seconds: idle_disconnect,
+                        dns_servers: if dns_auto { Some(Vec::new()) } else if dns.is_empty() { None } else { Some(dns) },
+                        add_routes: add_route,
+                        remove_routes: remove_route,
+                    };
+                    VpnManager::set(&config.vpn_name(name)?, &settings)?;
+                }
+                VpnAction::Dns { name, suffix, nrpt, remove_nrpt } => {
+                    VpnManager::dns(&config.vpn_name(name)?, &suffix, nrpt, remove_nrpt)?;
+                }
+                VpnAction::Export { name, format, file } => {
+                    VpnManager::export(&config.vpn_name(name)?, format, file.as_deref())?;
+                }
+                VpnAction::Import { file, name, replace } => {
+                    VpnManager::import(&file, name.as_deref(), replace)?;
+                }
+                VpnAction::RestoreNetwork { force } => {
+                    VpnManager::restore_network(force)?;
+                }
             }
         }
         Commands::Drive { action } => {
             match action {
-                DriveAction::Mount { username, domain, password, drive, open } => {
-                    DriveManager::mount(&username, &domain, password.as_deref(), drive, open)?;
+                DriveAction::Mount { username, domain, password, drive, open, timeout, wait_for_vpn, auto_vpn, skip_credential_check, share_template, webdav, no_webdav_fallback, webdav_template, override_lockout, no_explorer_refresh, persistent, remap, profile, all_profiles } => {
+                    // The connection --auto-vpn falls back to is resolved first, so a missing one fails before mounting
+                    let auto_vpn = if auto_vpn { Some(config.vpn_name(None)?) } else { None };
+                    let webdav = if webdav {
+                        WebdavMode::Always
+                    } else if no_webdav_fallback {
+                        WebdavMode::Never
+                    } else {
+                        WebdavMode::Fallback
+                    };
+
+                    // Profiles fill in what isn't given as an argument (the username and domain, or the drive with --profile)
+                    if profile.is_some() || all_profiles {
+                        if all_profiles && matches!(drive, Some(DriveChoice::Letter(_))) {
+                            return Err(UpvError::InvalidValue(
+                                "--drive only takes 'auto' with --all-profiles, since the profiles can't share one letter".into()
+                            ).with_hint("Use --drive auto to move the profiles whose letter is taken to free ones").into());
+                        }
+                        let profiles: Vec<(&String, &DriveProfile)> = match &profile {
+                            Some(name) => vec![(name, config.drive_profile(name)?)],
+                            None if config.drive_profiles.is_empty() => return Err(UpvError::Config(
+                                "There are no drive profiles in the config file".into()
+                            ).with_hint("Add them with 'upv config edit' ([drive_profiles.<NAME>])").into()),
+                            None => config.drive_profiles.iter().collect(),
+                        };
+
+                        let mut mounts = Vec::new();
+                        for (name, entry) in profiles {
+                            // With --all-profiles, --drive auto is applied to the letters in conflict once they are all known
+                            let drive = match drive {
+                                Some(DriveChoice::Letter(letter)) => letter,
+                                Some(DriveChoice::Auto) if !all_profiles => DriveManager::first_free_drive(&config.drive_candidates(Some(entry.drive)))?,
+                                _ => entry.drive,
+                            };
+                            // A profile's share only falls back to WebDAV if the profile says where
+                            let (share_template, webdav, webdav_template) = match &entry.share {
+                                Some(share) if entry.webdav.is_none() => (Some(share.clone()), WebdavMode::Never, None),
+                                Some(share) => (Some(share.clone()), webdav, entry.webdav.clone()),
+                                None => (config.share_template(None), webdav, config.webdav_template(entry.webdav.clone())),
+                            };
+                            let (username, domain, credentials) = config.profile_identity(entry, username.clone(), domain.clone())?;
+                            let password = match &password {
+                                Some(password) => Some(password.clone()),
+                                None => config.password(credentials.as_ref(), &username, &domain)?,
+                            };
+                            let options = MountOptions {
+                                password,
+                                share_template,
+                                webdav,
+                                webdav_template,
+                                alternate_servers: config.nas_hosts.clone(),
+                                label: Some(name.clone()),
+                                drive: drive.to_ascii_uppercase(),
+                                open_explorer: open,
+                                timeout,
+                                wait_for_vpn,
+                                refresh_explorer: !no_explorer_refresh,
+                                check_credentials: !skip_credential_check,
+                                override_lockout,
+                                persistent,
+                                remap,
+                                auto_vpn: auto_vpn.clone(),
+                            };
+                            mounts.push((name.clone(), username, domain, options));
+                        }
+
+                        if all_profiles {
+                            DriveManager::mount_many(mounts, &config.drive_candidates(None), drive == Some(DriveChoice::Auto))?;
+                        } else if let Some((_, username, domain, options)) = mounts.first() {
+                            DriveManager::mount(username, domain, options)?;
+                        }
+                        return Ok(());
+                    }
+
+                    let auto = drive == Some(DriveChoice::Auto);
+                    let drive = match drive {
+                        Some(DriveChoice::Letter(letter)) => letter,
+                        Some(DriveChoice::Auto) => DriveManager::first_free_drive(&config.drive_candidates(None))?,
+                        None => config.drive(None),
+                    };
+                    let (username, domain) = (config.username(username)?, config.domain(domain)?);
+                    let password = match password {
+                        Some(password) => Some(password),
+                        None => config.password(None, &username, &domain)?,
+                    };
+                    let options = MountOptions {
+                        password,
+                        share_template: config.share_template(share_template),
+                        webdav,
+                        webdav_template: config.webdav_template(webdav_template),
+                        alternate_servers: config.nas_hosts.clone(),
+                        label: None,
+                        drive,
+                        open_explorer: open,
+                        timeout,
+                        wait_for_vpn,
+                        refresh_explorer: !no_explorer_refresh,
+                        check_credentials: !skip_credential_check,
+                        override_lockout,
+                        persistent,
+                        remap,
+                        auto_vpn,
+                    };
+                    DriveManager::mount(&username, &domain, &options)?;
+                    // The letter picked is printed last, on its own line, for scripts
+                    if auto {
+                        println!("{}:", drive);
+                    }
+                }
+                DriveAction::MountShare { name, username, domain, password, drive, open, timeout, wait_for_vpn, auto_vpn, skip_credential_check, override_lockout, no_explorer_refresh, persistent, remap } => {
+                    // The connection --auto-vpn falls back to is resolved first, so a missing one fails before mounting
+                    let auto_vpn = if auto_vpn { Some(config.vpn_name(None)?) } else { None };
+                    // UNC paths (departmental shares, group drives...) are mounted as they are, anything else is looked up in the catalogs
+                    let (path, label, suggested_drive) = if name.starts_with(r"\\") {
+                        (name.clone(), name, None)
+                    } else {
+                        let share = CatalogManager::find(&name)?;
+                        (share.path, share.name, share.drive)
+                    };
+                    let auto = drive == Some(DriveChoice::Auto);
+                    let drive = match drive {
+                        Some(DriveChoice::Letter(letter)) => letter,
+                        Some(DriveChoice::Auto) => DriveManager::first_free_drive(&config.drive_candidates(suggested_drive))?,
+                        None => suggested_drive.ok_or_else(|| UpvError::Drive(
+                            format!("No drive letter was given to mount '{}'", label).into()
+                        ).with_hint("Pick one with --drive (or --drive auto)"))?,
+                    };
+                    let (username, domain) = (config.username(username)?, config.domain(domain)?);
+                    let password = match password {
+                        Some(password) => Some(password),
+                        None => config.password(None, &username, &domain)?,
+                    };
+                    let options = MountOptions {
+                        password,
+                        share_template: Some(path),
+                        webdav: WebdavMode::Never,
+                        webdav_template: None,
+                        alternate_servers: config.nas_hosts.clone(),
+                        label: Some(label),
+                        drive: drive.to_ascii_uppercase(),
+                        open_explorer: open,
+                        timeout,
+                        wait_for_vpn,
+                        refresh_explorer: !no_explorer_refresh,
+                        check_credentials: !skip_credential_check,
+                        override_lockout,
+                        persistent,
+                        remap,
+                        auto_vpn,
+                    };
+                    DriveManager::mount(&username, &domain, &options)?;
+                    // The letter picked is printed last, on its own line, for scripts
+                    if auto {
+                        println!("{}:", drive);
+                    }
+                }
+                DriveAction::Shares => {
+                    CatalogManager::list()?;
+                }
+                DriveAction::Unmount { drive, force, stdin, all, restore_previous, no_explorer_refresh } => {
+                    if restore_previous {
+                        DriveManager::restore_previous(config.drive(drive), force, !no_explorer_refresh)?;
+                    } else if all {
+                        DriveManager::unmount_upv_drives(force, !no_explorer_refresh)?;
+                    } else if stdin {
+                        let drives = cli::read_stdin_lines()?
+                            .iter()
+                            .map(|line| cli::parse_drive_letter(line)
+                                .ok_or_else(|| UpvError::Drive(format!("'{}' is not a valid drive letter", line).into())))
+                            .collect::<Result<Vec<char>, UpvError>>()?;
+                        DriveManager::unmount_many(&drives, force, !no_explorer_refresh)?;
+                    } else {
+                        DriveManager::unmount(config.drive(drive), force, !no_explorer_refresh)?;
+                    }
+                }
+                DriveAction::Remount { drive, force, open, no_explorer_refresh } => {
+                    DriveManager::remount(config.drive(drive), force, open, !no_explorer_refresh)?;
+                }
+                DriveAction::EjectSafe { drive, no_explorer_refresh } => {
+                    DriveManager::eject_safe(config.drive(drive), !no_explorer_refresh)?;
+                }
+                DriveAction::Open { path, drive } => {
+                    match path {
+                        Some(path) => DriveManager::open_folder(config.drive(drive), &path)?,
+                        None => DriveManager::open_drive(config.drive(drive), true)?,
+                    }
+                }
+                DriveAction::Info { drive } => {
+                    DriveManager::info(config.drive(drive), json)?;
+                }
+                DriveAction::Sync { local, remote, drive, mirror, pull, force, throttle } => {
+                    DriveManager::sync(config.drive(drive), &local, &remote, &SyncOptions { mirror, pull, force, throttle }, json)?;
+                }
+                DriveAction::Check { drive, timeout } => {
+                    DriveManager::check(config.drive(drive), Duration::from_secs(timeout), json)?;
+                }
+                DriveAction::Quota { drive } => {
+                    DriveManager::quota(config.drive(drive), json)?;
+                }
+                DriveAction::Status { all, drive, watch, interval, verify } => {
+                    if watch {
+                        DriveManager::watch_status(interval)?;
+                    } else {
+                        DriveManager::status(all, drive)?;
+                        if verify {
+                            DriveManager::verify_mappings()?;
+                        }
+                    }
+                }
+                DriveAction::Provision { action } => {
+                    match action {
+                        ProvisionAction::Add { drive, path } => {
+                            ProvisionManager::add(drive, &path)?;
+                        }
+                        ProvisionAction::Remove { drive } => {
+                            ProvisionManager::remove(drive)?;
+                        }
+                        ProvisionAction::List => {
+                            ProvisionManager::list()?;
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Config { action } => {
+            match action {
+                ConfigAction::Get { key } => {
+                    ConfigManager::get(key)?;
+                }
+                ConfigAction::Set { key, value } => {
+                    ConfigManager::set(key, &value, json)?;
                 }
-                DriveAction::Unmount { drive, force } => {
-                    DriveManager::unmount(drive, force)?;
+                ConfigAction::Unset { key } => {
+                    ConfigManager::unset(key, json)?;
                 }
-                DriveAction::Open { drive } => {
-                    DriveManager::open_drive(drive, true)?;
+                ConfigAction::List => {
+                    ConfigManager::list()?;
                 }
-                DriveAction::Status => {
-                    DriveManager::status()?;
+                ConfigAction::Edit => {
+                    ConfigManager::edit()?;
                 }
             }
         }
+        Commands::Creds { action } => {
+            match action {
+                CredsAction::Set { username, domain, password_stdin } => {
+                    let password = if password_stdin {
+                        Some(cli::read_stdin_lines()?.into_iter().next().unwrap_or_default())
+                    } else {
+                        None
+                    };
+                    CredsManager::set(&config.username(username)?, config.domain(domain)?, password)?;
+                }
+                CredsAction::Show => {
+                    CredsManager::show()?;
+                }
+                CredsAction::Clear => {
+                    CredsManager::clear()?;
+                }
+            }
+        }
+        Commands::Schedule { action } => {
+            match action {
+                ScheduleAction::Add { task, logon: _, at, name } => {
+                    let trigger = match at {
+                        Some(at) => ScheduleTrigger::daily(&at)?,
+                        None => ScheduleTrigger::Logon,
+                    };
+                    let vpn_name = match task {
+                        ScheduledAction::Connect => Some(config.vpn_name(name)?),
+                        ScheduledAction::Disconnect => None,
+                    };
+                    ScheduleManager::add(task, &trigger, vpn_name.as_deref())?;
+                }
+                ScheduleAction::Remove { name } => {
+                    ScheduleManager::remove(&name)?;
+                }
+                ScheduleAction::Profiles => {
+                    let profiles: Vec<_> = config.drive_profiles.iter()
+                        .filter_map(|(name, profile)| profile.schedule.as_ref().map(|schedule| (name.as_str(), profile.drive, schedule)))
+                        .collect();
+                    // The tasks connect to the default VPN, so there has to be one
+                    if !profiles.is_empty() {
+                        config.vpn_name(None)?;
+                    }
+                    ScheduleManager::schedule_profiles(&profiles)?;
+                }
+                ScheduleAction::List => {
+                    ScheduleManager::list()?;
+                }
+            }
+        }
+        Commands::Net { action } => {
+            match action {
+                NetAction::Portal { no_open, wait, timeout } => {
+                    PortalManager::run(no_open, wait.then(|| Duration::from_secs(timeout)))?;
+                }
+            }
+        }
+        Commands::Status { watch, interval, max_age } => {
+            StatusManager::show(watch, interval, max_age, config.status_feed.as_deref())?;
+        }
+        Commands::About => {
+            AboutManager::show(json)?;
+        }
+        Commands::PackageManifest { manager, artifact, url, out_dir } => {
+            ManifestManager::generate(manager, &artifact, url.as_deref(), out_dir.as_deref())?;
+        }
+        Commands::Env { shell, drive } => {
+            EnvManager::export(shell, config.vpn_name.as_deref(), config.drive(drive), json)?;
+        }
+        Commands::Doctor => {
+            DoctorManager::run(json, config.status_feed.as_deref())?;
+        }
         Commands::Completions { shell } => {
-            generate(shell, &mut Cli::command(), "upv", &mut io::stdout());
+            generate(shell.generator(), &mut Cli::command(), "upv", &mut io::stdout());
         }
     }
     
@@ -105,19 +637,134 @@ fn run() -> Result<()> {
 // Usage examples:
 // upv vpn create "My UPV Connection" --connect
 // upv vpn create "UPV Work" -c  # Short flag for --connect
+// upv vpn create "UPV IKEv2" --tunnel-type ikev2  # Use IKEv2 instead of SSTP
+// upv vpn create "UPV Split" --split-tunnel        # Only UPV traffic goes through the VPN
+// upv vpn create "UPV Work" --idempotent  # No-op if it already exists and is correctly configured, repair it if not
+// upv vpn verify "UPV Work"               # List the settings that differ from the UPV ones (fails if any does)
+// upv --output json vpn verify "UPV Work"  # Same, as JSON (setting, current and expected value)
+// upv --dry-run vpn create "UPV Work" --idempotent  # Preview the repair
+// upv vpn create "UPV Custom" --eap-config .\MyEap.xml  # Use a custom EAP configuration instead of the built-in one
+// upv vpn create "UPV Work" --set-default  # Make it the default connection without asking
+// upv vpn create "UPV Work" --dns-suffix ""  # Don't set the upv.es DNS suffix
+// upv vpn create "UPV Campus" --server <HOST>.upv.es  # Use another UPV VPN server instead of vpn.upv.es
+// upv vpn create "UPV IKEv2" --from UPV --tunnel-type ikev2  # Copy the UPV connection (routes, DNS, triggers) with IKEv2, to compare both
 // upv vpn connect "My UPV Connection"
+// upv vpn connect "My UPV Connection" --silent     # No dialog, uses the stored credentials
+// upv vpn connect "My UPV Connection" --user user@upv.es --password "mypassword"
+// upv vpn connect "My UPV Connection" --override  # Try even after several recent failed logins
+// upv vpn connect "My UPV Connection" --wait --timeout 90  # Return only once connected (fails otherwise)
+// upv vpn connect --mount                # Connect and mount the drive with the configured username, domain and letter
+// upv vpn connect --profiled-routes      # Also route the extra subnets configured for the connection (e.g. lab networks)
+// upv vpn connect --dns-suffix           # Make short hostnames resolve under upv.es (and the configured dns_suffixes)
+// upv vpn connect "My UPV Connection" --silent --entry-options phonebook=D:\vpn.pbk,prefixsuffix  # Extra rasdial options
 // upv vpn disconnect
+// upv vpn disconnect --wait --timeout 20  # Return only once the tunnel is actually down
+// upv vpn disconnect --unmount          # Unmount the UPV drives first, so they aren't left broken
+// upv vpn toggle                     # Connect or disconnect the configured VPN, depending on its state
+// upv vpn watch "My UPV Connection"   # Redial it with the stored credentials whenever it drops
+// upv vpn watch --interval 30 --max-retries 0  # Check every 30s and never give up
 // upv vpn delete "My UPV Connection"
 // upv vpn delete "UPV Work" --force  # Skip confirmation
 // upv vpn list
+// upv vpn list --quiet               # Names only, one per line
+// upv vpn list --verbose             # Status, tunnel type, authentication and split tunneling of each one
+// upv vpn list --quiet | upv vpn delete --stdin --force  # Delete every connection listed on stdin
 // upv vpn purge                       # Delete all UPV connections (with double confirmation)
 // upv vpn purge --force              # Delete all UPV connections without confirmation
 // upv vpn purge --except "Keep This" # Delete all except specified connections
 // upv vpn purge -e "VPN1" -e "VPN2"  # Delete all except VPN1 and VPN2
+// upv vpn undo-delete                 # Recreate the connections removed by the last delete or purge
 // upv vpn status
+// upv vpn repair "UPV"               # Apply the UPV settings and the current built-in EAP configuration again
+// upv vpn repair --outdated-only    # Upgrade every connection created with an older built-in EAP configuration
+// upv vpn ping                       # Latency to the VPN server and the UPV network: slow UPV or broken VPN?
+// upv vpn logs --since 2h --errors-only  # Why recent connections failed (e.g. error 809 or 691), from the Windows event log
+// upv vpn set-default "UPV Work"  # Used by the vpn commands when no name is given
+// upv vpn show "UPV"
+// upv vpn set "UPV" --remember-credential off  # Don't let Windows cache the credentials
+// upv vpn set "UPV" --split-tunnel on --dns 158.42.249.8 --add-route 158.42.0.0/16
+// upv vpn restore-network            # Restore routes/DNS saved before connecting (as admin)
+// upv vpn dns "UPV"                  # Set the DNS suffix to upv.es, so short UPV host names resolve
+// upv vpn dns "UPV" --nrpt           # Also always resolve *.upv.es through the VPN (connected, as administrator)
+// upv vpn export "UPV" --format rasphone --file upv.pbk  # Export as a phonebook section
+// upv vpn export "UPV" --format powershell               # Print the commands to recreate it
+// upv vpn export "UPV" --format json --file upv.json     # Export a profile to recreate it on another PC
+// upv vpn import upv.json                               # Recreate it from the profile
+// upv vpn import upv.json --name "UPV Lab" --replace    # Under another name, replacing it if it exists
 // upv drive mount myuser UPVNET --drive W --open  # Uses VPN credentials
 // upv drive mount myuser UPVNET --password mypass --drive W --open  # Uses explicit credentials
+// upv drive mount myuser UPVNET  # Asks for the password (masked) if there are no credentials to reuse
 // upv drive mount myuser ALUMNO -d W -o  # Short flags, uses VPN credentials
 // upv drive mount myuser ALUMNO -p mypass -d W -o  # Short flags with password
+// upv drive mount myuser UPVNET --timeout 10 --wait-for-vpn  # Give up after 10s, retry once the VPN is connected
+// upv drive mount myuser UPVNET --auto-vpn  # Off campus, connect the default VPN connection first
+// upv drive mount myuser UPVNET --skip-credential-check  # Don't look for VPN/Wi-Fi/Kerberos credentials before mounting
+// upv drive mount myuser UPVNET --share-template "\\{server}\discos2\{initial}\{user}"  # Custom share path layout
+// upv drive mount myuser UPVNET --webdav -p mypass  # Mount over HTTPS (WebDAV), which works without the VPN
+// upv drive mount myuser UPVNET --no-webdav-fallback  # Don't switch to WebDAV when the server can't be reached through SMB
+// upv drive mount myuser UPVNET --persistent  # Restore the drive at every logon, so it survives reboots
+// upv drive mount --drive auto     # Use the first free letter if W: is taken (printed last, for scripts)
+// upv drive mount --drive W --remap  # Replace W: if it is mapped to another share, without asking
+// upv drive mount --profile shared-lab  # Mount the drive of a profile in the config file ([drive_profiles.shared-lab])
+// upv drive mount --all-profiles   # Mount the drives of every profile in the config file, going on when one fails
+// upv drive mount --all-profiles --drive auto   # Same, moving the profiles whose letter is taken to free ones
 // upv drive unmount --drive W
-// upv drive status
\ No newline at end of file
+// upv drive unmount --all          # Unmount every drive mapped to the UPV NAS
+// upv drive unmount --drive W --restore-previous  # Map back the share W: pointed at before 'drive mount --remap'
+// upv drive unmount --no-explorer-refresh  # Don't notify Explorer (headless sessions)
+// upv drive eject-safe --drive W     # Unmount only if nothing is using the drive, listing what is
+// upv drive remount --drive W        # Unmount and mount again with the same parameters as last time (stale drives after sleep)
+// "W`nX" | upv drive unmount --stdin  # Unmount every drive letter listed on stdin (PowerShell)
+// upv drive status                  # UPV drives, their state and whether they are persistent
+// upv drive status --all            # Every network drive, marking the UPV ones
+// upv drive status --drive W        # Fails if W: is not mounted, to test it from scripts
+// upv drive status --watch --interval 5  # Refresh every 5 seconds, highlighting changes
+// upv drive status --verify          # Also check that each UPV drive actually answers
+// upv drive info --drive W            # Share, status, SMB dialect, accounts and latency of a mapping
+// upv drive open docencia/practicas   # Open a folder of the drive in Explorer (relative to its root)
+// upv drive quota --drive W           # Used and free space of the drive (your quota), warning when it is almost full
+// upv drive check -d W               # Check that W: actually answers (and how fast), suggesting a remount if it is stale
+// upv drive sync C:\Projects\tfg backups\tfg  # Copy new and changed files to W:\backups\tfg (--pull to copy them back)
+// upv drive sync C:\Projects\tfg backups\tfg --mirror  # Make it an exact copy, deleting files that aren't in the project
+// upv --output json drive sync C:\Projects\tfg backups\tfg  # Print the summary (files copied and deleted, failed files) as JSON
+// upv drive sync C:\Projects\tfg backups\tfg --throttle 50  # Wait 50 ms between blocks of 64 KB, so the VPN stays usable meanwhile
+// upv drive shares                  # Shares listed in the share catalogs (e.g. faculty resources)
+// upv drive mount-share ETSINF-Software --drive S  # Mount a share from a catalog by its name
+// upv drive mount-share \\nasupv.upv.es\dept --drive P  # Mount any share by its UNC path
+// upv drive provision add S "\\server\software"  # Map S: for every user of this machine on logon (as admin)
+// upv drive provision remove S
+// upv drive provision list
+// upv config set username myuser     # Defaults used when arguments are omitted
+// upv config set domain UPVNET
+// upv config set vpn_name "UPV"
+// upv --dry-run config set drive S    # Show the change (current and new value) without saving it
+// upv config set drive_fallbacks X,Y  # Letters tried by --drive auto after the default one
+// upv config list
+// upv config edit                    # Open the config file in $EDITOR (or Notepad)
+// upv vpn connect                    # Connects to the configured VPN
+// upv drive mount --open             # Mounts the configured user's drive
+// upv creds set myuser UPVNET        # Save the credentials used by vpn connect and drive mount (asks for the password)
+// upv --account student drive mount  # Use the username, domain and defaults of another account in the config file ([accounts.student])
+// upv creds show
+// upv creds clear
+// upv status                        # VPN connections and network drives at once
+// upv status --watch --interval 5   # Redraw every 5 seconds, highlighting changes
+// upv status --max-age 30            # Instantly, from the last status read if it is at most 30s old (for prompts)
+// upv schedule add connect --logon  # Connect to the VPN every time you log on (through the Task Scheduler)
+// upv schedule add disconnect --at 20:00  # Disconnect every day at 20:00
+// upv schedule profiles  # Mount and unmount the drive profiles with a schedule at its start and end
+// upv schedule list
+// upv schedule remove disconnect-at-2000
+// upv net portal --wait              # Open the login page of a guest Wi-Fi portal, and wait until you have logged in
+// upv env | Invoke-Expression       # Export the VPN and drive state ($env:UPV_VPN_STATUS, $env:UPV_DRIVE...) for scripts
+// eval "$(upv env --shell posix)"   # The same in Git Bash or WSL
+// upv doctor                        # Check for common problems, with hints on how to fix them
+// upv about                         # Version and environment details to include in bug reports
+// upv package-manifest scoop target\release\upv.exe  # Scoop manifest of this version, with the hash of the binary
+// upv package-manifest winget target\release\upv.exe --out-dir manifests  # winget manifests, written to a directory
+// upv --output json doctor
+// upv --dry-run vpn create "UPV" --split-tunnel  # Print the PowerShell commands instead of running them
+// upv --dry-run drive mount myuser UPVNET -p mypass  # Print the equivalent net use command (the password is redacted)
+// upv --verbose drive mount myuser UPVNET -p mypass  # On errors, also print the command that failed (redacted)
+// upv --enable-experimental vpn list  # Try experimental features (also UPV_EXPERIMENTAL=1 or config set experimental true)
+// upv --output json vpn delete "Missing"  # Errors are printed to stderr as JSON
@@ -0,0 +1,221 @@
+use anyhow::{Result, Context};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use serde::Deserialize;
+
+use crate::error::UpvError;
+use crate::powershell;
+
+// UPV announces the maintenance of the VPN and the file servers ahead of time. With a status feed configured, 'upv doctor'
+// and 'upv status' tell about the windows going on or coming up, so an announced outage isn't mistaken for a broken setup.
+// The feed is either a maintenance calendar (an iCalendar file) or a JSON list of windows:
+// [{"title": "VPN upgrade", "start": "2026-10-17T02:00:00+02:00", "end": "2026-10-17T04:00:00+02:00"}]
+
+// How far ahead the windows are told about
+const LOOKAHEAD_HOURS: i64 = 24;
+
+// How long to wait for the feed before giving up on it
+const FETCH_TIMEOUT_SECS: u32 = 10;
+
+/// An announced maintenance window
+#[derive(Debug, Deserialize)]
+pub struct Window {
+    pub title: String,
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+}
+
+/// An iCalendar event being read, whose properties may come in any order
+#[derive(Default)]
+struct Event {
+    title: String,
+    start: Option<DateTime<Local>>,
+    end: Option<DateTime<Local>>,
+}
+
+pub struct MaintenanceManager;
+
+impl MaintenanceManager {
+    // Private utility functions
+
+    /// Downloads the feed (through PowerShell, since it is usually served over HTTPS)
+    fn fetch(url: &str) -> Result<String> {
+        let output = powershell::run(&format!(
+            "[Console]::OutputEncoding = [System.Text.Encoding]::UTF8; (Invoke-WebRequest -UseBasicParsing -TimeoutSec {} -Uri {}).Content",
+            FETCH_TIMEOUT_SECS, powershell::quote(url)
+        ))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::InternetUnreachable(
+                format!("Failed to read the status feed '{}': {}", url, error.trim()).into()
+            ).with_hint("Check the URL with 'upv config get status_feed', or remove it with 'upv config unset status_feed'").into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// A date or date-time of an iCalendar event: UTC if it ends in Z, local time otherwise (time zones aren't looked up)
+    fn ical_time(value: &str) -> Option<DateTime<Local>> {
+        let local = if let Some(utc) = value.strip_suffix('Z') {
+            let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+            return Some(Utc.from_utc_datetime(&time).with_timezone(&Local));
+        } else if value.contains('T') {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?
+        } else {
+            NaiveDate::parse_from_str(value, "%Y%m%d").ok()?.and_hms_opt(0, 0, 0)?
+        };
+        Local.from_local_datetime(&local).earliest()
+    }
+
+    /// Reads the events of an iCalendar file (events without an end last until the end of the day they start)
+    fn parse_ical(content: &str) -> Result<Vec<Window>> {
+        // Long lines are folded, continuing on the next ones after a space or a tab
+        let mut lines: Vec<String> = Vec::new();
+        for line in content.lines() {
+            match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+                (Some(rest), Some(last)) => last.push_str(rest),
+                _ => lines.push(line.to_string()),
+            }
+        }
+
+        let mut windows = Vec::new();
+        let mut event: Option<Event> = None;
+        for line in &lines {
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            // Parameters (like ;TZID=... or ;VALUE=DATE) come after the name
+            let name = name.split(';').next().unwrap_or_default().to_ascii_uppercase();
+
+            match (name.as_str(), &mut event) {
+                ("BEGIN", _) if value.eq_ignore_ascii_case("VEVENT") => event = Some(Event::default()),
+                ("SUMMARY", Some(event)) => {
+                    event.title = value.replace("\\n", " ").replace("\\N", " ").replace("\\,", ",").replace("\\;", ";").replace("\\\\", "\\");
+                }
+                ("DTSTART", Some(event)) => event.start = Self::ical_time(value.trim()),
+                ("DTEND", Some(event)) => event.end = Self::ical_time(value.trim()),
+                ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                    let Some(Event { title, start: Some(start), end }) = event.take() else {
+                        return Err(UpvError::InvalidValue("An event of the maintenance calendar has no valid start".into()).into());
+                    };
+                    let end = end.unwrap_or_else(|| {
+                        let next_day = start.date_naive().succ_opt().and_then(|day| day.and_hms_opt(0, 0, 0));
+                        next_day.and_then(|day| Local.from_local_datetime(&day).earliest()).unwrap_or(start)
+                    });
+                    windows.push(Window { title, start, end });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(windows)
+    }
+
+    /// Day of a time, relative to now ("today", "tomorrow" or the date), or "tonight" for the night ahead
+    fn day(time: &DateTime<Local>, now: &DateTime<Local>) -> String {
+        let (today, date) = (now.date_naive(), time.date_naive());
+        let tomorrow = today.succ_opt();
+        // The small hours of the next day are still tonight when told about in the afternoon or evening
+        let tonight = (date == today && time.hour() >= 20) || (Some(date) == tomorrow && time.hour() < 6 && now.hour() >= 12);
+
+        if tonight {
+            "tonight".to_string()
+        } else if date == today {
+            "today".to_string()
+        } else if Some(date) == tomorrow {
+            "tomorrow".to_string()
+        } else {
+            date.format("%b %-d").to_string()
+        }
+    }
+
+    // Public methods for maintenance windows
+
+    /// Reads a status feed: an iCalendar file if it looks like one, a JSON list of windows otherwise.
+    pub fn parse(content: &str) -> Result<Vec<Window>> {
+        let content = content.trim_start_matches('\u{feff}').trim();
+        if content.starts_with("BEGIN:VCALENDAR") {
+            return Self::parse_ical(content);
+        }
+
+        serde_json::from_str(content)
+            .map_err(|e| UpvError::InvalidValue(
+                format!("The status feed is neither a maintenance calendar nor a list of windows: {}", e).into()
+            ).with_hint("It has to be an iCalendar file, or JSON like [{\"title\": \"...\", \"start\": \"2026-10-17T02:00:00+02:00\", \"end\": \"...\"}]").into())
+    }
+
+    /// The maintenance windows going on or starting in the next hours, in the order they start.
+    pub fn upcoming(url: &str) -> Result<Vec<Window>> {
+        let content = Self::fetch(url)?;
+        let mut windows = Self::parse(&content)
+            .with_context(|| format!("Failed to read the status feed '{}'", url))?;
+
+        let now = Local::now();
+        let horizon = now + chrono::Duration::hours(LOOKAHEAD_HOURS);
+        windows.retain(|window| window.end > now && window.start < horizon);
+        windows.sort_by_key(|window| window.start);
+
+        Ok(windows)
+    }
+
+    /// Describes a window, e.g. "UPV has scheduled maintenance tonight 02:00–04:00 (VPN upgrade)"
+    pub fn describe(window: &Window, now: &DateTime<Local>) -> String {
+        let end = if window.end - window.start < chrono::Duration::hours(LOOKAHEAD_HOURS) {
+            window.end.format("%H:%M").to_string()
+        } else {
+            format!("{} {}", Self::day(&window.end, now), window.end.format("%H:%M"))
+        };
+        let title = if window.title.is_empty() { String::new() } else { format!(" ({})", window.title) };
+
+        if window.start <= *now {
+            format!("UPV is under scheduled maintenance until {}{}", end, title)
+        } else {
+            format!("UPV has scheduled maintenance {} {}–{}{}", Self::day(&window.start, now), window.start.format("%H:%M"), end, title)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(time: &str) -> DateTime<Local> {
+        Local.from_local_datetime(&NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reads_maintenance_calendars() {
+        let calendar = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:VPN upgrade\\, phase 1\r\n  and 2\r\n\
+            DTSTART;TZID=Europe/Madrid:20261017T020000\r\nDTEND:20261017T040000\r\nEND:VEVENT\r\n\
+            BEGIN:VEVENT\r\nSUMMARY:NAS\r\nDTSTART;VALUE=DATE:20261020\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let windows = MaintenanceManager::parse(calendar).unwrap();
+
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].title, "VPN upgrade, phase 1 and 2");
+        assert_eq!((windows[0].start, windows[0].end), (local("2026-10-17 02:00"), local("2026-10-17 04:00")));
+        assert_eq!((windows[1].start, windows[1].end), (local("2026-10-20 00:00"), local("2026-10-21 00:00")));
+    }
+
+    #[test]
+    fn reads_json_feeds() {
+        let feed = r#"[{"title": "VPN upgrade", "start": "2026-10-17T02:00:00+02:00", "end": "2026-10-17T04:00:00+02:00"}]"#;
+        let windows = MaintenanceManager::parse(feed).unwrap();
+        assert_eq!(windows[0].end - windows[0].start, chrono::Duration::hours(2));
+
+        assert!(MaintenanceManager::parse("<html>Service unavailable</html>").is_err());
+    }
+
+    #[test]
+    fn describes_windows_relative_to_now() {
+        let window = Window { title: "VPN upgrade".to_string(), start: local("2026-10-17 02:00"), end: local("2026-10-17 04:00") };
+
+        assert_eq!(
+            MaintenanceManager::describe(&window, &local("2026-10-16 15:00")),
+            "UPV has scheduled maintenance tonight 02:00–04:00 (VPN upgrade)"
+        );
+        assert_eq!(
+            MaintenanceManager::describe(&window, &local("2026-10-17 03:00")),
+            "UPV is under scheduled maintenance until 04:00 (VPN upgrade)"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, Context};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::error::UpvError;
+use crate::powershell;
+
+// Package metadata, taken from Cargo.toml so manifests always match the version being released
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
+const HOMEPAGE: &str = env!("CARGO_PKG_HOMEPAGE");
+const REPOSITORY: &str = env!("CARGO_PKG_REPOSITORY");
+const LICENSE: &str = env!("CARGO_PKG_LICENSE");
+
+// Identifier of the package in the winget-pkgs repository (<Publisher>.<Name>)
+const WINGET_IDENTIFIER: &str = "algono.upv-cli";
+const WINGET_MANIFEST_VERSION: &str = "1.6.0";
+
+/// Package manager to generate a manifest for
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PackageManager {
+    /// winget (version, installer and locale manifests, for the winget-pkgs repository)
+    Winget,
+    /// Scoop (app manifest, for a bucket)
+    Scoop,
+}
+
+/// Scoop app manifest (fields in the order Scoop buckets use)
+#[derive(Serialize)]
+struct ScoopManifest {
+    version: &'static str,
+    description: &'static str,
+    homepage: &'static str,
+    license: &'static str,
+    url: String,
+    hash: String,
+    bin: String,
+    checkver: &'static str,
+    autoupdate: ScoopAutoupdate,
+}
+
+#[derive(Serialize)]
+struct ScoopAutoupdate {
+    url: String,
+    hash: ScoopHashSource,
+}
+
+/// Where Scoop gets the hash of new versions from (the .sha256 file uploaded with each release)
+#[derive(Serialize)]
+struct ScoopHashSource {
+    url: &'static str,
+}
+
+pub struct ManifestManager;
+
+impl ManifestManager {
+    // Private utility functions
+
+    /// SHA256 hash of a file, in lowercase hex (as Scoop expects it; winget doesn't mind)
+    fn sha256(path: &Path) -> Result<String> {
+        if let Some(restriction) = powershell::restriction() {
+            return Err(powershell::restriction_error(restriction, "hashing the release artifact").into());
+        }
+
+        let hashes: Vec<String> = powershell::query(&format!(
+            "(Get-FileHash -LiteralPath '{}' -Algorithm SHA256 -ErrorAction Stop).Hash",
+            path.display().to_string().replace('\'', "''")
+        )).with_context(|| format!("Failed to hash '{}'", path.display()))?;
+
+        hashes.into_iter().next()
+            .map(|hash| hash.to_lowercase())
+            .with_context(|| format!("Failed to hash '{}'", path.display()))
+    }
+
+    /// Scoop app manifest, which Excavate (or 'scoop checkver') can keep up to date from then on
+    fn scoop(file_name: &str, url: &str, hash: &str) -> Result<String> {
+        let manifest = ScoopManifest {
+            version: VERSION,
+            description: DESCRIPTION,
+            homepage: HOMEPAGE,
+            license: LICENSE,
+            url: url.to_string(),
+            hash: hash.to_string(),
+            bin: file_name.to_string(),
+            checkver: "github",
+            autoupdate: ScoopAutoupdate {
+                url: format!("{}/releases/download/v$version/{}", REPOSITORY, file_name),
+                hash: ScoopHashSource { url: "$url.sha256" },
+            },
+        };
+
+        Ok(serde_json::to_string_pretty(&manifest).context("Failed to serialize the Scoop manifest")? + "\n")
+    }
+
+    /// winget multi-file manifest (version, installer and default locale), as the winget-pkgs repository requires it.
+    /// The executable is installed as a portable package, so it is available as `upv` in new terminals.
+    fn winget(file_name: &str, url: &str, hash: &str) -> Vec<(String, String)> {
+        let header = |kind: &str| format!(
+            "# yaml-language-server: $schema=https://aka.ms/winget-manifest.{}.{}.schema.json\n\n",
+            kind, WINGET_MANIFEST_VERSION
+        );
+        let command = file_name.trim_end_matches(".exe");
+
+        let version = format!(
+            "{}PackageIdentifier: {}\nPackageVersion: {}\nDefaultLocale: en-US\nManifestType: version\nManifestVersion: {}\n",
+            header("version"), WINGET_IDENTIFIER, VERSION, WINGET_MANIFEST_VERSION
+        );
+        let installer = format!(
+            "{}PackageIdentifier: {}\nPackageVersion: {}\nInstallerType: portable\nCommands:\n- {}\nInstallers:\n- Architecture: x64\n  InstallerUrl: {}\n  InstallerSha256: {}\nManifestType: installer\nManifestVersion: {}\n",
+            header("installer"), WINGET_IDENTIFIER, VERSION, command, url, hash.to_uppercase(), WINGET_MANIFEST_VERSION
+        );
+        let locale = format!(
+            "{}PackageIdentifier: {}\nPackageVersion: {}\nPackageLocale: en-US\nPublisher: algono\nPublisherUrl: https://github.com/algono\nPackageName: upv-cli\nPackageUrl: {}\nLicense: {}\nShortDescription: \"{}\"\nMoniker: {}\nTags:\n- vpn\n- upv\n- network-drive\nManifestType: defaultLocale\nManifestVersion: {}\n",
+            header("defaultLocale"), WINGET_IDENTIFIER, VERSION, HOMEPAGE, LICENSE, DESCRIPTION.replace('"', "\\\""), command, WINGET_MANIFEST_VERSION
+        );
+
+        vec![
+            (format!("{}.yaml", WINGET_IDENTIFIER), version),
+            (format!("{}.installer.yaml", WINGET_IDENTIFIER), installer),
+            (format!("{}.locale.en-US.yaml", WINGET_IDENTIFIER), locale),
+        ]
+    }
+
+    // Public methods for package manifests
+
+    /// Generates the manifest of a package manager for the current version, hashing the release artifact given.
+    /// The artifact is expected at the GitHub release of this version unless another URL is given.
+    /// Manifests are written to a directory if one is given, or printed to stdout (each winget file after its name).
+    pub fn generate(manager: PackageManager, artifact: &Path, url: Option<&str>, out_dir: Option<&Path>) -> Result<()> {
+        if !artifact.is_file() {
+            return Err(UpvError::ArtifactNotFound(
+                format!("The release artifact '{}' was not found", artifact.display()).into()
+            ).with_hint("Build it first with 'cargo build --release' (it is at target\\release\\upv.exe)").into());
+        }
+
+        let file_name = artifact.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .with_context(|| format!("'{}' is not a file name", artifact.display()))?;
+        let url = url.map(String::from)
+            .unwrap_or_else(|| format!("{}/releases/download/v{}/{}", REPOSITORY, VERSION, file_name));
+        let hash = Self::sha256(artifact)?;
+
+        let files = match manager {
+            PackageManager::Scoop => vec![("upv-cli.json".to_string(), Self::scoop(&file_name, &url, &hash)?)],
+            PackageManager::Winget => Self::winget(&file_name, &url, &hash),
+        };
+
+        match out_dir {
+            Some(dir) => {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+                for (name, content) in &files {
+                    let path = dir.join(name);
+                    fs::write(&path, content)
+                        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+                    println!("Wrote {}", path.display());
+                }
+            }
+            None if files.len() == 1 => print!("{}", files[0].1),
+            None => {
+                for (name, content) in &files {
+                    println!("# {}\n{}", name, content);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::dry_run;
+use crate::error::UpvError;
+use crate::paths;
+use crate::powershell;
+
+const SNAPSHOT_FILE: &str = "network-snapshot.json";
+
+/// A default route (0.0.0.0/0 or ::/0)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RouteEntry {
+    pub destination_prefix: String,
+    pub interface_alias: String,
+    pub next_hop: String,
+    pub route_metric: u32,
+}
+
+/// The DNS servers configured on a network interface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct DnsEntry {
+    pub interface_alias: String,
+    pub server_addresses: Vec<String>,
+    /// Whether the interface gets its settings from DHCP
+    pub dhcp: bool,
+}
+
+/// The network state relevant to the VPN (default routes and DNS servers)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkSnapshot {
+    pub taken_at: String,
+    pub routes: Vec<RouteEntry>,
+    pub dns: Vec<DnsEntry>,
+}
+
+/// A difference between the saved snapshot and the current network state
+#[derive(Debug)]
+enum NetworkChange {
+    ExtraRoute(RouteEntry),
+    MissingRoute(RouteEntry),
+    DnsChanged { saved: DnsEntry, current: Vec<String> },
+}
+
+impl std::fmt::Display for NetworkChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkChange::ExtraRoute(route) => write!(f, "Leftover default route {} via '{}' ({})", route.destination_prefix, route.interface_alias, route.next_hop),
+            NetworkChange::MissingRoute(route) => write!(f, "Missing default route {} via '{}' ({})", route.destination_prefix, route.interface_alias, route.next_hop),
+            NetworkChange::DnsChanged { saved, current } => write!(f, "DNS servers of '{}' changed: [{}] -> [{}]", saved.interface_alias, saved.server_addresses.join(", "), current.join(", ")),
+        }
+    }
+}
+
+pub struct NetworkManager;
+
+impl NetworkManager {
+    // Private utility functions
+
+    fn snapshot_path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(SNAPSHOT_FILE))
+    }
+
+    /// Reads the current default routes and DNS servers in a single PowerShell round trip.
+    fn current_state() -> Result<NetworkSnapshot> {
+        let ps_command = "\
+            $routes = @(Get-NetRoute -DestinationPrefix '0.0.0.0/0','::/0' -ErrorAction SilentlyContinue | \
+                Select-Object DestinationPrefix, InterfaceAlias, NextHop, @{n='RouteMetric';e={[uint32]$_.RouteMetric}}); \
+            $dns = @(Get-DnsClientServerAddress -AddressFamily IPv4 | Where-Object { $_.ServerAddresses.Count -gt 0 } | ForEach-Object { \
+                [pscustomobject]@{ \
+                    InterfaceAlias = $_.InterfaceAlias; \
+                    ServerAddresses = @($_.ServerAddresses); \
+                    Dhcp = [string](Get-NetIPInterface -InterfaceIndex $_.InterfaceIndex -AddressFamily IPv4).Dhcp -eq 'Enabled' \
+                } }); \
+            [pscustomobject]@{ routes = $routes; dns = $dns }";
+
+        #[derive(Deserialize)]
+        struct State {
+            routes: Vec<RouteEntry>,
+            dns: Vec<DnsEntry>,
+        }
+
+        let state = powershell::query::<State>(ps_command)
+            .map_err(|e| UpvError::Vpn(format!("Failed to read the network state: {}", e).into()))?
+            .pop()
+            .ok_or_else(|| UpvError::Vpn("Failed to read the network state".into()))?;
+
+        Ok(NetworkSnapshot {
+            taken_at: chrono::Local::now().to_rfc3339(),
+            routes: state.routes,
+            dns: state.dns,
+        })
+    }
+
+    fn load_snapshot() -> Result<Option<NetworkSnapshot>> {
+        let path = Self::snapshot_path()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+
+        let snapshot = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?;
+
+        Ok(Some(snapshot))
+    }
+
+    /// Compares the saved snapshot against the current state.
+    /// Extra routes are only reported when they go through a VPN adapter (or an adapter that no longer exists),
+    /// so routes of adapters plugged in after connecting are left alone.
+    fn diff(saved: &NetworkSnapshot, current: &NetworkSnapshot, vpn_names: &[String]) -> Vec<NetworkChange> {
+        let mut changes = Vec::new();
+
+        let saved_interfaces: Vec<&str> = saved.routes.iter()
+            .map(|route| route.interface_alias.as_str())
+            .chain(saved.dns.iter().map(|dns| dns.interface_alias.as_str()))
+            .collect();
+
+        for route in &current.routes {
+            let is_vpn_route = vpn_names.iter().any(|name| name == &route.interface_alias)
+                || !saved_interfaces.contains(&route.interface_alias.as_str());
+            if is_vpn_route && !saved.routes.contains(route) {
+                changes.push(NetworkChange::ExtraRoute(route.clone()));
+            }
+        }
+
+        for route in &saved.routes {
+            if !current.routes.contains(route) {
+                changes.push(NetworkChange::MissingRoute(route.clone()));
+            }
+        }
+
+        for saved_dns in &saved.dns {
+            let current_servers = current.dns.iter()
+                .find(|dns| dns.interface_alias == saved_dns.interface_alias)
+                .map(|dns| dns.server_addresses.clone())
+                .unwrap_or_default();
+            if current_servers != saved_dns.server_addresses {
+                changes.push(NetworkChange::DnsChanged { saved: saved_dns.clone(), current: current_servers });
+            }
+        }
+
+        changes
+    }
+
+    // Public methods for network state management
+
+    /// Saves the current default routes and DNS servers, to be checked after disconnecting the VPN.
+    pub fn save_snapshot() -> Result<()> {
+        let snapshot = Self::current_state()?;
+        let path = Self::snapshot_path()?;
+
+        let content = serde_json::to_string_pretty(&snapshot)
+            .context("Failed to serialize the network state")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Checks that the network state matches the saved snapshot, warning about any leftover changes.
+    pub fn verify_restored(vpn_names: &[String]) -> Result<()> {
+        let Some(saved) = Self::load_snapshot()? else {
+            return Ok(());
+        };
+
+        let changes = Self::diff(&saved, &Self::current_state()?, vpn_names);
+
+        if changes.is_empty() {
+            println!("Network state restored (default routes and DNS match the state before connecting)");
+        } else {
+            eprintln!("Warning: the network state wasn't fully restored after disconnecting:");
+            for change in &changes {
+                eprintln!("  - {}", change);
+            }
+            eprintln!("Run 'upv vpn restore-network' to restore the state from before connecting.");
+        }
+
+        Ok(())
+    }
+
+    /// Restores the default routes and DNS servers saved before connecting to the VPN.
+    pub fn restore(vpn_names: &[String], force: bool) -> Result<()> {
+        let saved = Self::load_snapshot()?.ok_or_else(|| UpvError::Vpn(
+            "There is no saved network state to restore (it is saved when connecting with 'upv vpn connect')".into()
+        ))?;
+
+        println!("Comparing the network state with the one saved at {}...", saved.taken_at);
+
+        let changes = Self::diff(&saved, &Self::current_state()?, vpn_names);
+
+        if changes.is_empty() {
+            println!("The network state already matches the saved one, nothing to restore.");
+            return Ok(());
+        }
+
+        println!("Found {} change(s) to revert:", changes.len());
+        for change in &changes {
+            println!("  - {}", change);
+        }
+
+        if !force {
+            print!("\nDo you want to restore the saved network state? (y/N): ");
+            io::stdout().flush().context("Failed to flush stdout")?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).context("Failed to read user input")?;
+
+            let confirmation = input.trim().to_lowercase();
+            if confirmation != "y" && confirmation != "yes" {
+                println!("Operation cancelled.");
+                return Ok(());
+            }
+        }
+
+        // Stop at the first error, so a failure is reported through the exit code
+        let mut commands = vec!["$ErrorActionPreference = 'Stop'".to_string()];
+        commands.extend(changes.iter()
+            .map(|change| match change {
+                NetworkChange::ExtraRoute(route) => format!(
+                    "Remove-NetRoute -DestinationPrefix '{}' -InterfaceAlias '{}' -NextHop '{}' -Confirm:$false",
+                    route.destination_prefix, route.interface_alias, route.next_hop
+                ),
+                NetworkChange::MissingRoute(route) => format!(
+                    "New-NetRoute -DestinationPrefix '{}' -InterfaceAlias '{}' -NextHop '{}' -RouteMetric {} -PolicyStore ActiveStore | Out-Null",
+                    route.destination_prefix, route.interface_alias, route.next_hop, route.route_metric
+                ),
+                NetworkChange::DnsChanged { saved, .. } if saved.dhcp => format!(
+                    "Set-DnsClientServerAddress -InterfaceAlias '{}' -ResetServerAddresses",
+                    saved.interface_alias
+                ),
+                NetworkChange::DnsChanged { saved, .. } => format!(
+                    "Set-DnsClientServerAddress -InterfaceAlias '{}' -ServerAddresses {}",
+                    saved.interface_alias,
+                    saved.server_addresses.iter().map(|server| format!("'{}'", server)).collect::<Vec<_>>().join(",")
+                ),
+            }));
+        commands.push("Clear-DnsClientCache".to_string());
+
+        if dry_run::enabled() {
+            dry_run::print_powershell(&commands.join("; "));
+            return Ok(());
+        }
+
+        println!("\nRestoring network state...");
+
+        let output = powershell::run(&commands.join("; "))?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to restore the network state: {}", error).into()
+            ).with_hint("Changing routes and DNS servers requires an elevated (administrator) terminal").into());
+        }
+
+        println!("Network state restored successfully");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+use std::fs;
+use std::path::PathBuf;
+use anyhow::{Result, Context};
+
+/// Directory where upv-cli keeps its local state (`%LOCALAPPDATA%\upv`), created if needed.
+pub fn data_dir() -> Result<PathBuf> {
+    let local_app_data = std::env::var("LOCALAPPDATA")
+        .context("Failed to find the local application data directory (LOCALAPPDATA is not set)")?;
+
+    let dir = PathBuf::from(local_app_data).join("upv");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create data directory '{}'", dir.display()))?;
+
+    Ok(dir)
+}
+
+/// Path of the config file (`%APPDATA%\upv\config.toml`), whose directory is created if needed.
+pub fn config_file() -> Result<PathBuf> {
+    let app_data = std::env::var("APPDATA")
+        .context("Failed to find the application data directory (APPDATA is not set)")?;
+
+    let dir = PathBuf::from(app_data).join("upv");
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create config directory '{}'", dir.display()))?;
+
+    Ok(dir.join("config.toml"))
+}
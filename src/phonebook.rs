@@ -0,0 +1,181 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use anyhow::{Result, Context};
+
+use crate::error::UpvError;
+
+// Phonebook files where Windows keeps the RAS (VPN) entries, as used by rasphone and rasdial.
+// Connections created with Add-VpnConnection go to the current user's one, unless created for all users.
+
+/// Paths of the current user's and the all-users phonebooks, in that order
+pub fn phonebook_paths() -> Vec<PathBuf> {
+    let pbk = r"Microsoft\Network\Connections\Pbk\rasphone.pbk";
+
+    let mut paths = Vec::new();
+    if let Ok(app_data) = std::env::var("APPDATA") {
+        paths.push(PathBuf::from(app_data).join(pbk));
+    }
+    let program_data = std::env::var("ProgramData").unwrap_or_else(|_| r"C:\ProgramData".to_string());
+    paths.push(PathBuf::from(program_data).join(pbk));
+
+    paths
+}
+
+/// Reads a phonebook file, which can be either UTF-16 (with BOM) or UTF-8/ANSI.
+/// Returns `None` if it doesn't exist.
+pub fn read_phonebook(path: &PathBuf) -> Result<Option<String>> {
+    Ok(read_phonebook_encoded(path)?.map(|(content, _)| content))
+}
+
+/// Reads a phonebook file, also returning whether it was UTF-16 so it can be written back the same way.
+fn read_phonebook_encoded(path: &PathBuf) -> Result<Option<(String, bool)>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read phonebook '{}'", path.display())),
+    };
+
+    let decoded = if let Some(utf16) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = utf16.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        (String::from_utf16_lossy(&units), true)
+    } else {
+        (String::from_utf8_lossy(bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(&bytes)).into_owned(), false)
+    };
+
+    Ok(Some(decoded))
+}
+
+/// Writes a phonebook file back, in the same encoding it was read with.
+fn write_phonebook(path: &PathBuf, lines: &[String], utf16: bool) -> Result<()> {
+    let content = lines.join("\r\n") + "\r\n";
+    let bytes = if utf16 {
+        [0xFF, 0xFE].into_iter()
+            .chain(content.encode_utf16().flat_map(u16::to_le_bytes))
+            .collect()
+    } else {
+        content.into_bytes()
+    };
+
+    fs::write(path, bytes)
+        .with_context(|| format!("Failed to write phonebook '{}'", path.display()))
+}
+
+/// Finds the `[name]` section of a phonebook's content, returning its lines (header included).
+pub fn find_section(content: &str, name: &str) -> Option<Vec<String>> {
+    let header = format!("[{}]", name);
+    let mut lines = content.lines().skip_while(|line| line.trim() != header);
+
+    let first = lines.next()?;
+    let mut section = vec![first.to_string()];
+    section.extend(
+        lines
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .map(str::to_string)
+    );
+
+    // Drop the blank lines separating this section from the next one
+    while section.last().is_some_and(|line| line.trim().is_empty()) {
+        section.pop();
+    }
+
+    Some(section)
+}
+
+/// Looks for an entry in all the phonebooks, returning the phonebook it was found in and its section.
+pub fn find_entry(name: &str) -> Result<Option<(PathBuf, Vec<String>)>> {
+    for path in phonebook_paths() {
+        if let Some(content) = read_phonebook(&path)?
+            && let Some(section) = find_section(&content, name) {
+            return Ok(Some((path, section)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Sets `key=value` lines in an entry's section, replacing the existing ones or adding them after the header.
+pub fn set_entry_values(name: &str, values: &[(&str, String)]) -> Result<()> {
+    let header = format!("[{}]", name);
+
+    for path in phonebook_paths() {
+        let Some((content, utf16)) = read_phonebook_encoded(&path)? else {
+            continue;
+        };
+
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+            continue;
+        };
+        let mut end = lines[start + 1..].iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .map_or(lines.len(), |offset| start + 1 + offset);
+
+        for (key, value) in values {
+            let prefix = format!("{}=", key);
+            let line = format!("{}{}", prefix, value);
+            match lines[start + 1..end].iter().position(|line| line.starts_with(&prefix)) {
+                Some(offset) => lines[start + 1 + offset] = line,
+                None => {
+                    lines.insert(start + 1, line);
+                    end += 1;
+                }
+            }
+        }
+
+        return write_phonebook(&path, &lines, utf16);
+    }
+
+    Err(UpvError::Vpn(
+        format!("VPN connection '{}' was not found in any phonebook", name).into()
+    ).into())
+}
+
+/// Lists the names of the entries (in all the phonebooks) that connect to any of the given servers.
+pub fn entries_with_servers(servers: &[String]) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+
+    for path in phonebook_paths() {
+        let Some(content) = read_phonebook(&path)? else {
+            continue;
+        };
+
+        let mut current = None;
+        for line in content.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                current = Some(name.to_string());
+            } else if line.strip_prefix("PhoneNumber=").is_some_and(|number| servers.iter().any(|server| number.eq_ignore_ascii_case(server)))
+                && let Some(name) = current.take() {
+                names.push(name);
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+/// Removes an entry's section from the phonebook it is in.
+pub fn remove_entry(name: &str) -> Result<()> {
+    let header = format!("[{}]", name);
+
+    for path in phonebook_paths() {
+        let Some((content, utf16)) = read_phonebook_encoded(&path)? else {
+            continue;
+        };
+
+        let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+        let Some(start) = lines.iter().position(|line| line.trim() == header) else {
+            continue;
+        };
+        let end = lines[start + 1..].iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .map_or(lines.len(), |offset| start + 1 + offset);
+        lines.drain(start..end);
+
+        return write_phonebook(&path, &lines, utf16);
+    }
+
+    Err(UpvError::Vpn(
+        format!("VPN connection '{}' was not found in any phonebook", name).into()
+    ).into())
+}
@@ -0,0 +1,164 @@
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+
+use crate::drive::{NAS_SERVER, SMB_PORT};
+use crate::error::UpvError;
+use crate::vpn::VpnManager;
+
+// Latency is measured as the time it takes to open a TCP connection, since UPV hosts don't always answer ICMP pings
+// (and the VPN server is reached over HTTPS anyway)
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+// Above this average, the UPV network is reported as slow
+const SLOW_THRESHOLD: Duration = Duration::from_millis(200);
+
+// Hosts in the UPV network (158.42.0.0/16), which is routed through the VPN while connected
+const INTERNAL_HOSTS: [(&str, &str, u16); 2] = [
+    ("UPV network drives", NAS_SERVER, SMB_PORT),
+    ("UPV website", "www.upv.es", 443),
+];
+
+/// Results of probing a host several times
+struct ProbeStats {
+    name: &'static str,
+    host: String,
+    port: u16,
+    sent: u32,
+    times: Vec<Duration>,
+}
+
+impl ProbeStats {
+    fn average(&self) -> Option<Duration> {
+        if self.times.is_empty() {
+            return None;
+        }
+        Some(self.times.iter().sum::<Duration>() / self.times.len() as u32)
+    }
+
+    fn loss_percent(&self) -> u32 {
+        if self.sent == 0 {
+            return 0;
+        }
+        (self.sent - self.times.len() as u32) * 100 / self.sent
+    }
+
+    fn all_lost(&self) -> bool {
+        self.times.is_empty()
+    }
+}
+
+pub struct PingManager;
+
+impl PingManager {
+    // Private utility functions
+
+    /// Resolves a host, returning its first address (or `None` if it can't be resolved).
+    fn resolve(host: &str, port: u16) -> Option<SocketAddr> {
+        (host, port).to_socket_addrs().ok()?.next()
+    }
+
+    /// Opens `count` TCP connections to a host, one after another, timing each of them.
+    fn probe(name: &'static str, host: &str, port: u16, count: u32) -> ProbeStats {
+        let mut stats = ProbeStats { name, host: host.to_string(), port, sent: count, times: Vec::new() };
+
+        // A host that can't be resolved counts as every probe lost
+        let Some(address) = Self::resolve(host, port) else {
+            return stats;
+        };
+
+        for attempt in 0..count {
+            if attempt > 0 {
+                thread::sleep(PROBE_INTERVAL);
+            }
+            let start = Instant::now();
+            if TcpStream::connect_timeout(&address, PROBE_TIMEOUT).is_ok() {
+                stats.times.push(start.elapsed());
+            }
+        }
+
+        stats
+    }
+
+    fn print_stats(stats: &ProbeStats) {
+        let target = format!("{} ({}:{})", stats.name, stats.host, stats.port);
+        if stats.all_lost() {
+            println!("  \x1b[31m✗\x1b[0m {}: no replies", target);
+            return;
+        }
+
+        let min = stats.times.iter().min().copied().unwrap_or_default();
+        let max = stats.times.iter().max().copied().unwrap_or_default();
+        let avg = stats.average().unwrap_or_default();
+        let symbol = if stats.loss_percent() > 0 || avg > SLOW_THRESHOLD { "\x1b[33m!\x1b[0m" } else { "\x1b[32m✓\x1b[0m" };
+        println!(
+            "  {} {}: {}/{} replies ({}% loss), min/avg/max {}/{}/{} ms",
+            symbol,
+            target,
+            stats.times.len(),
+            stats.sent,
+            stats.loss_percent(),
+            min.as_millis(),
+            avg.as_millis(),
+            max.as_millis()
+        );
+    }
+
+    // Public methods for the latency check
+
+    /// Times a single TCP connection to a host (`None` if it can't be resolved or doesn't answer in time).
+    pub fn connect_time(host: &str, port: u16) -> Option<Duration> {
+        let address = Self::resolve(host, port)?;
+        let start = Instant::now();
+        TcpStream::connect_timeout(&address, PROBE_TIMEOUT).ok()?;
+        Some(start.elapsed())
+    }
+
+    /// Measures latency and packet loss to the VPN server and, when connected, to hosts inside the UPV network,
+    /// printing a summary that tells a slow UPV network apart from a broken VPN.
+    pub fn run(count: u32) -> Result<()> {
+        let count = count.max(1);
+        let connected = VpnManager::get_connected_upv_connections()?;
+
+        println!("Measuring latency ({} probe(s) per host)...", count);
+
+        let server_address = VpnManager::server_address();
+        let server = Self::probe("VPN server", &server_address, 443, count);
+        Self::print_stats(&server);
+
+        if connected.is_empty() {
+            println!("\nNot connected to any UPV VPN, so only the VPN server was checked");
+            if server.all_lost() {
+                return Err(UpvError::Vpn(
+                    format!("The VPN server '{}' can't be reached", server_address).into()
+                ).with_hint("Check your Internet connection, or run 'upv doctor'").into());
+            }
+            return Ok(());
+        }
+
+        let internal: Vec<ProbeStats> = INTERNAL_HOSTS.iter()
+            .map(|(name, host, port)| Self::probe(name, host, *port, count))
+            .collect();
+        for stats in &internal {
+            Self::print_stats(stats);
+        }
+
+        println!();
+        if internal.iter().all(ProbeStats::all_lost) {
+            return Err(UpvError::Vpn(
+                format!("Connected to '{}', but the UPV network can't be reached through it", connected.join("', '")).into()
+            ).with_hint("The VPN is broken: reconnect with 'upv vpn disconnect' and 'upv vpn connect', or run 'upv doctor'").into());
+        }
+
+        let slow = internal.iter().any(|stats| stats.loss_percent() > 0 || stats.average().is_some_and(|avg| avg > SLOW_THRESHOLD));
+        if slow {
+            println!("\x1b[33mThe VPN is up, but the UPV network is slow or dropping connections\x1b[0m");
+        } else {
+            println!("\x1b[32mThe VPN and the UPV network are healthy\x1b[0m");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, Context};
+
+use crate::dry_run;
+use crate::error::UpvError;
+use crate::job;
+
+// Guest and public Wi-Fi networks (like the UPV guest one) hold every connection until you log in on their portal page,
+// so the VPN and the drives can't be reached until then. Windows detects them by fetching a known page over plain HTTP
+// (which portals intercept), and the same probe is used here.
+const PROBE_HOST: &str = "www.msftconnecttest.com";
+const PROBE_PATH: &str = "/connecttest.txt";
+const PROBE_CONTENT: &str = "Microsoft Connect Test";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often 'upv net portal --wait' checks whether the login went through
+const WAIT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What the connectivity probe found
+enum Connectivity {
+    /// The Internet is reachable
+    Online,
+    /// A captive portal intercepted the probe. Holds the URL of its login page
+    Portal(String),
+    /// There's no network connection at all
+    Offline,
+}
+
+pub struct PortalManager;
+
+impl PortalManager {
+    // Private utility functions
+
+    /// Fetches the probe page, reporting whether it came back intact, was redirected to a portal or couldn't be fetched.
+    fn probe() -> Connectivity {
+        let Some(address) = (PROBE_HOST, 80).to_socket_addrs().ok().and_then(|mut addresses| addresses.next()) else {
+            return Connectivity::Offline;
+        };
+        let Ok(mut stream) = TcpStream::connect_timeout(&address, PROBE_TIMEOUT) else {
+            return Connectivity::Offline;
+        };
+        let _ = stream.set_read_timeout(Some(PROBE_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(PROBE_TIMEOUT));
+
+        let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: upv-cli\r\nConnection: close\r\n\r\n", PROBE_PATH, PROBE_HOST);
+        let mut response = Vec::new();
+        if stream.write_all(request.as_bytes()).is_err() || (stream.read_to_end(&mut response).is_err() && response.is_empty()) {
+            return Connectivity::Offline;
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        let mut lines = head.lines();
+        let status: u16 = lines.next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|code| code.parse().ok())
+            .unwrap_or_default();
+
+        if status == 200 && body.trim() == PROBE_CONTENT {
+            return Connectivity::Online;
+        }
+
+        // Portals either redirect to their login page or answer with it (then, opening the probe URL shows it)
+        let location = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("location"))
+            .map(|(_, value)| value.trim().to_string());
+        match location {
+            Some(url) if (300..400).contains(&status) && !url.is_empty() => Connectivity::Portal(url),
+            _ => Connectivity::Portal(format!("http://{}{}", PROBE_HOST, PROBE_PATH)),
+        }
+    }
+
+    /// Opens the login page of a portal in the default browser.
+    fn open_portal(url: &str) -> Result<()> {
+        let mut command = Command::new("explorer.exe");
+        command.arg(url);
+
+        if dry_run::enabled() {
+            dry_run::print_command(&command, &[]);
+            return Ok(());
+        }
+
+        println!("Opening the login page in your browser...");
+        job::detached(&mut command)
+            .spawn()
+            .context("Failed to launch the browser")?;
+
+        Ok(())
+    }
+
+    // Public methods for captive portals
+
+    /// Checks whether a captive portal (like the one of the UPV guest Wi-Fi) is blocking the connection, opening its login
+    /// page if so (unless `no_open` is set). With `wait`, doesn't return until the login goes through (or the wait times
+    /// out), so it can be chained before VPN or drive commands.
+    pub fn run(no_open: bool, wait: Option<Duration>) -> Result<()> {
+        let url = match Self::probe() {
+            Connectivity::Online => {
+                println!("No captive portal detected, the Internet is reachable");
+                return Ok(());
+            }
+            Connectivity::Offline => return Err(UpvError::InternetUnreachable(
+                "The Internet can't be reached, and no captive portal answered".into()
+            ).with_hint("Check that you are connected to a network (Wi-Fi or cable)").into()),
+            Connectivity::Portal(url) => url,
+        };
+
+        println!("A captive portal is blocking the connection. Log in at: {}", url);
+        if !no_open {
+            Self::open_portal(&url)?;
+        }
+
+        let Some(timeout) = wait else {
+            return Ok(());
+        };
+
+        println!("Waiting for the login to go through (press Ctrl+C to cancel)...");
+        let start = Instant::now();
+        loop {
+            thread::sleep(WAIT_INTERVAL);
+
+            if let Connectivity::Online = Self::probe() {
+                println!("Logged in, the Internet is reachable");
+                return Ok(());
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(UpvError::InternetUnreachable(
+                    format!("The captive portal was still blocking the connection after {}s", timeout.as_secs()).into()
+                ).with_hint(format!("Log in at {} and try again", url)).into());
+            }
+        }
+    }
+}
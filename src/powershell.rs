@@ -0,0 +1,154 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use anyhow::{anyhow, Result, Context};
+use serde::de::DeserializeOwned;
+
+use crate::error::UpvError;
+
+// Every PowerShell command goes through these helpers, so the output can't be altered by the user's environment:
+// profiles are not loaded (they can print anything), and progress bars and warnings are silenced (except for the slow
+// scripts run through stdin, whose output is shown live).
+// Commands that return data convert it to JSON, which is parsed instead of splitting stdout into lines.
+
+const PREAMBLE: &str = "$ProgressPreference = 'SilentlyContinue'; $WarningPreference = 'SilentlyContinue'; ";
+
+// Scripts whose output is shown live keep their warnings, since they tell the user what the system is doing
+const LIVE_PREAMBLE: &str = "$ProgressPreference = 'SilentlyContinue'; ";
+
+// Managed machines may block PowerShell (AppLocker, WDAC) or restrict it to Constrained Language Mode.
+// This is only checked after a command fails, so the common case doesn't pay for an extra PowerShell start.
+static RESTRICTION: OnceLock<Option<String>> = OnceLock::new();
+
+fn powershell() -> Command {
+    let mut command = Command::new("powershell");
+    command.args(["-NoProfile", "-NonInteractive", "-Command"]);
+    command
+}
+
+/// Checks whether PowerShell is restricted on this machine, returning a description of the restriction if so.
+pub fn restriction() -> Option<&'static str> {
+    RESTRICTION.get_or_init(|| {
+        match powershell().arg("$ExecutionContext.SessionState.LanguageMode").output() {
+            Err(e) => Some(format!("PowerShell can't be started on this machine ({})", e)),
+            Ok(output) if !output.status.success() => Some(format!(
+                "PowerShell is blocked on this machine ({})",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+            Ok(output) => match String::from_utf8_lossy(&output.stdout).trim() {
+                "FullLanguage" => None,
+                mode => Some(format!("PowerShell is restricted to {} mode on this machine", mode)),
+            },
+        }
+    }).as_deref()
+}
+
+/// Error for an operation that needs PowerShell when it is restricted and there is no native alternative
+pub fn restriction_error(restriction: &str, operation: &str) -> UpvError {
+    UpvError::PowerShellFailed(
+        format!("{}, and {} can only be done through PowerShell", restriction, operation).into()
+    ).with_hint("This is usually enforced by your organization (AppLocker or WDAC policies). Ask your IT administrator, or run upv-cli from an unrestricted machine")
+}
+
+/// A value as a single-quoted PowerShell string, which is taken literally once its quotes are doubled
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Text as a single-quoted PowerShell here-string (@'...'@), which is taken literally.
+/// Fails if one of its lines starts with '@, since that would end the here-string early
+pub fn here_string(text: &str) -> Result<String> {
+    if text.lines().any(|line| line.trim_start().starts_with("'@")) {
+        return Err(UpvError::InvalidValue(
+            "A line of it starts with '@, which can't be passed to PowerShell".into()
+        ).into());
+    }
+    Ok(format!("@'\r\n{}\r\n'@", text))
+}
+
+/// The command that runs a PowerShell command (without the preamble), for showing it to the user.
+pub fn command(ps_command: &str) -> Command {
+    let mut command = powershell();
+    command.arg(ps_command);
+    command
+}
+
+/// Runs a PowerShell command, returning its raw output.
+pub fn run(ps_command: &str) -> Result<Output> {
+    powershell()
+        .arg(format!("{}{}", PREAMBLE, ps_command))
+        .output()
+        .context("Failed to execute PowerShell command")
+}
+
+/// Prints every line read from a process' output (dimmed and indented, to tell it apart from upv-cli's own output)
+/// as soon as it is written, returning all of it once the process closes it.
+fn echo_lines(reader: impl Read) -> Vec<u8> {
+    let mut collected = Vec::new();
+    for line in BufReader::new(reader).split(b'\n').map_while(Result::ok) {
+        let text = String::from_utf8_lossy(&line);
+        if !text.trim().is_empty() {
+            eprintln!("  \x1b[2m│ {}\x1b[0m", text.trim_end());
+        }
+        collected.extend_from_slice(&line);
+        collected.push(b'\n');
+    }
+    collected
+}
+
+/// Runs a PowerShell script by writing it to the process' stdin.
+/// Needed for scripts containing here-strings (like the EAP config XML).
+/// These are the slow ones (creating a connection can hang for half a minute), so the script's output, warnings
+/// and errors are shown while it runs instead of only afterwards.
+pub fn run_stdin(ps_command: &str) -> Result<Output> {
+    let mut child = powershell()
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn PowerShell process")?;
+
+    // Write the script to stdin, which is closed when it goes out of scope
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(LIVE_PREAMBLE.as_bytes())
+            .and_then(|_| stdin.write_all(ps_command.as_bytes()))
+            .context("Failed to write to PowerShell stdin")?;
+    }
+
+    // Both streams are read at the same time, so neither of them fills up and blocks the process
+    let stdout = child.stdout.take().map(|stdout| thread::spawn(move || echo_lines(stdout)));
+    let stderr = child.stderr.take().map(|stderr| thread::spawn(move || echo_lines(stderr)));
+
+    let status = child.wait().context("Failed to wait for PowerShell command")?;
+
+    Ok(Output {
+        status,
+        stdout: stdout.and_then(|reader| reader.join().ok()).unwrap_or_default(),
+        stderr: stderr.and_then(|reader| reader.join().ok()).unwrap_or_default(),
+    })
+}
+
+/// Runs a PowerShell command and parses the objects it outputs from JSON.
+/// The output is always wrapped in an array, so no results or a single result don't need special handling.
+/// If the command fails, the error contains its stderr.
+pub fn query<T: DeserializeOwned>(ps_command: &str) -> Result<Vec<T>> {
+    let output = run(&format!("ConvertTo-Json -InputObject @({}) -Depth 5 -Compress", ps_command))?;
+
+    if !output.status.success() {
+        if let Some(restriction) = restriction() {
+            return Err(restriction_error(restriction, "this operation").into());
+        }
+        return Err(anyhow!("{}", String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    // The JSON is printed in a single line, so anything else that made it to stdout is skipped
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json = stdout.lines()
+        .map(str::trim)
+        .rfind(|line| line.starts_with('['))
+        .unwrap_or("[]");
+
+    serde_json::from_str(json).context("Failed to parse PowerShell output")
+}
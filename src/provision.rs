@@ -0,0 +1,199 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, Context};
+
+use crate::error::UpvError;
+
+// Machine-wide drive mappings are provisioned through a script in the "All Users" Startup folder,
+// which Windows runs on every user logon. The same script can also be assigned as a GPO logon script.
+const SCRIPT_NAME: &str = "upv-drives.cmd";
+const SCRIPT_HEADER: &str = "@echo off\r\nrem Managed by upv-cli (upv drive provision). Changes made by hand may be overwritten.\r\n";
+// Characters cmd gives a meaning to even inside quotes (or that end the quotes, or the line), so a path with any of
+// them could run other commands at every user's logon
+const UNSAFE_PATH_CHARS: [char; 9] = ['"', '%', '&', '|', '<', '>', '^', '\r', '\n'];
+
+/// A drive mapping created for every user that logs on to this machine
+#[derive(Debug, Clone)]
+pub struct ProvisionedDrive {
+    pub drive: char,
+    pub path: String,
+}
+
+pub struct ProvisionManager;
+
+impl ProvisionManager {
+    // Private utility functions
+
+    /// Path of the managed script in the "All Users" Startup folder.
+    fn script_path() -> PathBuf {
+        let program_data = std::env::var("ProgramData").unwrap_or_else(|_| r"C:\ProgramData".to_string());
+        PathBuf::from(program_data)
+            .join(r"Microsoft\Windows\Start Menu\Programs\StartUp")
+            .join(SCRIPT_NAME)
+    }
+
+    /// Reads the mappings from a managed script (an empty list if it doesn't exist yet).
+    fn read_entries(path: &Path) -> Result<Vec<ProvisionedDrive>> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Failed to read the provisioning script"),
+        };
+
+        // Lines look like: net use W: "\\server\share" /persistent:no >nul 2>&1
+        let entries = content.lines()
+            .filter_map(|line| {
+                let rest = line.trim().strip_prefix("net use ")?;
+                let drive = rest.chars().next()?;
+                let path = rest.split('"').nth(1)?;
+                Some(ProvisionedDrive { drive, path: path.to_string() })
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Rewrites a managed script with the given mappings (removing it if there are none left).
+    fn write_entries(path: &Path, entries: &[ProvisionedDrive]) -> Result<()> {
+        let result = if entries.is_empty() {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                other => other,
+            }
+        } else {
+            let mut content = SCRIPT_HEADER.to_string();
+            for entry in entries {
+                content.push_str(&format!("net use {}: \"{}\" /persistent:no >nul 2>&1\r\n", entry.drive, entry.path));
+            }
+            fs::write(path, content)
+        };
+
+        result.map_err(|e| {
+            let error = UpvError::Drive(
+                format!("Failed to update the provisioning script '{}': {}", path.display(), e).into()
+            );
+            if e.kind() == ErrorKind::PermissionDenied {
+                error.with_hint("Machine-wide drives can only be provisioned from an elevated (administrator) terminal").into()
+            } else {
+                error.into()
+            }
+        })
+    }
+
+    /// Checks that a path is a UNC path that can be written into the script as it is
+    fn check_path(path: &str) -> Result<()> {
+        if !path.starts_with(r"\\") {
+            return Err(UpvError::Drive(
+                format!("'{}' is not a UNC path (expected something like \\\\server\\share)", path).into()
+            ).into());
+        }
+        if let Some(c) = path.chars().find(|c| UNSAFE_PATH_CHARS.contains(c)) {
+            return Err(UpvError::Drive(
+                format!("The path '{}' can't be provisioned, since it contains {:?}", path.escape_debug(), c).into()
+            ).with_hint("Paths of provisioned drives can't contain \" % & | < > ^ or line breaks").into());
+        }
+
+        Ok(())
+    }
+
+    // Public methods for drive provisioning
+
+    /// Adds (or replaces) a drive mapping for all users of this machine
+    pub fn add(drive: char, path: &str) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+
+        if !drive.is_ascii_alphabetic() {
+            return Err(UpvError::Drive(
+                format!("'{}' is not a valid drive letter", drive).into()
+            ).into());
+        }
+        Self::check_path(path)?;
+
+        let script = Self::script_path();
+        let mut entries = Self::read_entries(&script)?;
+        entries.retain(|entry| entry.drive != drive);
+        entries.push(ProvisionedDrive { drive, path: path.to_string() });
+        entries.sort_by_key(|entry| entry.drive);
+
+        Self::write_entries(&script, &entries)?;
+
+        println!("Drive {}: will be mapped to {} for every user on their next logon", drive, path);
+        println!("Provisioning script: {}", Self::script_path().display());
+
+        Ok(())
+    }
+
+    /// Removes a machine-wide drive mapping
+    pub fn remove(drive: char) -> Result<()> {
+        let drive = drive.to_ascii_uppercase();
+
+        let script = Self::script_path();
+        let mut entries = Self::read_entries(&script)?;
+        let count = entries.len();
+        entries.retain(|entry| entry.drive != drive);
+
+        if entries.len() == count {
+            return Err(UpvError::Drive(
+                format!("Drive {}: is not provisioned on this machine", drive).into()
+            ).into());
+        }
+
+        Self::write_entries(&script, &entries)?;
+
+        println!("Drive {}: will no longer be mapped on logon (already mapped sessions are not affected)", drive);
+
+        Ok(())
+    }
+
+    /// Lists the machine-wide drive mappings
+    pub fn list() -> Result<()> {
+        let entries = Self::read_entries(&Self::script_path())?;
+
+        if entries.is_empty() {
+            println!("No drives provisioned on this machine.");
+        } else {
+            println!("Found {} provisioned drive(s):", entries.len());
+            for entry in entries {
+                println!("  - {}: {}", entry.drive, entry.path);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_the_drives_it_writes() {
+        let dir = std::env::temp_dir().join(format!("upv-provision-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join(SCRIPT_NAME);
+
+        let entries = [
+            ProvisionedDrive { drive: 'P', path: r"\\nasupv.upv.es\Proyectos (2025)".to_string() },
+            ProvisionedDrive { drive: 'W', path: r"\\nasupv.upv.es\discos\a\alice".to_string() },
+        ];
+        ProvisionManager::write_entries(&script, &entries).unwrap();
+        let read: Vec<(char, String)> = ProvisionManager::read_entries(&script).unwrap()
+            .into_iter()
+            .map(|entry| (entry.drive, entry.path))
+            .collect();
+        assert_eq!(read, entries.iter().map(|entry| (entry.drive, entry.path.clone())).collect::<Vec<_>>());
+
+        ProvisionManager::write_entries(&script, &[]).unwrap();
+        assert!(ProvisionManager::read_entries(&script).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_paths_that_would_run_commands() {
+        assert!(ProvisionManager::check_path(r"\\nasupv.upv.es\Proyectos (2025)").is_ok());
+        for path in [r#"\\srv\s" & calc & ""#, r"\\srv\%PATH%", "\\\\srv\\s\r\ncalc", r"\\srv\s^|calc"] {
+            assert!(ProvisionManager::check_path(path).is_err(), "{}", path);
+        }
+    }
+}
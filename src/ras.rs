@@ -0,0 +1,247 @@
+use std::mem::size_of;
+use std::path::Path;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use windows_sys::Win32::NetworkManagement::Rras::{
+    RasDeleteEntryW, RasDialW, RasEnumConnectionsW, RasEnumEntriesW, RasGetConnectStatusW, RasGetEntryDialParamsW,
+    RasGetEntryPropertiesW, RasGetErrorStringW, RasHangUpW, ERROR_BUFFER_TOO_SMALL, ERROR_INTERACTIVE_MODE, HRASCONN,
+    RASCONNSTATUSW, RASCONNW, RASCS_Connected, RASDIALEXTENSIONS, RASDIALPARAMSW, RASENTRYNAMEW, RASENTRYW,
+    RDEOPT_UsePrefixSuffix,
+};
+
+use crate::error::UpvError;
+use crate::wide::{from_wide, to_wide};
+
+// Native access to the RAS (Remote Access Service) API, which manages the same phonebook entries and connections
+// as rasdial, rasphone and the VPN PowerShell cmdlets, without the cost of starting another process.
+// Every function uses the default phonebooks (current user and all users), like the other tools do, except for dialing,
+// which can be given another one like rasdial /PHONEBOOK.
+//
+// Entries are not created here: RasSetEntryProperties can write one, but the EAP settings UPV's VPN needs are a binary
+// blob that only EapHost can build from the EAP XML (through its COM XML interfaces), which is what Add-VpnConnection
+// does. So creating and configuring connections stays on the PowerShell cmdlets.
+
+// Returned by RasGetConnectStatus once a connection that was hung up is completely gone
+const ERROR_INVALID_HANDLE: u32 = 6;
+
+/// A RAS connection that is currently active (connected or still connecting)
+pub struct ActiveConnection {
+    pub name: String,
+    handle: HRASCONN,
+}
+
+impl ActiveConnection {
+    /// Whether the connection is completely established (instead of still connecting)
+    pub fn is_connected(&self) -> Result<bool> {
+        let mut status = RASCONNSTATUSW { dwSize: size_of::<RASCONNSTATUSW>() as u32, ..Default::default() };
+        // SAFETY: the status struct has its dwSize set and outlives the call
+        match unsafe { RasGetConnectStatusW(self.handle, &mut status) } {
+            0 => Ok(status.rasconnstate == RASCS_Connected),
+            // It was hung up since it was listed
+            ERROR_INVALID_HANDLE => Ok(false),
+            code => Err(ras_error(&format!("Failed to get the status of VPN connection '{}'", self.name), code)),
+        }
+    }
+
+    /// Hangs up the connection, waiting until it is completely gone
+    /// (the RAS API docs warn that it is torn down asynchronously after RasHangUp returns).
+    pub fn hang_up(&self) -> Result<()> {
+        // SAFETY: the handle was returned by RasEnumConnections, and RasHangUp accepts stale handles
+        let code = unsafe { RasHangUpW(self.handle) };
+        if code != 0 {
+            return Err(ras_error(&format!("Failed to disconnect from '{}'", self.name), code));
+        }
+
+        let mut status = RASCONNSTATUSW { dwSize: size_of::<RASCONNSTATUSW>() as u32, ..Default::default() };
+        for _ in 0..60 {
+            // SAFETY: the status struct has its dwSize set and outlives the call
+            if unsafe { RasGetConnectStatusW(self.handle, &mut status) } == ERROR_INVALID_HANDLE {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        Ok(())
+    }
+}
+
+/// How to dial an entry, besides its name. Unset fields are taken from the phonebook entry
+#[derive(Default)]
+pub struct DialOptions<'a> {
+    /// Phonebook (.pbk) file the entry is read from, instead of the default ones
+    pub phonebook: Option<&'a Path>,
+    /// Username and password. Without them, the ones saved in the entry are used
+    pub credentials: Option<(&'a str, &'a str)>,
+    /// Domain to authenticate in
+    pub domain: Option<&'a str>,
+    /// Server address to dial instead of the entry's
+    pub phone: Option<&'a str>,
+    /// Apply the dialing prefix and suffix of the entry
+    pub prefix_suffix: bool,
+}
+
+/// (Localized) description of a RAS error code
+pub fn describe(code: u32) -> String {
+    let mut buffer = [0u16; 512];
+    // SAFETY: the buffer is valid for writes of its whole length, which is the size given
+    match unsafe { RasGetErrorStringW(code, buffer.as_mut_ptr(), buffer.len() as u32) } {
+        0 => from_wide(&buffer).trim().to_string(),
+        _ => format!("RAS error {}", code),
+    }
+}
+
+/// Error for a failed RAS call, with the (localized) description of its error code
+fn ras_error(operation: &str, code: u32) -> anyhow::Error {
+    UpvError::Vpn(format!("{}: {}", operation, describe(code)).into())
+        .with_os_code(Some(code as i32))
+        .into()
+}
+
+/// A string in a fixed size field of a RAS struct (cut to fit, and always null-terminated)
+fn fixed<const N: usize>(value: &str) -> [u16; N] {
+    let mut field = [0u16; N];
+    for (slot, unit) in field[..N - 1].iter_mut().zip(value.encode_utf16()) {
+        *slot = unit;
+    }
+    field
+}
+
+/// Names of every VPN (and dial-up) entry in the phonebooks
+pub fn entries() -> Result<Vec<String>> {
+    let mut entries = vec![RASENTRYNAMEW { dwSize: size_of::<RASENTRYNAMEW>() as u32, ..Default::default() }];
+    loop {
+        let mut size = (entries.len() * size_of::<RASENTRYNAMEW>()) as u32;
+        let mut count = 0;
+        // SAFETY: the buffer holds `size` bytes of RASENTRYNAMEW structs, and the first one has its dwSize set
+        let code = unsafe { RasEnumEntriesW(ptr::null(), ptr::null(), entries.as_mut_ptr(), &mut size, &mut count) };
+
+        match code {
+            0 => return Ok(entries[..count as usize].iter().map(|entry| from_wide(&entry.szEntryName)).collect()),
+            ERROR_BUFFER_TOO_SMALL => {
+                let needed = (size as usize).div_ceil(size_of::<RASENTRYNAMEW>());
+                entries.resize(needed.max(entries.len() + 1), entries[0]);
+            }
+            _ => return Err(ras_error("Failed to list VPN connections", code)),
+        }
+    }
+}
+
+/// Server address of a phonebook entry
+pub fn entry_server(name: &str) -> Result<String> {
+    let name_wide = to_wide(name);
+
+    // Entries can have extra data after the struct (alternate addresses), so the size is asked for first
+    let mut size = 0;
+    // SAFETY: with a null entry, only the required size is written
+    unsafe { RasGetEntryPropertiesW(ptr::null(), name_wide.as_ptr(), ptr::null_mut(), &mut size, ptr::null_mut(), ptr::null_mut()) };
+
+    // A u64 buffer keeps the struct aligned
+    let size = (size as usize).max(size_of::<RASENTRYW>());
+    let mut buffer = vec![0u64; size.div_ceil(size_of::<u64>())];
+    let entry = buffer.as_mut_ptr().cast::<RASENTRYW>();
+    let mut size = size as u32;
+
+    // SAFETY: the buffer is zeroed, aligned and at least as big as a RASENTRYW, and outlives the call
+    let code = unsafe {
+        (*entry).dwSize = size_of::<RASENTRYW>() as u32;
+        RasGetEntryPropertiesW(ptr::null(), name_wide.as_ptr(), entry, &mut size, ptr::null_mut(), ptr::null_mut())
+    };
+    if code != 0 {
+        return Err(ras_error(&format!("Failed to read VPN connection '{}'", name), code));
+    }
+
+    // SAFETY: the call succeeded, so the struct was filled in
+    Ok(from_wide(unsafe { &(*entry).szLocalPhoneNumber }))
+}
+
+/// Deletes a phonebook entry
+pub fn delete_entry(name: &str) -> Result<()> {
+    let name_wide = to_wide(name);
+    // SAFETY: the name is a valid null-terminated UTF-16 string that outlives the call
+    match unsafe { RasDeleteEntryW(ptr::null(), name_wide.as_ptr()) } {
+        0 => Ok(()),
+        code => Err(ras_error(&format!("Failed to delete VPN connection '{}'", name), code)),
+    }
+}
+
+/// Connections that are currently active (connected or still connecting)
+pub fn active_connections() -> Result<Vec<ActiveConnection>> {
+    // SAFETY: an all-zeros RASCONNW is valid (null handle, empty strings)
+    let mut connections = vec![RASCONNW { dwSize: size_of::<RASCONNW>() as u32, ..unsafe { std::mem::zeroed() } }];
+    loop {
+        let mut size = (connections.len() * size_of::<RASCONNW>()) as u32;
+        let mut count = 0;
+        // SAFETY: the buffer holds `size` bytes of RASCONNW structs, and the first one has its dwSize set
+        let code = unsafe { RasEnumConnectionsW(connections.as_mut_ptr(), &mut size, &mut count) };
+
+        match code {
+            0 => {
+                return Ok(connections[..count as usize].iter()
+                    .map(|connection| ActiveConnection { name: from_wide(&{ connection.szEntryName }), handle: connection.hrasconn })
+                    .collect());
+            }
+            ERROR_BUFFER_TOO_SMALL => {
+                let needed = (size as usize).div_ceil(size_of::<RASCONNW>());
+                connections.resize(needed.max(connections.len() + 1), connections[0]);
+            }
+            _ => return Err(ras_error("Failed to list active VPN connections", code)),
+        }
+    }
+}
+
+/// Dials a phonebook entry and waits until it is connected (or fails), like rasdial does.
+/// Without credentials, the ones saved in the entry are used, failing with ERROR_INTERACTIVE_MODE if there are none.
+/// Fails with the RAS error code (e.g. 691 for a wrong password, or 623 for an entry that doesn't exist).
+pub fn dial(name: &str, options: &DialOptions) -> Result<(), u32> {
+    let phonebook = options.phonebook.map(|path| to_wide(&path.to_string_lossy()));
+    let phonebook_ptr = phonebook.as_ref().map_or(ptr::null(), |phonebook| phonebook.as_ptr());
+
+    let mut params = RASDIALPARAMSW { dwSize: size_of::<RASDIALPARAMSW>() as u32, ..Default::default() };
+    params.szEntryName = fixed(name);
+    match options.credentials {
+        Some((user, password)) => {
+            params.szUserName = fixed(user);
+            params.szPassword = fixed(password);
+        }
+        None => {
+            let mut has_password = 0;
+            // SAFETY: the params have their dwSize and entry name set, and outlive the call
+            let code = unsafe { RasGetEntryDialParamsW(phonebook_ptr, &mut params, &mut has_password) };
+            if code != 0 {
+                return Err(code);
+            }
+            if has_password == 0 {
+                return Err(ERROR_INTERACTIVE_MODE);
+            }
+        }
+    }
+    if let Some(domain) = options.domain {
+        params.szDomain = fixed(domain);
+    }
+    if let Some(phone) = options.phone {
+        params.szPhoneNumber = fixed(phone);
+    }
+
+    let extensions = RASDIALEXTENSIONS {
+        dwSize: size_of::<RASDIALEXTENSIONS>() as u32,
+        dwfOptions: if options.prefix_suffix { RDEOPT_UsePrefixSuffix } else { 0 },
+        ..Default::default()
+    };
+
+    let mut handle: HRASCONN = ptr::null_mut();
+    // SAFETY: the structs have their dwSize set and outlive the call. Without a notifier, the call is synchronous
+    let code = unsafe { RasDialW(&extensions, phonebook_ptr, &params, 0, ptr::null(), &mut handle) };
+
+    if code != 0 {
+        // A failed dial can still leave a handle, which has to be hung up to free the port
+        if !handle.is_null() {
+            // SAFETY: the handle was returned by RasDial and isn't used after this
+            unsafe { RasHangUpW(handle) };
+        }
+        return Err(code);
+    }
+
+    Ok(())
+}
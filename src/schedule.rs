@@ -0,0 +1,389 @@
+use std::fs;
+use std::process::Command;
+use anyhow::{Result, Context};
+use chrono::{NaiveTime, Weekday};
+use clap::ValueEnum;
+
+use crate::config::ProfileSchedule;
+use crate::dry_run;
+use crate::error::UpvError;
+use crate::paths;
+
+// Scheduled connects and disconnects are Task Scheduler tasks in their own folder, so they can be listed and removed
+// without touching anything else. They run upv itself, as the current user, only while they are logged on.
+const TASK_FOLDER: &str = r"\upv\";
+
+// Drive profiles with a schedule get a task that brings them up and another that brings them down, named after them
+// (e.g. "profile-lab-up" and "profile-lab-down")
+const PROFILE_TASK_PREFIX: &str = "profile-";
+
+/// What a scheduled task does
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ScheduledAction {
+    /// Connect to the VPN (without any dialog, using the stored credentials)
+    Connect,
+    /// Disconnect from the VPN
+    Disconnect,
+}
+
+impl ScheduledAction {
+    fn name(self) -> &'static str {
+        match self {
+            ScheduledAction::Connect => "connect",
+            ScheduledAction::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// When a scheduled task runs
+pub enum ScheduleTrigger {
+    /// Every time the current user logs on
+    Logon,
+    /// Every day at the given time
+    Daily(NaiveTime),
+    /// Every week on the given days, at the given time
+    Weekly(NaiveTime, Vec<Weekday>),
+}
+
+impl ScheduleTrigger {
+    /// Parses a time of day in 24-hour format (HH:MM)
+    fn time(time: &str) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| UpvError::InvalidValue(
+                format!("'{}' is not a valid time (expected HH:MM, e.g. 20:00)", time).into()
+            ).into())
+    }
+
+    /// Every day at a time of day in 24-hour format (HH:MM)
+    pub fn daily(time: &str) -> Result<Self> {
+        Self::time(time).map(ScheduleTrigger::Daily)
+    }
+
+    /// The triggers of the start and the end of a profile schedule, on each of its days (or every day).
+    /// It has to end on the day it starts, since the end runs on the same days.
+    pub fn profile(schedule: &ProfileSchedule) -> Result<(Self, Self)> {
+        let (from, to) = (Self::time(&schedule.from)?, Self::time(&schedule.to)?);
+        if from >= to {
+            return Err(UpvError::InvalidValue(
+                format!("The schedule from {} to {} doesn't end after it starts", schedule.from, schedule.to).into()
+            ).with_hint("Schedules can't go past midnight, split it in two profiles if needed").into());
+        }
+
+        if schedule.days.is_empty() {
+            return Ok((ScheduleTrigger::Daily(from), ScheduleTrigger::Daily(to)));
+        }
+        let days = schedule.days.iter()
+            .map(|day| day.parse::<Weekday>().map_err(|_| UpvError::InvalidValue(
+                format!("'{}' is not a day of the week (expected mon, tue, wed, thu, fri, sat or sun)", day).into()
+            ).into()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok((ScheduleTrigger::Weekly(from, days.clone()), ScheduleTrigger::Weekly(to, days)))
+    }
+
+    /// When it runs, for messages (e.g. "every day at 20:00")
+    fn describe(&self) -> String {
+        match self {
+            ScheduleTrigger::Logon => "at logon".to_string(),
+            ScheduleTrigger::Daily(time) => format!("every day at {}", time.format("%H:%M")),
+            ScheduleTrigger::Weekly(time, days) => {
+                let days: Vec<String> = days.iter().map(Weekday::to_string).collect();
+                format!("every {} at {}", days.join(", "), time.format("%H:%M"))
+            }
+        }
+    }
+}
+
+pub struct ScheduleManager;
+
+impl ScheduleManager {
+    // Private utility functions
+
+    /// Escapes a value to be put inside an XML element
+    fn escape_xml(value: &str) -> String {
+        value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+    }
+
+    /// Name of the task for an action and trigger (e.g. "connect-at-logon", "disconnect-at-2000")
+    fn task_name(action: ScheduledAction, trigger: &ScheduleTrigger) -> String {
+        match trigger {
+            ScheduleTrigger::Logon => format!("{}-at-logon", action.name()),
+            ScheduleTrigger::Daily(time) | ScheduleTrigger::Weekly(time, _) => format!("{}-at-{}", action.name(), time.format("%H%M")),
+        }
+    }
+
+    /// Name of a day of the week in a task definition
+    fn day_name(day: Weekday) -> &'static str {
+        match day {
+            Weekday::Mon => "Monday",
+            Weekday::Tue => "Tuesday",
+            Weekday::Wed => "Wednesday",
+            Weekday::Thu => "Thursday",
+            Weekday::Fri => "Friday",
+            Weekday::Sat => "Saturday",
+            Weekday::Sun => "Sunday",
+        }
+    }
+
+    /// Task Scheduler definition of a task that runs upv with each of the given arguments, one after the other.
+    fn task_xml(description: &str, trigger: &ScheduleTrigger, command: &str, arguments: &[String]) -> String {
+        let user = format!(
+            "{}\\{}",
+            std::env::var("USERDOMAIN").unwrap_or_default(),
+            std::env::var("USERNAME").unwrap_or_default()
+        );
+
+        let trigger = match trigger {
+            ScheduleTrigger::Logon => format!(
+                "    <LogonTrigger>\r\n      <Enabled>true</Enabled>\r\n      <UserId>{}</UserId>\r\n    </LogonTrigger>\r\n",
+                Self::escape_xml(&user)
+            ),
+            ScheduleTrigger::Daily(time) => format!(
+                "    <CalendarTrigger>\r\n      <StartBoundary>{}T{}</StartBoundary>\r\n      <Enabled>true</Enabled>\r\n      \
+                <ScheduleByDay>\r\n        <DaysInterval>1</DaysInterval>\r\n      </ScheduleByDay>\r\n    </CalendarTrigger>\r\n",
+                chrono::Local::now().format("%Y-%m-%d"),
+                time.format("%H:%M:%S")
+            ),
+            ScheduleTrigger::Weekly(time, days) => format!(
+                "    <CalendarTrigger>\r\n      <StartBoundary>{}T{}</StartBoundary>\r\n      <Enabled>true</Enabled>\r\n      \
+                <ScheduleByWeek>\r\n        <DaysOfWeek>{}</DaysOfWeek>\r\n        <WeeksInterval>1</WeeksInterval>\r\n      \
+                </ScheduleByWeek>\r\n    </CalendarTrigger>\r\n",
+                chrono::Local::now().format("%Y-%m-%d"),
+                time.format("%H:%M:%S"),
+                days.iter().map(|day| format!("<{} />", Self::day_name(*day))).collect::<String>()
+            ),
+        };
+        let actions: String = arguments.iter()
+            .map(|arguments| format!(
+                "    <Exec>\r\n      <Command>{}</Command>\r\n      <Arguments>{}</Arguments>\r\n    </Exec>\r\n",
+                Self::escape_xml(command),
+                Self::escape_xml(arguments)
+            ))
+            .collect();
+
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\r\n\
+            <Task version=\"1.2\" xmlns=\"http://schemas.microsoft.com/windows/2004/02/mit/task\">\r\n\
+            \x20 <RegistrationInfo>\r\n\
+            \x20   <Description>{}</Description>\r\n\
+            \x20 </RegistrationInfo>\r\n\
+            \x20 <Triggers>\r\n{}\
+            \x20 </Triggers>\r\n\
+            \x20 <Principals>\r\n\
+            \x20   <Principal id=\"Author\">\r\n\
+            \x20     <UserId>{}</UserId>\r\n\
+            \x20     <LogonType>InteractiveToken</LogonType>\r\n\
+            \x20     <RunLevel>LeastPrivilege</RunLevel>\r\n\
+            \x20   </Principal>\r\n\
+            \x20 </Principals>\r\n\
+            \x20 <Settings>\r\n\
+            \x20   <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>\r\n\
+            \x20   <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>\r\n\
+            \x20   <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>\r\n\
+            \x20   <StartWhenAvailable>true</StartWhenAvailable>\r\n\
+            \x20   <RunOnlyIfNetworkAvailable>true</RunOnlyIfNetworkAvailable>\r\n\
+            \x20   <ExecutionTimeLimit>PT10M</ExecutionTimeLimit>\r\n\
+            \x20 </Settings>\r\n\
+            \x20 <Actions Context=\"Author\">\r\n{}\
+            \x20 </Actions>\r\n\
+            </Task>\r\n",
+            Self::escape_xml(description),
+            trigger,
+            Self::escape_xml(&user),
+            actions
+        )
+    }
+
+    /// Runs schtasks, failing with its error message if it doesn't succeed.
+    fn schtasks(args: &[&str], operation: &str) -> Result<String> {
+        let mut command = Command::new("schtasks");
+        let output = command
+            .args(args)
+            .output()
+            .context("Failed to execute schtasks")?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::TaskScheduler(
+                format!("Failed to {}: {}", operation, error.trim()).into()
+            ).with_command(&command, &[]).into());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Creates (or replaces) a task that runs upv with each of the given arguments, one after the other.
+    fn register(task: &str, trigger: &ScheduleTrigger, arguments: &[String]) -> Result<()> {
+        let exe = std::env::current_exe().context("Failed to find the path of upv")?;
+        let commands: Vec<String> = arguments.iter().map(|arguments| format!("upv {}", arguments)).collect();
+        let when = trigger.describe();
+        let description = format!("{} ({}). Created by upv-cli, remove it with 'upv schedule remove'", commands.join(", then "), when);
+
+        let task = format!("{}{}", TASK_FOLDER, task);
+        let xml = Self::task_xml(&description, trigger, &exe.display().to_string(), arguments);
+
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Register the task '{}' to run '{}' {}", task, commands.join("', then '"), when));
+            return Ok(());
+        }
+
+        // schtasks reads the definition from a file, which has to be UTF-16 as its declaration says
+        let path = paths::data_dir()?.join("schedule-task.xml");
+        let content: Vec<u8> = std::iter::once(0xFEFF)
+            .chain(xml.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+        let result = Self::schtasks(
+            &["/Create", "/TN", &task, "/XML", &path.display().to_string(), "/F"],
+            &format!("register the task '{}'", task)
+        );
+        let _ = fs::remove_file(&path);
+        result?;
+
+        println!("Scheduled '{}' {} (task '{}')", commands.join("', then '"), when, task);
+
+        Ok(())
+    }
+
+    /// Names of the tasks created by upv-cli (without their folder), with the rest of their fields from schtasks
+    fn tasks() -> Result<Vec<Vec<String>>> {
+        // One quoted CSV line per task: "TaskName","Next Run Time","Status" (the header is left out)
+        let output = Self::schtasks(&["/Query", "/FO", "CSV", "/NH"], "list the scheduled tasks")?;
+        Ok(output.lines()
+            .map(|line| line.trim().trim_matches('"').split("\",\"").map(str::to_string).collect::<Vec<_>>())
+            .filter(|fields| fields.first().is_some_and(|name| name.starts_with(TASK_FOLDER)))
+            .map(|mut fields| {
+                fields[0] = fields[0].trim_start_matches(TASK_FOLDER).to_string();
+                fields
+            })
+            .collect())
+    }
+
+    // Public methods for scheduled connects and disconnects
+
+    /// Creates (or replaces) a task that connects to or disconnects from the VPN at logon or every day at a time.
+    /// The VPN name is only used to connect (disconnecting closes every UPV connection).
+    pub fn add(action: ScheduledAction, trigger: &ScheduleTrigger, vpn_name: Option<&str>) -> Result<()> {
+        let arguments = match action {
+            ScheduledAction::Connect => format!("vpn connect \"{}\" --silent", vpn_name.unwrap_or_default()),
+            ScheduledAction::Disconnect => "vpn disconnect".to_string(),
+        };
+
+        Self::register(&Self::task_name(action, trigger), trigger, &[arguments])
+    }
+
+    /// Creates the tasks that bring the drive profiles with a schedule up and down: at its start, the drive is mounted
+    /// (connecting to the default VPN first if the server can't be reached), and at its end, it is unmounted and the VPN
+    /// is disconnected. The tasks of profiles that no longer have a schedule are removed.
+    /// Every schedule is checked before any task is changed.
+    pub fn schedule_profiles(profiles: &[(&str, char, &ProfileSchedule)]) -> Result<()> {
+        let mut tasks = Vec::new();
+        for &(name, drive, schedule) in profiles {
+            // The name goes in the task name and its command line
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                return Err(UpvError::InvalidValue(
+                    format!("The profile '{}' can't be scheduled, since its name has characters other than letters, digits, '-' and '_'", name).into()
+                ).with_hint("Rename it with 'upv config edit'").into());
+            }
+            let (up, down) = ScheduleTrigger::profile(schedule)
+                .with_context(|| format!("The schedule of the profile '{}' is not valid", name))?;
+            tasks.push((format!("{}{}-up", PROFILE_TASK_PREFIX, name), up, vec![format!("drive mount --profile {} --auto-vpn", name)]));
+            tasks.push((
+                format!("{}{}-down", PROFILE_TASK_PREFIX, name),
+                down,
+                vec![format!("drive unmount --drive {} --force", drive.to_ascii_uppercase()), "vpn disconnect".to_string()],
+            ));
+        }
+
+        let stale: Vec<String> = Self::tasks()?
+            .into_iter()
+            .map(|mut fields| fields.swap_remove(0))
+            .filter(|task| task.starts_with(PROFILE_TASK_PREFIX) && !tasks.iter().any(|(name, ..)| name == task))
+            .collect();
+        if tasks.is_empty() && stale.is_empty() {
+            println!("No drive profiles have a schedule. Add one to a profile with 'upv config edit'");
+            return Ok(());
+        }
+
+        for task in &stale {
+            Self::remove(task)?;
+        }
+        for (task, trigger, arguments) in &tasks {
+            Self::register(task, trigger, arguments)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes a task created with `add` or `schedule_profiles`.
+    pub fn remove(name: &str) -> Result<()> {
+        let task = format!("{}{}", TASK_FOLDER, name.trim_start_matches(TASK_FOLDER));
+
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Delete the task '{}'", task));
+            return Ok(());
+        }
+
+        Self::schtasks(&["/Delete", "/TN", &task, "/F"], &format!("remove the task '{}'", task))
+            .map_err(|e| match e.downcast::<UpvError>() {
+                Ok(error) => error.with_hint("Run 'upv schedule list' to see the scheduled tasks").into(),
+                Err(e) => e,
+            })?;
+
+        println!("Task '{}' removed", task);
+
+        Ok(())
+    }
+
+    /// Lists the tasks created with `add` or `schedule_profiles`, with their next run time.
+    pub fn list() -> Result<()> {
+        let tasks = Self::tasks()?;
+
+        if tasks.is_empty() {
+            println!("No scheduled connects, disconnects or profiles. Add one with 'upv schedule add' or 'upv schedule profiles'");
+            return Ok(());
+        }
+
+        println!("Scheduled tasks:");
+        for fields in &tasks {
+            let name = &fields[0];
+            let next_run = fields.get(1).map(String::as_str).unwrap_or("-");
+            let status = fields.get(2).map(String::as_str).unwrap_or("-");
+            println!("  - {}  (next run: {}, {})", name, next_run, status);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(days: &[&str], from: &str, to: &str) -> ProfileSchedule {
+        ProfileSchedule { days: days.iter().map(|day| day.to_string()).collect(), from: from.to_string(), to: to.to_string() }
+    }
+
+    #[test]
+    fn schedules_profiles_on_their_days() {
+        let (up, down) = ScheduleTrigger::profile(&schedule(&["mon", "Friday"], "08:00", "18:30")).unwrap();
+        assert_eq!(up.describe(), "every Mon, Fri at 08:00");
+        assert_eq!(down.describe(), "every Mon, Fri at 18:30");
+
+        let xml = ScheduleManager::task_xml("", &up, "upv.exe", &["a".to_string(), "b".to_string()]);
+        assert!(xml.contains("<DaysOfWeek><Monday /><Friday /></DaysOfWeek>"));
+        assert_eq!(xml.matches("<Exec>").count(), 2);
+
+        let (up, _) = ScheduleTrigger::profile(&schedule(&[], "08:00", "18:00")).unwrap();
+        assert_eq!(up.describe(), "every day at 08:00");
+    }
+
+    #[test]
+    fn rejects_invalid_profile_schedules() {
+        assert!(ScheduleTrigger::profile(&schedule(&["someday"], "08:00", "18:00")).is_err());
+        assert!(ScheduleTrigger::profile(&schedule(&[], "8am", "18:00")).is_err());
+        assert!(ScheduleTrigger::profile(&schedule(&[], "22:00", "06:00")).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, Context};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::drive::DriveManager;
+use crate::maintenance::{MaintenanceManager, Window};
+use crate::paths;
+use crate::vpn::VpnManager;
+
+// Every status read live is saved, so shell prompts and scripts can get it instantly with --max-age instead of
+// querying Windows again (which takes long enough to notice). Commands that change it delete the file.
+const CACHE_FILE: &str = "status-cache.json";
+
+/// Statuses of a section by name, or the error that prevented reading them
+type Section = Result<BTreeMap<String, String>, String>;
+
+/// Status of everything shown at once: VPN connections (by name) and drive mappings (by drive and share)
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    taken_at: DateTime<Local>,
+    vpn: Section,
+    drives: Section,
+}
+
+pub struct StatusManager;
+
+impl StatusManager {
+    // Private utility functions
+
+    /// Reads the current state. Errors are kept in the snapshot, so a failed refresh doesn't stop the watch
+    fn snapshot() -> Snapshot {
+        let vpn = VpnManager::get_upv_connection_statuses()
+            .map(|connections| connections.into_iter().collect())
+            .map_err(|e| format!("{:#}", e));
+
+        let drives = DriveManager::get_mappings()
+            .map(|entries| entries.into_iter().map(|entry| (entry.key(), entry.status)).collect())
+            .map_err(|e| format!("{:#}", e));
+
+        Snapshot { taken_at: Local::now(), vpn, drives }
+    }
+
+    fn cache_path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(CACHE_FILE))
+    }
+
+    /// Reads the cached status, if there is one at most `max_age` seconds old.
+    fn load_cache(max_age: u64) -> Option<Snapshot> {
+        let content = fs::read_to_string(Self::cache_path().ok()?).ok()?;
+        let snapshot: Snapshot = serde_json::from_str(&content).ok()?;
+
+        let age = Local::now().signed_duration_since(snapshot.taken_at).num_seconds();
+        (0..=max_age as i64).contains(&age).then_some(snapshot)
+    }
+
+    /// Saves a status to the cache (best effort, the status itself is what matters).
+    /// Incomplete ones aren't saved, so the next read tries again instead of repeating the error.
+    fn save_cache(snapshot: &Snapshot) {
+        if snapshot.vpn.is_err() || snapshot.drives.is_err() {
+            return;
+        }
+        if let Ok(path) = Self::cache_path()
+            && let Ok(content) = serde_json::to_string(snapshot) {
+            let _ = fs::write(path, content);
+        }
+    }
+
+    /// Wraps a VPN connection status in an ANSI color (green when connected, dim when disconnected)
+    fn colorize_vpn_status(status: &str) -> String {
+        match status {
+            "Connected" => format!("\x1b[32m{}\x1b[0m", status),
+            "Disconnected" => format!("\x1b[2m{}\x1b[0m", status),
+            _ => format!("\x1b[33m{}\x1b[0m", status),
+        }
+    }
+
+    /// Prints a section, highlighting the entries whose status changed since the previous refresh
+    fn print_section(
+        title: &str,
+        current: &Section,
+        previous: Option<&Section>,
+        empty: &str,
+        colorize: fn(&str) -> String,
+    ) {
+        println!("\x1b[1m{}\x1b[0m", title);
+
+        let current = match current {
+            Ok(current) => current,
+            Err(e) => {
+                println!("  \x1b[33mFailed to refresh: {}\x1b[0m", e);
+                return;
+            }
+        };
+        let previous = previous.and_then(|previous| previous.as_ref().ok());
+
+        if current.is_empty() {
+            println!("  \x1b[2m{}\x1b[0m", empty);
+        }
+        for (name, status) in current {
+            let changed = previous.is_some_and(|previous| previous.get(name) != Some(status));
+            let marker = if changed { "  \x1b[1;33m← changed\x1b[0m" } else { "" };
+            println!("  {}  {}{}", name, colorize(status), marker);
+        }
+
+        // Entries that just went away are shown for one refresh, so the change isn't missed
+        for name in previous.into_iter().flat_map(|previous| previous.keys()).filter(|name| !current.contains_key(*name)) {
+            println!("  {}  \x1b[1;31m(removed)\x1b[0m", name);
+        }
+    }
+
+    fn print_snapshot(current: &Snapshot, previous: Option<&Snapshot>) {
+        Self::print_section(
+            "VPN connections:",
+            &current.vpn,
+            previous.map(|previous| &previous.vpn),
+            "No UPV VPN connections",
+            Self::colorize_vpn_status,
+        );
+        Self::print_section(
+            "Network drives:",
+            &current.drives,
+            previous.map(|previous| &previous.drives),
+            "No network drives mapped",
+            DriveManager::colorize_status,
+        );
+
+        if let Ok(vpn) = &current.vpn {
+            VpnManager::print_outdated_connections(&vpn.keys().cloned().collect::<Vec<_>>());
+        }
+    }
+
+    /// Prints the announced maintenance going on or coming up, if any (or why the feed couldn't be read)
+    fn print_maintenance(windows: &Result<Vec<Window>>) {
+        match windows {
+            Ok(windows) => {
+                let now = Local::now();
+                for window in windows.iter().filter(|window| window.end > now) {
+                    println!("\x1b[33m{}\x1b[0m", MaintenanceManager::describe(window, &now));
+                }
+            }
+            Err(e) => println!("\x1b[33mFailed to check for UPV maintenance: {:#}\x1b[0m", e),
+        }
+    }
+
+    // Public methods for the combined status
+
+    /// Deletes the cached status, for commands that are about to change it.
+    pub fn invalidate_cache() {
+        if let Ok(path) = Self::cache_path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Shows the status of the UPV VPN connections and the network drives.
+    /// With a maximum age, a status cached at most that many seconds ago is shown instead (saying how old it is).
+    /// In watch mode it is redrawn in place every `interval` seconds, highlighting what changed.
+    /// With a status feed, the announced maintenance is shown below (except for cached statuses, which have to be quick).
+    pub fn show(watch: bool, interval: u64, max_age: Option<u64>, status_feed: Option<&str>) -> Result<()> {
+        if !watch {
+            if let Some(cached) = max_age.and_then(Self::load_cache) {
+                let age = Local::now().signed_duration_since(cached.taken_at).num_seconds();
+                println!("\x1b[2m(cached {}s ago, at {})\x1b[0m", age, cached.taken_at.format("%H:%M:%S"));
+                Self::print_snapshot(&cached, None);
+                return Ok(());
+            }
+
+            let current = Self::snapshot();
+            Self::save_cache(&current);
+            Self::print_snapshot(&current, None);
+            if let Some(status_feed) = status_feed {
+                Self::print_maintenance(&MaintenanceManager::upcoming(status_feed));
+            }
+            return Ok(());
+        }
+
+        // The feed is read once, and the windows that are over stop being shown
+        let maintenance = status_feed.map(MaintenanceManager::upcoming);
+        let mut previous: Option<Snapshot> = None;
+        loop {
+            let current = Self::snapshot();
+            Self::save_cache(&current);
+
+            // Clear the screen and move the cursor to the top left, so it is redrawn in place
+            print!("\x1b[2J\x1b[H");
+            println!(
+                "UPV status at {} (refreshing every {}s, press Ctrl+C to stop)\n",
+                chrono::Local::now().format("%H:%M:%S"),
+                interval.max(1)
+            );
+            Self::print_snapshot(&current, previous.as_ref());
+            if let Some(maintenance) = &maintenance {
+                Self::print_maintenance(maintenance);
+            }
+            io::stdout().flush().context("Failed to flush stdout")?;
+
+            previous = Some(current);
+            thread::sleep(Duration::from_secs(interval.max(1)));
+        }
+    }
+}
@@ -1,315 +1,2235 @@
-use std::process::{Command, Stdio};
-use std::io::{self, Write};
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Command;
+use std::io::{self, IsTerminal, Write};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
 use anyhow::{Result, Context};
+use chrono::{Local, NaiveDateTime};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
 
-use crate::error::{UpvError, EXIT_UPV_VPN_ERROR};
+use crate::config::{ConfigKey, ConfigManager};
+use crate::confirm::{self, Level};
+use crate::creds::CredsManager;
+use crate::diff::{self, Difference};
+use crate::dry_run;
+use crate::error::UpvError;
+use crate::experimental;
+use crate::interrupt;
+use crate::lockout::LockoutGuard;
+use crate::network::NetworkManager;
+use crate::paths;
+use crate::phonebook;
+use crate::powershell;
+use crate::vpn_backend::{self, NativeBackend, PowerShellBackend, VpnBackend};
 
 // Docs on UPV VPN setup for Windows 11: https://wiki.upv.es/confluence/spaces/MANUALES/pages/903381153/Windows+-+VPN+con+Microsoft+Windows+11
 
 // Embed the EAP configuration XML file at compile time
 // (docs: https://docs.microsoft.com/es-es/windows/client-management/mdm/eap-configuration)
 const EAP_CONFIG_XML: &str = include_str!("../resources/UPV_Config.xml");
+// Version of the embedded EAP configuration. Bump it whenever the file changes, so the connections created with an older
+// one are flagged by 'upv vpn list' and the status commands, and upgraded by 'upv vpn repair --outdated-only'
+const EAP_CONFIG_VERSION: u32 = 1;
+// Which version of the embedded EAP configuration each connection was created (or last repaired) with, in the data dir
+const CONNECTIONS_FILE: &str = "connections.json";
+
+// Settings every UPV VPN connection is created with. The server can be changed (with 'vpn create --server' or the
+// 'vpn_server' setting), but only to a UPV host
+pub const VPN_SERVER_ADDRESS: &str = "vpn.upv.es";
+const UPV_HOST_SUFFIX: &str = ".upv.es";
+const VPN_AUTHENTICATION_METHOD: &str = "Eap";
+const VPN_ENCRYPTION_LEVEL: &str = "Required";
+
+// Values the VPN cmdlets take for the settings of imported connections, which go into a script unquoted
+const ENCRYPTION_LEVELS: [&str; 5] = ["NoEncryption", "Optional", "Required", "Maximum", "Custom"];
+const AUTHENTICATION_METHODS: [&str; 5] = ["Pap", "Chap", "MSChapv2", "Eap", "MachineCertificate"];
+
+// The 'vpn_server' setting, as main loaded it (connections to it are UPV ones too)
+static SERVER_ADDRESS: OnceLock<String> = OnceLock::new();
+
+// Networks routed through the VPN when split tunneling is enabled (the rest of the traffic doesn't go through it)
+const UPV_SUBNET_ROUTES: [&str; 1] = ["158.42.0.0/16"];
+
+// Domain of the UPV hosts (e.g. nasupv.upv.es), used as the DNS suffix of new connections
+pub const UPV_DNS_SUFFIX: &str = "upv.es";
+// How long 'upv vpn watch' waits for each redial, and the longest delay between failed ones
+const WATCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+const WATCH_MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+// Deleted connections are backed up here (in the data dir) so 'upv vpn undo-delete' can recreate them,
+// in a folder per run that deleted any (named after its time). Only the latest ones are kept
+const DELETED_BACKUPS_DIR: &str = "deleted-vpn";
+const MAX_DELETED_BACKUPS: usize = 10;
+const DELETED_BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+// Comment of the NRPT rules added by 'upv vpn dns', so they can be told apart from the ones added by others
+const NRPT_RULE_COMMENT: &str = "upv-cli";
+
+/// VPN protocol used by a connection.
+/// Both use the same EAP (PEAP) authentication, so only the tunnel changes.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum TunnelType {
+    /// SSTP over HTTPS (port 443), which works on most networks
+    #[default]
+    Sstp,
+    /// IKEv2 (UDP ports 500 and 4500), usually faster, but blocked on some networks
+    Ikev2,
+    /// Let Windows try the available protocols
+    Automatic,
+}
+
+impl TunnelType {
+    /// Value of the -TunnelType parameter of the VPN cmdlets
+    fn as_powershell(self) -> &'static str {
+        match self {
+            TunnelType::Sstp => "Sstp",
+            TunnelType::Ikev2 => "Ikev2",
+            TunnelType::Automatic => "Automatic",
+        }
+    }
+}
+
+/// Format used to export a VPN connection
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Phonebook (.pbk) section, as used by rasphone and rasdial
+    Rasphone,
+    /// PowerShell commands to recreate the connection
+    Powershell,
+    /// Portable profile with every setting, which can be recreated on another machine with 'upv vpn import'
+    Json,
+}
+
+/// Extra option passed to rasdial (or rasphone) when connecting, for setups the other arguments don't cover
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryOption {
+    /// Phonebook (.pbk) file the connection is read from, instead of the default ones
+    Phonebook(PathBuf),
+    /// Domain to authenticate in
+    Domain(String),
+    /// Server address to dial instead of the one stored in the phonebook entry
+    Phone(String),
+    /// Apply the dialing prefix and suffix of the phonebook entry
+    PrefixSuffix,
+}
+
+impl EntryOption {
+    /// rasdial argument for the option
+    pub fn rasdial_arg(&self) -> String {
+        match self {
+            EntryOption::Phonebook(path) => format!("/PHONEBOOK:{}", path.display()),
+            EntryOption::Domain(domain) => format!("/DOMAIN:{}", domain),
+            EntryOption::Phone(phone) => format!("/PHONE:{}", phone),
+            EntryOption::PrefixSuffix => "/PREFIXSUFFIX".to_string(),
+        }
+    }
+}
+
+impl FromStr for EntryOption {
+    type Err = String;
+
+    /// Parses `phonebook=<PATH>`, `domain=<DOMAIN>`, `phone=<ADDRESS>` or `prefixsuffix`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (key, argument) = match value.split_once('=') {
+            Some((key, argument)) => (key.trim(), Some(argument.trim())),
+            None => (value.trim(), None),
+        };
+
+        match (key.to_ascii_lowercase().as_str(), argument) {
+            ("phonebook", Some(path)) if !path.is_empty() => Ok(EntryOption::Phonebook(PathBuf::from(path))),
+            ("domain", Some(domain)) if !domain.is_empty() => Ok(EntryOption::Domain(domain.to_string())),
+            ("phone", Some(phone)) if !phone.is_empty() => Ok(EntryOption::Phone(phone.to_string())),
+            ("prefixsuffix", None) => Ok(EntryOption::PrefixSuffix),
+            ("phonebook" | "domain" | "phone", _) => Err(format!("'{}' needs a value ({}=<VALUE>)", key, key)),
+            ("prefixsuffix", Some(_)) => Err("'prefixsuffix' doesn't take a value".to_string()),
+            _ => Err(format!("unknown entry option '{}' (expected phonebook, domain, phone or prefixsuffix)", key)),
+        }
+    }
+}
+
+/// Settings of an existing VPN connection, as reported by Get-VpnConnection.
+/// It is also the format of JSON exports, so the settings that can be left out of one have defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct VpnConnectionInfo {
+    pub name: String,
+    pub server_address: String,
+    pub tunnel_type: String,
+    pub authentication_method: String,
+    pub encryption_level: String,
+    #[serde(default)]
+    pub split_tunneling: bool,
+    #[serde(default)]
+    pub remember_credential: bool,
+    #[serde(default)]
+    pub connection_status: String,
+    #[serde(default)]
+    pub dns_suffix: Option<String>,
+    #[serde(default)]
+    pub idle_disconnect_seconds: u32,
+    #[serde(default)]
+    pub eap_config_xml: Option<String>,
+    #[serde(default)]
+    pub routes: Vec<String>,
+}
+
+/// What upv-cli knows about a connection it created, by connection name
+#[derive(Debug, Serialize, Deserialize)]
+struct ConnectionMetadata {
+    /// Version of the embedded EAP configuration it was created with (`None` if it was created with a custom one)
+    eap_config_version: Option<u32>,
+}
+
+/// Options for creating a UPV VPN connection
+#[derive(Debug, Default)]
+pub struct CreateOptions {
+    /// Address of the VPN server (a UPV host)
+    pub server: String,
+    /// VPN protocol to use
+    pub tunnel_type: TunnelType,
+    /// Only send UPV traffic through the VPN
+    pub split_tunnel: bool,
+    /// Connect right after creating it
+    pub auto_connect: bool,
+    /// Succeed without changes if it already exists and is correctly configured (repairing it otherwise)
+    pub idempotent: bool,
+    /// EAP config XML to use instead of the embedded one
+    pub eap_config: Option<PathBuf>,
+    /// Make it the default connection without asking
+    pub set_default: bool,
+    /// Connection-specific DNS suffix, so short names like "nasupv" resolve to UPV hosts
+    pub dns_suffix: Option<String>,
+}
+
+/// Changes to apply to an existing VPN connection (`None` leaves a setting untouched)
+#[derive(Debug, Default)]
+pub struct VpnSettings {
+    pub remember_credential: Option<bool>,
+    pub split_tunneling: Option<bool>,
+    pub dns_suffix: Option<String>,
+    pub idle_disconnect_seconds: Option<u32>,
+    /// DNS servers to use while connected (an empty list goes back to the ones assigned by the server)
+    pub dns_servers: Option<Vec<Ipv4Addr>>,
+    pub add_routes: Vec<String>,
+    pub remove_routes: Vec<String>,
+}
 
 pub struct VpnManager;
 
-impl VpnManager {
-    // Private utility functions
+impl VpnManager {
+    // Private utility functions
+
+    /// Runs an operation with PowerShell (and rasdial). With experimental features on, the native RAS API is tried first,
+    /// falling back to PowerShell if it fails.
+    fn with_backend<T>(operation: impl Fn(&dyn VpnBackend) -> Result<T>) -> Result<T> {
+        if !experimental::enabled() {
+            return operation(&PowerShellBackend);
+        }
+        operation(&NativeBackend).or_else(|_| operation(&PowerShellBackend))
+    }
+
+    /// Sets the address of the VPN server whose connections are the UPV ones (the configured one), once the config is loaded
+    pub fn set_server_address(server: String) {
+        let _ = SERVER_ADDRESS.set(server);
+    }
+
+    /// Address of the VPN server: the configured one, or vpn.upv.es
+    pub fn server_address() -> String {
+        SERVER_ADDRESS.get().cloned().unwrap_or_else(|| VPN_SERVER_ADDRESS.to_string())
+    }
+
+    /// Checks that a VPN server address is a UPV one, so credentials aren't sent to a mistyped (or someone else's) server.
+    pub fn check_server(server: &str) -> Result<()> {
+        let server = server.trim().to_ascii_lowercase();
+        if server.ends_with(UPV_HOST_SUFFIX) && server.len() > UPV_HOST_SUFFIX.len() {
+            return Ok(());
+        }
+
+        Err(UpvError::Vpn(
+            format!("'{}' is not a UPV VPN server (expected {} or another *{} host)", server, VPN_SERVER_ADDRESS, UPV_HOST_SUFFIX).into()
+        ).into())
+    }
+
+    /// Name of the backend the VPN commands are using (the native one with experimental features on, unless the RAS API fails)
+    pub fn backend_name() -> &'static str {
+        if experimental::enabled() && NativeBackend.connected().is_ok() { "native (RAS API)" } else { "PowerShell (with rasdial)" }
+    }
+
+    /// Servers whose connections are UPV ones: the default one and the configured one, if different
+    fn upv_servers() -> Vec<String> {
+        let server = Self::server_address();
+        if server.eq_ignore_ascii_case(VPN_SERVER_ADDRESS) {
+            vec![server]
+        } else {
+            vec![VPN_SERVER_ADDRESS.to_string(), server]
+        }
+    }
+
+    /// Retrieves all UPV VPN connections by filtering based on the server address.
+    fn get_upv_connections() -> Result<Vec<String>> {
+        Self::with_backend(|backend| backend.connections(&Self::upv_servers()))
+    }
+    
+    /// Retrieves the UPV VPN connections that are currently connected.
+    pub fn get_connected_upv_connections() -> Result<Vec<String>> {
+        Self::with_backend(|backend| {
+            let upv_connections = backend.connections(&Self::upv_servers())?;
+            Ok(backend.connected()?.into_iter().filter(|name| upv_connections.contains(name)).collect())
+        })
+    }
+
+    /// Retrieves the names of all connected RAS connections (to any server).
+    fn get_connected_connections() -> Result<Vec<String>> {
+        Self::with_backend(|backend| backend.connected())
+    }
+    
+    /// Retrieves all UPV VPN connections along with their connection status ("Connected", "Disconnected"...).
+    pub fn get_upv_connection_statuses() -> Result<Vec<(String, String)>> {
+        Self::with_backend(|backend| backend.statuses(&Self::upv_servers()))
+    }
+    
+    /// Deletes a VPN connection by name, backing it up first so it can be restored with 'upv vpn undo-delete'.
+    fn delete_connection(name: &str) -> Result<()> {
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Delete VPN connection '{}'", name));
+            return Ok(());
+        }
+
+        // A connection that can't be backed up (e.g. without PowerShell) can still be deleted, since that was asked for
+        if let Err(e) = Self::backup_connection(name) {
+            eprintln!("Warning: failed to back up VPN connection '{}', so 'upv vpn undo-delete' won't be able to restore it: {}", name, e);
+        }
+
+        Self::with_backend(|backend| backend.delete(name))?;
+        Self::forget_connection(name);
+
+        Ok(())
+    }
+
+    fn connections_path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(CONNECTIONS_FILE))
+    }
+
+    /// Reads what is known about the connections created by upv-cli
+    fn load_connections() -> Result<BTreeMap<String, ConnectionMetadata>> {
+        let path = Self::connections_path()?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", path.display())),
+        };
+
+        // A corrupted file only means the versions aren't known anymore
+        Ok(serde_json::from_str(&content).unwrap_or_default())
+    }
+
+    fn save_connections(connections: &BTreeMap<String, ConnectionMetadata>) -> Result<()> {
+        let path = Self::connections_path()?;
+        let content = serde_json::to_string_pretty(connections).context("Failed to serialize the connection metadata")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))
+    }
+
+    /// Records the EAP configuration a connection was just created (or repaired) with: the embedded one, or a custom one.
+    /// It is best effort, since the connection itself is what matters.
+    fn record_eap_config(name: &str, custom: bool) {
+        if dry_run::enabled() {
+            return;
+        }
+
+        let result = Self::load_connections().and_then(|mut connections| {
+            connections.insert(name.to_string(), ConnectionMetadata {
+                eap_config_version: (!custom).then_some(EAP_CONFIG_VERSION),
+            });
+            Self::save_connections(&connections)
+        });
+        if let Err(e) = result {
+            eprintln!("Warning: failed to record the EAP configuration of VPN connection '{}': {}", name, e);
+        }
+    }
+
+    /// Forgets what was recorded about a connection that was deleted (or replaced by one not created by upv-cli).
+    fn forget_connection(name: &str) {
+        if let Ok(mut connections) = Self::load_connections()
+            && connections.remove(name).is_some() {
+            let _ = Self::save_connections(&connections);
+        }
+    }
+
+    /// The given connections that were created with an older version of the embedded EAP configuration, along with it.
+    /// Connections created with a custom one, or not by upv-cli, aren't known to be outdated.
+    fn outdated_connections(names: &[String]) -> Vec<(String, u32)> {
+        let connections = Self::load_connections().unwrap_or_default();
+        names.iter()
+            .filter_map(|name| connections.get(name)
+                .and_then(|metadata| metadata.eap_config_version)
+                .filter(|version| *version < EAP_CONFIG_VERSION)
+                .map(|version| (name.clone(), version)))
+            .collect()
+    }
+
+    /// Warns about the given connections that were created with an older version of the embedded EAP configuration.
+    pub fn print_outdated_connections(names: &[String]) {
+        let outdated = Self::outdated_connections(names);
+        for (name, version) in &outdated {
+            println!(
+                "\x1b[33m!\x1b[0m '{}' was created with an outdated EAP configuration (version {}, the current one is {})",
+                name, version, EAP_CONFIG_VERSION
+            );
+        }
+        if !outdated.is_empty() {
+            println!("  Upgrade them with 'upv vpn repair --outdated-only'");
+        }
+    }
+
+    fn deleted_backups_dir() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join(DELETED_BACKUPS_DIR))
+    }
+
+    /// Folders with the backups of each run that deleted connections, oldest first (their names sort by time)
+    fn deleted_backups() -> Result<Vec<PathBuf>> {
+        let dir = Self::deleted_backups_dir()?;
+        let mut backups: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.is_dir()).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read '{}'", dir.display())),
+        };
+        backups.sort();
+        Ok(backups)
+    }
+
+    /// Saves the settings of a connection as a JSON export in the backup folder of this run, dropping the oldest backups.
+    /// Connections deleted by the same run (e.g. a purge) share the folder, so they are restored together.
+    fn backup_connection(name: &str) -> Result<()> {
+        static RUN_FOLDER: OnceLock<String> = OnceLock::new();
+
+        let info = Self::get_existing_connection_info(name)?;
+        let dir = Self::deleted_backups_dir()?
+            .join(RUN_FOLDER.get_or_init(|| Local::now().format(DELETED_BACKUP_TIME_FORMAT).to_string()));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+
+        // Connection names can have characters that aren't allowed in file names (the name is kept inside the file)
+        let file_name: String = name.chars().map(|c| if r#"\/:*?"<>|"#.contains(c) { '_' } else { c }).collect();
+        let path = dir.join(format!("{}.json", file_name));
+        let content = serde_json::to_string_pretty(&info).context("Failed to serialize VPN connection settings")?;
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+        let backups = Self::deleted_backups()?;
+        for old in backups.iter().take(backups.len().saturating_sub(MAX_DELETED_BACKUPS)) {
+            let _ = fs::remove_dir_all(old);
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves which of the given connections still have their network adapter up.
+    /// Without PowerShell the adapters can't be checked, so none are reported.
+    fn get_connected_adapters(names: &[String]) -> Result<Vec<String>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let aliases: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+        let ps_command = format!(
+            "Get-NetIPInterface -InterfaceAlias {} -ErrorAction SilentlyContinue | Where-Object {{$_.ConnectionState -eq 'Connected'}} | ForEach-Object {{ $_.InterfaceAlias }} | Select-Object -Unique",
+            aliases.join(",")
+        );
+
+        match powershell::query(&ps_command) {
+            Ok(adapters) => Ok(adapters),
+            Err(_) if powershell::restriction().is_some() => Ok(Vec::new()),
+            Err(e) => Err(UpvError::Vpn(format!("Failed to get VPN adapter status: {}", e).into()).into()),
+        }
+    }
+
+    /// Polls until no UPV connection has a RAS session or an adapter up, since a hang-up may be reported
+    /// as successful while the tunnel is still being torn down.
+    fn wait_for_disconnection(timeout: Duration) -> Result<()> {
+        println!("Waiting for the VPN connection to go down...");
+
+        let names = Self::get_upv_connections()?;
+        let start = Instant::now();
+        loop {
+            let sessions: Vec<String> = Self::get_connected_connections()?
+                .into_iter()
+                .filter(|name| names.contains(name))
+                .collect();
+            let adapters = Self::get_connected_adapters(&names)?;
+
+            if sessions.is_empty() && adapters.is_empty() {
+                return Ok(());
+            }
+
+            if start.elapsed() >= timeout {
+                let lingering = if sessions.is_empty() { adapters } else { sessions };
+                return Err(UpvError::Vpn(
+                    format!("VPN connection '{}' was still up after {}s", lingering.join("', '"), timeout.as_secs()).into()
+                ).with_hint("Try disconnecting again, or wait a bit longer with --timeout").into());
+            }
+
+            thread::sleep(Duration::from_millis(500));
+        }
+    }
+
+    /// Starts connecting with the first available credentials: the given user, the stored credentials,
+    /// the ones saved for the connection (if silent) or the connection dialog.
+    fn start_connection(name: &str, silent: bool, user: Option<&str>, password: Option<&str>, override_lockout: bool, entry_options: &[EntryOption]) -> Result<()> {
+        // Without a password, use the credentials stored with 'upv creds set' (if they belong to the given user)
+        let stored = if password.is_none() { CredsManager::load()? } else { None };
+
+        if let Some(user) = user {
+            let stored_password = stored
+                .filter(|credentials| credentials.vpn_username().eq_ignore_ascii_case(user) || credentials.username.eq_ignore_ascii_case(user))
+                .map(|credentials| credentials.password);
+            return Self::dial(name, Some(user), password.or(stored_password.as_deref()), override_lockout, entry_options);
+        }
+        if let Some(credentials) = stored {
+            println!("Using the UPV credentials stored for {}", credentials.vpn_username());
+            return Self::dial(name, Some(&credentials.vpn_username()), Some(&credentials.password), override_lockout, entry_options);
+        }
+        if silent {
+            return Self::dial(name, None, None, override_lockout, entry_options);
+        }
+
+        println!("Opening connection dialog for '{}'...", name);
+        
+        // Use rasphone to open the connection dialog
+        let mut command = Command::new("rasphone");
+        command.arg("-d").arg(name);
+        for option in entry_options {
+            match option {
+                EntryOption::Phonebook(path) => { command.arg("-f").arg(path); }
+                _ => eprintln!("Warning: '{}' is only supported when connecting without the dialog (with --silent or --user)", option.rasdial_arg()),
+            }
+        }
+
+        if dry_run::enabled() {
+            dry_run::print_command(&command, &[]);
+            return Ok(());
+        }
+
+        let output = command
+            .output()
+            .context("Failed to execute rasphone command")?;
+        
+        if output.status.success() {
+            println!("Connection dialog opened for '{}'", name);
+        } else {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to open connection dialog for '{}': {}", name, error).into()
+            ).into());
+        }
+        
+        Ok(())
+    }
+
+    /// Retrieves the status of a connection ("Connected", "Disconnected"...).
+    /// Connections to other servers are only reported as connected or disconnected.
+    fn get_connection_status(name: &str) -> Result<String> {
+        if let Some((_, status)) = Self::get_upv_connection_statuses()?.into_iter().find(|(connection, _)| connection == name) {
+            return Ok(status);
+        }
+
+        let connected = Self::get_connected_connections()?.iter().any(|connection| connection == name);
+        Ok(if connected { "Connected" } else { "Disconnected" }.to_string())
+    }
+
+    /// Polls until a connection is established, failing if it goes back to disconnected after trying
+    /// (e.g. the dialog was cancelled or the credentials were rejected) or the timeout expires.
+    fn wait_for_connection(name: &str, timeout: Duration) -> Result<()> {
+        println!("Waiting for VPN connection '{}' to be established...", name);
+
+        let start = Instant::now();
+        let mut was_connecting = false;
+        loop {
+            interrupt::check()?;
+            match Self::get_connection_status(name)?.as_str() {
+                "Connected" => {
+                    println!("VPN connection '{}' is up", name);
+                    return Ok(());
+                }
+                "Disconnected" if was_connecting => {
+                    return Err(UpvError::Vpn(
+                        format!("VPN connection '{}' failed to connect", name).into()
+                    ).with_hint("Check your credentials and network, or connect without --wait to see the connection dialog").into());
+                }
+                "Disconnected" => {}
+                _ => was_connecting = true,
+            }
+
+            if start.elapsed() >= timeout {
+                return Err(UpvError::Vpn(
+                    format!("VPN connection '{}' was not established within {}s", name, timeout.as_secs()).into()
+                ).with_hint("Wait a bit longer with --timeout, or check the connection with 'upv vpn status'").into());
+            }
+
+            thread::sleep(Duration::from_secs(1));
+        }
+    }
+
+    /// Returns the EAP config XML ready to be embedded in a PowerShell here-string.
+    fn eap_config_xml() -> &'static str {
+        EAP_CONFIG_XML.trim().trim_start_matches('\u{feff}') // Remove BOM if present
+    }
+
+    /// Reads a user-supplied EAP config XML, checking that it is well-formed and is an EAP host configuration,
+    /// since Add-VpnConnection would otherwise fail with a much less helpful error (or create a connection that can't authenticate).
+    fn load_eap_config(path: &Path) -> Result<String> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read the EAP config '{}'", path.display()))?;
+
+        // Checked first, since the XML is passed to the VPN cmdlets in a here-string
+        powershell::here_string(&content)
+            .with_context(|| format!("The EAP config '{}' can't be used", path.display()))?;
+
+        let ps_command = format!(
+            "([xml](Get-Content -Raw -LiteralPath {})).DocumentElement.LocalName",
+            powershell::quote(&path.display().to_string())
+        );
+
+        let root: String = powershell::query(&ps_command)
+            .map_err(|e| UpvError::Vpn(
+                format!("The EAP config '{}' is not valid XML: {}", path.display(), e).into()
+            ))?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+
+        if root != "EapHostConfig" {
+            return Err(UpvError::Vpn(
+                format!("The EAP config '{}' is not an EAP host configuration (its root element is '{}')", path.display(), root).into()
+            ).with_hint("The XML of a working connection is included in 'upv vpn export --format powershell'").into());
+        }
+
+        Ok(content.trim().trim_start_matches('\u{feff}').to_string())
+    }
+
+    /// Retrieves the settings of a VPN connection (or `None` if it doesn't exist) in a single PowerShell round trip.
+    pub fn get_connection_info(name: &str) -> Result<Option<VpnConnectionInfo>> {
+        let ps_command = Self::connection_info_command(&format!("Get-VpnConnection -Name {} -ErrorAction SilentlyContinue", powershell::quote(name)));
+
+        let info = powershell::query(&ps_command)
+            .map_err(|e| UpvError::Vpn(format!("Failed to get VPN connection '{}': {}", name, e).into()))?
+            .pop();
+
+        Ok(info)
+    }
+
+    /// IPv4 address the VPN server assigned to a connection, if it is connected.
+    pub fn connection_ip(name: &str) -> Result<Option<String>> {
+        let addresses: Vec<String> = powershell::query(&format!(
+            "Get-NetIPAddress -InterfaceAlias '{}' -AddressFamily IPv4 -ErrorAction SilentlyContinue | ForEach-Object {{ $_.IPAddress }}",
+            name
+        )).with_context(|| format!("Failed to get the IP address of VPN connection '{}'", name))?;
+
+        Ok(addresses.into_iter().next())
+    }
+
+    /// Retrieves the settings of every UPV VPN connection at once.
+    fn get_upv_connection_infos() -> Result<Vec<VpnConnectionInfo>> {
+        let servers: Vec<String> = Self::upv_servers().iter().map(|server| format!("'{}'", server)).collect();
+        let ps_command = Self::connection_info_command(&format!(
+            "Get-VpnConnection | Where-Object {{$_.ServerAddress -in @({})}}",
+            servers.join(",")
+        ));
+
+        powershell::query(&ps_command)
+            .map_err(|e| UpvError::Vpn(format!("Failed to get VPN connections: {}", e).into()).into())
+    }
+
+    /// PowerShell command that reads the settings of the connections output by `connections` (a Get-VpnConnection pipeline).
+    fn connection_info_command(connections: &str) -> String {
+        // Enums are converted to strings explicitly, since ConvertTo-Json would turn them into numbers,
+        // and the routes are wrapped with "," so PowerShell doesn't unroll empty or single-item arrays
+        format!(
+            "{} | Select-Object Name, ServerAddress, \
+            @{{n='TunnelType';e={{[string]$_.TunnelType}}}}, \
+            @{{n='AuthenticationMethod';e={{$_.AuthenticationMethod -join ','}}}}, \
+            @{{n='EncryptionLevel';e={{[string]$_.EncryptionLevel}}}}, \
+            SplitTunneling, RememberCredential, \
+            @{{n='ConnectionStatus';e={{[string]$_.ConnectionStatus}}}}, \
+            DnsSuffix, \
+            @{{n='IdleDisconnectSeconds';e={{[uint32]$_.IdleDisconnectSeconds}}}}, \
+            @{{n='EapConfigXml';e={{$_.EapConfigXmlStream.OuterXml}}}}, \
+            @{{n='Routes';e={{,@($_.Routes | ForEach-Object {{ $_.DestinationPrefix }})}}}}",
+            connections
+        )
+    }
+
+    /// Like `get_connection_info`, but failing if the connection doesn't exist.
+    fn get_existing_connection_info(name: &str) -> Result<VpnConnectionInfo> {
+        Self::get_connection_info(name)?.ok_or_else(|| UpvError::VpnNotFound(
+            format!("VPN connection '{}' does not exist", name).into()
+        ).into())
+    }
+
+    /// Value of a setting that has to be one of `allowed` (ignoring case), as the cmdlets spell it
+    fn allowed_value(setting: &str, value: &str, allowed: &[&'static str]) -> Result<&'static str> {
+        allowed.iter().copied().find(|allowed| allowed.eq_ignore_ascii_case(value.trim())).ok_or_else(|| UpvError::Vpn(
+            format!("'{}' is not a valid {} (expected {})", value, setting, allowed.join(", ")).into()
+        ).into())
+    }
+
+    /// PowerShell commands that create a connection with the given settings (and routes).
+    /// The settings may come from a file, so the ones that can't be quoted are checked against the values the cmdlets take.
+    fn recreate_script(info: &VpnConnectionInfo) -> Result<String> {
+        let tunnel_type = TunnelType::from_str(info.tunnel_type.trim(), true).map_err(|_| UpvError::Vpn(
+            format!("'{}' is not a valid tunnel type (expected Sstp, Ikev2 or Automatic)", info.tunnel_type).into()
+        ))?;
+        let encryption_level = Self::allowed_value("encryption level", &info.encryption_level, &ENCRYPTION_LEVELS)?;
+        let authentication_methods = info.authentication_method.split(',')
+            .map(|method| Self::allowed_value("authentication method", method, &AUTHENTICATION_METHODS))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut script = format!(
+            "Add-VpnConnection -Name {} -ServerAddress {} -TunnelType {} -EncryptionLevel {} -AuthenticationMethod {} -SplitTunneling:${} -RememberCredential:${}",
+            powershell::quote(&info.name),
+            powershell::quote(&info.server_address),
+            tunnel_type.as_powershell(),
+            encryption_level,
+            authentication_methods.join(","),
+            info.split_tunneling,
+            info.remember_credential
+        );
+        if let Some(dns_suffix) = info.dns_suffix.as_deref().filter(|suffix| !suffix.is_empty()) {
+            script.push_str(&format!(" -DnsSuffix {}", powershell::quote(dns_suffix)));
+        }
+        if info.idle_disconnect_seconds > 0 {
+            script.push_str(&format!(" -IdleDisconnectSeconds {}", info.idle_disconnect_seconds));
+        }
+        if let Some(xml) = &info.eap_config_xml {
+            let xml = powershell::here_string(xml).context("The EAP configuration can't be used")?;
+            script.push_str(&format!(" -EapConfigXmlStream {}", xml));
+        }
+        script.push_str("\r\n");
+
+        for route in &info.routes {
+            script.push_str(&format!(
+                "Add-VpnConnectionRoute -ConnectionName {} -DestinationPrefix {}\r\n",
+                powershell::quote(&info.name),
+                powershell::quote(route)
+            ));
+        }
+
+        Ok(script)
+    }
+
+    /// PowerShell commands that copy the triggers of a connection (the apps, DNS names and trusted networks that make
+    /// Windows dial it on its own) to another one.
+    fn copy_triggers_script(source: &str, target: &str) -> String {
+        format!(
+            "$trigger = Get-VpnConnectionTrigger -ConnectionName '{source}' -ErrorAction SilentlyContinue\r\n\
+            foreach ($app in @($trigger.ApplicationID | Where-Object {{ $_ }})) {{ Add-VpnConnectionTriggerApplication -ConnectionName '{target}' -ApplicationID $app -Force }}\r\n\
+            $networks = @($trigger.TrustedNetwork | Where-Object {{ $_ }})\r\n\
+            if ($networks) {{ Add-VpnConnectionTriggerTrustedNetwork -ConnectionName '{target}' -DnsSuffix $networks -Force }}\r\n\
+            foreach ($dns in @(Get-VpnConnectionTriggerDnsConfiguration -ConnectionName '{source}' -ErrorAction SilentlyContinue)) {{\r\n\
+            \x20   if ($dns.DnsIPAddress) {{ Add-VpnConnectionTriggerDnsConfiguration -ConnectionName '{target}' -DnsSuffix $dns.DnsSuffix -DnsIPAddress $dns.DnsIPAddress -Force }}\r\n\
+            \x20   else {{ Add-VpnConnectionTriggerDnsConfiguration -ConnectionName '{target}' -DnsSuffix $dns.DnsSuffix -Force }}\r\n\
+            }}\r\n"
+        )
+    }
+
+    /// An EAP config XML without what doesn't change its meaning (the BOM, the XML declaration and the whitespace
+    /// between elements), since Windows gives it back reformatted
+    fn normalize_xml(xml: &str) -> String {
+        let xml = xml.trim().trim_start_matches('\u{feff}');
+        let xml = match xml.strip_prefix("<?xml").and_then(|rest| rest.split_once("?>")) {
+            Some((_, rest)) => rest,
+            None => xml,
+        };
+
+        let mut normalized = String::with_capacity(xml.len());
+        let mut pending_whitespace = String::new();
+        for c in xml.trim().chars() {
+            if c.is_whitespace() {
+                pending_whitespace.push(c);
+                continue;
+            }
+            // Whitespace is only dropped between a tag's end and the next tag's start
+            if !(c == '<' && normalized.ends_with('>')) {
+                normalized.push_str(&pending_whitespace);
+            }
+            pending_whitespace.clear();
+            normalized.push(c);
+        }
+        normalized
+    }
+
+    /// Compares an existing connection against the settings this tool creates connections with (including the EAP
+    /// configuration, and the DNS suffix if one is expected).
+    /// Returns `None` if the connection doesn't exist, or the list of differing settings otherwise.
+    fn get_config_differences(name: &str, server: &str, tunnel_type: TunnelType, eap_config_xml: &str, dns_suffix: Option<&str>) -> Result<Option<Vec<Difference>>> {
+        let Some(info) = Self::get_connection_info(name)? else {
+            return Ok(None);
+        };
+
+        let current_dns_suffix = info.dns_suffix.clone().unwrap_or_default();
+        let mut expected = vec![
+            ("ServerAddress", &info.server_address, server),
+            ("TunnelType", &info.tunnel_type, tunnel_type.as_powershell()),
+            ("AuthenticationMethod", &info.authentication_method, VPN_AUTHENTICATION_METHOD),
+            ("EncryptionLevel", &info.encryption_level, VPN_ENCRYPTION_LEVEL),
+        ];
+        if let Some(dns_suffix) = dns_suffix.filter(|suffix| !suffix.is_empty()) {
+            expected.push(("DnsSuffix", &current_dns_suffix, dns_suffix));
+        }
+
+        let mut differences: Vec<Difference> = expected.iter()
+            .filter(|(_, current_value, expected_value)| !current_value.eq_ignore_ascii_case(expected_value))
+            .map(|(setting, current_value, expected_value)| Difference::new(*setting, current_value.as_str(), *expected_value))
+            .collect();
+
+        // The XML is too long for the table, so only whether it is there (and the expected one) is shown
+        let current_xml = info.eap_config_xml.as_deref().map(Self::normalize_xml).unwrap_or_default();
+        if current_xml != Self::normalize_xml(eap_config_xml) {
+            let current = if current_xml.is_empty() { "" } else { "another EAP configuration" };
+            differences.push(Difference::new("EapConfigXml", current, "the expected EAP configuration"));
+        }
+
+        Ok(Some(differences))
+    }
+
+    /// Looks for problems in the settings of an existing UPV connection that would prevent it from connecting.
+    /// Any tunnel type is accepted, since it can be chosen when creating the connection.
+    pub fn get_connection_problems(name: &str) -> Result<Vec<String>> {
+        let info = Self::get_existing_connection_info(name)?;
+
+        let mut problems = Vec::new();
+        if !info.authentication_method.eq_ignore_ascii_case(VPN_AUTHENTICATION_METHOD) {
+            problems.push(format!("authentication method is '{}' (expected '{}')", info.authentication_method, VPN_AUTHENTICATION_METHOD));
+        }
+        if !info.encryption_level.eq_ignore_ascii_case(VPN_ENCRYPTION_LEVEL) {
+            problems.push(format!("encryption level is '{}' (expected '{}')", info.encryption_level, VPN_ENCRYPTION_LEVEL));
+        }
+        if info.eap_config_xml.as_deref().is_none_or(|xml| xml.trim().is_empty()) {
+            problems.push("it has no EAP configuration".to_string());
+        }
+
+        Ok(problems)
+    }
+
+    /// Re-applies the UPV settings (including the EAP config, and the DNS suffix if given) to an existing connection.
+    fn repair_connection(name: &str, server: &str, tunnel_type: TunnelType, eap_config_xml: &str, dns_suffix: Option<&str>) -> Result<()> {
+        let dns_suffix = match dns_suffix.filter(|suffix| !suffix.is_empty()) {
+            Some(suffix) => format!(" -DnsSuffix {}", powershell::quote(suffix)),
+            None => String::new(),
+        };
+        let ps_command = format!(
+            "Set-VpnConnection -Name {} -ServerAddress {} -AuthenticationMethod {} -EncryptionLevel {} -TunnelType {}{} -Force -EapConfigXmlStream {}\r\n\r\n",
+            powershell::quote(name),
+            powershell::quote(server),
+            VPN_AUTHENTICATION_METHOD,
+            VPN_ENCRYPTION_LEVEL,
+            tunnel_type.as_powershell(),
+            dns_suffix,
+            powershell::here_string(eap_config_xml)?
+        );
+
+        if dry_run::enabled() {
+            dry_run::print_powershell_stdin(&ps_command);
+            return Ok(());
+        }
+
+        let output = powershell::run_stdin(&ps_command)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to repair VPN connection '{}': {}", name, error).into()
+            ).into());
+        }
+
+        Ok(())
+    }
+
+    /// PowerShell command that enables split tunneling on a connection, adding the UPV subnet routes it doesn't have yet.
+    fn split_tunneling_command(name: &str, existing_routes: &[String]) -> String {
+        let mut commands = vec![
+            "$ErrorActionPreference = 'Stop'".to_string(),
+            format!("Set-VpnConnection -Name '{}' -SplitTunneling $true -Force", name),
+        ];
+        for route in UPV_SUBNET_ROUTES.iter().filter(|route| !existing_routes.iter().any(|existing| existing == *route)) {
+            commands.push(format!("Add-VpnConnectionRoute -ConnectionName '{}' -DestinationPrefix '{}'", name, route));
+        }
+        commands.join("; ")
+    }
+
+    /// Enables split tunneling on a connection, adding the UPV subnet routes it doesn't have yet.
+    fn enable_split_tunneling(name: &str) -> Result<()> {
+        let info = Self::get_existing_connection_info(name)?;
+        let ps_command = Self::split_tunneling_command(name, &info.routes);
+
+        if dry_run::enabled() {
+            dry_run::print_powershell(&ps_command);
+            return Ok(());
+        }
+
+        let output = powershell::run(&ps_command)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to enable split tunneling on VPN connection '{}': {}", name, error).into()
+            ).into());
+        }
+
+        println!("Split tunneling enabled: only traffic to {} goes through the VPN", UPV_SUBNET_ROUTES.join(", "));
+
+        Ok(())
+    }
+
+    /// Routes configured for a connection in the config file, if any
+    fn profiled_routes(name: &str) -> Vec<String> {
+        // A broken config file shouldn't prevent disconnecting
+        ConfigManager::load().map(|config| config.routes(name).to_vec()).unwrap_or_default()
+    }
+
+    /// Removes the routes added by `add_profiled_routes` from a connection. Best effort, since they go away
+    /// with the VPN interface anyway.
+    fn remove_profiled_routes(name: &str, routes: &[String]) {
+        let ps_command = routes.iter()
+            .map(|route| format!("Remove-NetRoute -DestinationPrefix '{}' -InterfaceAlias '{}' -Confirm:$false -ErrorAction SilentlyContinue", route, name))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if dry_run::enabled() {
+            dry_run::print_powershell(&ps_command);
+            return;
+        }
+
+        match powershell::run(&ps_command) {
+            Ok(output) if output.status.success() => println!("Removed {} profiled route(s) from '{}'", routes.len(), name),
+            Ok(output) => eprintln!("Warning: failed to remove the profiled routes from '{}': {}", name, String::from_utf8_lossy(&output.stderr).trim()),
+            Err(e) => eprintln!("Warning: failed to remove the profiled routes from '{}': {}", name, e),
+        }
+    }
+
+    /// Connects without any dialog using rasdial, either with the given credentials or with the ones stored for the connection.
+    /// If only the user is given, rasdial asks for the password in the terminal.
+    fn dial(name: &str, user: Option<&str>, password: Option<&str>, override_lockout: bool, entry_options: &[EntryOption]) -> Result<()> {
+        if let Some(user) = user {
+            LockoutGuard::check(user, override_lockout, UpvError::Vpn)?;
+        }
+
+        println!("Connecting to '{}'...", name);
+
+        if dry_run::enabled() {
+            // The native backend asks rasdial for the password it can't prompt for
+            if experimental::enabled() && (user.is_none() || password.is_some()) {
+                dry_run::print_change(&format!("Dial '{}' through the RAS API", name));
+            } else {
+                let command = vpn_backend::rasdial_command(name, user, password, entry_options);
+                dry_run::print_command(&command, &[password.unwrap_or_default()]);
+            }
+            return Ok(());
+        }
+
+        let failure = match Self::with_backend(|backend| backend.dial(name, user, password, entry_options))? {
+            Ok(()) => {
+                println!("Connected to '{}' successfully", name);
+                if let Some(user) = user {
+                    LockoutGuard::record_success(user)?;
+                }
+                return Ok(());
+            }
+            Err(failure) => failure,
+        };
+
+        let code = failure.code;
+        if code == 691 && let Some(user) = user {
+            LockoutGuard::record_failure(user)?;
+        }
+
+        let cause = match code {
+            623 => UpvError::VpnNotFound,
+            691 => UpvError::VpnAuthFailed,
+            800 | 809 => UpvError::VpnUnreachable,
+            _ => UpvError::Vpn,
+        };
+        let mut error = cause(
+            format!("Failed to connect to '{}': {}", name, failure.message).into()
+        ).with_os_code(Some(code));
+        if let Some(command) = &failure.command {
+            error = error.with_command(command, &[password.unwrap_or_default()]);
+        }
+        let hint = match code {
+            623 => Some("The connection doesn't exist. Run 'upv vpn list' to see the available ones"),
+            691 => Some("The username or password is incorrect (UPV's VPN uses your UPV email as the username)"),
+            703 => Some("The connection needs credentials that aren't stored. Save them with 'upv creds set', pass them with --user and --password, or connect without --silent"),
+            800 | 809 => Some("The VPN server couldn't be reached. Check your internet connection"),
+            _ => None,
+        };
+
+        Err(match hint {
+            Some(hint) => error.with_hint(hint),
+            None => error,
+        }.into())
+    }
+
+    /// Name of the default connection in the config file, if any
+    fn default_connection() -> Option<String> {
+        // A broken config file shouldn't prevent deleting connections
+        ConfigManager::load().ok().and_then(|config| config.vpn_name)
+    }
+
+    /// After deleting the default connection, removes it from the config so it doesn't point at a nonexistent connection.
+    /// With `force` it is removed right away, otherwise the user is asked first.
+    fn forget_default_connection(name: &str, force: bool) -> Result<()> {
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Unset '{}' as the default VPN connection in the config", name));
+            return Ok(());
+        }
+
+        if !force {
+            print!("'{}' was your default VPN connection. Remove it from the config? (Y/n): ", name);
+            io::stdout().flush().context("Failed to flush stdout")?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).context("Failed to read user input")?;
+
+            let answer = input.trim().to_lowercase();
+            if answer == "n" || answer == "no" {
+                println!("The config still points at '{}'. Change it with 'upv config set vpn_name <NAME>'", name);
+                return Ok(());
+            }
+        }
+
+        ConfigManager::unset(ConfigKey::VpnName, false)
+    }
+
+    /// After creating a connection, makes it the default one: right away with `set_default`,
+    /// otherwise by asking (only in an interactive terminal, so scripts don't get stuck on the question).
+    fn offer_default_connection(name: &str, set_default: bool) -> Result<()> {
+        let current = Self::default_connection();
+        if current.as_deref() == Some(name) {
+            return Ok(());
+        }
+
+        if !set_default {
+            if !io::stdin().is_terminal() {
+                return Ok(());
+            }
+
+            // Replacing an existing default is opt-in, setting the first one is opt-out
+            match &current {
+                Some(current) => print!("Make '{}' the default VPN connection instead of '{}'? (y/N): ", name, current),
+                None => print!("Make '{}' the default VPN connection? (Y/n): ", name),
+            }
+            io::stdout().flush().context("Failed to flush stdout")?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).context("Failed to read user input")?;
+
+            let answer = input.trim().to_lowercase();
+            let accepted = match current {
+                Some(_) => answer == "y" || answer == "yes",
+                None => answer != "n" && answer != "no",
+            };
+            if !accepted {
+                return Ok(());
+            }
+        }
+
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Set '{}' as the default VPN connection in the config", name));
+            return Ok(());
+        }
+
+        ConfigManager::set(ConfigKey::VpnName, name, false)
+    }
+
+    // Public methods for VPN management
+
+    /// Creates a new UPV VPN connection with the specified name and options.
+    /// In idempotent mode, an existing connection is left as is if it is correctly configured, or repaired otherwise.
+    /// Afterwards, the new connection is made the default one if `set_default` is set (or the user agrees to it).
+    pub fn create(name: &str, options: &CreateOptions) -> Result<()> {
+        // Checked first, so an invalid file or server doesn't leave a half-configured connection behind
+        Self::check_server(&options.server)?;
+        if !Self::upv_servers().iter().any(|server| server.eq_ignore_ascii_case(&options.server)) {
+            println!("Note: the other vpn commands only find connections to {}. Run 'upv config set vpn_server {}' for them to find this one", Self::upv_servers().join(" and "), options.server);
+        }
+        let xml_content = match &options.eap_config {
+            Some(path) => Self::load_eap_config(path)?,
+            None => Self::eap_config_xml().to_string(),
+        };
+
+        if options.idempotent && let Some(differences) = Self::get_config_differences(name, &options.server, options.tunnel_type, &xml_content, options.dns_suffix.as_deref())? {
+            if differences.is_empty() {
+                println!("VPN connection '{}' already exists and is correctly configured", name);
+            } else {
+                println!("VPN connection '{}' already exists, but its configuration differs:", name);
+                diff::print(&differences, false)?;
+                println!("Repairing VPN connection '{}'...", name);
+                Self::repair_connection(name, &options.server, options.tunnel_type, &xml_content, options.dns_suffix.as_deref())?;
+                Self::record_eap_config(name, options.eap_config.is_some());
+                println!("VPN connection '{}' repaired successfully", name);
+            }
+
+            if options.split_tunnel {
+                Self::enable_split_tunneling(name)?;
+            }
+
+            if options.set_default {
+                Self::offer_default_connection(name, true)?;
+            }
+
+            if options.auto_connect {
+                Self::connect(name, false, None, None, false, None, &[])?;
+            }
+
+            return Ok(());
+        }
+
+        println!("Creating VPN connection '{}'...", name);
+        
+        let dns_suffix = match options.dns_suffix.as_deref().filter(|suffix| !suffix.is_empty()) {
+            Some(suffix) => format!(" -DnsSuffix {}", powershell::quote(suffix)),
+            None => String::new(),
+        };
+        let ps_command = format!(
+            "Add-VpnConnection -Name {} -ServerAddress {} -AuthenticationMethod {} -EncryptionLevel {} -TunnelType {}{} -EapConfigXmlStream {}\r\n\r\n",
+            powershell::quote(name),
+            powershell::quote(&options.server),
+            VPN_AUTHENTICATION_METHOD,
+            VPN_ENCRYPTION_LEVEL,
+            options.tunnel_type.as_powershell(),
+            dns_suffix,
+            powershell::here_string(&xml_content)?
+        );
+
+        // The connection doesn't exist yet, so the commands that would follow are printed from here
+        if dry_run::enabled() {
+            dry_run::print_powershell_stdin(&ps_command);
+            if options.split_tunnel {
+                dry_run::print_powershell(&Self::split_tunneling_command(name, &[]));
+            }
+            Self::offer_default_connection(name, options.set_default)?;
+            if options.auto_connect {
+                Self::connect(name, false, None, None, false, None, &[])?;
+            }
+            return Ok(());
+        }
+        
+        let output = powershell::run_stdin(&ps_command)?;
+        
+        if output.status.success() {
+            println!("VPN connection '{}' created successfully", name);
+            Self::record_eap_config(name, options.eap_config.is_some());
+
+            if options.split_tunnel {
+                Self::enable_split_tunneling(name)?;
+            }
+
+            Self::offer_default_connection(name, options.set_default)?;
+            
+            // Auto-connect if requested
+            if options.auto_connect {
+                Self::connect(name, false, None, None, false, None, &[])?;
+            }
+        } else {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to create VPN connection '{}': {}", name, error).into()
+            ).into());
+        }
+        
+        Ok(())
+    }
+    
+    /// Creates a connection with the settings of an existing one (server, authentication, split tunneling, routes, DNS suffix
+    /// and triggers), e.g. to try another protocol side by side. The EAP configuration isn't copied but generated again
+    /// (the built-in one, or the given file), so state Windows keeps in the source's one doesn't carry over.
+    pub fn clone_connection(source: &str, name: &str, options: &CreateOptions, tunnel_type: Option<TunnelType>) -> Result<()> {
+        let mut info = Self::get_existing_connection_info(source)?;
+
+        if Self::get_connection_info(name)?.is_some() {
+            return Err(UpvError::Vpn(
+                format!("VPN connection '{}' already exists", name).into()
+            ).with_hint("Pick another name, or delete it first with 'upv vpn delete'").into());
+        }
+
+        info.name = name.to_string();
+        if let Some(tunnel_type) = tunnel_type {
+            info.tunnel_type = tunnel_type.as_powershell().to_string();
+        }
+        info.eap_config_xml = if info.authentication_method.eq_ignore_ascii_case(VPN_AUTHENTICATION_METHOD) {
+            Some(match &options.eap_config {
+                Some(path) => Self::load_eap_config(path)?,
+                None => Self::eap_config_xml().to_string(),
+            })
+        } else {
+            None
+        };
+
+        println!("Creating VPN connection '{}' from '{}' ({})...", name, source, info.tunnel_type);
+
+        let script = format!(
+            "$ErrorActionPreference = 'Stop'\r\n{}{}",
+            Self::recreate_script(&info)?,
+            Self::copy_triggers_script(source, name)
+        );
+
+        if dry_run::enabled() {
+            dry_run::print_powershell_stdin(&script);
+        } else {
+            let output = powershell::run_stdin(&script)?;
+
+            if !output.status.success() {
+                let error = String::from_utf8_lossy(&output.stderr);
+                return Err(UpvError::Vpn(
+                    format!("Failed to create VPN connection '{}' from '{}': {}", name, source, error).into()
+                ).into());
+            }
+
+            println!("VPN connection '{}' created successfully", name);
+            if info.eap_config_xml.is_some() {
+                Self::record_eap_config(name, options.eap_config.is_some());
+            }
+        }
+
+        Self::offer_default_connection(name, options.set_default)?;
+
+        if options.auto_connect {
+            Self::connect(name, false, None, None, false, None, &[])?;
+        }
+
+        Ok(())
+    }
+
+    /// Purges all UPV VPN connections, with optional exceptions and force confirmation.
+    pub fn purge(force: bool, except_names: Vec<String>) -> Result<()> {
+        // Get the list of UPV connections
+        let all_connections = Self::get_upv_connections()
+            .context("Failed to retrieve UPV VPN connections")?;
+        
+        if all_connections.is_empty() {
+            println!("No UPV VPN connections found to delete.");
+            return Ok(());
+        }
+        
+        // Filter out the connections to except (only if there are exceptions)
+        let connections = if except_names.is_empty() {
+            all_connections
+        } else {
+            all_connections
+                .into_iter()
+                .filter(|conn| !except_names.contains(conn))
+                .collect()
+        };
+        
+        if connections.is_empty() {
+            println!("No UPV VPN connections found to delete.");
+            return Ok(());
+        }
+        
+        // Show what will be deleted
+        let default_connection = Self::default_connection().filter(|name| connections.contains(name));
+        println!("Found {} UPV VPN connection(s) to delete:", connections.len());
+        for conn in &connections {
+            if default_connection.as_ref() == Some(conn) {
+                println!("  - {} \x1b[33m(default connection in the config)\x1b[0m", conn);
+            } else {
+                println!("  - {}", conn);
+            }
+        }
+        if let Some(name) = &default_connection {
+            println!("\nWarning: '{}' is your default VPN connection. Use --except \"{}\" to keep it.", name, name);
+        }
+        
+        // Asked twice by default (extra safety), unless the config says otherwise
+        if !force && !confirm::ask(
+            confirm::levels().purge.unwrap_or(Level::Double),
+            &format!("\nAre you sure you want to delete ALL {} UPV VPN connections?", connections.len()),
+            "This action cannot be undone. Type 'DELETE' to confirm:",
+            "DELETE"
+        )? {
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+        
+        println!("\nDeleting {} UPV VPN connections...", connections.len());
+        
+        let mut deleted_count = 0;
+        let mut failed_count = 0;
+        let mut default_deleted = false;
+        
+        for connection in connections {
+            match Self::delete_connection(&connection) {
+                Ok(()) => {
+                    println!("  ✓ Deleted '{}'", connection);
+                    deleted_count += 1;
+                    default_deleted |= default_connection.as_ref() == Some(&connection);
+                }
+                Err(e) => {
+                    eprintln!("  ✗ Failed to delete '{}': {}", connection, e);
+                    failed_count += 1;
+                }
+            }
+        }
+        
+        println!("\nPurge completed:");
+        println!("  {} connections deleted successfully", deleted_count);
+        if failed_count > 0 {
+            println!("  {} connections failed to delete", failed_count);
+        }
+
+        if default_deleted && let Some(name) = &default_connection {
+            println!();
+            Self::forget_default_connection(name, force)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Connects to an existing UPV VPN connection using rasphone.
+    /// If `wait` is set, doesn't return until the connection is established (failing if it isn't).
+    /// The entry options are passed on to rasdial (or rasphone).
+    pub fn connect(name: &str, silent: bool, user: Option<&str>, password: Option<&str>, override_lockout: bool, wait: Option<Duration>, entry_options: &[EntryOption]) -> Result<()> {
+        // Save the network state from before the VPN, so it can be checked (and restored) after disconnecting.
+        // If a UPV VPN is already connected, the state saved when it connected is kept instead.
+        let already_connected = Self::get_connected_upv_connections().map(|c| !c.is_empty()).unwrap_or(false);
+        if !already_connected && !dry_run::enabled() && let Err(e) = NetworkManager::save_snapshot() {
+            eprintln!("Warning: failed to save the network state before connecting: {}", e);
+        }
+
+        Self::start_connection(name, silent, user, password, override_lockout, entry_options)?;
+
+        if let Some(timeout) = wait && !dry_run::enabled() {
+            Self::wait_for_connection(name, timeout)?;
+        }
+
+        Ok(())
+    }
+    
+    /// Checks the routes configured for a connection before connecting, so a mistake doesn't leave it half set up.
+    pub fn check_profiled_routes(name: &str, routes: &[String]) -> Result<()> {
+        if routes.is_empty() {
+            return Err(UpvError::Vpn(
+                format!("There are no routes configured for '{}'", name).into()
+            ).with_hint(format!("Add them to the config file with 'upv config edit', e.g. routes.{} = [\"10.236.0.0/16\"]", name)).into());
+        }
+
+        if let Some(route) = routes.iter().find(|route| !route.contains('/')) {
+            return Err(UpvError::Vpn(
+                format!("The route '{}' configured for '{}' is not a network prefix (expected something like 10.236.0.0/16)", route, name).into()
+            ).with_hint("Fix it with 'upv config edit'").into());
+        }
+
+        Ok(())
+    }
+
+    /// Adds the routes configured for a connection (in the `routes` table of the config file) through its interface,
+    /// for subnets it doesn't route by itself. They are removed by `disconnect`.
+    pub fn add_profiled_routes(name: &str, routes: &[String]) -> Result<()> {
+        // Routes that already exist (e.g. from a previous connect) are left as they are, since New-NetRoute would fail on them
+        let mut commands = vec!["$ErrorActionPreference = 'Stop'".to_string()];
+        for route in routes {
+            commands.push(format!(
+                "if (-not (Get-NetRoute -DestinationPrefix '{0}' -InterfaceAlias '{1}' -ErrorAction SilentlyContinue)) {{ New-NetRoute -DestinationPrefix '{0}' -InterfaceAlias '{1}' -PolicyStore ActiveStore | Out-Null }}",
+                route, name
+            ));
+        }
+        let ps_command = commands.join("; ");
+
+        if dry_run::enabled() {
+            dry_run::print_powershell(&ps_command);
+            return Ok(());
+        }
+
+        let output = powershell::run(&ps_command)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to add the profiled routes to '{}': {}", name, error.trim()).into()
+            ).with_hint("Adding routes requires administrator rights on some machines. Try again from an elevated terminal").into());
+        }
+
+        println!("Routed {} through '{}'", routes.join(", "), name);
+
+        Ok(())
+    }
+
+    /// Makes sure names are looked up under the UPV DNS suffix (and the extra ones given) while connected, so the short
+    /// hostnames used in lab instructions resolve. The suffix is set on the connection's adapter, since Windows doesn't
+    /// always apply the one configured for the connection, and extra suffixes are appended to the global search list
+    /// (after the primary DNS suffix, which Windows stops using by itself once the list isn't empty).
+    pub fn apply_dns_suffixes(name: &str, extra_suffixes: &[String]) -> Result<()> {
+        let mut commands = vec![
+            "$ErrorActionPreference = 'Stop'".to_string(),
+            format!(
+                "if ((Get-DnsClient -InterfaceAlias '{0}').ConnectionSpecificSuffix -ne '{1}') {{ Set-DnsClient -InterfaceAlias '{0}' -ConnectionSpecificSuffix '{1}' }}",
+                name, UPV_DNS_SUFFIX
+            ),
+        ];
+        if !extra_suffixes.is_empty() {
+            let suffixes: Vec<String> = extra_suffixes.iter().map(|suffix| format!("'{}'", suffix)).collect();
+            commands.push(format!(
+                "$list = @((Get-DnsClientGlobalSetting).SuffixSearchList | Where-Object {{ $_ }}); \
+                $missing = @(@({}) | Where-Object {{ $list -notcontains $_ }}); \
+                if ($missing) {{ \
+                    $primary = [System.Net.NetworkInformation.IPGlobalProperties]::GetIPGlobalProperties().DomainName; \
+                    if (-not $list -and $primary) {{ $list = @($primary) }}; \
+                    Set-DnsClientGlobalSetting -SuffixSearchList @($list + $missing) \
+                }}",
+                suffixes.join(", ")
+            ));
+        }
+        let ps_command = commands.join("; ");
+
+        if dry_run::enabled() {
+            dry_run::print_powershell(&ps_command);
+            return Ok(());
+        }
+
+        let output = powershell::run(&ps_command)?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to set the DNS suffixes of '{}': {}", name, error.trim()).into()
+            ).with_hint("Changing DNS suffixes requires administrator rights. Try again from an elevated terminal").into());
+        }
+
+        let suffixes: Vec<&str> = std::iter::once(UPV_DNS_SUFFIX).chain(extra_suffixes.iter().map(String::as_str)).collect();
+        println!("Names are now looked up under {} while connected to '{}'", suffixes.join(", "), name);
+
+        Ok(())
+    }
+
+    /// Removes the profiled routes of a connection and disconnects from it
+    fn hang_up(name: &str) -> Result<()> {
+        let routes = Self::profiled_routes(name);
+        if !routes.is_empty() {
+            Self::remove_profiled_routes(name, &routes);
+        }
+
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Disconnect from '{}'", name));
+            return Ok(());
+        }
+
+        Self::with_backend(|backend| backend.disconnect(name))
+    }
+
+    /// Undoes a connection made by a command that failed or was interrupted before finishing (e.g. 'vpn connect --mount'
+    /// when the drive can't be mounted), telling what is left behind. Nothing is done if it didn't get to connect.
+    pub fn undo_connect(name: &str, extra_suffixes: &[String]) {
+        match Self::get_connected_upv_connections() {
+            Ok(connected) if !connected.iter().any(|connection| connection == name) => return,
+            Err(e) => {
+                eprintln!("Warning: couldn't tell whether '{}' is still connected ({}), check it with 'upv vpn status'", name, e);
+                return;
+            }
+            Ok(_) => {}
+        }
+
+        eprintln!("Undoing the connection to '{}'...", name);
+        match Self::hang_up(name) {
+            Ok(()) => eprintln!("  ✓ Disconnected from '{}', along with its routes", name),
+            Err(e) => eprintln!("  ✗ '{}' is still connected, since disconnecting failed: {}", name, e),
+        }
+        if !extra_suffixes.is_empty() {
+            eprintln!("  The extra DNS suffixes in the search list ({}) stay there, as after any disconnection", extra_suffixes.join(", "));
+        }
+    }
+
+    /// Disconnects from the connected UPV VPN connections.
+    /// If `wait` is set, doesn't return until the connection is actually gone (or the timeout expires).
+    pub fn disconnect(wait: Option<Duration>) -> Result<()> {
+        println!("Disconnecting from VPN...");
+
+        let connected = Self::get_connected_upv_connections()?;
+        if connected.is_empty() {
+            println!("Not connected to any UPV VPN");
+            return Ok(());
+        }
+
+        for name in &connected {
+            Self::hang_up(name)?;
+        }
+        if dry_run::enabled() {
+            return Ok(());
+        }
+
+        if let Some(timeout) = wait {
+            Self::wait_for_disconnection(timeout)?;
+        }
+
+        println!("Disconnected from VPN successfully");
+
+        // Checking the network state is best effort, the disconnection itself already succeeded
+        let result = Self::get_upv_connections()
+            .and_then(|names| NetworkManager::verify_restored(&names));
+        if let Err(e) = result {
+            eprintln!("Warning: failed to check the network state after disconnecting: {}", e);
+        }
+        
+        Ok(())
+    }
+    
+    /// Keeps a connection up: checks it every `interval` and redials it (without any dialog, using the stored credentials)
+    /// when it drops. Failed redials are retried after a delay that starts at `backoff` and doubles each time,
+    /// giving up after `max_retries` failures in a row (0 to never give up).
+    pub fn watch(name: &str, interval: Duration, backoff: Duration, max_retries: u32) -> Result<()> {
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Watch '{}' and redial it whenever it drops", name));
+            return Ok(());
+        }
+
+        println!("Watching VPN connection '{}' (checking every {}s, press Ctrl+C to stop)", name, interval.as_secs());
+
+        let mut failures = 0;
+        loop {
+            let status = match Self::get_connection_status(name) {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!("Warning: failed to check VPN connection '{}': {}", name, e);
+                    thread::sleep(interval);
+                    continue;
+                }
+            };
+
+            if status != "Disconnected" {
+                thread::sleep(interval);
+                continue;
+            }
+
+            if max_retries > 0 && failures >= max_retries {
+                return Err(UpvError::Vpn(
+                    format!("Gave up reconnecting to '{}' after {} failed attempts", name, failures).into()
+                ).with_hint("Check the connection with 'upv vpn ping' or 'upv doctor'").into());
+            }
+
+            if failures > 0 {
+                let delay = backoff.saturating_mul(2u32.saturating_pow(failures - 1)).min(WATCH_MAX_BACKOFF);
+                println!("Retrying in {}s...", delay.as_secs());
+                thread::sleep(delay);
+            }
+
+            println!("[{}] VPN connection '{}' is down, reconnecting...", chrono::Local::now().format("%H:%M:%S"), name);
+            match Self::connect(name, true, None, None, false, Some(WATCH_CONNECT_TIMEOUT), &[]) {
+                Ok(()) => failures = 0,
+                Err(e) => {
+                    failures += 1;
+                    eprintln!("Warning: reconnecting to '{}' failed: {}", name, e);
+                }
+            }
+        }
+    }
+
+    /// Connects to a VPN connection if it is disconnected, or disconnects from it (and only from it) if it is connected.
+    pub fn toggle(name: &str) -> Result<()> {
+        if Self::get_connection_status(name)? == "Connected" {
+            println!("Disconnecting from '{}'...", name);
+            Self::hang_up(name)?;
+            if !dry_run::enabled() {
+                println!("Disconnected from '{}' successfully", name);
+            }
+            Ok(())
+        } else {
+            Self::connect(name, false, None, None, false, None, &[])
+        }
+    }
+    
+    /// Deletes a specific UPV VPN connection by name, with optional confirmation.
+    /// Deleting the default connection from the config needs the name to be typed to confirm (unless the config sets
+    /// another confirmation level), and offers to remove it from the config.
+    pub fn delete(name: &str, force: bool) -> Result<()> {
+        let is_default = Self::default_connection().is_some_and(|default| default == name);
+
+        if is_default {
+            println!("Warning: '{}' is your default VPN connection (vpn_name in the config)", name);
+        }
+
+        let level = match confirm::levels().delete {
+            Some(level) => level,
+            None if is_default => Level::Typed,
+            None => Level::Yes,
+        };
+        if !force && !confirm::ask(
+            level,
+            &format!("Are you sure you want to delete VPN connection '{}'?", name),
+            "Type the name of the connection to confirm you want to delete it:",
+            name
+        )? {
+            println!("Operation cancelled.");
+            return Ok(());
+        }
+        
+        println!("Deleting VPN connection '{}'...", name);
+        
+        Self::delete_connection(name)?;
+
+        println!("VPN connection '{}' deleted successfully", name);
+
+        if is_default {
+            Self::forget_default_connection(name, force)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Deletes several UPV VPN connections at once (e.g. names read from stdin), reporting the ones that failed.
+    pub fn delete_many(names: &[String], force: bool) -> Result<()> {
+        // The names come from stdin, so there's no way to answer a confirmation prompt
+        if !force {
+            return Err(UpvError::Vpn(
+                "Deleting VPN connections read from stdin requires --force".into()
+            ).into());
+        }
+
+        if names.is_empty() {
+            println!("No VPN connection names were given.");
+            return Ok(());
+        }
+
+        // Only UPV connections are deleted (like 'upv vpn list' and 'upv vpn purge' only see those), so a mistaken pipe
+        // can't delete unrelated VPN profiles
+        let upv_connections = Self::get_upv_connections()?;
+        let (names, skipped): (Vec<&String>, Vec<&String>) = names.iter()
+            .partition(|name| upv_connections.iter().any(|upv| upv.eq_ignore_ascii_case(name)));
+        for name in &skipped {
+            eprintln!("  - Skipped '{}': it is not a UPV VPN connection", name);
+        }
+
+        if names.is_empty() {
+            println!("None of the names given are UPV VPN connections, so nothing was deleted.");
+            return Ok(());
+        }
+
+        println!("Deleting {} VPN connections...", names.len());
+
+        let default_connection = Self::default_connection();
+        let mut failed_count = 0;
+        for name in names.iter().copied() {
+            match Self::delete_connection(name) {
+                Ok(()) if default_connection.as_ref() == Some(name) => {
+                    println!("  ✓ Deleted '{}' (your default VPN connection)", name);
+                    Self::forget_default_connection(name, true)?;
+                }
+                Ok(()) => println!("  ✓ Deleted '{}'", name),
+                Err(e) => {
+                    eprintln!("  ✗ Failed to delete '{}': {}", name, e);
+                    failed_count += 1;
+                }
+            }
+        }
+
+        if failed_count > 0 {
+            return Err(UpvError::Vpn(
+                format!("{} of {} VPN connections failed to delete", failed_count, names.len()).into()
+            ).into());
+        }
+
+        println!("{} VPN connections deleted successfully", names.len());
+
+        Ok(())
+    }
+    
+    /// Lists all UPV VPN connections.
+    /// In quiet mode, only the bare names are printed (one per line), so the output can be piped.
+    pub fn list(quiet: bool, verbose: bool) -> Result<()> {
+        if quiet {
+            for conn in Self::get_upv_connections()? {
+                println!("{}", conn);
+            }
+            return Ok(());
+        }
+
+        if verbose {
+            return Self::list_verbose();
+        }
+
+        println!("Listing UPV VPN connections...");
+        
+        let connections = Self::get_upv_connections()?;
+        
+        if connections.is_empty() {
+            println!("No UPV VPN connections found.");
+        } else {
+            println!("Found {} UPV VPN connection(s):", connections.len());
+            for conn in &connections {
+                println!("  - {}", conn);
+            }
+            Self::print_outdated_connections(&connections);
+        }
+        
+        Ok(())
+    }
+    
+    /// Lists the UPV VPN connections with their status and main settings, in an aligned table.
+    fn list_verbose() -> Result<()> {
+        let connections = Self::get_upv_connection_infos()?;
+
+        if connections.is_empty() {
+            println!("No UPV VPN connections found.");
+            return Ok(());
+        }
+
+        let rows: Vec<[String; 5]> = connections.iter()
+            .map(|info| [
+                info.name.clone(),
+                info.connection_status.clone(),
+                info.tunnel_type.clone(),
+                info.authentication_method.clone(),
+                if info.split_tunneling { "on" } else { "off" }.to_string(),
+            ])
+            .collect();
+        let header = ["NAME", "STATUS", "TUNNEL", "AUTHENTICATION", "SPLIT TUNNELING"];
+        let widths: Vec<usize> = (0..header.len())
+            .map(|column| rows.iter().map(|row| row[column].chars().count()).chain([header[column].len()]).max().unwrap_or_default())
+            .collect();
+
+        let pad = |cells: [&str; 5]| -> Vec<String> {
+            cells.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect()
+        };
+
+        println!("\x1b[1m{}\x1b[0m", pad(header).join("  ").trim_end());
+        for row in &rows {
+            let mut cells = pad([&row[0], &row[1], &row[2], &row[3], &row[4]]);
+            // The status is colored after padding, so the escape codes don't throw off the alignment
+            if row[1] == "Connected" {
+                cells[1] = format!("\x1b[32m{}\x1b[0m", cells[1]);
+            }
+            println!("{}", cells.join("  ").trim_end());
+        }
+
+        let names: Vec<String> = connections.into_iter().map(|info| info.name).collect();
+        Self::print_outdated_connections(&names);
+
+        Ok(())
+    }
+
+    /// Shows the settings of a VPN connection (as JSON if requested).
+    pub fn show(name: &str, json: bool) -> Result<()> {
+        let info = Self::get_existing_connection_info(name)?;
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&info).context("Failed to serialize VPN connection settings")?);
+            return Ok(());
+        }
+
+        let on_off = |value: bool| if value { "on" } else { "off" };
+
+        println!("VPN connection '{}':", info.name);
+        println!("  Server address:       {}", info.server_address);
+        println!("  Status:               {}", info.connection_status);
+        println!("  Tunnel type:          {}", info.tunnel_type);
+        println!("  Authentication:       {}", info.authentication_method);
+        println!("  Encryption level:     {}", info.encryption_level);
+        println!("  Split tunneling:      {}", on_off(info.split_tunneling));
+        println!("  Remember credential:  {}", on_off(info.remember_credential));
+        if let Some(dns_suffix) = info.dns_suffix.as_deref().filter(|suffix| !suffix.is_empty()) {
+            println!("  DNS suffix:           {}", dns_suffix);
+        }
+        if !info.routes.is_empty() {
+            println!("  Routes:               {}", info.routes.join(", "));
+        }
+
+        Ok(())
+    }
+
+    /// Changes settings of an existing VPN connection without recreating it.
+    pub fn set(name: &str, settings: &VpnSettings) -> Result<()> {
+        if settings.dns_servers.as_ref().is_some_and(|servers| servers.len() > 2) {
+            return Err(UpvError::Vpn(
+                "At most two DNS servers can be set (primary and secondary)".into()
+            ).into());
+        }
+
+        let mut params = Vec::new();
+        if let Some(remember_credential) = settings.remember_credential {
+            params.push(format!("-RememberCredential:${}", remember_credential));
+        }
+        if let Some(split_tunneling) = settings.split_tunneling {
+            params.push(format!("-SplitTunneling:${}", split_tunneling));
+        }
+        if let Some(dns_suffix) = &settings.dns_suffix {
+            params.push(format!("-DnsSuffix {}", powershell::quote(dns_suffix)));
+        }
+        if let Some(idle_disconnect_seconds) = settings.idle_disconnect_seconds {
+            params.push(format!("-IdleDisconnectSeconds {}", idle_disconnect_seconds));
+        }
+
+        let mut commands = vec!["$ErrorActionPreference = 'Stop'".to_string()];
+        if !params.is_empty() {
+            commands.push(format!("Set-VpnConnection -Name {} {} -Force", powershell::quote(name), params.join(" ")));
+        }
+        let mut add_routes = settings.add_routes.clone();
+        if settings.split_tunneling == Some(true) {
+            // Without routes, no traffic would go through the VPN, so the UPV subnets are added if missing
+            let info = Self::get_existing_connection_info(name)?;
+            add_routes.extend(UPV_SUBNET_ROUTES.iter()
+                .filter(|route| !info.routes.iter().chain(&add_routes).any(|existing| existing == *route))
+                .map(|route| route.to_string())
+                .collect::<Vec<_>>());
+        }
+        for route in &add_routes {
+            commands.push(format!("Add-VpnConnectionRoute -ConnectionName {} -DestinationPrefix {}", powershell::quote(name), powershell::quote(route)));
+        }
+        for route in &settings.remove_routes {
+            commands.push(format!("Remove-VpnConnectionRoute -ConnectionName {} -DestinationPrefix {}", powershell::quote(name), powershell::quote(route)));
+        }
+
+        if commands.len() == 1 && settings.dns_servers.is_none() {
+            return Err(UpvError::Vpn(
+                "No settings to change were given".into()
+            ).with_hint("Run 'upv vpn set --help' to see the available settings").into());
+        }
+
+        println!("Updating VPN connection '{}'...", name);
+
+        if dry_run::enabled() {
+            if commands.len() > 1 {
+                dry_run::print_powershell(&commands.join("; "));
+            }
+            if let Some(dns_servers) = &settings.dns_servers {
+                let servers = if dns_servers.is_empty() {
+                    "the ones assigned by the server".to_string()
+                } else {
+                    dns_servers.iter().map(Ipv4Addr::to_string).collect::<Vec<_>>().join(", ")
+                };
+                dry_run::print_change(&format!("Set the DNS servers of '{}' to {} in its phonebook entry", name, servers));
+            }
+            return Ok(());
+        }
+
+        if commands.len() > 1 {
+            let output = powershell::run(&commands.join("; "))?;
+
+            if !output.status.success() {
+                let error = String::from_utf8_lossy(&output.stderr);
+                return Err(UpvError::Vpn(
+                    format!("Failed to update VPN connection '{}': {}", name, error).into()
+                ).into());
+            }
+        }
+
+        // Set-VpnConnection has no option for DNS servers, so they are set in the phonebook entry
+        if let Some(dns_servers) = &settings.dns_servers {
+            let values = if dns_servers.is_empty() {
+                vec![("IpNameAssign", "1".to_string())]
+            } else {
+                vec![
+                    ("IpNameAssign", "2".to_string()),
+                    ("IpDnsAddress", dns_servers[0].to_string()),
+                    ("IpDns2Address", dns_servers.get(1).map(Ipv4Addr::to_string).unwrap_or_default()),
+                ]
+            };
+            phonebook::set_entry_values(name, &values)?;
+        }
+
+        println!("VPN connection '{}' updated successfully", name);
+        if Self::get_connected_upv_connections()?.iter().any(|connected| connected == name) {
+            println!("The changes will take effect the next time you connect.");
+        }
+
+        Ok(())
+    }
+
+    /// Exports a VPN connection as a phonebook section or as the PowerShell commands to recreate it,
+    /// printing it to stdout or writing it to a file.
+    pub fn export(name: &str, format: ExportFormat, file: Option<&Path>) -> Result<()> {
+        let content = match format {
+            ExportFormat::Rasphone => {
+                let (_, section) = phonebook::find_entry(name)?.ok_or_else(|| UpvError::VpnNotFound(
+                    format!("VPN connection '{}' was not found in any phonebook", name).into()
+                ))?;
+                section.join("\r\n") + "\r\n"
+            }
+            ExportFormat::Powershell => Self::recreate_script(&Self::get_existing_connection_info(name)?)?,
+            ExportFormat::Json => {
+                let info = Self::get_existing_connection_info(name)?;
+                serde_json::to_string_pretty(&info).context("Failed to serialize VPN connection settings")? + "\n"
+            }
+        };
 
-    /// Retrieves all UPV VPN connections by filtering based on the server address.
-    fn get_upv_connections() -> Result<Vec<String>> {
-        let server_address = "vpn.upv.es";
-        let ps_command = format!(
-            "Get-VpnConnection | Where-Object {{$_.ServerAddress -eq '{}'}} | Select-Object -ExpandProperty Name",
-            server_address
-        );
-        
-        let output = Command::new("powershell")
-            .arg("-Command")
-            .arg(&ps_command)
-            .output()
-            .context("Failed to execute PowerShell command")?;
-        
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to get VPN connections: {}", error),
-                EXIT_UPV_VPN_ERROR
-            ).into());
+        match file {
+            Some(path) => {
+                fs::write(path, content)
+                    .with_context(|| format!("Failed to write '{}'", path.display()))?;
+                println!("VPN connection '{}' exported to '{}'", name, path.display());
+            }
+            None => print!("{}", content),
         }
-        
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let connections: Vec<String> = stdout.lines()
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect();
-        
-        Ok(connections)
+
+        Ok(())
     }
     
-    /// Deletes a VPN connection by name using PowerShell.
-    fn delete_connection(name: &str) -> Result<()> {
-        let ps_command = format!("Remove-VpnConnection -Name '{}' -Force", name);
-        
-        let output = Command::new("powershell")
-            .arg("-Command")
-            .arg(&ps_command)
-            .output()
-            .context("Failed to execute PowerShell command")?;
-        
+    /// Sets the DNS suffix of a connection and, optionally, an NRPT (Name Resolution Policy Table) rule that sends
+    /// every name under the suffix to the DNS servers of the VPN, even when other adapters' servers would answer first.
+    /// The NRPT rule needs the VPN connected (to know its DNS servers) and an elevated terminal.
+    pub fn dns(name: &str, suffix: &str, nrpt: bool, remove_nrpt: bool) -> Result<()> {
+        Self::set(name, &VpnSettings { dns_suffix: Some(suffix.to_string()), ..Default::default() })?;
+        println!("DNS suffix of '{}' set to '{}'", name, suffix);
+
+        let namespace = format!(".{}", suffix.trim_start_matches('.'));
+        let remove_existing = format!(
+            "Get-DnsClientNrptRule | Where-Object {{ $_.Comment -eq '{}' -and $_.Namespace -contains '{}' }} | Remove-DnsClientNrptRule -Force",
+            NRPT_RULE_COMMENT,
+            namespace
+        );
+
+        let ps_command = if nrpt {
+            let servers: Vec<String> = powershell::query(&format!(
+                "Get-DnsClientServerAddress -InterfaceAlias '{}' -AddressFamily IPv4 -ErrorAction SilentlyContinue | ForEach-Object {{ $_.ServerAddresses }}",
+                name
+            )).context("Failed to get the DNS servers of the VPN")?;
+
+            if servers.is_empty() {
+                return Err(UpvError::Vpn(
+                    format!("VPN connection '{}' has no DNS servers to send '{}' names to", name, namespace).into()
+                ).with_hint(format!("Connect first with 'upv vpn connect \"{}\"', so the servers it gets can be used", name)).into());
+            }
+
+            let servers: Vec<String> = servers.iter().map(|server| format!("'{}'", server)).collect();
+            format!(
+                "$ErrorActionPreference = 'Stop'; {}; Add-DnsClientNrptRule -Namespace '{}' -NameServers {} -Comment '{}'",
+                remove_existing,
+                namespace,
+                servers.join(","),
+                NRPT_RULE_COMMENT
+            )
+        } else if remove_nrpt {
+            format!("$ErrorActionPreference = 'Stop'; {}", remove_existing)
+        } else {
+            return Ok(());
+        };
+
+        if dry_run::enabled() {
+            dry_run::print_powershell(&ps_command);
+            return Ok(());
+        }
+
+        let output = powershell::run(&ps_command)?;
+
         if !output.status.success() {
             let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to delete VPN connection '{}': {}", name, error),
-                EXIT_UPV_VPN_ERROR
-            ).into());
+            return Err(UpvError::Vpn(
+                format!("Failed to update the NRPT rule for '{}': {}", namespace, error.trim()).into()
+            ).with_hint("Changing the Name Resolution Policy Table requires an elevated (administrator) terminal").into());
         }
-        
+
+        if nrpt {
+            println!("Names under '{}' are now resolved by the DNS servers of '{}'", namespace, name);
+        } else {
+            println!("NRPT rule for '{}' removed", namespace);
+        }
+
         Ok(())
     }
 
-    // Public methods for VPN management
+    /// Recreates a VPN connection from a JSON export, optionally under another name.
+    /// An existing connection with the same name is only replaced if `replace` is set.
+    pub fn import(file: &Path, name: Option<&str>, replace: bool) -> Result<()> {
+        let content = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read '{}'", file.display()))?;
+        let mut info: VpnConnectionInfo = serde_json::from_str(content.trim_start_matches('\u{feff}')).map_err(|e| UpvError::Vpn(
+            format!("'{}' is not a valid VPN connection export: {}", file.display(), e).into()
+        ).with_hint("Create one with 'upv vpn export --format json'"))?;
 
-    /// Creates a new UPV VPN connection with the specified name and optional auto-connect.
-    pub fn create(name: &str, auto_connect: bool) -> Result<()> {
-        println!("Creating VPN connection '{}'...", name);
-        
-        let server_address = "vpn.upv.es";
-        
-        // Clean the XML content and create here-string like your .NET approach
-        let xml_content = EAP_CONFIG_XML.trim().trim_start_matches('\u{feff}'); // Remove BOM if present
-        
-        let ps_command = format!(
-            "Add-VpnConnection -Name '{}' -ServerAddress '{}' -AuthenticationMethod Eap -EncryptionLevel Required -TunnelType Sstp -EapConfigXmlStream @'\r\n{}\r\n'@\r\n\r\n",
-            name,
-            server_address,
-            xml_content
-        );
-        
-        // Execute PowerShell with command via stdin
-        let mut child = Command::new("powershell")
-            .arg("-Command")
-            .arg("-")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Failed to spawn PowerShell process")?;
-        
-        // Write command to stdin and close it
-        if let Some(stdin) = child.stdin.take() {
-            let mut stdin = stdin;
-            stdin.write_all(ps_command.as_bytes())
-                .context("Failed to write to PowerShell stdin")?;
-            // stdin is automatically closed when it goes out of scope
+        if let Some(name) = name {
+            info.name = name.to_string();
         }
-        
-        let output = child.wait_with_output()
-            .context("Failed to wait for PowerShell command")?;
-        
-        if output.status.success() {
-            println!("VPN connection '{}' created successfully", name);
-            
-            // Auto-connect if requested
-            if auto_connect {
-                Self::connect(name)?;
+        // EAP connections can't authenticate without it, so exports that leave it out get the built-in one
+        let embedded_eap_config = info.authentication_method.eq_ignore_ascii_case(VPN_AUTHENTICATION_METHOD)
+            && info.eap_config_xml.as_deref().is_none_or(|xml| xml.trim().is_empty());
+        if embedded_eap_config {
+            info.eap_config_xml = Some(Self::eap_config_xml().to_string());
+        }
+
+        // Everything is checked before an existing connection is replaced, so a bad file doesn't leave it deleted
+        Self::check_server(&info.server_address)?;
+        let script = format!("$ErrorActionPreference = 'Stop'\r\n{}\r\n", Self::recreate_script(&info)?);
+
+        if Self::get_connection_info(&info.name)?.is_some() {
+            if !replace {
+                return Err(UpvError::Vpn(
+                    format!("VPN connection '{}' already exists", info.name).into()
+                ).with_hint("Replace it with --replace, or import it under another name with --name").into());
             }
-        } else {
+            println!("Replacing VPN connection '{}'...", info.name);
+            Self::delete_connection(&info.name)?;
+        }
+
+        println!("Importing VPN connection '{}'...", info.name);
+
+        if dry_run::enabled() {
+            dry_run::print_powershell_stdin(&script);
+            return Ok(());
+        }
+
+        let output = powershell::run_stdin(&script)?;
+
+        if !output.status.success() {
             let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to create VPN connection '{}': {}", name, error),
-                EXIT_UPV_VPN_ERROR
+            return Err(UpvError::Vpn(
+                format!("Failed to import VPN connection '{}': {}", info.name, error).into()
             ).into());
         }
-        
+
+        println!("VPN connection '{}' imported successfully", info.name);
+        // The EAP configuration of an export may come from anywhere, so only the embedded one has a known version
+        if embedded_eap_config {
+            Self::record_eap_config(&info.name, false);
+        } else {
+            Self::forget_connection(&info.name);
+        }
+
         Ok(())
     }
     
-    /// Purges all UPV VPN connections, with optional exceptions and force confirmation.
-    pub fn purge(force: bool, except_names: Vec<String>) -> Result<()> {
-        // Get the list of UPV connections
-        let all_connections = Self::get_upv_connections()
-            .context("Failed to retrieve UPV VPN connections")?;
-        
-        if all_connections.is_empty() {
-            println!("No UPV VPN connections found to delete.");
-            return Ok(());
-        }
-        
-        // Filter out the connections to except (only if there are exceptions)
-        let connections = if except_names.is_empty() {
-            all_connections
-        } else {
-            all_connections
-                .into_iter()
-                .filter(|conn| !except_names.contains(conn))
-                .collect()
-        };
-        
-        if connections.is_empty() {
-            println!("No UPV VPN connections found to delete.");
-            return Ok(());
-        }
-        
-        // Show what will be deleted
-        println!("Found {} UPV VPN connection(s) to delete:", connections.len());
-        for conn in &connections {
-            println!("  - {}", conn);
-        }
-        
-        if !force {
-            // First confirmation
-            print!("\nAre you sure you want to delete ALL {} UPV VPN connections? (y/N): ", connections.len());
-            io::stdout().flush().context("Failed to flush stdout")?;
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).context("Failed to read user input")?;
-            
-            let confirmation = input.trim().to_lowercase();
-            if confirmation != "y" && confirmation != "yes" {
-                println!("Operation cancelled.");
-                return Ok(());
-            }
-            
-            // Second confirmation (extra safety)
-            print!("This action cannot be undone. Type 'DELETE' to confirm: ");
-            io::stdout().flush().context("Failed to flush stdout")?;
-            
-            let mut input2 = String::new();
-            io::stdin().read_line(&mut input2).context("Failed to read user input")?;
-            
-            if input2.trim() != "DELETE" {
-                println!("Operation cancelled.");
-                return Ok(());
-            }
-        }
-        
-        println!("\nDeleting {} UPV VPN connections...", connections.len());
-        
-        let mut deleted_count = 0;
+    /// Recreates the connections deleted by the latest run that deleted any (a delete, a purge...) from their backups.
+    /// The backups of the ones restored are removed, so running it again restores the run before.
+    pub fn undo_delete() -> Result<()> {
+        let dir = Self::deleted_backups()?.pop().ok_or_else(|| UpvError::VpnNotFound(
+            "There are no deleted VPN connections to restore".into()
+        ).with_hint(format!("Only the connections deleted in the last {} runs that deleted any are kept", MAX_DELETED_BACKUPS)))?;
+
+        let folder = dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let deleted_at = NaiveDateTime::parse_from_str(&folder, DELETED_BACKUP_TIME_FORMAT)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or(folder);
+
+        let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read '{}'", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        files.sort();
+
+        println!("Restoring the VPN connections deleted on {}...", deleted_at);
+
         let mut failed_count = 0;
-        
-        for connection in connections {
-            match Self::delete_connection(&connection) {
-                Ok(()) => {
-                    println!("  ✓ Deleted '{}'", connection);
-                    deleted_count += 1;
+        for file in &files {
+            match Self::import(file, None, false) {
+                Ok(()) if !dry_run::enabled() => {
+                    let _ = fs::remove_file(file);
                 }
+                Ok(()) => {}
                 Err(e) => {
-                    eprintln!("  ✗ Failed to delete '{}': {}", connection, e);
+                    eprintln!("  ✗ Failed to restore '{}': {}", file.display(), e);
                     failed_count += 1;
                 }
             }
         }
-        
-        println!("\nPurge completed:");
-        println!("  {} connections deleted successfully", deleted_count);
+
         if failed_count > 0 {
-            println!("  {} connections failed to delete", failed_count);
+            return Err(UpvError::Vpn(
+                format!("{} of {} deleted VPN connections couldn't be restored", failed_count, files.len()).into()
+            ).with_hint(format!("Their backups are kept in '{}', and can be restored with 'upv vpn import <FILE>'", dir.display())).into());
         }
-        
+
+        // Once every connection is back, the next undo restores the run before
+        if !dry_run::enabled() {
+            let _ = fs::remove_dir_all(&dir);
+        }
+
         Ok(())
     }
+
+    /// Restores the default routes and DNS servers saved before connecting to the VPN.
+    pub fn restore_network(force: bool) -> Result<()> {
+        let names = Self::get_upv_connections()?;
+        NetworkManager::restore(&names, force)
+    }
     
-    /// Connects to an existing UPV VPN connection using rasphone.
-    pub fn connect(name: &str) -> Result<()> {
-        println!("Opening connection dialog for '{}'...", name);
-        
-        // Use rasphone to open the connection dialog
-        let output = Command::new("rasphone")
-            .arg("-d")
-            .arg(name)
-            .output()
-            .context("Failed to execute rasphone command")?;
-        
-        if output.status.success() {
-            println!("Connection dialog opened for '{}'", name);
+    /// Checks which VPN connections are currently connected.
+    pub fn status() -> Result<()> {
+        println!("Checking VPN status...");
+
+        let connected = Self::get_connected_connections()?;
+
+        if connected.is_empty() {
+            println!("Not connected to any VPN");
         } else {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to open connection dialog for '{}': {}", name, error),
-                EXIT_UPV_VPN_ERROR
-            ).into());
+            println!("Connected to:");
+            for name in &connected {
+                println!("  - {}", name);
+            }
+        }
+
+        let mut names = connected.clone();
+        if let Some(default) = Self::default_connection() {
+            let status = if connected.contains(&default) { "connected" } else { "not connected" };
+            println!("Default connection: {} ({})", default, status);
+            if !names.contains(&default) {
+                names.push(default);
+            }
         }
+
+        Self::print_outdated_connections(&names);
         
         Ok(())
     }
-    
-    /// Disconnects from the current UPV VPN connection using rasdial.
-    pub fn disconnect() -> Result<()> {
-        println!("Disconnecting from VPN...");
-        
-        let output = Command::new("rasdial")
-            .arg("/disconnect")
-            .output()
-            .context("Failed to execute rasdial disconnect")?;
-        
-        if output.status.success() {
-            println!("Disconnected from VPN successfully");
-        } else {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(UpvError::new(
-                format!("Failed to disconnect from VPN: {}", error),
-                EXIT_UPV_VPN_ERROR
-            ).into());
+
+    /// Applies the settings 'upv vpn create' gives connections (and the current embedded EAP configuration) to an existing
+    /// connection, keeping its server and protocol.
+    pub fn repair(name: &str) -> Result<()> {
+        let info = Self::get_existing_connection_info(name)?;
+        let tunnel_type = <TunnelType as ValueEnum>::from_str(&info.tunnel_type, true).map_err(|_| UpvError::Vpn(
+            format!("VPN connection '{}' uses the {} protocol, which can't be repaired (only SSTP, IKEv2 and Automatic are supported)", name, info.tunnel_type).into()
+        ).with_hint(format!("Recreate it with 'upv vpn delete \"{}\"' and 'upv vpn create \"{}\"'", name, name)))?;
+
+        println!("Repairing VPN connection '{}'...", name);
+        Self::repair_connection(name, &info.server_address, tunnel_type, Self::eap_config_xml(), None)?;
+        Self::record_eap_config(name, false);
+
+        if !dry_run::enabled() {
+            println!("VPN connection '{}' repaired successfully", name);
         }
-        
+
         Ok(())
     }
-    
-    /// Deletes a specific UPV VPN connection by name, with optional confirmation.
-    pub fn delete(name: &str, force: bool) -> Result<()> {
-        if !force {
-            print!("Are you sure you want to delete VPN connection '{}'? (y/N): ", name);
-            io::stdout().flush().context("Failed to flush stdout")?;
-            
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).context("Failed to read user input")?;
-            
-            let confirmation = input.trim().to_lowercase();
-            if confirmation != "y" && confirmation != "yes" {
-                println!("Operation cancelled.");
-                return Ok(());
+
+    /// Repairs every UPV connection created with an older version of the embedded EAP configuration, going on when one fails.
+    pub fn repair_outdated() -> Result<()> {
+        let outdated = Self::outdated_connections(&Self::get_upv_connections()?);
+
+        if outdated.is_empty() {
+            println!("No VPN connections were created with an outdated EAP configuration (the current one is version {})", EAP_CONFIG_VERSION);
+            return Ok(());
+        }
+
+        let mut failed: Vec<&str> = Vec::new();
+        for (name, _) in &outdated {
+            if let Err(e) = Self::repair(name) {
+                eprintln!("  ✗ {}", e);
+                failed.push(name);
             }
         }
-        
-        println!("Deleting VPN connection '{}'...", name);
-        
-        Self::delete_connection(name)?;
 
-        println!("VPN connection '{}' deleted successfully", name);
-        
+        if !failed.is_empty() {
+            return Err(UpvError::Vpn(
+                format!("{} of {} VPN connections failed to be repaired ({})", failed.len(), outdated.len(), failed.join(", ")).into()
+            ).into());
+        }
+
         Ok(())
     }
-    
-    /// Lists all UPV VPN connections.
-    pub fn list() -> Result<()> {
-        println!("Listing UPV VPN connections...");
-        
-        let connections = Self::get_upv_connections()?;
-        
-        if connections.is_empty() {
-            println!("No UPV VPN connections found.");
+
+    /// Compares a connection against the settings 'upv vpn create' would give it (with the given EAP config, or the
+    /// embedded one), printing the differing ones (as JSON if requested). Fails if there are any, so scripts can check
+    /// a whole fleet before repairing it.
+    pub fn verify(name: &str, server: &str, tunnel_type: TunnelType, eap_config: Option<&Path>, json: bool) -> Result<()> {
+        let xml_content = match eap_config {
+            Some(path) => Self::load_eap_config(path)?,
+            None => Self::eap_config_xml().to_string(),
+        };
+        let differences = Self::get_config_differences(name, server, tunnel_type, &xml_content, Some(UPV_DNS_SUFFIX))?
+            .ok_or_else(|| UpvError::VpnNotFound(
+                format!("VPN connection '{}' not found", name).into()
+            ).with_hint("Run 'upv vpn list' to see the available ones, or create it with 'upv vpn create'"))?;
+
+        if json {
+            diff::print(&differences, true)?;
+        } else if differences.is_empty() {
+            println!("VPN connection '{}' is correctly configured", name);
         } else {
-            println!("Found {} UPV VPN connection(s):", connections.len());
-            for conn in connections {
-                println!("  - {}", conn);
-            }
+            println!("VPN connection '{}' differs from the UPV settings:", name);
+            diff::print(&differences, false)?;
         }
-        
+
+        if !differences.is_empty() {
+            return Err(UpvError::Vpn(
+                format!("{} settings of VPN connection '{}' differ from the expected ones", differences.len(), name).into()
+            ).with_hint(format!("Repair it with 'upv vpn create \"{}\" --idempotent' (add --dry-run to preview it)", name)).into());
+        }
+
         Ok(())
     }
-    
-    /// Checks the status of the current VPN connection using rasdial.
-    pub fn status() -> Result<()> {
-        println!("Checking VPN status...");
-        
-        let output = Command::new("rasdial")
-            .output()
-            .context("Failed to check VPN status")?;
-        
-        let status = String::from_utf8_lossy(&output.stdout);
-        println!("{}", status);
-        
+
+    /// Makes a connection the default one, used by the vpn commands when no name is given.
+    pub fn set_default(name: &str) -> Result<()> {
+        if !Self::get_upv_connections()?.iter().any(|connection| connection == name) {
+            return Err(UpvError::VpnNotFound(
+                format!("There is no UPV VPN connection named '{}'", name).into()
+            ).with_hint("Run 'upv vpn list' to see the available ones, or create it with 'upv vpn create'").into());
+        }
+
+        if dry_run::enabled() {
+            dry_run::print_change(&format!("Set '{}' as the default VPN connection in the config", name));
+            return Ok(());
+        }
+
+        ConfigManager::set(ConfigKey::VpnName, name, false)?;
+        println!("'{}' is now the default VPN connection", name);
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exported(name: &str) -> VpnConnectionInfo {
+        VpnConnectionInfo {
+            name: name.to_string(),
+            server_address: VPN_SERVER_ADDRESS.to_string(),
+            tunnel_type: "Sstp".to_string(),
+            authentication_method: "Eap".to_string(),
+            encryption_level: "Required".to_string(),
+            split_tunneling: true,
+            remember_credential: true,
+            connection_status: String::new(),
+            dns_suffix: Some("upv.es".to_string()),
+            idle_disconnect_seconds: 0,
+            eap_config_xml: Some("<EapHostConfig/>".to_string()),
+            routes: vec!["158.42.0.0/16".to_string()],
+        }
+    }
+
+    #[test]
+    fn quotes_the_settings_of_imported_connections() {
+        let script = VpnManager::recreate_script(&exported("O'Brien's VPN")).unwrap();
+        assert!(script.starts_with("Add-VpnConnection -Name 'O''Brien''s VPN' -ServerAddress 'vpn.upv.es' -TunnelType Sstp -EncryptionLevel Required -AuthenticationMethod Eap "));
+        assert!(script.contains("Add-VpnConnectionRoute -ConnectionName 'O''Brien''s VPN' -DestinationPrefix '158.42.0.0/16'"));
+    }
+
+    #[test]
+    fn compares_eap_configs_by_their_content() {
+        let written = "\u{feff}<?xml version=\"1.0\"?>\r\n<EapHostConfig xmlns=\"a\">\r\n  <EapMethod>\r\n    <Type>25</Type>\r\n  </EapMethod>\r\n</EapHostConfig>\r\n";
+        let read_back = "<EapHostConfig xmlns=\"a\"><EapMethod><Type>25</Type></EapMethod></EapHostConfig>";
+        assert_eq!(VpnManager::normalize_xml(written), VpnManager::normalize_xml(read_back));
+        assert_ne!(VpnManager::normalize_xml(read_back), VpnManager::normalize_xml(&read_back.replace("25", "26")));
+        assert_eq!(VpnManager::normalize_xml("<Name>UPV VPN</Name>"), "<Name>UPV VPN</Name>");
+    }
+
+    #[test]
+    fn rejects_settings_that_would_run_code() {
+        let mut info = exported("UPV");
+        info.tunnel_type = "Sstp; Start-Process calc".to_string();
+        assert!(VpnManager::recreate_script(&info).is_err());
+
+        let mut info = exported("UPV");
+        info.authentication_method = "Eap,(calc)".to_string();
+        assert!(VpnManager::recreate_script(&info).is_err());
+
+        let mut info = exported("UPV");
+        info.eap_config_xml = Some("<EapHostConfig>\r\n'@\r\nStart-Process calc\r\n</EapHostConfig>".to_string());
+        assert!(VpnManager::recreate_script(&info).is_err());
+    }
+
+    #[test]
+    fn parses_entry_options() {
+        assert_eq!("phonebook = D:\\vpn.pbk".parse(), Ok(EntryOption::Phonebook(PathBuf::from(r"D:\vpn.pbk"))));
+        assert_eq!("PrefixSuffix".parse(), Ok(EntryOption::PrefixSuffix));
+
+        assert_eq!("domain".parse::<EntryOption>(), Err("'domain' needs a value (domain=<VALUE>)".to_string()));
+        assert_eq!("phone=".parse::<EntryOption>(), Err("'phone' needs a value (phone=<VALUE>)".to_string()));
+        assert_eq!("prefixsuffix=yes".parse::<EntryOption>(), Err("'prefixsuffix' doesn't take a value".to_string()));
+        assert!("callback=123".parse::<EntryOption>().unwrap_err().starts_with("unknown entry option 'callback'"));
+    }
+}
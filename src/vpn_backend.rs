@@ -0,0 +1,280 @@
+use std::process::{Command, Stdio};
+use anyhow::{anyhow, Result, Context};
+use serde::Deserialize;
+
+use crate::error::UpvError;
+use crate::phonebook;
+use crate::powershell;
+use crate::ras::{self, DialOptions};
+use crate::vpn::EntryOption;
+
+/// A connection attempt that the remote access service turned down
+pub struct DialFailure {
+    /// RAS error code (e.g. 691 for a wrong username or password)
+    pub code: i32,
+    /// Description of the error
+    pub message: String,
+    /// Command that was run to dial, if it was dialed through one
+    pub command: Option<Command>,
+}
+
+/// Ways of listing, connecting, disconnecting and deleting VPN connections.
+/// Creating and configuring them needs the VPN PowerShell cmdlets (the EAP configuration can only be turned into the
+/// blob stored in the phonebook by EapHost, see ras.rs), so they are not part of this.
+pub trait VpnBackend {
+    /// Names of the connections to any of the given servers
+    fn connections(&self, servers: &[String]) -> Result<Vec<String>>;
+
+    /// Names of the connections (to any server) that are currently connected
+    fn connected(&self) -> Result<Vec<String>>;
+
+    /// Connections to any of the given servers along with their status ("Connected", "Disconnected"...)
+    fn statuses(&self, servers: &[String]) -> Result<Vec<(String, String)>> {
+        let connected = self.connected()?;
+        Ok(self.connections(servers)?
+            .into_iter()
+            .map(|name| {
+                let status = if connected.contains(&name) { "Connected" } else { "Disconnected" };
+                (name, status.to_string())
+            })
+            .collect())
+    }
+
+    /// Deletes a connection
+    fn delete(&self, name: &str) -> Result<()>;
+
+    /// Disconnects from a connection
+    fn disconnect(&self, name: &str) -> Result<()>;
+
+    /// Connects to a connection, with the given credentials or, without a user, the ones saved for it.
+    /// The outer error means it couldn't be tried, and the inner one that it was tried and failed (which is not tried
+    /// again with another backend, so a wrong password isn't sent twice)
+    fn dial(&self, name: &str, user: Option<&str>, password: Option<&str>, entry_options: &[EntryOption]) -> Result<Result<(), DialFailure>>;
+}
+
+/// rasdial command that connects to a connection. Without a password, rasdial asks for it
+pub fn rasdial_command(name: &str, user: Option<&str>, password: Option<&str>, entry_options: &[EntryOption]) -> Command {
+    let mut command = Command::new("rasdial");
+    command.arg(name);
+    if let Some(user) = user {
+        command.arg(user).arg(password.unwrap_or("*"));
+    }
+    command.args(entry_options.iter().map(EntryOption::rasdial_arg));
+    command
+}
+
+/// Calls the RAS API directly, which takes milliseconds instead of the seconds it takes to start PowerShell.
+pub struct NativeBackend;
+
+impl VpnBackend for NativeBackend {
+    fn connections(&self, servers: &[String]) -> Result<Vec<String>> {
+        let mut connections = Vec::new();
+        for name in ras::entries()? {
+            let server = ras::entry_server(&name)?;
+            if servers.iter().any(|upv_server| upv_server.eq_ignore_ascii_case(&server)) {
+                connections.push(name);
+            }
+        }
+        Ok(connections)
+    }
+
+    fn connected(&self) -> Result<Vec<String>> {
+        let mut connected = Vec::new();
+        for connection in ras::active_connections()? {
+            if connection.is_connected()? {
+                connected.push(connection.name);
+            }
+        }
+        Ok(connected)
+    }
+
+    fn statuses(&self, servers: &[String]) -> Result<Vec<(String, String)>> {
+        let active = ras::active_connections()?;
+
+        let mut statuses = Vec::new();
+        for name in self.connections(servers)? {
+            let status = match active.iter().find(|connection| connection.name == name) {
+                Some(connection) if connection.is_connected()? => "Connected",
+                Some(_) => "Connecting",
+                None => "Disconnected",
+            };
+            statuses.push((name, status.to_string()));
+        }
+        Ok(statuses)
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        ras::delete_entry(name)
+    }
+
+    fn disconnect(&self, name: &str) -> Result<()> {
+        match ras::active_connections()?.into_iter().find(|connection| connection.name == name) {
+            Some(connection) => connection.hang_up(),
+            None => Ok(()),
+        }
+    }
+
+    fn dial(&self, name: &str, user: Option<&str>, password: Option<&str>, entry_options: &[EntryOption]) -> Result<Result<(), DialFailure>> {
+        let credentials = match (user, password) {
+            (Some(user), Some(password)) => Some((user, password)),
+            // rasdial can ask for the password
+            (Some(_), None) => return Err(anyhow!("The password has to be asked for")),
+            (None, _) => None,
+        };
+
+        let mut options = DialOptions { credentials, ..Default::default() };
+        for option in entry_options {
+            match option {
+                EntryOption::Phonebook(path) => options.phonebook = Some(path),
+                EntryOption::Domain(domain) => options.domain = Some(domain),
+                EntryOption::Phone(phone) => options.phone = Some(phone),
+                EntryOption::PrefixSuffix => options.prefix_suffix = true,
+            }
+        }
+
+        Ok(ras::dial(name, &options).map_err(|code| DialFailure {
+            code: code as i32,
+            message: ras::describe(code),
+            command: None,
+        }))
+    }
+}
+
+/// Uses the VPN PowerShell cmdlets and rasdial, falling back to editing the phonebooks when PowerShell is restricted.
+pub struct PowerShellBackend;
+
+impl VpnBackend for PowerShellBackend {
+    fn connections(&self, servers: &[String]) -> Result<Vec<String>> {
+        let ps_command = format!(
+            "Get-VpnConnection | Where-Object {{$_.ServerAddress -in @({})}} | ForEach-Object {{ $_.Name }}",
+            server_list(servers)
+        );
+
+        match powershell::query(&ps_command) {
+            Ok(connections) => Ok(connections),
+            // Without PowerShell, the connections can still be found in the phonebooks
+            Err(_) if powershell::restriction().is_some() => phonebook::entries_with_servers(servers),
+            Err(e) => Err(UpvError::Vpn(format!("Failed to get VPN connections: {}", e).into()).into()),
+        }
+    }
+
+    /// Parses the output of rasdial (which doesn't need PowerShell).
+    fn connected(&self) -> Result<Vec<String>> {
+        let output = Command::new("rasdial")
+            .output()
+            .context("Failed to execute rasdial command")?;
+
+        if !output.status.success() {
+            return Err(UpvError::Vpn(
+                format!("Failed to get VPN connection status: {}", String::from_utf8_lossy(&output.stdout).trim()).into()
+            ).with_os_code(output.status.code()).into());
+        }
+
+        // The output is a (localized) header line, one line per connection and a (localized) result line
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let connections = match lines.as_slice() {
+            [_, connections @ .., _] => connections.iter().map(|name| name.to_string()).collect(),
+            _ => Vec::new(),
+        };
+
+        Ok(connections)
+    }
+
+    fn statuses(&self, servers: &[String]) -> Result<Vec<(String, String)>> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct ConnectionStatus {
+            name: String,
+            connection_status: String,
+        }
+
+        let ps_command = format!(
+            "Get-VpnConnection | Where-Object {{$_.ServerAddress -in @({})}} | Select-Object Name, @{{n='ConnectionStatus';e={{[string]$_.ConnectionStatus}}}}",
+            server_list(servers)
+        );
+
+        let connections = match powershell::query::<ConnectionStatus>(&ps_command) {
+            Ok(connections) => connections,
+            Err(_) if powershell::restriction().is_some() => {
+                let connected = self.connected()?;
+                return Ok(phonebook::entries_with_servers(servers)?
+                    .into_iter()
+                    .map(|name| {
+                        let status = if connected.contains(&name) { "Connected" } else { "Disconnected" };
+                        (name, status.to_string())
+                    })
+                    .collect());
+            }
+            Err(e) => return Err(UpvError::Vpn(format!("Failed to get VPN connection status: {}", e).into()).into()),
+        };
+
+        Ok(connections.into_iter().map(|connection| (connection.name, connection.connection_status)).collect())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let output = powershell::run(&format!("Remove-VpnConnection -Name '{}' -Force", name));
+
+        // Without PowerShell, the connection can still be removed from its phonebook
+        if !output.as_ref().is_ok_and(|output| output.status.success()) && powershell::restriction().is_some() {
+            return phonebook::remove_entry(name);
+        }
+
+        let output = output?;
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(UpvError::Vpn(
+                format!("Failed to delete VPN connection '{}': {}", name, error).into()
+            ).into());
+        }
+
+        Ok(())
+    }
+
+    fn disconnect(&self, name: &str) -> Result<()> {
+        let output = Command::new("rasdial")
+            .arg(name)
+            .arg("/disconnect")
+            .output()
+            .context("Failed to execute rasdial disconnect")?;
+
+        if !output.status.success() {
+            // rasdial exits with the RAS error code
+            let error = String::from_utf8_lossy(&output.stdout);
+            return Err(UpvError::Vpn(
+                format!("Failed to disconnect from '{}': {}", name, error.trim()).into()
+            ).with_os_code(output.status.code()).into());
+        }
+
+        Ok(())
+    }
+
+    fn dial(&self, name: &str, user: Option<&str>, password: Option<&str>, entry_options: &[EntryOption]) -> Result<Result<(), DialFailure>> {
+        let mut command = rasdial_command(name, user, password, entry_options);
+
+        // Only capture stdout, so rasdial can still prompt for the password
+        let output = command
+            .stderr(Stdio::inherit())
+            .output()
+            .context("Failed to execute rasdial command")?;
+
+        if output.status.success() {
+            return Ok(Ok(()));
+        }
+
+        // rasdial exits with the RAS error code, and prints something like "Remote Access error 691 - ..."
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let code = output.status.code().unwrap_or_default();
+        let message = stdout.lines()
+            .map(str::trim)
+            .find(|line| line.contains(&code.to_string()))
+            .unwrap_or_else(|| stdout.trim());
+
+        Ok(Err(DialFailure { code, message: message.to_string(), command: Some(command) }))
+    }
+}
+
+/// Servers as a PowerShell list ('a','b'). `-in` compares them ignoring case, like the native backend
+fn server_list(servers: &[String]) -> String {
+    servers.iter().map(|server| format!("'{}'", server)).collect::<Vec<_>>().join(",")
+}
@@ -0,0 +1,29 @@
+use std::slice;
+
+// Conversions between Rust strings and the null-terminated UTF-16 strings the Windows API takes and returns
+
+/// Converts a string to a null-terminated UTF-16 string
+pub fn to_wide(value: &str) -> Vec<u16> {
+    value.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Converts a null-terminated UTF-16 buffer (like the fixed-size arrays in RAS structs) to a string
+pub fn from_wide(buffer: &[u16]) -> String {
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
+
+/// Reads a null-terminated UTF-16 string returned by the API (`None` for a null or empty one)
+///
+/// # Safety
+/// The pointer must be null or point to a null-terminated UTF-16 string
+pub unsafe fn from_wide_ptr(value: *const u16) -> Option<String> {
+    if value.is_null() {
+        return None;
+    }
+    // SAFETY: the string is null-terminated, so every position read is part of it
+    let len = (0..).take_while(|&i| unsafe { *value.add(i) } != 0).count();
+    // SAFETY: the first `len` characters were just read
+    let value = String::from_utf16_lossy(unsafe { slice::from_raw_parts(value, len) });
+    (!value.is_empty()).then_some(value)
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::mem::size_of;
+use std::ptr;
+use std::slice;
+use windows_sys::Win32::Foundation::{
+    ERROR_BAD_PROVIDER, ERROR_EXTENDED_ERROR, ERROR_MORE_DATA, ERROR_NO_MORE_ITEMS, ERROR_NO_NET_OR_BAD_PATH, HANDLE,
+};
+use windows_sys::Win32::NetworkManagement::NetManagement::{
+    NetApiBufferFree, NetUseEnum, USE_CONN, USE_DISCONN, USE_INFO_1, USE_NETERR, USE_OK, USE_PAUSED, USE_RECONN,
+};
+use windows_sys::Win32::NetworkManagement::WNet::{
+    WNetAddConnection2W, WNetCancelConnection2W, WNetCloseEnum, WNetEnumResourceW, WNetOpenEnumW, CONNECT_UPDATE_PROFILE,
+    NETRESOURCEW, NET_RESOURCE_SCOPE, RESOURCETYPE_ANY, RESOURCETYPE_DISK, RESOURCE_CONNECTED, RESOURCE_REMEMBERED,
+};
+
+use crate::wide::{from_wide_ptr, to_wide};
+
+// Native access to the Windows Networking (WNet) API, which maps and lists network drives like `net use` does, without
+// starting another process or parsing its (localized) output. Failures come back as Win32 error codes
+// (e.g. 1326 for a wrong password, or 67 for a share that doesn't exist), as std::io errors.
+
+// Failures that only the network provider (e.g. the WebDAV client) can describe. `net use` asks it for the details,
+// so it is worth running instead
+pub const PROVIDER_ERRORS: [u32; 3] = [ERROR_NO_NET_OR_BAD_PATH, ERROR_BAD_PROVIDER, ERROR_EXTENDED_ERROR];
+
+// Value of NetUseEnum's max preferred length that makes it allocate as much as needed
+const MAX_PREFERRED_LENGTH: u32 = u32::MAX;
+
+/// A connection to a network resource, like a row of `net use`
+pub struct Connection {
+    /// Connection state ("OK", "Disconnected", "Unavailable"...). Empty for connections that aren't through SMB (WebDAV)
+    pub status: String,
+    /// Local device (e.g. "W:"), if the connection has one
+    pub local: Option<String>,
+    /// UNC path of the share
+    pub remote: String,
+}
+
+/// Turns the result of a WNet call into an io::Result, which keeps the error code
+fn check(code: u32) -> io::Result<()> {
+    match code {
+        0 => Ok(()),
+        code => Err(io::Error::from_raw_os_error(code as i32)),
+    }
+}
+
+/// Description of a failed call, without the "(os error N)" suffix (the code is shown separately)
+pub fn describe(error: &io::Error) -> String {
+    let description = error.to_string();
+    match error.raw_os_error() {
+        Some(code) => description.trim_end_matches(&format!(" (os error {})", code)).to_string(),
+        None => description,
+    }
+}
+
+/// Connections of a scope (current or remembered ones)
+fn enumerate(scope: NET_RESOURCE_SCOPE) -> io::Result<Vec<(Option<String>, String)>> {
+    let mut handle: HANDLE = ptr::null_mut();
+    // SAFETY: a null resource enumerates from the root, and the handle outlives the call
+    check(unsafe { WNetOpenEnumW(scope, RESOURCETYPE_ANY, 0, ptr::null(), &mut handle) })?;
+
+    // The strings the structs point at are stored after them in the buffer. A u64 buffer keeps the structs aligned
+    let mut buffer = vec![0u64; 16 * 1024 / size_of::<u64>()];
+    let mut connections = Vec::new();
+    let result = loop {
+        let mut count = u32::MAX;
+        let mut size = (buffer.len() * size_of::<u64>()) as u32;
+        // SAFETY: the buffer holds `size` bytes, and the handle was opened above
+        match unsafe { WNetEnumResourceW(handle, &mut count, buffer.as_mut_ptr().cast(), &mut size) } {
+            0 => {
+                // SAFETY: the call succeeded, so the buffer starts with `count` filled in structs
+                let resources = unsafe { slice::from_raw_parts(buffer.as_ptr().cast::<NETRESOURCEW>(), count as usize) };
+                for resource in resources {
+                    // SAFETY: the names are null or point to strings in the buffer, which is still alive
+                    let (local, remote) = unsafe { (from_wide_ptr(resource.lpLocalName), from_wide_ptr(resource.lpRemoteName)) };
+                    if let Some(remote) = remote {
+                        connections.push((local.map(|local| local.to_uppercase()), remote));
+                    }
+                }
+            }
+            ERROR_NO_MORE_ITEMS => break Ok(connections),
+            ERROR_MORE_DATA => buffer.resize((size as usize).div_ceil(size_of::<u64>()), 0),
+            code => break check(code).map(|_| connections),
+        }
+    };
+
+    // SAFETY: the handle was opened above and isn't used after this
+    unsafe { WNetCloseEnum(handle) };
+    result
+}
+
+/// Status of the SMB connections, as `net use` names it, by local device (or remote path, for those without one).
+/// Other providers (like WebDAV) don't report one. It is only a detail, so it is left out if it can't be read
+fn smb_statuses() -> BTreeMap<String, &'static str> {
+    let mut statuses = BTreeMap::new();
+    let mut buffer: *mut u8 = ptr::null_mut();
+    let (mut read, mut total) = (0, 0);
+    // SAFETY: a null server means the local computer, and the outputs outlive the call
+    let code = unsafe { NetUseEnum(ptr::null(), 1, &mut buffer, MAX_PREFERRED_LENGTH, &mut read, &mut total, ptr::null_mut()) };
+
+    if code == 0 && !buffer.is_null() {
+        // SAFETY: the call succeeded, so the buffer holds `read` USE_INFO_1 structs
+        let uses = unsafe { slice::from_raw_parts(buffer.cast::<USE_INFO_1>(), read as usize) };
+        for info in uses {
+            let status = match info.ui1_status {
+                USE_OK => "OK",
+                USE_PAUSED => "Paused",
+                USE_DISCONN => "Disconnected",
+                USE_NETERR => "Error",
+                USE_CONN => "Connecting",
+                USE_RECONN => "Reconnecting",
+                _ => continue,
+            };
+            // SAFETY: the names are null or point to strings in the buffer, which is still alive
+            let key = unsafe { from_wide_ptr(info.ui1_local).or_else(|| from_wide_ptr(info.ui1_remote)) };
+            if let Some(key) = key {
+                statuses.insert(key.to_uppercase(), status);
+            }
+        }
+    }
+
+    if !buffer.is_null() {
+        // SAFETY: the buffer was allocated by NetUseEnum and isn't used after this
+        unsafe { NetApiBufferFree(buffer.cast()) };
+    }
+    statuses
+}
+
+/// Current connections (including the ones whose server stopped answering), followed by the remembered ones that
+/// aren't connected (persistent mappings that couldn't be restored at logon), which `net use` lists as "Unavailable"
+pub fn connections() -> io::Result<Vec<Connection>> {
+    let statuses = smb_statuses();
+    let mut connections: Vec<Connection> = enumerate(RESOURCE_CONNECTED)?
+        .into_iter()
+        .map(|(local, remote)| {
+            let key = local.clone().unwrap_or_else(|| remote.to_uppercase());
+            Connection { status: statuses.get(&key).copied().unwrap_or_default().to_string(), local, remote }
+        })
+        .collect();
+
+    for (local, remote) in enumerate(RESOURCE_REMEMBERED)? {
+        if !connections.iter().any(|connection| connection.local.is_some() && connection.local == local) {
+            connections.push(Connection { status: "Unavailable".to_string(), local, remote });
+        }
+    }
+
+    Ok(connections)
+}
+
+/// Maps a share (UNC path or WebDAV URL) to a drive letter, with the given credentials or, without them, the ones
+/// Windows already has for the server. Persistent mappings are restored at the next logon.
+pub fn add_connection(drive: char, remote: &str, username: Option<&str>, password: Option<&str>, persistent: bool) -> io::Result<()> {
+    let mut local = to_wide(&format!("{}:", drive));
+    let mut remote = to_wide(remote);
+    let username = username.map(to_wide);
+    let password = password.map(to_wide);
+
+    let resource = NETRESOURCEW {
+        dwType: RESOURCETYPE_DISK,
+        lpLocalName: local.as_mut_ptr(),
+        lpRemoteName: remote.as_mut_ptr(),
+        ..Default::default()
+    };
+    // Without CONNECT_UPDATE_PROFILE, the mapping lasts until logoff, like `net use /persistent:no`
+    let flags = if persistent { CONNECT_UPDATE_PROFILE } else { 0 };
+
+    // SAFETY: every string is null-terminated (or null, to use the current credentials) and outlives the call
+    check(unsafe {
+        WNetAddConnection2W(
+            &resource,
+            password.as_ref().map_or(ptr::null(), |password| password.as_ptr()),
+            username.as_ref().map_or(ptr::null(), |username| username.as_ptr()),
+            flags,
+        )
+    })
+}
+
+/// Removes the mapping of a drive letter, so it isn't restored at the next logon either.
+/// Unless forced, it fails with ERROR_OPEN_FILES or ERROR_DEVICE_IN_USE while the drive is in use.
+pub fn cancel_connection(drive: char, force: bool) -> io::Result<()> {
+    let local = to_wide(&format!("{}:", drive));
+    // SAFETY: the name is a valid null-terminated UTF-16 string that outlives the call
+    check(unsafe { WNetCancelConnection2W(local.as_ptr(), CONNECT_UPDATE_PROFILE, force.into()) })
+}